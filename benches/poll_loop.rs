@@ -0,0 +1,129 @@
+//! Benchmarks of the work done in one poll cycle.
+//!
+//! Run with `cargo bench --bench poll_loop`. The stages are those of the
+//! latency budget in `tests/poll_budget.rs`: decoding a sample from the
+//! registers, computing the derived signals, writing a storage batch and
+//! rendering the charts. The register map is the one of
+//! `rustsys.example.toml`, read from an in-memory transport.
+
+#[path = "../tests/support/mod.rs"]
+mod support;
+
+use codesys::codesys::axis::ValueAxis;
+use codesys::codesys::config;
+use codesys::codesys::{
+    calc, charts, disk, draw_chart, insert_motor_data_batch, jitter, read_motor_data, sentinels, setup_database,
+    MotorData, Signal, CHART_SIZE,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use support::{FixedTransport, START_MS};
+
+fn example_config() -> config::MonitorConfig {
+    config::parse(include_str!("../rustsys.example.toml")).expect("rustsys.example.toml is valid")
+}
+
+fn target_dir() -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("bench");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// One sample per second for `secs`.
+fn samples(secs: i64) -> Vec<MotorData> {
+    support::samples(START_MS, 1000, secs)
+}
+
+fn decode(c: &mut Criterion) {
+    let config = example_config();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut transport = FixedTransport;
+    let mut clock = jitter::SampleClock::new(Duration::from_millis(config.poll_interval_ms));
+    let mut filter = sentinels::SentinelFilter::new(config.registers.sentinels.clone());
+    c.bench_function("decode/read_motor_data", |b| {
+        b.iter(|| runtime.block_on(read_motor_data(&mut transport, &config.registers, &mut clock, &mut filter)).unwrap())
+    });
+}
+
+fn derive(c: &mut Criterion) {
+    c.bench_function("derive/power_and_cycles", |b| {
+        b.iter(|| {
+            let power = calc::calculate_power(std::hint::black_box(400.0), std::hint::black_box(6.0));
+            let cycles = calc::calculate_cycles(std::hint::black_box(10.1), std::hint::black_box(1.0));
+            (power, cycles)
+        })
+    });
+    let window = samples(600);
+    c.bench_function("derive/series_push_600", |b| {
+        b.iter(|| {
+            let mut series = charts::SeriesBuffer::new(600);
+            for data in &window {
+                series.push(data);
+            }
+            series
+        })
+    });
+}
+
+fn store(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let path = target_dir().join("store.db");
+    let _ = std::fs::remove_file(&path);
+    let pool = runtime.block_on(setup_database(&format!("sqlite://{}?mode=rwc", path.display())));
+    let batch = samples(60);
+    c.bench_function("store/insert_batch_60", |b| {
+        b.iter(|| runtime.block_on(insert_motor_data_batch(&pool, &batch)))
+    });
+}
+
+fn render(c: &mut Criterion) {
+    let dir = target_dir();
+    let window = samples(600);
+    let mut series = charts::SeriesBuffer::new(600);
+    for data in &window {
+        series.push(data);
+    }
+    let points = series.series(Signal::Power);
+    let path = dir.join("power.png");
+    c.bench_function("render/chart_600", |b| {
+        b.iter(|| draw_chart(path.to_str().unwrap(), &points, "Current Power", "Time", "Power (W)").unwrap())
+    });
+
+    let config = example_config();
+    let format = config.format.clone();
+    let axis = ValueAxis::default();
+    c.bench_function("render/incremental_600", |b| {
+        let mut cache = charts::RenderCache::incremental(&config.motor_id, format.clone(), disk::Space::default());
+        let filename = dir.join("power_incremental.png");
+        let mut points = points.clone();
+        let mut next = START_MS + 600_000;
+        b.iter_batched(
+            || {
+                points.remove(0);
+                points.push((next, 1200.0));
+                next += 1000;
+                points.clone()
+            },
+            |points| {
+                cache
+                    .draw_if_changed(
+                        filename.to_str().unwrap(),
+                        CHART_SIZE,
+                        &points,
+                        &[],
+                        600,
+                        &axis,
+                        "Current Power",
+                        "Time",
+                        "Power (W)",
+                    )
+                    .unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, decode, derive, store, render);
+criterion_main!(benches);
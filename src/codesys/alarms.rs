@@ -0,0 +1,490 @@
+//! Alarm records: raised when a condition trips, cleared when it recovers.
+//!
+//! Operators acknowledge alarms and may shelve a rule for a limited time
+//! (ISA-18.2): alarms raised while their rule is shelved are still recorded
+//! but flagged `shelved` and not annunciated. Shelves expire on their own.
+//!
+//! A threshold alarm may have a startup grace period: for that many seconds
+//! after a motor start (see `StartDetector`) its condition is ignored, so
+//! the inrush current and temperature transients of every start do not
+//! alarm. An alarm already active when the motor starts stays active.
+
+use chrono::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Severity> {
+        match name {
+            "info" => Some(Severity::Info),
+            "warning" => Some(Severity::Warning),
+            "critical" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Alarm {
+    pub id: i64,
+    pub motor_id: String,
+    /// Name of the rule that raised the alarm.
+    pub rule: String,
+    pub signal: Option<String>,
+    pub severity: Severity,
+    pub message: String,
+    pub raised_at: i64,
+    pub cleared_at: Option<i64>,
+    /// Folder holding the charts captured when the alarm tripped.
+    pub snapshot_path: Option<String>,
+    pub acknowledged_at: Option<i64>,
+    pub acknowledged_by: Option<String>,
+    /// Raised while its rule was shelved.
+    pub shelved: bool,
+}
+
+/// A rule suppressed by an operator until `shelved_until`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Shelf {
+    pub id: i64,
+    pub motor_id: String,
+    pub rule: String,
+    pub reason: String,
+    pub shelved_by: String,
+    pub shelved_at: i64,
+    pub shelved_until: i64,
+    /// Set when the shelf expired or was lifted early.
+    pub unshelved_at: Option<i64>,
+}
+
+/// Filter for `list_alarms`; unset fields match everything.
+#[derive(Debug, Default, Clone)]
+pub struct AlarmFilter {
+    pub motor_id: Option<String>,
+    pub rule: Option<String>,
+    pub severity: Option<Severity>,
+    pub active_only: bool,
+    /// Only alarms not yet acknowledged.
+    pub unacknowledged_only: bool,
+    /// Also return alarms raised while their rule was shelved.
+    pub include_shelved: bool,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Detects motor starts: the first sample above `start_speed_rpm` after
+/// one at or below it.
+#[derive(Debug)]
+pub struct StartDetector {
+    start_speed_rpm: f64,
+    running: Option<bool>,
+}
+
+impl StartDetector {
+    pub fn new(start_speed_rpm: f64) -> Self {
+        StartDetector { start_speed_rpm, running: None }
+    }
+
+    /// True if `speed` starts the motor. The first sample only sets the
+    /// state, since the monitor may come up with the motor already running.
+    pub fn observe(&mut self, speed: f64) -> bool {
+        let running = speed > self.start_speed_rpm;
+        let started = self.running == Some(false) && running;
+        self.running = Some(running);
+        started
+    }
+}
+
+/// A condition that raises an alarm once it has held for `delay_secs` and
+/// clears it as soon as the condition goes away.
+#[derive(Debug)]
+pub struct SustainedAlarm {
+    pub rule: String,
+    pub signal: Option<String>,
+    pub severity: Severity,
+    pub delay_secs: i64,
+    /// Seconds after a motor start during which the condition is ignored.
+    pub startup_grace_secs: i64,
+    since: Option<i64>,
+    active: Option<i64>,
+    grace_until: Option<i64>,
+}
+
+impl SustainedAlarm {
+    pub fn new(rule: &str, signal: Option<&str>, severity: Severity, delay_secs: i64) -> Self {
+        SustainedAlarm {
+            rule: rule.to_string(),
+            signal: signal.map(str::to_string),
+            severity,
+            delay_secs,
+            startup_grace_secs: 0,
+            since: None,
+            active: None,
+            grace_until: None,
+        }
+    }
+
+    pub fn with_startup_grace(mut self, secs: i64) -> Self {
+        self.startup_grace_secs = secs;
+        self
+    }
+
+    /// Starts the grace period of a motor start at `at` (seconds).
+    pub fn motor_started(&mut self, at: i64) {
+        if self.startup_grace_secs > 0 {
+            self.grace_until = Some(at + self.startup_grace_secs);
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Feeds the condition state at `now`, raising or clearing the alarm in
+    /// the database as needed. `message` is only built when raising.
+    /// Returns the id of a newly raised alarm.
+    pub async fn update(
+        &mut self,
+        pool: &SqlitePool,
+        motor_id: &str,
+        condition: bool,
+        now: i64,
+        message: impl FnOnce() -> String,
+    ) -> Option<i64> {
+        if !condition {
+            self.since = None;
+            if let Some(id) = self.active.take() {
+                clear_alarm(pool, id, now).await;
+                log::info!("alarm cleared: {}", self.rule);
+            }
+            return None;
+        }
+        // Within the grace period the delay does not start counting.
+        if self.active.is_none() && self.grace_until.is_some_and(|until| now < until) {
+            self.since = None;
+            return None;
+        }
+        let since = *self.since.get_or_insert(now);
+        if self.active.is_none() && now - since >= self.delay_secs {
+            let message = message();
+            let shelved = is_shelved(pool, motor_id, &self.rule, now).await;
+            if shelved {
+                log::info!("alarm raised while shelved: {}: {}", self.rule, message);
+            } else {
+                log::warn!("alarm raised: {}: {}", self.rule, message);
+            }
+            let id = raise_alarm(pool, motor_id, &self.rule, self.signal.as_deref(), self.severity, &message, now).await;
+            if shelved {
+                sqlx::query("UPDATE alarms SET shelved = 1 WHERE id = ?").bind(id).execute(pool).await.unwrap();
+            }
+            self.active = Some(id);
+            return Some(id);
+        }
+        None
+    }
+}
+
+pub async fn setup_alarms_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS alarms (
+            id INTEGER PRIMARY KEY,
+            motor_id TEXT NOT NULL,
+            rule TEXT NOT NULL,
+            signal TEXT,
+            severity TEXT NOT NULL,
+            message TEXT NOT NULL,
+            raised_at INTEGER NOT NULL,
+            cleared_at INTEGER,
+            snapshot_path TEXT,
+            acknowledged_at INTEGER,
+            acknowledged_by TEXT,
+            shelved INTEGER NOT NULL DEFAULT 0
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    super::ensure_column(pool, "alarms", "snapshot_path", "TEXT").await;
+    super::ensure_column(pool, "alarms", "acknowledged_at", "INTEGER").await;
+    super::ensure_column(pool, "alarms", "acknowledged_by", "TEXT").await;
+    super::ensure_column(pool, "alarms", "shelved", "INTEGER NOT NULL DEFAULT 0").await;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS alarm_shelves (
+            id INTEGER PRIMARY KEY,
+            motor_id TEXT NOT NULL,
+            rule TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            shelved_by TEXT NOT NULL,
+            shelved_at INTEGER NOT NULL,
+            shelved_until INTEGER NOT NULL,
+            unshelved_at INTEGER
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Records a newly raised alarm and returns its id.
+pub async fn raise_alarm(
+    pool: &SqlitePool,
+    motor_id: &str,
+    rule: &str,
+    signal: Option<&str>,
+    severity: Severity,
+    message: &str,
+    raised_at: i64,
+) -> i64 {
+    sqlx::query(
+        r#"
+        INSERT INTO alarms (motor_id, rule, signal, severity, message, raised_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(motor_id)
+    .bind(rule)
+    .bind(signal)
+    .bind(severity.name())
+    .bind(message)
+    .bind(raised_at)
+    .execute(pool)
+    .await
+    .unwrap()
+    .last_insert_rowid()
+}
+
+pub async fn clear_alarm(pool: &SqlitePool, id: i64, cleared_at: i64) {
+    sqlx::query("UPDATE alarms SET cleared_at = ? WHERE id = ? AND cleared_at IS NULL")
+        .bind(cleared_at)
+        .bind(id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+pub async fn attach_snapshot(pool: &SqlitePool, id: i64, path: &str) {
+    sqlx::query("UPDATE alarms SET snapshot_path = ? WHERE id = ?")
+        .bind(path)
+        .bind(id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// Acknowledges an alarm; returns false if it does not exist or was
+/// already acknowledged.
+pub async fn acknowledge_alarm(pool: &SqlitePool, id: i64, by: &str, at: i64) -> bool {
+    sqlx::query("UPDATE alarms SET acknowledged_at = ?, acknowledged_by = ? WHERE id = ? AND acknowledged_at IS NULL")
+        .bind(at)
+        .bind(by)
+        .bind(id)
+        .execute(pool)
+        .await
+        .unwrap()
+        .rows_affected()
+        > 0
+}
+
+/// Shelves `rule` until `until`, replacing any shelf already in place.
+pub async fn shelve_rule(
+    pool: &SqlitePool,
+    motor_id: &str,
+    rule: &str,
+    reason: &str,
+    by: &str,
+    at: i64,
+    until: i64,
+) -> i64 {
+    unshelve_rule(pool, motor_id, rule, at).await;
+    log::info!("rule {} shelved by {} until {}: {}", rule, by, until, reason);
+    sqlx::query(
+        r#"
+        INSERT INTO alarm_shelves (motor_id, rule, reason, shelved_by, shelved_at, shelved_until)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(motor_id)
+    .bind(rule)
+    .bind(reason)
+    .bind(by)
+    .bind(at)
+    .bind(until)
+    .execute(pool)
+    .await
+    .unwrap()
+    .last_insert_rowid()
+}
+
+/// Lifts the shelf on `rule` early; returns false if it was not shelved.
+pub async fn unshelve_rule(pool: &SqlitePool, motor_id: &str, rule: &str, at: i64) -> bool {
+    sqlx::query(
+        r#"
+        UPDATE alarm_shelves SET unshelved_at = ?1
+        WHERE motor_id = ?2 AND rule = ?3 AND unshelved_at IS NULL AND shelved_until > ?1
+        "#,
+    )
+    .bind(at)
+    .bind(motor_id)
+    .bind(rule)
+    .execute(pool)
+    .await
+    .unwrap()
+    .rows_affected()
+        > 0
+}
+
+pub async fn is_shelved(pool: &SqlitePool, motor_id: &str, rule: &str, at: i64) -> bool {
+    let (count,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*) FROM alarm_shelves
+        WHERE motor_id = ?1 AND rule = ?2 AND unshelved_at IS NULL AND shelved_until > ?3
+        "#,
+    )
+    .bind(motor_id)
+    .bind(rule)
+    .bind(at)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    count > 0
+}
+
+/// Closes shelves whose time is up and logs an `alarm_unshelved` event for
+/// each. Returns the rules that were unshelved.
+pub async fn expire_shelves(pool: &SqlitePool, at: i64) -> Vec<String> {
+    let expired: Vec<(i64, String, String, i64)> = sqlx::query_as(
+        "SELECT id, motor_id, rule, shelved_until FROM alarm_shelves WHERE unshelved_at IS NULL AND shelved_until <= ?",
+    )
+    .bind(at)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    let mut rules = Vec::new();
+    for (id, motor_id, rule, until) in expired {
+        sqlx::query("UPDATE alarm_shelves SET unshelved_at = ? WHERE id = ?")
+            .bind(until)
+            .bind(id)
+            .execute(pool)
+            .await
+            .unwrap();
+        let message = format!("shelf on {} expired", rule);
+        log::info!("{}", message);
+        super::events::record_event(pool, &motor_id, until, "alarm_unshelved", &message).await;
+        rules.push(rule);
+    }
+    rules
+}
+
+/// Expires shelves once a minute.
+pub async fn run_shelf_expiry(pool: Arc<SqlitePool>) {
+    let mut interval = time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        expire_shelves(&pool, Local::now().timestamp()).await;
+    }
+}
+
+/// Shelves in place at `at` (or all of them, newest first, with `active_only = false`).
+pub async fn list_shelves(pool: &SqlitePool, active_only: bool, at: i64) -> Vec<Shelf> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, motor_id, rule, reason, shelved_by, shelved_at, shelved_until, unshelved_at FROM alarm_shelves
+        WHERE ?1 = 0 OR (unshelved_at IS NULL AND shelved_until > ?2)
+        ORDER BY shelved_at DESC
+        "#,
+    )
+    .bind(active_only)
+    .bind(at)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    rows.iter()
+        .map(|row| Shelf {
+            id: row.get("id"),
+            motor_id: row.get("motor_id"),
+            rule: row.get("rule"),
+            reason: row.get("reason"),
+            shelved_by: row.get("shelved_by"),
+            shelved_at: row.get("shelved_at"),
+            shelved_until: row.get("shelved_until"),
+            unshelved_at: row.get("unshelved_at"),
+        })
+        .collect()
+}
+
+pub async fn list_alarms(pool: &SqlitePool, filter: &AlarmFilter) -> Vec<Alarm> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, motor_id, rule, signal, severity, message, raised_at, cleared_at, snapshot_path,
+               acknowledged_at, acknowledged_by, shelved
+        FROM alarms
+        WHERE (?1 IS NULL OR motor_id = ?1)
+          AND (?2 IS NULL OR rule = ?2)
+          AND (?3 IS NULL OR severity = ?3)
+          AND (?4 = 0 OR cleared_at IS NULL)
+          AND (?5 IS NULL OR raised_at >= ?5)
+          AND (?6 IS NULL OR raised_at < ?6)
+          AND (?8 = 0 OR acknowledged_at IS NULL)
+          AND (?9 = 1 OR shelved = 0)
+        ORDER BY raised_at DESC
+        LIMIT ?7
+        "#,
+    )
+    .bind(filter.motor_id.as_deref())
+    .bind(filter.rule.as_deref())
+    .bind(filter.severity.map(|s| s.name()))
+    .bind(filter.active_only)
+    .bind(filter.from)
+    .bind(filter.to)
+    .bind(filter.limit.unwrap_or(-1))
+    .bind(filter.unacknowledged_only)
+    .bind(filter.include_shelved)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    rows.iter()
+        .map(|row| Alarm {
+            id: row.get("id"),
+            motor_id: row.get("motor_id"),
+            rule: row.get("rule"),
+            signal: row.get("signal"),
+            severity: Severity::from_name(row.get("severity")).unwrap_or(Severity::Warning),
+            message: row.get("message"),
+            raised_at: row.get("raised_at"),
+            cleared_at: row.get("cleared_at"),
+            snapshot_path: row.get("snapshot_path"),
+            acknowledged_at: row.get("acknowledged_at"),
+            acknowledged_by: row.get("acknowledged_by"),
+            shelved: row.get("shelved"),
+        })
+        .collect()
+}
@@ -0,0 +1,160 @@
+//! Stable ids behind the names of temperature channels and pulse inputs.
+//!
+//! Rows in `temperature_data` and `pulse_data`, and the samples in archive
+//! blocks, carry the channel name they were written under. Renaming a
+//! channel in the configuration would leave its history under the old name,
+//! out of reach of charts, rules and reports. `renamed_from` on a channel
+//! lists its former names instead: at start-up every configured channel is
+//! given an id in the `signals` table, and every name it was ever stored
+//! under points at that id in `signal_names`. Reads look the stored name up
+//! and return the values under the current name, so a rename rewrites no
+//! rows. Hours compacted after a rename are archived under the new name.
+//!
+//! Listing two channels that each have history as one's `renamed_from`
+//! merges them: both names then read as the current one.
+
+use super::config::RegisterMap;
+use super::MotorData;
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+
+pub const TEMPERATURE: &str = "temperature";
+pub const PULSE: &str = "pulse";
+
+pub async fn setup_signal_tables(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS signals (
+            id INTEGER PRIMARY KEY,
+            kind TEXT NOT NULL,
+            name TEXT NOT NULL,
+            UNIQUE (kind, name)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS signal_names (
+            kind TEXT NOT NULL,
+            name TEXT NOT NULL,
+            signal_id INTEGER NOT NULL,
+            PRIMARY KEY (kind, name)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Gives `name` an id, reusing (and merging) the ids of `renamed_from`.
+async fn register_one(pool: &SqlitePool, kind: &str, name: &str, renamed_from: &[String]) {
+    let mut tx = pool.begin().await.unwrap();
+    let mut ids = Vec::new();
+    for known in std::iter::once(name).chain(renamed_from.iter().map(String::as_str)) {
+        let row: Option<(i64,)> = sqlx::query_as("SELECT signal_id FROM signal_names WHERE kind = ? AND name = ?")
+            .bind(kind)
+            .bind(known)
+            .fetch_optional(&mut tx)
+            .await
+            .unwrap();
+        if let Some((id,)) = row {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    // Oldest id first; later ones are merged into it.
+    for &merged in ids.iter().skip(1) {
+        log::info!("merging the history of {} signal id {} into id {}", kind, merged, ids[0]);
+        sqlx::query("UPDATE signal_names SET signal_id = ? WHERE signal_id = ?")
+            .bind(ids[0])
+            .bind(merged)
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM signals WHERE id = ?").bind(merged).execute(&mut tx).await.unwrap();
+    }
+    let id = match ids.first() {
+        Some(&id) => {
+            sqlx::query("UPDATE signals SET name = ? WHERE id = ?").bind(name).bind(id).execute(&mut tx).await.unwrap();
+            id
+        }
+        None => sqlx::query("INSERT INTO signals (kind, name) VALUES (?, ?)")
+            .bind(kind)
+            .bind(name)
+            .execute(&mut tx)
+            .await
+            .unwrap()
+            .last_insert_rowid(),
+    };
+    for known in std::iter::once(name).chain(renamed_from.iter().map(String::as_str)) {
+        sqlx::query("INSERT OR REPLACE INTO signal_names (kind, name, signal_id) VALUES (?, ?, ?)")
+            .bind(kind)
+            .bind(known)
+            .bind(id)
+            .execute(&mut tx)
+            .await
+            .unwrap();
+    }
+    tx.commit().await.unwrap();
+}
+
+/// Registers the configured temperature channels and pulse inputs.
+pub async fn register(pool: &SqlitePool, registers: &RegisterMap) {
+    for (name, channel) in &registers.temperatures {
+        register_one(pool, TEMPERATURE, name, &channel.renamed_from).await;
+    }
+    for (name, input) in &registers.pulses {
+        register_one(pool, PULSE, name, &input.renamed_from).await;
+    }
+}
+
+/// Stored names that now read as another name.
+#[derive(Debug, Default)]
+pub struct SignalNames {
+    current: HashMap<(String, String), String>,
+}
+
+impl SignalNames {
+    /// Empty for a database without the tables, e.g. one opened read-only.
+    pub async fn load(pool: &SqlitePool) -> Self {
+        let rows: Vec<(String, String, String)> = sqlx::query_as(
+            r#"
+            SELECT n.kind, n.name, s.name FROM signal_names n
+            JOIN signals s ON s.id = n.signal_id
+            WHERE n.name != s.name
+            "#,
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+        SignalNames { current: rows.into_iter().map(|(kind, stored, name)| ((kind, stored), name)).collect() }
+    }
+
+    /// Current name of a channel stored as `stored`.
+    pub fn current<'a>(&'a self, kind: &str, stored: &'a str) -> &'a str {
+        self.current.get(&(kind.to_string(), stored.to_string())).map_or(stored, String::as_str)
+    }
+
+    /// Moves the values of renamed channels in `samples` to their current names.
+    pub fn apply(&self, samples: &mut [MotorData]) {
+        if self.current.is_empty() {
+            return;
+        }
+        for data in samples {
+            for (kind, values) in [(TEMPERATURE, &mut data.temperatures), (PULSE, &mut data.pulses)] {
+                let renamed: Vec<String> =
+                    values.keys().filter(|stored| self.current(kind, stored) != stored.as_str()).cloned().collect();
+                for stored in renamed {
+                    let value = values.remove(&stored).unwrap();
+                    let name = self.current(kind, &stored).to_string();
+                    values.entry(name).or_insert(value);
+                }
+            }
+        }
+    }
+}
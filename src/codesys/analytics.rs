@@ -0,0 +1,68 @@
+//! Reliability analytics over the alarms and events tables.
+
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+
+/// Alarm history of one rule over a reporting period.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleAlarmStats {
+    pub rule: String,
+    pub count: i64,
+    /// Mean time between consecutive alarms of this rule, in seconds.
+    /// `None` when the rule tripped fewer than twice.
+    pub mean_time_between_secs: Option<f64>,
+    /// Mean time from raise to clear over cleared alarms, in seconds.
+    pub avg_clear_secs: Option<f64>,
+    /// Alarms of this rule still active.
+    pub active: i64,
+}
+
+/// `(rule, count, mean time between, mean clear time, active)`.
+type FrequencyRow = (String, i64, Option<f64>, Option<f64>, i64);
+
+/// Per-rule alarm statistics for alarms raised in `[from, to)`, most frequent first.
+pub async fn alarm_frequency(pool: &SqlitePool, from: i64, to: i64) -> Vec<RuleAlarmStats> {
+    // With n alarms, the mean of the n-1 gaps between consecutive raises is
+    // (last - first) / (n - 1).
+    let rows: Vec<FrequencyRow> = sqlx::query_as(
+        r#"
+        SELECT rule,
+               COUNT(*) AS count,
+               CASE WHEN COUNT(*) > 1
+                    THEN CAST(MAX(raised_at) - MIN(raised_at) AS REAL) / (COUNT(*) - 1)
+               END AS mtba,
+               AVG(CASE WHEN cleared_at IS NOT NULL THEN cleared_at - raised_at END) AS avg_clear,
+               SUM(CASE WHEN cleared_at IS NULL THEN 1 ELSE 0 END) AS active
+        FROM alarms
+        WHERE raised_at >= ? AND raised_at < ?
+        GROUP BY rule
+        ORDER BY count DESC, rule
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    rows.into_iter()
+        .map(|(rule, count, mean_time_between_secs, avg_clear_secs, active)| RuleAlarmStats {
+            rule,
+            count,
+            mean_time_between_secs,
+            avg_clear_secs,
+            active,
+        })
+        .collect()
+}
+
+/// Formats a duration in seconds as e.g. `3d 4h`, `2h 05m` or `42s`.
+pub fn format_duration(secs: f64) -> String {
+    let secs = secs.max(0.0).round() as i64;
+    match secs {
+        s if s >= 86_400 => format!("{}d {}h", s / 86_400, (s % 86_400) / 3600),
+        s if s >= 3600 => format!("{}h {:02}m", s / 3600, (s % 3600) / 60),
+        s if s >= 60 => format!("{}m {:02}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
@@ -0,0 +1,414 @@
+//! HTTP API server.
+
+use super::config::{FormatConfig, TlsConfig};
+use super::config_history;
+use super::control::{self, Action, ControlHandle, Refusal};
+use super::demand::DemandSettings;
+use super::devices;
+use super::export::{self, ExportFormat};
+use super::export_jobs::{ExportJob, ExportQueue, JobStatus};
+use super::format::parse_timestamp;
+use super::graphql;
+use super::jitter::JitterHandle;
+use super::lineage;
+use super::location::Location;
+use super::oee::KpiSettings;
+use super::query_gate::QueryGate;
+use super::schema;
+use super::setpoint::{SetpointHandle, SetpointKind};
+use super::sink::SinkHealthMap;
+use super::startup;
+use super::tls;
+use super::units::Units;
+use super::MotorSpecs;
+use async_graphql::http::GraphiQLSource;
+use async_graphql_axum::GraphQL;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Json, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use schemars::JsonSchema;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+
+/// Shared state handed to every API handler.
+#[derive(Debug)]
+pub struct ApiState {
+    pub pool: Arc<SqlitePool>,
+    /// Routes are served under `/sites/<site>` when set.
+    pub site: Option<String>,
+    pub motor_id: String,
+    pub specs: MotorSpecs,
+    pub format: FormatConfig,
+    /// Display units of exported values, served by GraphQL `units`.
+    pub units: Units,
+    /// Set by `--read-only`; GraphQL mutations are rejected.
+    pub read_only: bool,
+    /// Present when `[setpoints]` is configured.
+    pub setpoints: Option<SetpointHandle>,
+    /// Present when `[control]` is configured.
+    pub control: Option<ControlHandle>,
+    /// Background exports; also limits inline `GET /export`.
+    pub exports: Arc<ExportQueue>,
+    /// Health of the monitor's sinks, served by GraphQL `sinks`.
+    pub sinks: SinkHealthMap,
+    /// Sampling jitter of the polling loop, served by GraphQL `sampling`.
+    pub sampling: JitterHandle,
+    /// Served by GraphQL `kpis`.
+    pub kpis: KpiSettings,
+    /// Served by GraphQL `demand`; present when `[demand]` is configured.
+    pub demand: Option<DemandSettings>,
+    /// Stamped into every export; see `lineage`.
+    pub lineage: lineage::Source,
+    /// Served with the motor and attached to exports.
+    pub location: Location,
+    /// Read pool and per-client limits of the routes; see `query_gate`.
+    pub queries: Arc<QueryGate>,
+}
+
+impl ApiState {
+    /// Prefix of the motor's routes: `/sites/<site>`, or empty.
+    pub fn base_path(&self) -> String {
+        self.site.as_ref().map_or_else(String::new, |site| format!("/sites/{}", site))
+    }
+}
+
+pub fn router(state: ApiState) -> Router {
+    let state = Arc::new(state);
+    let schema = graphql::build_schema(Arc::clone(&state));
+    let routes = Router::new()
+        .route("/graphql", get(graphiql).post_service(GraphQL::new(schema)))
+        .route("/export", get(export_samples))
+        .route("/exports", post(submit_export))
+        .route("/exports/:id", get(export_job))
+        .route("/exports/:id/download", get(download_export))
+        .route("/control", get(control_panel))
+        .route("/control/:action", post(prepare_control))
+        .route("/control/confirm/:token", post(confirm_control));
+    let routes = match &state.site {
+        Some(_) => Router::new().nest(&state.base_path(), routes),
+        None => routes,
+    };
+    routes
+        .route("/schemas/:name", get(json_schema))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state), rate_limit))
+        .with_state(state)
+}
+
+/// Refuses a client's request with 429 once its `api.queries` allowance is
+/// used up.
+async fn rate_limit(
+    State(state): State<Arc<ApiState>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ConnectInfo(client)) = client {
+        if let Err(retry_after) = state.queries.admit(client.ip(), std::time::Instant::now()) {
+            let retry_after = retry_after.as_secs().max(1).to_string();
+            return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after)], "too many requests; slow down")
+                .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Accepts epoch milliseconds or RFC 3339 regardless of `format.timestamps`.
+fn timestamp_param<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_timestamp(&text)
+        .ok_or_else(|| serde::de::Error::custom(format!("expected epoch milliseconds or RFC 3339, got {:?}", text)))
+}
+
+/// Query parameters of `GET /export`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ExportParams {
+    /// Inclusive; epoch milliseconds or RFC 3339.
+    #[serde(deserialize_with = "timestamp_param")]
+    #[schemars(with = "String")]
+    from: i64,
+    /// Exclusive; epoch milliseconds or RFC 3339.
+    #[serde(deserialize_with = "timestamp_param")]
+    #[schemars(with = "String")]
+    to: i64,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// Export metadata: motor, device identification, site, location, the
+/// lineage of the samples in `[from, to)` and the configuration snapshots in
+/// force over it.
+async fn export_metadata(state: &ApiState, from: i64, to: i64) -> BTreeMap<String, String> {
+    let pool = state.queries.pool();
+    let mut metadata = devices::export_metadata(pool, &state.motor_id, state.site.as_deref()).await;
+    metadata.extend(state.location.metadata());
+    metadata.extend(lineage::Stamp::new(&state.lineage, from, to).metadata());
+    metadata.extend(config_history::metadata(pool, from, to).await);
+    metadata
+}
+
+fn too_many_exports(message: String) -> Response {
+    (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, "30")], message).into_response()
+}
+
+/// `GET /export?from=..&to=..&format=csv|arrow` streams samples page by page,
+/// so arbitrarily long ranges never have to fit in memory. Refused with 429
+/// while `api.exports.max_concurrent` exports are running.
+async fn export_samples(State(state): State<Arc<ApiState>>, Query(params): Query<ExportParams>) -> Response {
+    if params.from >= params.to {
+        return (StatusCode::BAD_REQUEST, "`from` must be before `to`").into_response();
+    }
+    let Some(permit) = state.exports.try_inline() else {
+        return too_many_exports("too many exports running; retry later or use POST /exports".to_string());
+    };
+    let filename = format!("{}_{}_{}.{}", state.motor_id, params.from, params.to, params.format.extension());
+    let metadata = export_metadata(&state, params.from, params.to).await;
+    let stream = export::stream_samples(
+        Arc::clone(state.queries.pool()),
+        state.format.clone(),
+        state.units.clone(),
+        metadata,
+        params.format,
+        params.from,
+        params.to,
+    )
+    .map(move |chunk| {
+        // The slot is released when the response body is dropped.
+        let _permit = &permit;
+        chunk
+    });
+    Response::builder()
+        .header(header::CONTENT_TYPE, params.format.content_type())
+        .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename))
+        .body(Body::from_stream(stream))
+        .unwrap()
+}
+
+/// An export job as returned by the API, with its download link once done.
+#[derive(Debug, Serialize)]
+struct JobResponse {
+    #[serde(flatten)]
+    job: ExportJob,
+    download: Option<String>,
+}
+
+impl JobResponse {
+    fn new(state: &ApiState, job: ExportJob) -> Self {
+        let download = (job.status == JobStatus::Done).then(|| format!("{}/exports/{}/download", state.base_path(), job.id));
+        JobResponse { job, download }
+    }
+
+    /// The response body, its times in the `format.timestamps` style.
+    fn json(self, state: &ApiState) -> Json<serde_json::Value> {
+        let mut json = serde_json::to_value(self).expect("job serializes");
+        state.format.timestamps.restyle(&mut json, &["from", "to", "submitted_at", "finished_at"]);
+        Json(json)
+    }
+}
+
+/// `POST /exports?from=..&to=..&format=..` queues an export (same parameters
+/// as `GET /export`) and answers 202 with the job and its `Location`.
+async fn submit_export(State(state): State<Arc<ApiState>>, Query(params): Query<ExportParams>) -> Response {
+    if params.from >= params.to {
+        return (StatusCode::BAD_REQUEST, "`from` must be before `to`").into_response();
+    }
+    let metadata = export_metadata(&state, params.from, params.to).await;
+    let submitted = state.exports.submit(
+        Arc::clone(state.queries.pool()),
+        state.format.clone(),
+        state.units.clone(),
+        metadata,
+        params.format,
+        params.from,
+        params.to,
+    );
+    match submitted {
+        Ok(job) => {
+            let location = format!("{}/exports/{}", state.base_path(), job.id);
+            let body = JobResponse::new(&state, job).json(&state);
+            (StatusCode::ACCEPTED, [(header::LOCATION, location)], body).into_response()
+        }
+        Err(message) => too_many_exports(message),
+    }
+}
+
+/// `GET /exports/<id>` returns the job's status.
+async fn export_job(State(state): State<Arc<ApiState>>, Path(id): Path<u64>) -> Response {
+    match state.exports.job(id) {
+        Some(job) => JobResponse::new(&state, job).json(&state).into_response(),
+        None => (StatusCode::NOT_FOUND, "unknown or expired export job").into_response(),
+    }
+}
+
+/// `GET /exports/<id>/download` streams the result of a finished job.
+async fn download_export(State(state): State<Arc<ApiState>>, Path(id): Path<u64>) -> Response {
+    let Some(job) = state.exports.job(id) else {
+        return (StatusCode::NOT_FOUND, "unknown or expired export job").into_response();
+    };
+    if job.status != JobStatus::Done {
+        return (StatusCode::CONFLICT, format!("export job is {:?}", job.status).to_lowercase()).into_response();
+    }
+    let file = match tokio::fs::File::open(state.exports.result_path(&job)).await {
+        Ok(file) => file,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    let chunks = stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buffer = vec![0; 64 * 1024];
+        match file.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buffer.truncate(n);
+                Some((Ok(buffer), Some(file)))
+            }
+            Err(err) => Some((Err(err), None)),
+        }
+    });
+    let filename = format!("{}_{}_{}.{}", state.motor_id, job.from, job.to, job.format.extension());
+    Response::builder()
+        .header(header::CONTENT_TYPE, job.format.content_type())
+        .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename))
+        .body(Body::from_stream(chunks))
+        .unwrap()
+}
+
+/// The control handle and the operator named by the request's bearer token.
+#[allow(clippy::result_large_err)]
+fn control_operator<'a>(state: &'a ApiState, headers: &HeaderMap) -> Result<(&'a ControlHandle, String), Response> {
+    let Some(control) = &state.control else {
+        return Err((StatusCode::NOT_FOUND, "control is not configured").into_response());
+    };
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match bearer.and_then(|bearer| control.operator(bearer)) {
+        Some(operator) => Ok((control, operator)),
+        None => {
+            let message = "an operator token with the control role is required";
+            Err((StatusCode::UNAUTHORIZED, message).into_response())
+        }
+    }
+}
+
+fn refused(refusal: Refusal) -> Response {
+    let status = match refusal {
+        Refusal::Invalid(_) => StatusCode::BAD_REQUEST,
+        Refusal::Interlocked => StatusCode::CONFLICT,
+        Refusal::UnknownToken => StatusCode::NOT_FOUND,
+        Refusal::Busy => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, refusal.message()).into_response()
+}
+
+/// Query parameters of `POST /control/setpoint` and
+/// `POST /control/parameter_set`.
+#[derive(Debug, Deserialize)]
+struct ControlParams {
+    kind: Option<String>,
+    value: Option<f64>,
+    name: Option<String>,
+}
+
+/// `POST /control/<action>` (`start`, `stop`, `reset_fault`, `setpoint`
+/// with `kind=speed|torque&value=..`, or `parameter_set` with `name=..`)
+/// answers with a confirmation token.
+async fn prepare_control(
+    State(state): State<Arc<ApiState>>,
+    Path(action): Path<String>,
+    Query(params): Query<ControlParams>,
+    headers: HeaderMap,
+) -> Response {
+    let (control, operator) = match control_operator(&state, &headers) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let action = match (action.as_str(), params.kind.as_deref(), params.value) {
+        ("start", ..) => Action::Start,
+        ("stop", ..) => Action::Stop,
+        ("reset_fault", ..) => Action::ResetFault,
+        ("setpoint", Some("speed"), Some(value)) => Action::Setpoint { kind: SetpointKind::Speed, value },
+        ("setpoint", Some("torque"), Some(value)) => Action::Setpoint { kind: SetpointKind::Torque, value },
+        ("setpoint", ..) => {
+            return (StatusCode::BAD_REQUEST, "expected kind=speed|torque and a value").into_response();
+        }
+        ("parameter_set", ..) => match &params.name {
+            Some(name) => Action::ParameterSet(name.clone()),
+            None => return (StatusCode::BAD_REQUEST, "expected the name of a parameter set").into_response(),
+        },
+        _ => return (StatusCode::NOT_FOUND, "unknown control action").into_response(),
+    };
+    match control.prepare(&operator, action) {
+        Ok(prepared) => Json(prepared).into_response(),
+        Err(refusal) => refused(refusal),
+    }
+}
+
+/// `POST /control/confirm/<token>` carries out a prepared action.
+async fn confirm_control(
+    State(state): State<Arc<ApiState>>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let (control, operator) = match control_operator(&state, &headers) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    match control.confirm(&operator, &token) {
+        Ok(action) => Json(serde_json::json!({ "action": action.describe() })).into_response(),
+        Err(refusal) => refused(refusal),
+    }
+}
+
+/// `GET /control` serves the control panel.
+async fn control_panel(State(state): State<Arc<ApiState>>) -> Response {
+    if state.control.is_none() {
+        return (StatusCode::NOT_FOUND, "control is not configured").into_response();
+    }
+    Html(control::PANEL_HTML.replace("{base}", &state.base_path())).into_response()
+}
+
+/// `GET /schemas/<name>` returns the JSON Schema of a configuration or payload type.
+async fn json_schema(Path(name): Path<String>) -> Response {
+    let name = name.trim_end_matches(".json");
+    match schema::schema(name) {
+        Some(schema) => Json(schema).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("unknown schema; available: {}", schema::NAMES.join(", "))).into_response(),
+    }
+}
+
+async fn graphiql(State(state): State<Arc<ApiState>>) -> Html<String> {
+    Html(GraphiQLSource::build().endpoint(&format!("{}/graphql", state.base_path())).finish())
+}
+
+/// Serves the API on `listen`, over HTTPS with `tls`. Waits until the
+/// certificate is in place and the port is free; see `startup`.
+pub async fn serve(listen: SocketAddr, state: ApiState, tls: Option<TlsConfig>) {
+    let component = format!("API on {}", listen);
+    let config = match &tls {
+        Some(tls) => match startup::retry(&component, || tls::api_config(tls)).await {
+            Ok(config) => Some(config),
+            Err(_) => return,
+        },
+        None => None,
+    };
+    let Ok(listener) = startup::retry(&component, || tokio::net::TcpListener::bind(listen)).await else {
+        return;
+    };
+    let app = router(state).into_make_service_with_connect_info::<SocketAddr>();
+    let Some(config) = config else {
+        log::info!("API listening on {}", listen);
+        axum::serve(listener, app).await.unwrap();
+        return;
+    };
+    log::info!("API listening on {} (HTTPS)", listen);
+    axum_server::from_tcp_rustls(listener.into_std().unwrap(), config).serve(app).await.unwrap();
+}
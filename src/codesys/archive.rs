@@ -0,0 +1,429 @@
+//! Compaction of old raw samples into compressed hour blocks.
+//!
+//! Each hour of `motor_data` (plus its `temperature_data` and `pulse_data`
+//! rows) older than `after_hours` is rewritten as one row of the `archive`
+//! table: timestamps are delta-encoded, each value column is XOR-ed against
+//! its predecessor, sample sequence numbers are delta-encoded like the
+//! timestamps (format version 2), pulse inputs follow the temperature
+//! channels (format version 3), each sample's estimated values are a bit
+//! mask over the block's list of them (format version 4), and the block is
+//! zstd-compressed. `motor_data_range` decodes archived
+//! blocks transparently, so readers never see the difference.
+
+use super::aliases::SignalNames;
+use super::config::ArchiveConfig;
+use super::disk;
+use super::site;
+use super::{raw_motor_data_range, MotorData};
+use chrono::prelude::*;
+use sqlx::sqlite::{Sqlite, SqlitePool};
+use sqlx::Transaction;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+
+const BLOCK_MS: i64 = 3_600_000;
+const FORMAT_VERSION: u8 = 4;
+
+/// Reads one column's value off a sample.
+type Column = Box<dyn Fn(&MotorData) -> f64>;
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(std::io::Error),
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Io(err) => write!(f, "compression error: {}", err),
+            ArchiveError::Corrupt(what) => write!(f, "corrupt archive block: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<std::io::Error> for ArchiveError {
+    fn from(err: std::io::Error) -> Self {
+        ArchiveError::Io(err)
+    }
+}
+
+pub async fn setup_archive_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS archive (
+            hour_start INTEGER PRIMARY KEY,
+            sample_count INTEGER NOT NULL,
+            data BLOB NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+fn sorted_names<'a>(names: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
+    let mut names: Vec<&String> = names.collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Encodes samples (sorted by timestamp) into a compressed block.
+pub fn encode_block(samples: &[MotorData], level: i32) -> Result<Vec<u8>, ArchiveError> {
+    let channels = sorted_names(samples.iter().flat_map(|d| d.temperatures.keys()));
+    let pulses = sorted_names(samples.iter().flat_map(|d| d.pulses.keys()));
+    let estimated = sorted_names(samples.iter().flat_map(|d| d.estimated.iter()));
+
+    let mut out = vec![FORMAT_VERSION];
+    out.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+    for names in [&channels, &pulses, &estimated] {
+        out.extend_from_slice(&(names.len() as u16).to_le_bytes());
+        for name in names {
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+        }
+    }
+
+    let mut previous = 0i64;
+    for data in samples {
+        write_varint(&mut out, zigzag(data.timestamp - previous));
+        previous = data.timestamp;
+    }
+    // Sequence numbers start at 1; 0 stands for an unnumbered sample.
+    let mut previous = 0i64;
+    for data in samples {
+        let sequence = data.sequence.unwrap_or(0);
+        write_varint(&mut out, zigzag(sequence - previous));
+        previous = sequence;
+    }
+    for data in samples {
+        let mut mask = 0u64;
+        for (bit, &name) in estimated.iter().enumerate() {
+            if data.estimated.contains(name) {
+                mask |= 1 << bit;
+            }
+        }
+        write_varint(&mut out, mask);
+    }
+
+    // Missing optional values are stored as NaN.
+    let mut columns: Vec<Column> = vec![
+        Box::new(|d| d.current_power),
+        Box::new(|d| d.current_torque),
+        Box::new(|d| d.current_speed),
+        Box::new(|d| d.current_heat),
+        Box::new(|d| d.current_cycles),
+        Box::new(|d| d.speed_reference.unwrap_or(f64::NAN)),
+    ];
+    for name in &channels {
+        let name = (*name).clone();
+        columns.push(Box::new(move |d| d.temperatures.get(&name).copied().unwrap_or(f64::NAN)));
+    }
+    for name in &pulses {
+        let name = (*name).clone();
+        columns.push(Box::new(move |d| d.pulses.get(&name).copied().unwrap_or(f64::NAN)));
+    }
+    for column in &columns {
+        let mut previous = 0u64;
+        for data in samples {
+            let bits = column(data).to_bits();
+            out.extend_from_slice(&(bits ^ previous).to_le_bytes());
+            previous = bits;
+        }
+    }
+
+    Ok(zstd::encode_all(out.as_slice(), level)?)
+}
+
+pub fn decode_block(block: &[u8]) -> Result<Vec<MotorData>, ArchiveError> {
+    let raw = zstd::decode_all(block)?;
+    let mut reader = Reader { bytes: &raw, pos: 0 };
+
+    // Version 1 blocks predate sequence numbers, version 2 pulse inputs,
+    // version 3 estimated values.
+    let version = reader.take(1)?[0];
+    if !(1..=FORMAT_VERSION).contains(&version) {
+        return Err(ArchiveError::Corrupt("unsupported format version"));
+    }
+    let count = u32::from_le_bytes(reader.take(4)?.try_into().unwrap()) as usize;
+    let channels = reader.names()?;
+    let pulses = if version >= 3 { reader.names()? } else { Vec::new() };
+    let estimated = if version >= 4 { reader.names()? } else { Vec::new() };
+    let channel_count = channels.len() + pulses.len();
+
+    let mut timestamps = Vec::with_capacity(count);
+    let mut previous = 0i64;
+    for _ in 0..count {
+        previous += unzigzag(reader.varint()?);
+        timestamps.push(previous);
+    }
+    let mut sequences = vec![0i64; count];
+    if version >= 2 {
+        let mut previous = 0i64;
+        for sequence in &mut sequences {
+            previous += unzigzag(reader.varint()?);
+            *sequence = previous;
+        }
+    }
+    let mut masks = vec![0u64; count];
+    if version >= 4 {
+        for mask in &mut masks {
+            *mask = reader.varint()?;
+        }
+    }
+
+    let mut columns = Vec::with_capacity(6 + channel_count);
+    for _ in 0..6 + channel_count {
+        let mut values = Vec::with_capacity(count);
+        let mut previous = 0u64;
+        for _ in 0..count {
+            previous ^= u64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+            values.push(f64::from_bits(previous));
+        }
+        columns.push(values);
+    }
+
+    Ok((0..count)
+        .map(|i| {
+            let present = |names: &[String], first: usize| {
+                let mut values = BTreeMap::new();
+                for (c, name) in names.iter().enumerate() {
+                    let value = columns[first + c][i];
+                    if !value.is_nan() {
+                        values.insert(name.clone(), value);
+                    }
+                }
+                values
+            };
+            let speed_reference = columns[5][i];
+            MotorData {
+                timestamp: timestamps[i],
+                current_power: columns[0][i],
+                current_torque: columns[1][i],
+                current_speed: columns[2][i],
+                current_heat: columns[3][i],
+                current_cycles: columns[4][i],
+                speed_reference: (!speed_reference.is_nan()).then_some(speed_reference),
+                temperatures: present(&channels, 6),
+                pulses: present(&pulses, 6 + channels.len()),
+                sequence: (sequences[i] > 0).then_some(sequences[i]),
+                current: None,
+                estimated: estimated
+                    .iter()
+                    .enumerate()
+                    .filter(|(bit, _)| masks[i] & 1 << bit != 0)
+                    .map(|(_, name)| name.clone())
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// Archived samples with `from <= timestamp < to`, oldest first, at most
+/// `limit`. Blocks are read and decoded one at a time, and none past the
+/// one that reaches `limit`, so a page costs its own blocks only.
+pub async fn archived_range(pool: &SqlitePool, from: i64, to: i64, limit: Option<i64>) -> Vec<MotorData> {
+    let hours: Vec<(i64,)> =
+        sqlx::query_as("SELECT hour_start FROM archive WHERE hour_start > ? AND hour_start < ? ORDER BY hour_start")
+            .bind(from - BLOCK_MS)
+            .bind(to)
+            .fetch_all(pool)
+            .await
+            .unwrap();
+    let limit = limit.map(|limit| limit.max(0) as usize);
+
+    let mut samples = Vec::new();
+    for (hour_start,) in hours {
+        if limit.is_some_and(|limit| samples.len() >= limit) {
+            break;
+        }
+        let block: Option<(Vec<u8>,)> = sqlx::query_as("SELECT data FROM archive WHERE hour_start = ?")
+            .bind(hour_start)
+            .fetch_optional(pool)
+            .await
+            .unwrap();
+        let Some((block,)) = block else {
+            continue;
+        };
+        match decode_block(&block) {
+            Ok(decoded) => samples.extend(decoded.into_iter().filter(|d| d.timestamp >= from && d.timestamp < to)),
+            Err(err) => log::error!("skipping archive block {}: {}", hour_start, err),
+        }
+    }
+    if let Some(limit) = limit {
+        samples.truncate(limit);
+    }
+    SignalNames::load(pool).await.apply(&mut samples);
+    samples
+}
+
+/// Highest sequence number in the newest archive block.
+pub async fn latest_sequence(pool: &SqlitePool) -> Option<i64> {
+    let (block,): (Vec<u8>,) = sqlx::query_as("SELECT data FROM archive ORDER BY hour_start DESC LIMIT 1")
+        .fetch_optional(pool)
+        .await
+        .unwrap()?;
+    match decode_block(&block) {
+        Ok(samples) => samples.iter().filter_map(|d| d.sequence).max(),
+        Err(err) => {
+            log::error!("cannot read the newest archive block: {}", err);
+            None
+        }
+    }
+}
+
+/// Compacts every complete hour older than `cutoff` (milliseconds). Returns
+/// the number of hour blocks written.
+pub async fn compact(pool: &SqlitePool, cutoff: i64, level: i32) -> usize {
+    let cutoff = cutoff - cutoff.rem_euclid(BLOCK_MS);
+    let hours: Vec<(i64,)> = sqlx::query_as(&format!(
+        "SELECT DISTINCT timestamp - timestamp % 3600000 FROM motor_data WHERE {} AND timestamp < ? ORDER BY 1",
+        site::SAMPLES
+    ))
+    .bind(cutoff)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    let mut written = 0;
+    for (hour_start,) in hours {
+        let hour_end = hour_start + BLOCK_MS;
+        // Rows that arrive late for an already archived hour are merged in.
+        let mut samples = archived_range(pool, hour_start, hour_end, None).await;
+        samples.extend(raw_motor_data_range(pool, hour_start, hour_end, None).await);
+        samples.sort_by_key(|d| d.timestamp);
+
+        let block = match encode_block(&samples, level) {
+            Ok(block) => block,
+            Err(err) => {
+                log::error!("cannot compact hour {}: {}", hour_start, err);
+                continue;
+            }
+        };
+
+        let mut tx = pool.begin().await.unwrap();
+        sqlx::query("INSERT OR REPLACE INTO archive (hour_start, sample_count, data) VALUES (?, ?, ?)")
+            .bind(hour_start)
+            .bind(samples.len() as i64)
+            .bind(&block)
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        // Sample rows of another site were not archived and stay.
+        for table in ["motor_data", "temperature_data", "pulse_data"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE {} AND timestamp >= ? AND timestamp < ?", table, site::SAMPLES))
+                .bind(hour_start)
+                .bind(hour_end)
+                .execute(&mut tx)
+                .await
+                .unwrap();
+        }
+        tx.commit().await.unwrap();
+        written += 1;
+    }
+    written
+}
+
+/// Rewrites blocks archived with second timestamps (keyed by
+/// `hour_start` in seconds) to milliseconds, within the caller's
+/// transaction `tx`.
+pub async fn migrate_to_millis(tx: &mut Transaction<'_, Sqlite>) {
+    let blocks: Vec<(i64, Vec<u8>)> =
+        sqlx::query_as("SELECT hour_start, data FROM archive").fetch_all(&mut *tx).await.unwrap();
+    sqlx::query("DELETE FROM archive").execute(&mut *tx).await.unwrap();
+    for (hour_start, block) in blocks {
+        let mut samples = match decode_block(&block) {
+            Ok(samples) => samples,
+            Err(err) => {
+                log::error!("dropping unreadable archive block {}: {}", hour_start, err);
+                continue;
+            }
+        };
+        for data in &mut samples {
+            data.timestamp *= 1000;
+        }
+        let block = encode_block(&samples, 3).unwrap();
+        sqlx::query("INSERT INTO archive (hour_start, sample_count, data) VALUES (?, ?, ?)")
+            .bind(hour_start * 1000)
+            .bind(samples.len() as i64)
+            .bind(&block)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+    }
+}
+
+pub async fn run_compaction(pool: Arc<SqlitePool>, config: ArchiveConfig, space: disk::Space) {
+    let mut interval = time::interval(Duration::from_secs(config.check_interval_minutes * 60));
+    loop {
+        interval.tick().await;
+        if space.is_low() {
+            continue;
+        }
+        let cutoff = Local::now().timestamp_millis() - config.after_hours * 3_600_000;
+        let written = compact(&pool, cutoff, config.level).await;
+        if written > 0 {
+            log::info!("archived {} hour block(s) older than {} h", written, config.after_hours);
+        }
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ArchiveError> {
+        let slice = self.bytes.get(self.pos..self.pos + len).ok_or(ArchiveError::Corrupt("truncated"))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn varint(&mut self) -> Result<u64, ArchiveError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ArchiveError::Corrupt("varint too long"))
+    }
+
+    /// A count-prefixed list of channel names.
+    fn names(&mut self) -> Result<Vec<String>, ArchiveError> {
+        let count = u16::from_le_bytes(self.take(2)?.try_into().unwrap()) as usize;
+        let mut names = Vec::with_capacity(count);
+        for _ in 0..count {
+            let len = u16::from_le_bytes(self.take(2)?.try_into().unwrap()) as usize;
+            let name = std::str::from_utf8(self.take(len)?).map_err(|_| ArchiveError::Corrupt("channel name"))?;
+            names.push(name.to_string());
+        }
+        Ok(names)
+    }
+}
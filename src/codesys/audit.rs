@@ -0,0 +1,80 @@
+//! Audit log: who did what to the motor and why. Every command written to
+//! the drive (including automatic protective actions) is recorded here.
+
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub motor_id: String,
+    pub timestamp: i64,
+    /// Operator name, or `interlock` etc. for automatic actions.
+    pub actor: String,
+    /// Short machine-readable action, e.g. `stop`.
+    pub action: String,
+    pub detail: String,
+}
+
+pub async fn setup_audit_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY,
+            motor_id TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            actor TEXT NOT NULL,
+            action TEXT NOT NULL,
+            detail TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+pub async fn record(pool: &SqlitePool, motor_id: &str, timestamp: i64, actor: &str, action: &str, detail: &str) -> i64 {
+    log::info!("audit: {} {} on {}: {}", actor, action, motor_id, detail);
+    sqlx::query("INSERT INTO audit_log (motor_id, timestamp, actor, action, detail) VALUES (?, ?, ?, ?, ?)")
+        .bind(motor_id)
+        .bind(timestamp)
+        .bind(actor)
+        .bind(action)
+        .bind(detail)
+        .execute(pool)
+        .await
+        .unwrap()
+        .last_insert_rowid()
+}
+
+/// Entries with `from <= timestamp < to`, newest first.
+pub async fn list_entries(pool: &SqlitePool, from: Option<i64>, to: Option<i64>, limit: Option<i64>) -> Vec<AuditEntry> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, motor_id, timestamp, actor, action, detail FROM audit_log
+        WHERE (?1 IS NULL OR timestamp >= ?1)
+          AND (?2 IS NULL OR timestamp < ?2)
+        ORDER BY timestamp DESC, id DESC
+        LIMIT ?3
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .bind(limit.unwrap_or(-1))
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    rows.iter()
+        .map(|row| AuditEntry {
+            id: row.get("id"),
+            motor_id: row.get("motor_id"),
+            timestamp: row.get("timestamp"),
+            actor: row.get("actor"),
+            action: row.get("action"),
+            detail: row.get("detail"),
+        })
+        .collect()
+}
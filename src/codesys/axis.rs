@@ -0,0 +1,70 @@
+//! Axis ranges of line charts.
+//!
+//! The time axis spans the earliest to the latest sample, whatever their
+//! order, so a clock that stepped back cannot turn it round; a single sample
+//! gets a minute around it. The value axis runs from zero, or the lowest value if
+//! negative, to the highest value, plus `charts.y_padding` of the span as
+//! headroom. NaN and infinite values are left out, both of the range and of
+//! the drawn line. A series that is all zero, or has no finite value at all,
+//! gets a 0–1 axis instead of an empty one. `charts.y_range.<signal>` fixes
+//! either end of a chart's value axis.
+
+use super::config::{AxisRange, ChartsConfig};
+use std::ops::Range;
+
+/// Half the time axis drawn around a single sample, in milliseconds.
+const SINGLE_SAMPLE_MS: i64 = 30_000;
+
+/// How a chart's value axis is ranged.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ValueAxis {
+    /// Fixed ends; unset ends follow the data.
+    pub range: AxisRange,
+    /// Fraction of the span added beyond the data.
+    pub padding: f64,
+}
+
+impl ValueAxis {
+    /// The axis of the chart of `signal` (a signal name).
+    pub fn for_chart(config: &ChartsConfig, signal: &str) -> Self {
+        ValueAxis { range: config.y_range.get(signal).copied().unwrap_or_default(), padding: config.y_padding }
+    }
+
+    /// The value range showing `values`.
+    pub fn range(&self, values: impl IntoIterator<Item = f64>) -> Range<f64> {
+        let (lo, hi) = values
+            .into_iter()
+            .filter(|v| v.is_finite())
+            .fold((0.0f64, 0.0f64), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        let (mut lo, mut hi) = if hi > lo { (lo, hi) } else { (0.0, 1.0) };
+        let pad = (hi - lo) * self.padding;
+        if hi > 0.0 {
+            hi += pad;
+        }
+        if lo < 0.0 {
+            lo -= pad;
+        }
+        let lo = self.range.min.unwrap_or(lo);
+        let hi = self.range.max.unwrap_or(hi);
+        if hi > lo {
+            lo..hi
+        } else {
+            // A fixed end beyond the data; keep the axis the right way up.
+            match (self.range.min, self.range.max) {
+                (Some(min), None) => min..min + 1.0,
+                _ => hi - 1.0..hi,
+            }
+        }
+    }
+}
+
+/// The time range (milliseconds) showing `data`; `None` when it is empty.
+pub fn time_range(data: &[(i64, f64)]) -> Option<Range<i64>> {
+    let first = data.iter().map(|(t, _)| *t).min()?;
+    let last = data.iter().map(|(t, _)| *t).max()?;
+    if last > first {
+        Some(first..last)
+    } else {
+        Some(first - SINGLE_SAMPLE_MS..first + SINGLE_SAMPLE_MS)
+    }
+}
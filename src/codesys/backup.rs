@@ -0,0 +1,213 @@
+//! Scheduled online backups and `rustsys db restore`.
+//!
+//! A backup is a consistent copy of the live database written with
+//! `VACUUM INTO` while the monitor keeps running. The copy is verified
+//! (integrity check, schema version, sample table) before it is renamed
+//! into `dir`, so a backup on disk is always usable; the oldest are pruned
+//! beyond `keep`. An optional `upload_command` ships each new backup to a
+//! remote target (e.g. `rclone` or `scp`). Outcomes are recorded as
+//! `backup` / `backup_failed` events.
+//!
+//! Restoring verifies the file the same way, but also accepts a backup of
+//! an older schema: it is migrated (see `setup_database`) in a copy before
+//! that replaces the current database atomically. The current database is
+//! kept as `<database>.pre-restore-<date>_<time>`.
+
+use super::config::BackupConfig;
+use super::disk;
+use super::events;
+use super::output::{ensure_parent, temp_path};
+use super::{setup_database, SCHEMA_VERSION};
+use chrono::prelude::*;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time;
+
+/// What a verified database file holds.
+#[derive(Debug, Clone)]
+pub struct Verified {
+    pub samples: i64,
+    /// Newest raw sample (epoch milliseconds).
+    pub latest: Option<i64>,
+    pub size_bytes: u64,
+}
+
+/// File system path of a `sqlite:` database URL.
+pub fn database_path(database_url: &str) -> PathBuf {
+    let path = database_url.trim_start_matches("sqlite:").trim_start_matches("//");
+    PathBuf::from(path.split('?').next().unwrap_or_default())
+}
+
+/// Opens `path` read-only and checks that it is an intact rustsys database
+/// of the current schema or an older one, which `setup_database` migrates.
+pub async fn verify(path: &Path) -> Result<Verified, Box<dyn Error>> {
+    let size_bytes = fs::metadata(path)?.len();
+    let options = SqliteConnectOptions::new().filename(path).read_only(true);
+    let pool = SqlitePool::connect_with(options).await?;
+    let problems: Vec<(String,)> = sqlx::query_as("PRAGMA integrity_check").fetch_all(&pool).await?;
+    let problems: Vec<String> = problems.into_iter().map(|(line,)| line).filter(|line| line != "ok").collect();
+    if !problems.is_empty() {
+        return Err(format!("{}: integrity check failed: {}", path.display(), problems.join("; ")).into());
+    }
+    let (version,): (i64,) = sqlx::query_as("PRAGMA user_version").fetch_one(&pool).await?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "{}: schema version {} is newer than this version supports ({})",
+            path.display(),
+            version,
+            SCHEMA_VERSION
+        )
+        .into());
+    }
+    let (samples, latest): (i64, Option<i64>) = sqlx::query_as("SELECT COUNT(*), MAX(timestamp) FROM motor_data")
+        .fetch_one(&pool)
+        .await
+        .map_err(|err| format!("{}: not a rustsys database: {}", path.display(), err))?;
+    pool.close().await;
+    Ok(Verified { samples, latest, size_bytes })
+}
+
+fn backup_name(motor_id: &str, at: &DateTime<Local>) -> String {
+    format!("{}_{}.sqlite", motor_id, at.format("%Y%m%d_%H%M%S"))
+}
+
+/// Writes a verified backup of the database into `dir` and returns its path.
+pub async fn backup(
+    pool: &SqlitePool,
+    dir: &Path,
+    motor_id: &str,
+    space: &disk::Space,
+) -> Result<(PathBuf, Verified), Box<dyn Error>> {
+    let path = dir.join(backup_name(motor_id, &Local::now()));
+    ensure_parent(&path, space)?;
+    let temp = temp_path(&path);
+    let _ = fs::remove_file(&temp);
+    let result = async {
+        sqlx::query("VACUUM INTO ?").bind(temp.to_string_lossy().as_ref()).execute(pool).await?;
+        verify(&temp).await
+    }
+    .await;
+    match result {
+        Ok(verified) => {
+            fs::rename(&temp, &path)?;
+            Ok((path, verified))
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&temp);
+            Err(err)
+        }
+    }
+}
+
+/// Removes the oldest backups of `motor_id` in `dir` beyond `keep`.
+pub fn prune(dir: &Path, motor_id: &str, keep: usize) -> std::io::Result<Vec<PathBuf>> {
+    let prefix = format!("{}_", motor_id);
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            name.starts_with(&prefix) && name.ends_with(".sqlite")
+        })
+        .collect();
+    // Names end in the timestamp, so they sort oldest first.
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    let removed: Vec<PathBuf> = backups.into_iter().take(excess).collect();
+    for path in &removed {
+        fs::remove_file(path)?;
+    }
+    Ok(removed)
+}
+
+/// Runs `command` with `{file}` and `{name}` replaced by the backup's path
+/// and file name.
+async fn upload(command: &[String], path: &Path) -> Result<(), String> {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let args: Vec<String> =
+        command.iter().map(|arg| arg.replace("{file}", &path.to_string_lossy()).replace("{name}", &name)).collect();
+    let output = Command::new(&args[0]).args(&args[1..]).output().await.map_err(|err| format!("{}: {}", args[0], err))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}: {}", args[0], output.status, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+pub async fn run_backup_scheduler(pool: Arc<SqlitePool>, motor_id: String, config: BackupConfig, space: disk::Space) {
+    let dir = PathBuf::from(&config.dir);
+    let mut interval = time::interval(Duration::from_secs(config.interval_hours * 3600));
+    // The first tick completes immediately; the first backup is due after one interval.
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let now = Local::now().timestamp();
+        let (path, verified) = match backup(&pool, &dir, &motor_id, &space).await.map_err(|err| err.to_string()) {
+            Ok(done) => done,
+            Err(err) => {
+                log::error!("database backup failed: {}", err);
+                events::record_event(&pool, &motor_id, now, "backup_failed", &err).await;
+                continue;
+            }
+        };
+        let message = format!("{} ({} bytes, {} samples)", path.display(), verified.size_bytes, verified.samples);
+        log::info!("database backup written to {}", message);
+        events::record_event(&pool, &motor_id, now, "backup", &message).await;
+
+        if !config.upload_command.is_empty() {
+            if let Err(err) = upload(&config.upload_command, &path).await {
+                log::error!("cannot upload backup {}: {}", path.display(), err);
+                events::record_event(&pool, &motor_id, now, "backup_failed", &format!("upload: {}", err)).await;
+            }
+        }
+        match prune(&dir, &motor_id, config.keep) {
+            Ok(removed) => removed.iter().for_each(|path| log::info!("removed old backup {}", path.display())),
+            Err(err) => log::warn!("cannot prune backups in {}: {}", dir.display(), err),
+        }
+    }
+}
+
+/// Replaces the database of `database_url` with the backup `file` after
+/// verifying it. The monitor must be stopped. Returns a summary line.
+pub async fn restore(database_url: &str, file: &Path) -> Result<String, Box<dyn Error>> {
+    verify(file).await?;
+    let target = database_path(database_url);
+    if target.exists() {
+        let stamp = Local::now().format("%Y%m%d_%H%M%S");
+        for suffix in ["", "-wal", "-shm"] {
+            let current = PathBuf::from(format!("{}{}", target.display(), suffix));
+            if current.exists() {
+                fs::copy(&current, format!("{}.pre-restore-{}{}", target.display(), stamp, suffix))?;
+            }
+        }
+    }
+    let temp = temp_path(&target);
+    fs::copy(file, &temp)?;
+    setup_database(&format!("sqlite:{}", temp.display())).await.close().await;
+    let verified = match verify(&temp).await {
+        Ok(verified) => verified,
+        Err(err) => {
+            let _ = fs::remove_file(&temp);
+            return Err(err);
+        }
+    };
+    fs::rename(&temp, &target)?;
+    // A leftover write-ahead log belongs to the replaced database.
+    for suffix in ["-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", target.display(), suffix));
+    }
+    let latest = verified.latest.map_or("none".to_string(), |ms| {
+        Local.timestamp_millis_opt(ms).single().map_or(ms.to_string(), |t| t.to_rfc3339())
+    });
+    Ok(format!(
+        "restored {} into {}: {} samples, newest {}",
+        file.display(),
+        target.display(),
+        verified.samples,
+        latest
+    ))
+}
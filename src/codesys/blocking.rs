@@ -0,0 +1,114 @@
+//! Blocking facade for applications that are not async, or that run a
+//! runtime other than Tokio.
+//!
+//! Each handle owns a small internal Tokio runtime, so a few synchronous
+//! calls are enough to embed the monitor:
+//!
+//! ```ignore
+//! use codesys::codesys::{blocking, config};
+//!
+//! let config = config::load("rustsys.toml").unwrap();
+//! let database_url = config.database_url.clone();
+//! let monitor = blocking::Monitor::start(config).unwrap();
+//!
+//! let db = blocking::Database::open_read_only(&database_url).unwrap();
+//! let now = chrono::Local::now().timestamp_millis();
+//! for sample in db.samples(now - 60_000, now, None) {
+//!     println!("{} W", sample.current_power);
+//! }
+//! monitor.stop();
+//! ```
+//!
+//! Do not call these from inside an async context; Tokio panics when a
+//! runtime is blocked on from one of its own worker threads.
+
+use super::alarms::{self, Alarm, AlarmFilter};
+use super::events::{self, Event, EventFilter};
+use super::{motor_data_range, open_database_read_only, run_motor_monitoring, setup_database, MonitorConfig, MotorData};
+use sqlx::sqlite::SqlitePool;
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+use tokio::runtime::{self, Runtime};
+use tokio::task::JoinHandle;
+
+fn build_runtime(worker_threads: usize) -> io::Result<Runtime> {
+    runtime::Builder::new_multi_thread().worker_threads(worker_threads).enable_all().build()
+}
+
+/// A running monitor (polling, storage, alarms, API) on background threads.
+pub struct Monitor {
+    runtime: Runtime,
+    task: JoinHandle<()>,
+}
+
+impl Monitor {
+    /// Starts monitoring as `rustsys` itself would; returns immediately.
+    pub fn start(config: MonitorConfig) -> io::Result<Self> {
+        let runtime = build_runtime(2)?;
+        let task = runtime.spawn(async move {
+            if let Err(err) = run_motor_monitoring(config).await {
+                log::error!("{}", err);
+            }
+        });
+        Ok(Monitor { runtime, task })
+    }
+
+    /// False once monitoring has ended, which only happens on a panic or
+    /// when it cannot start (logged).
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// Stops monitoring and waits up to five seconds for tasks to wind down.
+    /// Samples still queued for storage may be lost.
+    pub fn stop(self) {
+        self.task.abort();
+        self.runtime.shutdown_timeout(Duration::from_secs(5));
+    }
+}
+
+/// Synchronous access to a monitor database.
+pub struct Database {
+    runtime: Runtime,
+    pool: SqlitePool,
+}
+
+impl Database {
+    /// Opens (creating and migrating if needed) the database at `database_url`.
+    pub fn open(database_url: &str) -> io::Result<Self> {
+        let runtime = build_runtime(1)?;
+        let pool = runtime.block_on(setup_database(database_url));
+        Ok(Database { runtime, pool })
+    }
+
+    /// Opens an existing database without writing to it; safe next to a
+    /// running monitor.
+    pub fn open_read_only(database_url: &str) -> Result<Self, Box<dyn Error>> {
+        let runtime = build_runtime(1)?;
+        let pool = runtime.block_on(open_database_read_only(database_url))?;
+        Ok(Database { runtime, pool })
+    }
+
+    /// Samples with `from <= timestamp < to` (epoch milliseconds), oldest first.
+    pub fn samples(&self, from: i64, to: i64, limit: Option<i64>) -> Vec<MotorData> {
+        self.runtime.block_on(motor_data_range(&self.pool, from, to, limit))
+    }
+
+    pub fn alarms(&self, filter: &AlarmFilter) -> Vec<Alarm> {
+        self.runtime.block_on(alarms::list_alarms(&self.pool, filter))
+    }
+
+    pub fn events(&self, filter: &EventFilter) -> Vec<Event> {
+        self.runtime.block_on(events::list_events(&self.pool, filter))
+    }
+
+    /// The underlying pool, for queries not covered here; run them with `block_on`.
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    pub fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+}
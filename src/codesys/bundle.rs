@@ -0,0 +1,129 @@
+//! Signed configuration bundles.
+//!
+//! A bundle is a tar archive holding `rustsys.toml` (configuration, register
+//! map and thresholds) next to a detached Ed25519 signature over the whole
+//! archive, stored as `<bundle>.sig`. The monitor only accepts a bundle whose
+//! signature verifies against the trusted public key, so alarm limits cannot
+//! be changed on site without re-signing.
+
+use super::config::{self, ConfigError, MonitorConfig};
+use ed25519_dalek::{Signature, VerifyingKey};
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Name of the configuration file inside the bundle.
+pub const BUNDLE_CONFIG_NAME: &str = "rustsys.toml";
+
+#[derive(Debug)]
+pub enum BundleError {
+    Io(PathBuf, std::io::Error),
+    Unsigned(PathBuf),
+    InvalidKey(String),
+    InvalidSignature(String),
+    SignatureMismatch,
+    Archive(String),
+    MissingConfig,
+    Config(ConfigError),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::Io(path, err) => write!(f, "cannot read {}: {}", path.display(), err),
+            BundleError::Unsigned(path) => {
+                write!(f, "refusing unsigned bundle: signature file {} not found", path.display())
+            }
+            BundleError::InvalidKey(msg) => write!(f, "invalid trusted key: {}", msg),
+            BundleError::InvalidSignature(msg) => write!(f, "invalid signature file: {}", msg),
+            BundleError::SignatureMismatch => {
+                write!(f, "refusing bundle: signature does not match (bundle modified or signed by another key)")
+            }
+            BundleError::Archive(msg) => write!(f, "invalid bundle archive: {}", msg),
+            BundleError::MissingConfig => write!(f, "bundle does not contain {}", BUNDLE_CONFIG_NAME),
+            BundleError::Config(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+/// Path of the detached signature belonging to `bundle`.
+pub fn signature_path(bundle: &Path) -> PathBuf {
+    let mut name = bundle.as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+/// Verifies `bundle` against the public key in `trusted_key` and loads the
+/// configuration it contains.
+pub fn load_bundle(bundle: &Path, trusted_key: &Path) -> Result<MonitorConfig, BundleError> {
+    let key = read_key(trusted_key)?;
+
+    let sig_path = signature_path(bundle);
+    if !sig_path.exists() {
+        return Err(BundleError::Unsigned(sig_path));
+    }
+    let signature = read_signature(&sig_path)?;
+    let archive = fs::read(bundle).map_err(|e| BundleError::Io(bundle.to_path_buf(), e))?;
+    key.verify_strict(&archive, &signature).map_err(|_| BundleError::SignatureMismatch)?;
+
+    let source = extract_config(&archive)?;
+    let display = format!("{}:{}", bundle.display(), BUNDLE_CONFIG_NAME);
+    config::parse(&source).map_err(|issues| BundleError::Config(ConfigError::Invalid(display, issues)))
+}
+
+fn extract_config(archive: &[u8]) -> Result<String, BundleError> {
+    let mut tar = tar::Archive::new(archive);
+    let entries = tar.entries().map_err(|e| BundleError::Archive(e.to_string()))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| BundleError::Archive(e.to_string()))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().map_err(|e| BundleError::Archive(e.to_string()))?;
+        if path.as_os_str() != BUNDLE_CONFIG_NAME && path.as_os_str() != "./rustsys.toml" {
+            continue;
+        }
+        let mut source = String::new();
+        entry.read_to_string(&mut source).map_err(|e| BundleError::Archive(e.to_string()))?;
+        return Ok(source);
+    }
+    Err(BundleError::MissingConfig)
+}
+
+/// Reads a hex-encoded 32-byte Ed25519 public key.
+fn read_key(path: &Path) -> Result<VerifyingKey, BundleError> {
+    let text = fs::read_to_string(path).map_err(|e| BundleError::Io(path.to_path_buf(), e))?;
+    let bytes = decode_hex(text.trim()).ok_or_else(|| BundleError::InvalidKey("not valid hex".to_string()))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|b: Vec<u8>| BundleError::InvalidKey(format!("expected 32 bytes, got {}", b.len())))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| BundleError::InvalidKey(e.to_string()))
+}
+
+/// Reads a signature stored either as 64 raw bytes or as hex text.
+fn read_signature(path: &Path) -> Result<Signature, BundleError> {
+    let raw = fs::read(path).map_err(|e| BundleError::Io(path.to_path_buf(), e))?;
+    let bytes = if raw.len() == 64 {
+        raw
+    } else {
+        let text = String::from_utf8(raw).map_err(|_| BundleError::InvalidSignature("not valid hex".to_string()))?;
+        decode_hex(text.trim()).ok_or_else(|| BundleError::InvalidSignature("not valid hex".to_string()))?
+    };
+    let bytes: [u8; 64] = bytes
+        .try_into()
+        .map_err(|b: Vec<u8>| BundleError::InvalidSignature(format!("expected 64 bytes, got {}", b.len())))?;
+    Ok(Signature::from_bytes(&bytes))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,139 @@
+//! Derived motor quantities.
+//!
+//! Units throughout: volts, amperes, kilowatts, newton-metres, rpm and
+//! seconds. Mechanical power and torque are related by
+//! `P [kW] = T [N·m] · ω [rad/s] / 1000` with `ω = 2π · n [rpm] / 60`.
+
+use std::f64::consts::PI;
+
+/// Speeds (rpm) at or below this are treated as standstill, where torque
+/// cannot be derived from power.
+pub const STANDSTILL_RPM: f64 = 1e-6;
+
+/// Electrical power in kW: `P = U · I / 1000`.
+///
+/// This is DC (or single-phase, unity power factor) power; see
+/// `three_phase_power` for line quantities of a three-phase supply.
+pub fn calculate_power(volts: f64, amps: f64) -> f64 {
+    volts * amps / 1000.0
+}
+
+/// Three-phase active power in kW: `P = √3 · U_line · I · cos φ / 1000`.
+pub fn three_phase_power(line_volts: f64, amps: f64, power_factor: f64) -> f64 {
+    3f64.sqrt() * line_volts * amps * power_factor / 1000.0
+}
+
+/// Angular speed in rad/s for a speed in rpm: `ω = 2π · n / 60`.
+pub fn angular_speed(rpm: f64) -> f64 {
+    2.0 * PI * rpm / 60.0
+}
+
+/// Mechanical power in kW delivered at `torque` (N·m) and `rpm`:
+/// `P = T · ω / 1000`.
+pub fn power_from_torque(torque: f64, rpm: f64) -> f64 {
+    torque * angular_speed(rpm) / 1000.0
+}
+
+/// Torque in N·m needed for `power` (kW) at `rpm`: `T = 1000 · P / ω`.
+/// `None` at standstill.
+pub fn torque_from_power(power: f64, rpm: f64) -> Option<f64> {
+    (rpm.abs() > STANDSTILL_RPM).then(|| 1000.0 * power / angular_speed(rpm))
+}
+
+/// Load cycles over `period` seconds: `torque · period` (N·m·s), the
+/// torque-time integral for a constant torque.
+pub fn calculate_cycles(torque: f64, period: f64) -> f64 {
+    torque * period
+}
+
+/// Rounds `value` to the nearest multiple of `step`, without the binary
+/// noise of a plain `(value / step).round() * step` (0.1 steps give
+/// `23.4`, not `23.400000000000002`).
+pub fn quantize(value: f64, step: f64) -> f64 {
+    let decimals = (-step.log10()).ceil().clamp(0.0, 15.0) as i32;
+    let factor = 10f64.powi(decimals);
+    ((value / step).round() * step * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    #[test]
+    fn power_of_known_values() {
+        assert_eq!(calculate_power(400.0, 10.0), 4.0);
+        assert_eq!(calculate_power(230.0, 0.0), 0.0);
+        assert!(close(three_phase_power(400.0, 10.0, 0.85), 5.888972745734183));
+    }
+
+    #[test]
+    fn rated_point_of_example_motor() {
+        // EY630EAK: 2.4 kW at 1450 rpm is about 15.8 N·m.
+        let torque = torque_from_power(2.4, 1450.0).unwrap();
+        assert!((torque - 15.806).abs() < 1e-3, "{}", torque);
+    }
+
+    #[test]
+    fn no_torque_at_standstill() {
+        assert_eq!(torque_from_power(1.0, 0.0), None);
+    }
+
+    #[test]
+    fn cycles_of_known_values() {
+        assert_eq!(calculate_cycles(10.1, 1.0), 10.1);
+        assert_eq!(calculate_cycles(10.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn quantize_to_steps() {
+        assert_eq!(quantize(23.456, 0.1), 23.5);
+        assert_eq!(quantize(2.34567, 0.001), 2.346);
+        assert_eq!(quantize(1447.0, 5.0), 1445.0);
+        assert_eq!(quantize(0.1 + 0.2, 0.1), 0.3);
+    }
+
+    proptest! {
+        #[test]
+        fn power_is_monotonic_in_current(volts in 0.0..1000.0f64, a in 0.0..500.0f64, b in 0.0..500.0f64) {
+            let (low, high) = if a <= b { (a, b) } else { (b, a) };
+            prop_assert!(calculate_power(volts, low) <= calculate_power(volts, high));
+        }
+
+        #[test]
+        fn power_scales_with_current(volts in -1000.0..1000.0f64, amps in -500.0..500.0f64, k in 0.0..10.0f64) {
+            prop_assert!(close(calculate_power(volts, k * amps), k * calculate_power(volts, amps)));
+        }
+
+        #[test]
+        fn three_phase_is_root_three_times_single_phase(volts in 0.0..1000.0f64, amps in 0.0..500.0f64) {
+            prop_assert!(close(three_phase_power(volts, amps, 1.0), 3f64.sqrt() * calculate_power(volts, amps)));
+        }
+
+        #[test]
+        fn torque_and_power_round_trip(torque in -100.0..100.0f64, rpm in 1.0..10_000.0f64) {
+            let power = power_from_torque(torque, rpm);
+            prop_assert!(close(torque_from_power(power, rpm).unwrap(), torque));
+        }
+
+        #[test]
+        fn power_scales_linearly_with_speed(torque in 0.0..100.0f64, rpm in 0.0..5000.0f64) {
+            prop_assert!(close(power_from_torque(torque, 2.0 * rpm), 2.0 * power_from_torque(torque, rpm)));
+        }
+
+        #[test]
+        fn quantize_stays_within_half_a_step(value in -10_000.0..10_000.0f64, exponent in -3i32..3) {
+            let step = 10f64.powi(exponent);
+            prop_assert!((quantize(value, step) - value).abs() <= step / 2.0 + 1e-9);
+        }
+
+        #[test]
+        fn cycles_are_additive_over_time(torque in 0.0..100.0f64, t1 in 0.0..3600.0f64, t2 in 0.0..3600.0f64) {
+            prop_assert!(close(calculate_cycles(torque, t1 + t2), calculate_cycles(torque, t1) + calculate_cycles(torque, t2)));
+        }
+    }
+}
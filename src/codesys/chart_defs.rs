@@ -0,0 +1,151 @@
+//! Charts declared in the configuration, picked up again while running.
+//!
+//! Without `[[charts.chart]]` every signal gets a chart named after it, with
+//! the window, path and `every_secs` of `[charts]`, as before. Each entry
+//! declares a chart instead: its `signal`, `window_secs`, `size`,
+//! `path_template` and `every_secs`; `{signal}` in the path stands for the
+//! chart's `name`, so two charts of one signal write different files; two
+//! charts writing the same file are rejected.
+//!
+//! The monitor looks at its configuration file every `RELOAD_CHECK_SECS`.
+//! When the file has changed and still validates, the charts it declares
+//! are drawn from the next render on: new ones are added, removed ones are
+//! no longer drawn (their last image stays on disk) and changed ones are
+//! redrawn. Only the charts are reloaded; any other change needs a restart,
+//! and an invalid file is logged and ignored. The samples kept in memory
+//! span the `charts.window_secs` the monitor was started with, so a
+//! reloaded chart's window is cut to that. The charts are part of the
+//! configuration snapshot (see `config_history`), so a reload that changes
+//! them records a new one.
+
+use super::config::{self, ChartsConfig, MonitorConfig};
+use super::{Signal, CHART_SIZE};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Seconds between looks at the configuration file.
+pub const RELOAD_CHECK_SECS: u64 = 5;
+
+/// A chart to draw.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChartDef {
+    pub name: String,
+    pub signal: Signal,
+    pub window_secs: i64,
+    pub size: (u32, u32),
+    pub path_template: String,
+    pub every_secs: Option<u64>,
+}
+
+/// The charts `config` declares; the configuration has been validated.
+pub fn from_config(config: &ChartsConfig) -> Vec<ChartDef> {
+    if config.chart.is_empty() {
+        return Signal::ALL
+            .into_iter()
+            .map(|signal| ChartDef {
+                name: signal.name().to_string(),
+                signal,
+                window_secs: config.window_secs,
+                size: CHART_SIZE,
+                path_template: config.path_template.clone(),
+                every_secs: config.every_secs.get(signal.name()).copied(),
+            })
+            .collect();
+    }
+    config
+        .chart
+        .iter()
+        .map(|chart| {
+            let signal = Signal::from_name(&chart.signal).expect("validated signal");
+            ChartDef {
+                name: chart.name.clone(),
+                signal,
+                window_secs: chart.window_secs.unwrap_or(config.window_secs),
+                size: chart.size,
+                path_template: chart.path_template.clone().unwrap_or_else(|| config.path_template.clone()),
+                every_secs: chart.every_secs.or_else(|| config.every_secs.get(signal.name()).copied()),
+            }
+        })
+        .collect()
+}
+
+/// The charts in effect and the file they come from.
+#[derive(Debug)]
+pub struct ChartSet {
+    source: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    /// Span of the samples kept in memory, in seconds.
+    max_window_secs: i64,
+    charts: Vec<ChartDef>,
+}
+
+impl ChartSet {
+    pub fn new(config: &MonitorConfig) -> Self {
+        ChartSet {
+            source: config.source.clone(),
+            modified: config.source.as_deref().and_then(modified),
+            max_window_secs: config.charts.window_secs,
+            charts: from_config(&config.charts),
+        }
+    }
+
+    pub fn charts(&self) -> &[ChartDef] {
+        &self.charts
+    }
+
+    /// Redraw intervals by chart name, for `charts::RenderSchedule`.
+    pub fn every_secs(&self) -> BTreeMap<String, u64> {
+        self.charts.iter().filter_map(|chart| Some((chart.name.clone(), chart.every_secs?))).collect()
+    }
+
+    /// Loads the charts again if the configuration file has changed since it
+    /// was last read. Returns whether the charts changed.
+    pub fn reload(&mut self) -> bool {
+        let Some(source) = self.source.clone() else {
+            return false;
+        };
+        let modified = modified(&source);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        let config = match config::load(&source) {
+            Ok(config) => config,
+            Err(err) => {
+                log::warn!("keeping the current charts: {}", err.to_string().trim_end());
+                return false;
+            }
+        };
+        let mut charts = from_config(&config.charts);
+        for chart in charts.iter_mut().filter(|chart| chart.window_secs > self.max_window_secs) {
+            log::warn!(
+                "chart {} asks for {} s but only {} s are kept in memory until a restart",
+                chart.name,
+                chart.window_secs,
+                self.max_window_secs
+            );
+            chart.window_secs = self.max_window_secs;
+        }
+        if charts == self.charts {
+            return false;
+        }
+        for chart in &charts {
+            match self.charts.iter().find(|old| old.name == chart.name) {
+                None => log::info!("chart {} added", chart.name),
+                Some(old) if old != chart => log::info!("chart {} changed", chart.name),
+                Some(_) => {}
+            }
+        }
+        for old in self.charts.iter().filter(|old| charts.iter().all(|chart| chart.name != old.name)) {
+            log::info!("chart {} removed", old.name);
+        }
+        self.charts = charts;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -0,0 +1,585 @@
+//! Per-signal chart series and a render cache that skips unchanged charts.
+//!
+//! The series stay in time order when the host clock steps back, e.g. on an
+//! NTP correction: a sample older than the latest one is put in its place,
+//! and one from before the whole window, after a larger step, starts the
+//! series afresh. Either way the incrementally drawn charts are redrawn.
+//!
+//! Every rendered chart gets a JSON sidecar next to it (`power.png` →
+//! `power.json`) saying when it was rendered and which window it shows, so
+//! an HMI can tell a stale image from a quiet signal. The same facts are
+//! embedded in the PNG itself as text chunks.
+//!
+//! `charts.every_secs` gives a chart its own redraw interval, so a slow
+//! signal such as cycles need not be redrawn as often as power.
+//!
+//! With `charts.incremental` the render cache keeps each chart's pixels in
+//! memory and only draws the line from the last point to the new samples,
+//! instead of redrawing axes, labels and the whole series every tick. To
+//! leave room for them, a full redraw spans `INCREMENTAL_HEADROOM` more time
+//! than the window and at least that much more value range. The chart is
+//! drawn afresh when a sample falls outside the frame or markers, labels or
+//! axis settings change; between redraws the chart shows more than the
+//! window, as its sidecar says. Each chart holds about 1 MB of pixels.
+//!
+//! The charts are drawn by `ChartSink`, which runs under the `sink`
+//! supervisor like the other sinks: the polling loop hands it each tick's
+//! samples, and drawing a slow or failing chart never delays polling. It
+//! keeps its own series, render cache and chart set, and picks up charts
+//! changed in the configuration file every `chart_defs::RELOAD_CHECK_SECS`.
+
+use super::axis::ValueAxis;
+use super::output::{self, chart_text, ensure_parent, write_atomically};
+use super::chart_defs::{self, ChartSet};
+use super::config::{ChartsConfig, FormatConfig};
+use super::config_history;
+use super::disk;
+use super::notes;
+use super::sink::Sink;
+use super::units::Units;
+use super::axis::time_range;
+use super::{
+    draw_chart_on_axis, draw_chart_segment, motor_data_range, render_chart_pixels, write_chart_pixels, MotorData,
+    Signal,
+};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Rolling per-signal series covering the last `window_secs` seconds,
+/// keyed by millisecond timestamps.
+#[derive(Debug, Clone)]
+pub struct SeriesBuffer {
+    window_secs: i64,
+    series: HashMap<Signal, VecDeque<(i64, f64)>>,
+}
+
+impl SeriesBuffer {
+    pub fn new(window_secs: i64) -> Self {
+        SeriesBuffer { window_secs, series: HashMap::new() }
+    }
+
+    /// Adds a sample in time order; returns whether it came in order.
+    pub fn push(&mut self, data: &MotorData) -> bool {
+        let window_ms = self.window_secs * 1000;
+        let latest = self.series.values().filter_map(|series| series.back()).map(|(t, _)| *t).max();
+        let in_order = latest.is_none_or(|latest| data.timestamp >= latest);
+        let latest = match latest {
+            Some(latest) if latest - data.timestamp > window_ms => {
+                let behind = (latest - data.timestamp) / 1000;
+                log::warn!("the clock stepped back by {} s; restarting the chart series", behind);
+                self.series.clear();
+                data.timestamp
+            }
+            Some(latest) => latest.max(data.timestamp),
+            None => data.timestamp,
+        };
+        let cutoff = latest - window_ms;
+        for signal in Signal::ALL {
+            let series = self.series.entry(signal).or_default();
+            let at = series.partition_point(|(t, _)| *t <= data.timestamp);
+            series.insert(at, (data.timestamp, data.value(signal)));
+            while series.front().is_some_and(|(t, _)| *t < cutoff) {
+                series.pop_front();
+            }
+        }
+        in_order
+    }
+
+    /// Fills the buffer with stored samples from the `secs` seconds before
+    /// `now` (milliseconds), so charts don't start empty after a restart.
+    /// Returns the number of samples loaded.
+    pub async fn preload(&mut self, pool: &SqlitePool, now: i64, secs: i64) -> usize {
+        let samples = motor_data_range(pool, now - secs.min(self.window_secs) * 1000, now + 1, None).await;
+        for data in &samples {
+            self.push(data);
+        }
+        samples.len()
+    }
+
+    pub fn series(&self, signal: Signal) -> Vec<(i64, f64)> {
+        self.series.get(&signal).map(|s| s.iter().copied().collect()).unwrap_or_default()
+    }
+
+    /// The last `window_secs` seconds of the series, up to its latest sample.
+    pub fn window(&self, signal: Signal, window_secs: i64) -> Vec<(i64, f64)> {
+        let mut series = self.series(signal);
+        if let Some(&(latest, _)) = series.last() {
+            series.retain(|(t, _)| *t >= latest - window_secs * 1000);
+        }
+        series
+    }
+}
+
+/// Title and Y axis label, with the display unit, of the chart drawn for
+/// each signal.
+pub fn chart_spec(signal: Signal, units: &Units) -> (&'static str, String) {
+    let (title, label) = match signal {
+        Signal::Power => ("Current Power", "Power"),
+        Signal::Torque => ("Current Torque", "Torque"),
+        Signal::Speed => ("Current Speed", "Speed"),
+        Signal::Heat => ("Current Heat", "Heat"),
+        Signal::Cycles => ("Current Cycles", "Cycles"),
+    };
+    (title, format!("{} ({})", label, units.unit(signal.name())))
+}
+
+/// Contents of a chart's JSON sidecar. Timestamps are epoch milliseconds.
+#[derive(Debug, Serialize)]
+pub struct ChartInfo {
+    pub rendered_at: i64,
+    pub window_secs: i64,
+    /// First and last sample shown.
+    pub from: i64,
+    pub to: i64,
+    pub samples: usize,
+}
+
+/// Sidecar path of a chart: same name, `.json` extension.
+pub fn sidecar_path(filename: &str) -> std::path::PathBuf {
+    Path::new(filename).with_extension("json")
+}
+
+/// Per-chart redraw intervals (`charts.every_secs`), by chart name.
+#[derive(Debug, Default)]
+pub struct RenderSchedule {
+    every_secs: BTreeMap<String, u64>,
+    /// When each chart was last due, in milliseconds.
+    last: HashMap<String, i64>,
+}
+
+impl RenderSchedule {
+    pub fn new(every_secs: BTreeMap<String, u64>) -> Self {
+        RenderSchedule { every_secs, last: HashMap::new() }
+    }
+
+    /// Whether the chart of `name` is due at `now_ms`; a due chart counts as
+    /// drawn from then on. A chart is due when the clock stepped back past
+    /// its last drawing.
+    pub fn due(&mut self, name: &str, now_ms: i64) -> bool {
+        let Some(&every) = self.every_secs.get(name) else {
+            return true;
+        };
+        if self.last.get(name).is_some_and(|&last| (0..every as i64 * 1000).contains(&(now_ms - last))) {
+            return false;
+        }
+        self.last.insert(name.to_string(), now_ms);
+        true
+    }
+}
+
+/// Share of the window, and of the value span, left free on an
+/// incrementally drawn chart for the samples to come.
+const INCREMENTAL_HEADROOM: f64 = 0.1;
+
+/// Where a chart was drawn: its axis ranges, the pixels of its plotting
+/// area and the size of the image.
+#[derive(Debug, Clone)]
+pub struct ChartFrame {
+    pub x_range: Range<i64>,
+    pub y_range: Range<f64>,
+    pub pixel_range: (Range<i32>, Range<i32>),
+    pub size: (u32, u32),
+}
+
+/// A chart kept in memory by an incremental render cache.
+#[derive(Debug)]
+struct Frame {
+    pixels: Vec<u8>,
+    frame: ChartFrame,
+    /// Hash of everything but the samples; a change needs a full redraw.
+    layout: u64,
+    /// First sample shown.
+    from: i64,
+    /// Latest sample drawn, finite or not.
+    to: i64,
+    /// Last finite point, where the line continues from.
+    last_point: Option<(i64, f64)>,
+    samples: usize,
+}
+
+impl Frame {
+    /// Whether `points`, all later than `to`, can be drawn onto this frame.
+    fn fits(&self, points: &[(i64, f64)]) -> bool {
+        let Some(&(latest, _)) = points.last() else {
+            return true;
+        };
+        let (x, y) = (&self.frame.x_range, &self.frame.y_range);
+        latest < x.end && points.iter().all(|(_, v)| !v.is_finite() || (y.start <= *v && *v <= y.end))
+    }
+}
+
+/// Remembers the data version last rendered into each output file.
+///
+/// The version is a hash of the series values plus the window start rounded to
+/// a tenth of the window, so a flat signal is only redrawn when its values
+/// change or the time axis has drifted noticeably.
+#[derive(Debug, Default)]
+pub struct RenderCache {
+    motor_id: String,
+    format: FormatConfig,
+    rendered: HashMap<String, u64>,
+    /// Charts kept for drawing incrementally; `None` redraws them in full.
+    frames: Option<HashMap<String, Frame>>,
+    space: disk::Space,
+}
+
+impl RenderCache {
+    pub fn new(motor_id: &str, format: FormatConfig, space: disk::Space) -> Self {
+        RenderCache { motor_id: motor_id.to_string(), format, rendered: HashMap::new(), frames: None, space }
+    }
+
+    /// A cache that draws only the new samples onto the charts it keeps.
+    pub fn incremental(motor_id: &str, format: FormatConfig, space: disk::Space) -> Self {
+        RenderCache { frames: Some(HashMap::new()), ..RenderCache::new(motor_id, format, space) }
+    }
+
+    /// Draws the chart, `size` pixels large, unless `data` and `markers`
+    /// hash to the version already on disk. Returns whether the chart was
+    /// rendered.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_if_changed(
+        &mut self,
+        filename: &str,
+        size: (u32, u32),
+        data: &[(i64, f64)],
+        markers: &[(i64, String)],
+        window_secs: i64,
+        axis: &ValueAxis,
+        title: &str,
+        x_label: &str,
+        y_label: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        if data.is_empty() {
+            return Ok(false);
+        }
+        let version = data_version(data, markers, window_secs, size, (title, x_label, y_label));
+        if self.rendered.get(filename) == Some(&version) {
+            return Ok(false);
+        }
+        ensure_parent(Path::new(filename), &self.space)?;
+        let mut info = ChartInfo {
+            rendered_at: Local::now().timestamp_millis(),
+            window_secs,
+            from: data[0].0,
+            to: data[data.len() - 1].0,
+            samples: data.len(),
+        };
+        if self.frames.is_some() {
+            let labels = (title, x_label, y_label);
+            let (from, samples) = self.draw_incrementally(filename, size, data, markers, window_secs, axis, labels)?;
+            info.from = from;
+            info.samples = samples;
+        } else {
+            let text = chart_text(&self.motor_id, info.from, info.to);
+            draw_chart_on_axis(filename, size, data, markers, &self.format, axis, title, x_label, y_label, &text)?;
+        }
+        write_atomically(&sidecar_path(filename), |path| Ok(std::fs::write(path, serde_json::to_vec_pretty(&info)?)?))?;
+        self.rendered.insert(filename.to_string(), version);
+        Ok(true)
+    }
+
+    /// Draws the samples of `data` newer than the kept frame onto it, or the
+    /// whole chart afresh when they do not fit. Returns the first sample
+    /// shown and the number of samples shown.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_incrementally(
+        &mut self,
+        filename: &str,
+        size: (u32, u32),
+        data: &[(i64, f64)],
+        markers: &[(i64, String)],
+        window_secs: i64,
+        axis: &ValueAxis,
+        labels: (&str, &str, &str),
+    ) -> Result<(i64, usize), Box<dyn Error>> {
+        let layout = layout_version(markers, window_secs, size, axis, labels);
+        let frames = self.frames.as_mut().expect("incremental render cache");
+        if let Some(kept) = frames.get_mut(filename).filter(|kept| kept.layout == layout) {
+            let start = data.partition_point(|(t, _)| *t <= kept.to);
+            let new = &data[start..];
+            if kept.fits(new) {
+                let mut points: Vec<(i64, f64)> = kept.last_point.into_iter().collect();
+                points.extend(new.iter().filter(|(_, v)| v.is_finite()));
+                if points.len() > 1 {
+                    draw_chart_segment(&mut kept.pixels, &kept.frame, &points)?;
+                    let text = chart_text(&self.motor_id, kept.from, data[data.len() - 1].0);
+                    write_chart_pixels(filename, size, &kept.pixels, &text)?;
+                }
+                kept.last_point = points.last().copied();
+                kept.to = kept.to.max(data[data.len() - 1].0);
+                kept.samples += new.len();
+                return Ok((kept.from, kept.samples));
+            }
+        }
+
+        let (from, to) = (data[0].0, data[data.len() - 1].0);
+        let window_ms = window_secs * 1000;
+        let x_end = from + window_ms + (window_ms as f64 * INCREMENTAL_HEADROOM) as i64;
+        let x_range = match time_range(data) {
+            Some(range) if range.end > x_end => range,
+            Some(range) => range.start..x_end,
+            None => return Err("no samples to chart".into()),
+        };
+        let axis = ValueAxis { padding: axis.padding.max(INCREMENTAL_HEADROOM), ..*axis };
+        let mut pixels = vec![0; (size.0 * size.1 * 3) as usize];
+        let (title, x_label, y_label) = labels;
+        let frame = render_chart_pixels(
+            &mut pixels,
+            size,
+            x_range,
+            data,
+            markers,
+            &self.format,
+            &axis,
+            title,
+            x_label,
+            y_label,
+        )?;
+        write_chart_pixels(filename, size, &pixels, &chart_text(&self.motor_id, from, to))?;
+        let last_point = data.iter().rev().find(|(_, v)| v.is_finite()).copied();
+        let kept = Frame { pixels, frame, layout, from, to, last_point, samples: data.len() };
+        frames.insert(filename.to_string(), kept);
+        Ok((from, data.len()))
+    }
+
+    /// Forgets every chart, so that each is drawn afresh.
+    pub fn reset(&mut self) {
+        self.rendered.clear();
+        if let Some(frames) = &mut self.frames {
+            frames.clear();
+        }
+    }
+
+    /// Forgets every chart whose file `keep` rejects, e.g. charts no longer
+    /// drawn.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.rendered.retain(|filename, _| keep(filename));
+        if let Some(frames) = &mut self.frames {
+            frames.retain(|filename, _| keep(filename));
+        }
+    }
+
+    /// Forgets the cached version so the next call redraws `filename`.
+    pub fn invalidate(&mut self, filename: &str) {
+        self.rendered.remove(filename);
+        if let Some(frames) = &mut self.frames {
+            frames.remove(filename);
+        }
+    }
+}
+
+fn layout_version(
+    markers: &[(i64, String)],
+    window_secs: i64,
+    size: (u32, u32),
+    axis: &ValueAxis,
+    labels: (&str, &str, &str),
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    labels.hash(&mut hasher);
+    markers.hash(&mut hasher);
+    window_secs.hash(&mut hasher);
+    size.hash(&mut hasher);
+    axis.range.min.map(f64::to_bits).hash(&mut hasher);
+    axis.range.max.map(f64::to_bits).hash(&mut hasher);
+    axis.padding.to_bits().hash(&mut hasher);
+    hasher.finish()
+}
+
+fn data_version(
+    data: &[(i64, f64)],
+    markers: &[(i64, String)],
+    window_secs: i64,
+    size: (u32, u32),
+    labels: (&str, &str, &str),
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    labels.hash(&mut hasher);
+    markers.hash(&mut hasher);
+    size.hash(&mut hasher);
+    let step = (window_secs * 100).max(1);
+    if let Some((first, _)) = data.first() {
+        first.div_euclid(step).hash(&mut hasher);
+    }
+    data.len().hash(&mut hasher);
+    for (_, value) in data {
+        value.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// One poll tick's samples for `ChartSink`; `render` asks for the due
+/// charts to be drawn after adding them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChartUpdate {
+    pub samples: Vec<MotorData>,
+    pub render: bool,
+}
+
+/// Draws the configured charts from the samples it is given.
+pub struct ChartSink {
+    pool: Arc<SqlitePool>,
+    motor_id: String,
+    config: ChartsConfig,
+    units: Units,
+    series: SeriesBuffer,
+    cache: RenderCache,
+    chart_set: ChartSet,
+    /// Recorded again with the charts when a reload changes them.
+    history: config_history::Snapshot,
+    schedule: RenderSchedule,
+    last_reload_check: Instant,
+    space: disk::Space,
+    /// Timestamp of the last sample added, so an update written again
+    /// after a failure does not add its samples twice.
+    applied: Option<i64>,
+}
+
+impl ChartSink {
+    /// `series` is the buffer to start from, e.g. one preloaded with stored
+    /// samples.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        pool: Arc<SqlitePool>,
+        motor_id: &str,
+        config: ChartsConfig,
+        format: FormatConfig,
+        units: Units,
+        chart_set: ChartSet,
+        history: config_history::Snapshot,
+        series: SeriesBuffer,
+        space: disk::Space,
+    ) -> Self {
+        let cache = if config.incremental {
+            RenderCache::incremental(motor_id, format, space.clone())
+        } else {
+            RenderCache::new(motor_id, format, space.clone())
+        };
+        ChartSink {
+            pool,
+            motor_id: motor_id.to_string(),
+            config,
+            units,
+            series,
+            cache,
+            schedule: RenderSchedule::new(chart_set.every_secs()),
+            chart_set,
+            history,
+            last_reload_check: Instant::now(),
+            space,
+            applied: None,
+        }
+    }
+
+    fn push(&mut self, samples: &[MotorData]) {
+        let Some(last) = samples.last() else {
+            return;
+        };
+        if self.applied == Some(last.timestamp) {
+            return;
+        }
+        for data in samples {
+            if !self.series.push(data) {
+                // The clock stepped back; charts kept in memory no longer match the series.
+                self.cache.reset();
+            }
+        }
+        self.applied = Some(last.timestamp);
+    }
+
+    /// Picks up charts added, changed or removed in the configuration file
+    /// and records the configuration snapshot with them. The cache lets go
+    /// of the charts no longer drawn to the same file.
+    async fn maybe_reload(&mut self) {
+        if self.last_reload_check.elapsed() < Duration::from_secs(chart_defs::RELOAD_CHECK_SECS) {
+            return;
+        }
+        self.last_reload_check = Instant::now();
+        if self.chart_set.reload() {
+            self.schedule = RenderSchedule::new(self.chart_set.every_secs());
+            let now = Local::now().timestamp_millis();
+            let paths: HashSet<String> = self
+                .chart_set
+                .charts()
+                .iter()
+                .map(|chart| {
+                    let (motor, signal) = (self.motor_id.as_str(), chart.name.as_str());
+                    let ctx = output::OutputContext { motor, signal, timestamp: now / 1000 };
+                    output::render_path(&chart.path_template, &ctx).to_string_lossy().into_owned()
+                })
+                .collect();
+            self.cache.retain(|filename| paths.contains(filename));
+            config_history::record(&self.pool, &self.history, self.chart_set.charts(), now).await;
+        }
+    }
+
+    /// Draws the due charts whose data changed since the last render. Every
+    /// chart is tried; the first failure is returned.
+    async fn render(&mut self) -> Result<(), String> {
+        let now_ms = Local::now().timestamp_millis();
+        let due: Vec<chart_defs::ChartDef> = self
+            .chart_set
+            .charts()
+            .iter()
+            .filter(|chart| self.schedule.due(&chart.name, now_ms))
+            .cloned()
+            .collect();
+        if due.is_empty() {
+            return Ok(());
+        }
+        let now = now_ms.div_euclid(1000);
+        let window_start = now_ms - self.config.window_secs * 1000;
+        let markers = notes::markers(&self.pool, &self.motor_id, window_start, now_ms + 1).await;
+        let mut failed = None;
+        for chart in due {
+            let signal = chart.signal;
+            let (title, y_label) = chart_spec(signal, &self.units);
+            let ctx = output::OutputContext { motor: &self.motor_id, signal: &chart.name, timestamp: now };
+            let path = output::render_path(&chart.path_template, &ctx);
+            let points: Vec<(i64, f64)> = self
+                .series
+                .window(signal, chart.window_secs)
+                .into_iter()
+                .map(|(t, v)| (t, self.units.display(signal.name(), v)))
+                .collect();
+            let axis = ValueAxis::for_chart(&self.config, signal.name());
+            let drawn = self.cache.draw_if_changed(
+                &path.to_string_lossy(),
+                chart.size,
+                &points,
+                &markers,
+                chart.window_secs,
+                &axis,
+                title,
+                "Time",
+                &y_label,
+            );
+            if let Err(err) = drawn {
+                failed.get_or_insert_with(|| format!("cannot write chart {}: {}", path.display(), err));
+            }
+        }
+        failed.map_or(Ok(()), Err)
+    }
+}
+
+#[async_trait]
+impl Sink<ChartUpdate> for ChartSink {
+    async fn write(&mut self, update: &ChartUpdate) -> Result<(), String> {
+        self.push(&update.samples);
+        if !update.render || self.space.is_low() {
+            return Ok(());
+        }
+        self.maybe_reload().await;
+        self.render().await
+    }
+}
@@ -0,0 +1,334 @@
+//! Typed async client for the monitor's HTTP API (enabled by the `client`
+//! feature), so other Rust services can integrate without hand-written
+//! queries.
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), codesys::codesys::client::ClientError> {
+//! use codesys::codesys::client::ApiClient;
+//! let api = ApiClient::new("http://edge-box:8080");
+//! for motor in api.motors().await? {
+//!     println!("{} rated {} kW", motor.id, motor.specs.rated_power);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::MotorData;
+use futures::stream::{self, Stream};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Http(reqwest::Error),
+    /// The server answered with GraphQL errors.
+    Api(Vec<String>),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(err) => write!(f, "HTTP error: {}", err),
+            ClientError::Api(messages) => write!(f, "API error: {}", messages.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(err: reqwest::Error) -> Self {
+        ClientError::Http(err)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Motor {
+    pub id: String,
+    pub specs: Specs,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Specs {
+    pub rated_power: f64,
+    pub rated_torque: f64,
+    pub rated_speed: f64,
+    pub peak_torque: f64,
+    pub max_speed: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sample {
+    pub timestamp: i64,
+    pub power: f64,
+    pub torque: f64,
+    pub speed: f64,
+    pub heat: f64,
+    pub cycles: f64,
+    #[serde(default)]
+    pub speed_reference: Option<f64>,
+    #[serde(default)]
+    pub temperatures: Vec<Temperature>,
+    #[serde(default)]
+    pub sequence: Option<i64>,
+    #[serde(default)]
+    pub estimated: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Temperature {
+    pub channel: String,
+    pub value: f64,
+}
+
+impl From<Sample> for MotorData {
+    fn from(s: Sample) -> Self {
+        MotorData {
+            timestamp: s.timestamp,
+            current_power: s.power,
+            current_torque: s.torque,
+            current_speed: s.speed,
+            current_heat: s.heat,
+            current_cycles: s.cycles,
+            speed_reference: s.speed_reference,
+            temperatures: s.temperatures.into_iter().map(|t| (t.channel, t.value)).collect(),
+            pulses: BTreeMap::new(),
+            sequence: s.sequence,
+            current: None,
+            estimated: s.estimated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Aggregation {
+    pub signal: String,
+    pub window: String,
+    pub window_start: i64,
+    pub count: i64,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub stddev: f64,
+    #[serde(default)]
+    pub aggregates: Vec<AggregateValue>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AggregateValue {
+    pub name: String,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alarm {
+    pub id: i64,
+    pub motor_id: String,
+    pub rule: String,
+    pub signal: Option<String>,
+    pub severity: String,
+    pub message: String,
+    pub raised_at: i64,
+    pub cleared_at: Option<i64>,
+    #[serde(default)]
+    pub snapshot_path: Option<String>,
+    #[serde(default)]
+    pub acknowledged_at: Option<i64>,
+    #[serde(default)]
+    pub acknowledged_by: Option<String>,
+    #[serde(default)]
+    pub shelved: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Shelf {
+    pub id: i64,
+    pub motor_id: String,
+    pub rule: String,
+    pub reason: String,
+    pub shelved_by: String,
+    pub shelved_at: i64,
+    pub shelved_until: i64,
+    pub unshelved_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Note {
+    pub id: i64,
+    pub motor_id: String,
+    pub timestamp: i64,
+    pub author: String,
+    pub text: String,
+    pub created_at: i64,
+}
+
+const NOTE_FIELDS: &str = "id motorId timestamp author text createdAt";
+
+const SHELF_FIELDS: &str = "id motorId rule reason shelvedBy shelvedAt shelvedUntil unshelvedAt";
+
+const SAMPLE_FIELDS: &str =
+    "timestamp power torque speed heat cycles speedReference temperatures { channel value } sequence estimated";
+
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    http: reqwest::Client,
+    endpoint: String,
+}
+
+impl ApiClient {
+    /// `base_url` is the server root, e.g. `http://10.0.0.5:8080`, or the
+    /// site root of a monitor with `site` set (`http://10.0.0.5:8080/sites/acme`).
+    pub fn new(base_url: &str) -> Self {
+        ApiClient {
+            http: reqwest::Client::new(),
+            endpoint: format!("{}/graphql", base_url.trim_end_matches('/')),
+        }
+    }
+
+    async fn query<T: DeserializeOwned>(&self, query: &str, variables: Value, field: &str) -> Result<T, ClientError> {
+        let response: Value = self
+            .http
+            .post(&self.endpoint)
+            .json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if let Some(errors) = response.get("errors").and_then(Value::as_array) {
+            if !errors.is_empty() {
+                return Err(ClientError::Api(
+                    errors
+                        .iter()
+                        .map(|e| e.get("message").and_then(Value::as_str).unwrap_or("unknown error").to_string())
+                        .collect(),
+                ));
+            }
+        }
+        let data = response.get("data").and_then(|d| d.get(field)).cloned().unwrap_or(Value::Null);
+        serde_json::from_value(data).map_err(|e| ClientError::Api(vec![e.to_string()]))
+    }
+
+    pub async fn motors(&self) -> Result<Vec<Motor>, ClientError> {
+        self.query(
+            "{ motors { id specs { ratedPower ratedTorque ratedSpeed peakTorque maxSpeed } } }",
+            json!({}),
+            "motors",
+        )
+        .await
+    }
+
+    /// Raw samples with `from <= timestamp < to` (epoch milliseconds), oldest first.
+    pub async fn samples(&self, from: i64, to: i64, limit: Option<i64>) -> Result<Vec<Sample>, ClientError> {
+        let query = format!(
+            "query($from: Int!, $to: Int!, $limit: Int) {{ samples(from: $from, to: $to, limit: $limit) {{ {} }} }}",
+            SAMPLE_FIELDS
+        );
+        self.query(&query, json!({ "from": from, "to": to, "limit": limit }), "samples").await
+    }
+
+    /// Window statistics; `window` is `1m`, `15m` or `1h`.
+    pub async fn aggregations(
+        &self,
+        signal: &str,
+        window: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Aggregation>, ClientError> {
+        self.query(
+            "query($signal: String!, $window: String!, $from: Int!, $to: Int!) { \
+             aggregations(signal: $signal, window: $window, from: $from, to: $to) \
+             { signal window windowStart count min max avg stddev aggregates { name value } } }",
+            json!({ "signal": signal, "window": window, "from": from, "to": to }),
+            "aggregations",
+        )
+        .await
+    }
+
+    pub async fn alarms(&self, active_only: bool, limit: Option<i64>) -> Result<Vec<Alarm>, ClientError> {
+        self.query(
+            "query($activeOnly: Boolean!, $limit: Int) { alarms(activeOnly: $activeOnly, limit: $limit) \
+             { id motorId rule signal severity message raisedAt clearedAt snapshotPath \
+             acknowledgedAt acknowledgedBy shelved } }",
+            json!({ "activeOnly": active_only, "limit": limit }),
+            "alarms",
+        )
+        .await
+    }
+
+    pub async fn acknowledge_alarm(&self, id: i64, by: &str) -> Result<bool, ClientError> {
+        self.query(
+            "mutation($id: Int!, $by: String!) { acknowledgeAlarm(id: $id, by: $by) }",
+            json!({ "id": id, "by": by }),
+            "acknowledgeAlarm",
+        )
+        .await
+    }
+
+    pub async fn shelves(&self, active_only: bool) -> Result<Vec<Shelf>, ClientError> {
+        let query = format!("query($activeOnly: Boolean!) {{ shelves(activeOnly: $activeOnly) {{ {} }} }}", SHELF_FIELDS);
+        self.query(&query, json!({ "activeOnly": active_only }), "shelves").await
+    }
+
+    /// Suppresses alarms of `rule` for `hours` (at most a week).
+    pub async fn shelve_rule(&self, rule: &str, hours: f64, reason: &str, by: &str) -> Result<Shelf, ClientError> {
+        let query = format!(
+            "mutation($rule: String!, $hours: Float!, $reason: String!, $by: String!) \
+             {{ shelveRule(rule: $rule, hours: $hours, reason: $reason, by: $by) {{ {} }} }}",
+            SHELF_FIELDS
+        );
+        self.query(&query, json!({ "rule": rule, "hours": hours, "reason": reason, "by": by }), "shelveRule").await
+    }
+
+    pub async fn unshelve_rule(&self, rule: &str) -> Result<bool, ClientError> {
+        self.query("mutation($rule: String!) { unshelveRule(rule: $rule) }", json!({ "rule": rule }), "unshelveRule")
+            .await
+    }
+
+    /// Notes with `from <= timestamp < to` (epoch seconds), oldest first.
+    pub async fn notes(&self, from: i64, to: i64) -> Result<Vec<Note>, ClientError> {
+        let query = format!("query($from: Int!, $to: Int!) {{ notes(from: $from, to: $to) {{ {} }} }}", NOTE_FIELDS);
+        self.query(&query, json!({ "from": from, "to": to }), "notes").await
+    }
+
+    /// Adds a note at `timestamp` (epoch seconds), or now when `None`.
+    pub async fn add_note(&self, text: &str, author: &str, timestamp: Option<i64>) -> Result<Note, ClientError> {
+        let query = format!(
+            "mutation($text: String!, $author: String!, $timestamp: Int) \
+             {{ addNote(text: $text, author: $author, timestamp: $timestamp) {{ {} }} }}",
+            NOTE_FIELDS
+        );
+        self.query(&query, json!({ "text": text, "author": author, "timestamp": timestamp }), "addNote").await
+    }
+
+    /// Streams new samples as they are stored, starting after `since` (ms),
+    /// by polling the server every `poll`.
+    pub fn live(&self, since: i64, poll: Duration) -> impl Stream<Item = Result<Sample, ClientError>> + '_ {
+        let state = (since, VecDeque::new());
+        stream::unfold(state, move |(mut since, mut buffered): (i64, VecDeque<Sample>)| async move {
+            loop {
+                if let Some(sample) = buffered.pop_front() {
+                    since = since.max(sample.timestamp);
+                    return Some((Ok(sample), (since, buffered)));
+                }
+                tokio::time::sleep(poll).await;
+                match self.samples(since + 1, i64::MAX, None).await {
+                    Ok(samples) => buffered.extend(samples),
+                    Err(err) => return Some((Err(err), (since, buffered))),
+                }
+            }
+        })
+    }
+}
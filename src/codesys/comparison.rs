@@ -0,0 +1,202 @@
+//! Comparison charts: one signal over two time windows, overlaid.
+//!
+//! The earlier window is shifted forward by `offset_hours` onto the time
+//! axis of the current one, so today's curve is drawn over yesterday's (or
+//! this shift's over the last one's) and a deviation from the usual pattern
+//! is obvious at a glance.
+//!
+//! Both windows are kept in memory and slide with every render: only the
+//! samples newer than the last one kept are read from the database, and
+//! those that fell out of the window are dropped.
+
+use super::config::{ComparisonConfig, FormatConfig};
+use super::disk;
+use super::output::{add_png_text, chart_text, ensure_parent, render_path, write_atomically, OutputContext};
+use super::units::Units;
+use super::{format, motor_data_range, Signal};
+use chrono::prelude::*;
+use plotters::prelude::*;
+use sqlx::sqlite::SqlitePool;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+
+/// Legend label of the earlier window, e.g. `yesterday` or `8 h earlier`.
+pub fn offset_label(offset_hours: i64) -> String {
+    match offset_hours {
+        24 => "yesterday".to_string(),
+        168 => "same day last week".to_string(),
+        h if h % 24 == 0 => format!("{} days earlier", h / 24),
+        h => format!("{} h earlier", h),
+    }
+}
+
+/// The compared signals over a window that slides forward, in display
+/// units and shifted forward by `shift_ms`.
+#[derive(Debug)]
+struct SlidingWindow {
+    signals: Vec<Signal>,
+    shift_ms: i64,
+    /// Shifted timestamp and the value of each of `signals`, oldest first.
+    samples: VecDeque<(i64, Vec<f64>)>,
+    /// Just past the newest sample read, unshifted.
+    read_to: Option<i64>,
+}
+
+impl SlidingWindow {
+    fn new(signals: Vec<Signal>, shift_ms: i64) -> Self {
+        SlidingWindow { signals, shift_ms, samples: VecDeque::new(), read_to: None }
+    }
+
+    /// Moves the window to `[from, to)` (milliseconds, unshifted), reading
+    /// only the samples not read yet. Reading resumes after the newest
+    /// sample rather than at `to`, so samples stored late are not missed.
+    async fn advance(&mut self, pool: &SqlitePool, units: &Units, from: i64, to: i64) {
+        let start = self.read_to.map_or(from, |read_to| read_to.max(from));
+        if start < to {
+            for data in motor_data_range(pool, start, to, None).await {
+                let values =
+                    self.signals.iter().map(|&signal| units.display(signal.name(), data.value(signal))).collect();
+                self.samples.push_back((data.timestamp + self.shift_ms, values));
+                self.read_to = Some(data.timestamp + 1);
+            }
+        }
+        while self.samples.front().is_some_and(|(t, _)| *t - self.shift_ms < from) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The `index`th signal over the window.
+    fn series(&self, index: usize) -> Vec<(i64, f64)> {
+        self.samples.iter().map(|(t, values)| (*t, values[index])).collect()
+    }
+}
+
+/// Draws `current` (red) over `previous` (grey), both on the time axis of
+/// `current`, with `text` as PNG text chunks; the file is replaced
+/// atomically.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_comparison(
+    filename: &str,
+    current: &[(i64, f64)],
+    previous: &[(i64, f64)],
+    x_range: (i64, i64),
+    format: &FormatConfig,
+    title: &str,
+    y_label: &str,
+    previous_label: &str,
+    text: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    write_atomically(Path::new(filename), |path| {
+        render_comparison(path, current, previous, x_range, format, title, y_label, previous_label)?;
+        add_png_text(path, text)
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_comparison(
+    path: &Path,
+    current: &[(i64, f64)],
+    previous: &[(i64, f64)],
+    x_range: (i64, i64),
+    format: &FormatConfig,
+    title: &str,
+    y_label: &str,
+    previous_label: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (low, high) = current
+        .iter()
+        .chain(previous)
+        .map(|(_, v)| *v)
+        .filter(|v| v.is_finite())
+        .fold((0.0f64, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    let high = if high > low { high } else { low + 1.0 };
+
+    let root = BitMapBackend::new(path, (640, 480)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 30).into_font())
+        .margin(5)
+        .x_label_area_size(30)
+        .y_label_area_size(40)
+        .build_cartesian_2d(x_range.0..x_range.1, low..high)?;
+
+    let time_label = |ms: &i64| format.time(*ms);
+    let decimals = format::axis_decimals(high - low);
+    let value_label = |v: &f64| format.number(*v, decimals);
+    chart
+        .configure_mesh()
+        .x_desc("Time")
+        .y_desc(y_label)
+        .x_label_formatter(&time_label)
+        .y_label_formatter(&value_label)
+        .draw()?;
+
+    let grey = BLACK.mix(0.35);
+    chart
+        .draw_series(LineSeries::new(previous.iter().copied(), grey))?
+        .label(previous_label)
+        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], grey));
+    chart
+        .draw_series(LineSeries::new(current.iter().copied(), &RED))?
+        .label("now")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+/// Renders the comparison charts of one `[[comparisons]]` entry every
+/// `every_minutes`.
+pub async fn run_comparison_renderer(
+    pool: Arc<SqlitePool>,
+    motor_id: String,
+    config: ComparisonConfig,
+    format: FormatConfig,
+    units: Units,
+    space: disk::Space,
+) {
+    let mut interval = time::interval(Duration::from_secs(config.every_minutes * 60));
+    let window_ms = config.window_hours * 3_600_000;
+    let offset_ms = config.offset_hours * 3_600_000;
+    let previous_label = offset_label(config.offset_hours);
+    let signals: Vec<Signal> = config.signals.iter().filter_map(|name| Signal::from_name(name)).collect();
+    let mut current_window = SlidingWindow::new(signals.clone(), 0);
+    let mut previous_window = SlidingWindow::new(signals.clone(), offset_ms);
+    loop {
+        interval.tick().await;
+        let now = Local::now().timestamp_millis();
+        let from = now - window_ms;
+        current_window.advance(&pool, &units, from, now).await;
+        previous_window.advance(&pool, &units, from - offset_ms, now - offset_ms).await;
+        for (index, &signal) in signals.iter().enumerate() {
+            let current = current_window.series(index);
+            let previous = previous_window.series(index);
+            if current.is_empty() && previous.is_empty() {
+                continue;
+            }
+
+            let ctx = OutputContext { motor: &motor_id, signal: signal.name(), timestamp: now.div_euclid(1000) };
+            let path = render_path(&config.path_template, &ctx);
+            let (title, y_label) = super::charts::chart_spec(signal, &units);
+            let title = format!("{}, last {} h vs. {}", title, config.window_hours, previous_label);
+            let mut text = chart_text(&motor_id, from, now);
+            text.push(("compared_with", previous_label.clone()));
+            let result = ensure_parent(&path, &space).map_err(Box::<dyn Error>::from).and_then(|_| {
+                let filename = path.to_string_lossy();
+                draw_comparison(&filename, &current, &previous, (from, now), &format, &title, &y_label, &previous_label, &text)
+            });
+            if let Err(err) = result {
+                log::error!("cannot write comparison chart {}: {}", path.display(), err);
+            }
+        }
+    }
+}
@@ -0,0 +1,3997 @@
+//! Monitor configuration, loaded from a TOML file and validated at startup.
+//!
+//! Parsing rejects unknown keys and malformed values; `validate` then checks
+//! ranges and register overlaps. Every problem is reported with the line and
+//! column of the offending key so mistakes surface before the polling loop
+//! starts rather than as a panic deep inside it.
+//!
+//! Sites with many motors can share settings: `extends = "site.toml"` pulls
+//! in other files, `[templates.<name>]` tables hold reusable sections and
+//! `template = "<name>"` applies them. Precedence, lowest first: extended
+//! files, templates, the file itself. Tables merge key by key; any other
+//! value is replaced.
+
+use super::alarms::Severity;
+use super::limits;
+use super::lineage;
+use super::location;
+use super::output;
+use super::rules;
+use super::schedule;
+use super::secrets;
+use super::sentinels;
+use super::tls;
+use super::transport;
+use super::zoom;
+use super::{MotorSpecs, Signal};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+const STANDARD_BAUD_RATES: [u32; 8] = [1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200];
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MonitorConfig {
+    #[serde(default = "default_database_url")]
+    pub database_url: String,
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// Poll interval by motor state, replacing `poll_interval_ms`; fixed
+    /// interval when absent.
+    pub adaptive_polling: Option<AdaptivePollingConfig>,
+    /// Identifier used for this motor in the API and in alarm/event records.
+    #[serde(default = "default_motor_id")]
+    pub motor_id: String,
+    /// Site (tenant) the motor belongs to; see `site`. Single-site when absent.
+    pub site: Option<String>,
+    /// Group of motors, e.g. a production line, whose KPIs the fleet report
+    /// adds up; see `oee`.
+    pub group: Option<String>,
+    /// Time zone and site labels of the motor; see `location`. The host's
+    /// time zone when absent.
+    pub location: Option<LocationConfig>,
+    /// Never write to the field bus: setpoint and interlock writes are only
+    /// logged and audited, whatever their `writes_enabled`. Also `--dry-run`.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Refuse plain network connections beyond the machine; see `tls`.
+    #[serde(default)]
+    pub require_tls: bool,
+    #[serde(default)]
+    pub transport: TransportKind,
+    #[serde(default)]
+    pub serial: SerialConfig,
+    /// Required when `transport = "ethernet_ip"`.
+    pub ethernet_ip: Option<EthernetIpConfig>,
+    pub motor: MotorSpecs,
+    #[serde(default)]
+    pub registers: RegisterMap,
+    #[serde(default)]
+    pub charts: ChartsConfig,
+    #[serde(default)]
+    pub format: FormatConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Declarative alarm rules, one `[[alarm_rules]]` entry each; see `rules`.
+    #[serde(default)]
+    pub alarm_rules: Vec<AlarmRuleConfig>,
+    /// Storage resolution per signal, `speed_reference` or temperature
+    /// channel, e.g. `heat = 0.1` (°C) or `power = 0.001` (kW, i.e. 1 W).
+    /// Values are rounded to a multiple of the step before insert; unlisted
+    /// values are stored as read.
+    #[serde(default)]
+    pub precision: BTreeMap<String, f64>,
+    /// Extra window aggregations per signal, e.g. `[rollups.heat]`; see `stats`.
+    #[serde(default)]
+    pub rollups: BTreeMap<String, RollupConfig>,
+    #[serde(default)]
+    pub governor: GovernorConfig,
+    #[serde(default)]
+    pub sinks: SinksConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
+    /// Active/standby pairing with a second monitor; disabled when absent.
+    pub redundancy: Option<RedundancyConfig>,
+    /// Periodic reports; disabled when absent.
+    pub report: Option<ReportConfig>,
+    /// Speed tracking-error alarm; requires `registers.speed_reference`.
+    pub tracking: Option<TrackingConfig>,
+    /// Charts captured when an alarm trips; disabled when absent.
+    pub snapshots: Option<SnapshotConfig>,
+    /// Compaction of old raw samples into compressed hour blocks; disabled when absent.
+    pub archive: Option<ArchiveConfig>,
+    /// Database integrity checks, checkpoints and vacuuming; disabled when absent.
+    pub maintenance: Option<MaintenanceConfig>,
+    /// Scheduled online database backups; disabled when absent.
+    pub backup: Option<BackupConfig>,
+    /// Free-space checks and pruning of generated files; disabled when absent.
+    pub disk: Option<DiskConfig>,
+    /// Automatic stop on sustained overload or over-temperature; disabled when absent.
+    pub interlock: Option<InterlockConfig>,
+    /// Read-back of every control write; disabled when absent.
+    pub write_verify: Option<WriteVerifyConfig>,
+    /// I²t thermal overload model; disabled when absent.
+    pub thermal: Option<ThermalConfig>,
+    /// Speed/torque setpoints written through the API; disabled when absent.
+    pub setpoints: Option<SetpointConfig>,
+    /// Operator start/stop, setpoint and fault reset over HTTP; disabled when absent.
+    pub control: Option<ControlConfig>,
+    /// Temperature-vs-load dwell heatmap; disabled when absent.
+    pub heatmap: Option<HeatmapConfig>,
+    /// Overlay charts of two time windows, one `[[comparisons]]` entry each.
+    #[serde(default)]
+    pub comparisons: Vec<ComparisonConfig>,
+    /// Charts pre-rendered at several horizons; disabled when absent.
+    pub zoom: Option<ZoomConfig>,
+    /// Alarms on signals that stop changing; disabled when absent.
+    pub stale: Option<StaleConfig>,
+    /// Expected operating hours and their alarms; disabled when absent.
+    pub schedule: Option<ScheduleConfig>,
+    /// Driven equipment and its expected power; disabled when absent.
+    pub equipment: Option<EquipmentConfig>,
+    /// Peak demand tracking; disabled when absent.
+    pub demand: Option<DemandConfig>,
+    /// Summaries at every shift handover; disabled when absent.
+    pub shifts: Option<ShiftsConfig>,
+    /// Long-term trends and RUL estimate in reports; disabled when absent.
+    pub trends: Option<TrendsConfig>,
+    /// Cross-signal correlation in reports; disabled when absent.
+    pub correlation: Option<CorrelationConfig>,
+    /// Motor current signature analysis; disabled when absent.
+    pub mcsa: Option<McsaConfig>,
+    /// Ambient sensors on the bus; disabled when absent.
+    pub environment: Option<EnvironmentConfig>,
+    /// Cumulative energy and run-hour registers; disabled when absent.
+    pub counters: Option<CountersConfig>,
+    /// Fault history registers of the drive, synced into events; disabled when absent.
+    pub fault_history: Option<FaultHistoryConfig>,
+    /// Modbus TCP server answering from the polled registers; disabled when absent.
+    pub modbus_proxy: Option<ModbusProxyConfig>,
+    /// Local JSON-RPC socket for `rustsys status`, `tail` and `setpoint`;
+    /// disabled when absent.
+    pub ipc: Option<IpcConfig>,
+    /// Analytics plugins, one `[[plugins]]` entry each.
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
+    /// Publishing of samples to an MQTT broker; disabled when absent.
+    pub mqtt: Option<MqttConfig>,
+    /// Publishing of samples and events to NATS JetStream; disabled when
+    /// absent.
+    pub nats: Option<NatsConfig>,
+    /// SHA-256 of the parsed configuration, set when it is loaded; see `lineage`.
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub config_hash: String,
+    /// The file the configuration was loaded from, if any; see `chart_defs`.
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub source: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportKind {
+    #[default]
+    ModbusRtu,
+    EthernetIp,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SerialConfig {
+    #[serde(default = "default_serial_path")]
+    pub path: String,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    #[serde(default = "default_slave_id")]
+    pub slave_id: u8,
+    #[serde(default)]
+    pub parity: Parity,
+    /// USB adapter to use instead of `path`, looked up whenever the bus is
+    /// opened; see `serial_ports`.
+    pub usb: Option<UsbSerialId>,
+}
+
+/// A USB-serial adapter, as listed by `rustsys ports`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UsbSerialId {
+    /// USB vendor id, e.g. `0x0403` (FTDI).
+    pub vid: u16,
+    /// USB product id, e.g. `0x6001`.
+    pub pid: u16,
+    /// Serial number; needed when several adapters share vendor and product.
+    pub serial_number: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    #[default]
+    None,
+    Even,
+    Odd,
+}
+
+/// EtherNet/IP explicit messaging target. Register addresses are read as
+/// instances of `class`, attribute `attribute`.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EthernetIpConfig {
+    /// Drive address, e.g. `192.168.1.20:44818`.
+    pub address: String,
+    /// CIP class; defaults to the Parameter object (0x0F).
+    #[serde(default = "default_cip_class")]
+    pub class: u16,
+    #[serde(default = "default_cip_attribute")]
+    pub attribute: u8,
+    #[serde(default = "default_ethernet_ip_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ChartsConfig {
+    /// Time span shown on each chart, in seconds.
+    #[serde(default = "default_chart_window_secs")]
+    pub window_secs: i64,
+    /// Output path per chart; must contain `{signal}`.
+    #[serde(default = "default_chart_path_template")]
+    pub path_template: String,
+    /// Minutes of stored history loaded into the charts at startup; the
+    /// whole window when unset, nothing when 0.
+    pub preload_minutes: Option<i64>,
+    /// Headroom beyond the data on the value axis, as a fraction of its
+    /// span; see `axis`.
+    #[serde(default)]
+    pub y_padding: f64,
+    /// Fixed value-axis ends per signal, in display units, e.g.
+    /// `heat = { min = 20.0, max = 120.0 }`.
+    #[serde(default)]
+    pub y_range: BTreeMap<String, AxisRange>,
+    /// Seconds between redraws per signal, e.g. `{ power = 5, cycles = 60 }`;
+    /// a chart not listed is redrawn whenever the charts are rendered.
+    #[serde(default)]
+    pub every_secs: BTreeMap<String, u64>,
+    /// Keep the charts in memory and draw only new samples onto them; see
+    /// `charts`.
+    #[serde(default)]
+    pub incremental: bool,
+    /// Charts to draw, `[[charts.chart]]`; one per signal, named after it,
+    /// when empty. See `chart_defs`.
+    #[serde(default)]
+    pub chart: Vec<ChartConfig>,
+}
+
+/// A chart declared in the configuration.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ChartConfig {
+    /// Unique name, used for `{signal}` in the output path.
+    pub name: String,
+    /// Signal drawn: power, torque, speed, heat or cycles.
+    pub signal: String,
+    /// Time span shown, in seconds; at most, and by default,
+    /// `charts.window_secs`.
+    pub window_secs: Option<i64>,
+    /// Image size in pixels, `[width, height]`.
+    #[serde(default = "default_chart_size")]
+    pub size: (u32, u32),
+    /// Output path; `charts.path_template` when unset.
+    pub path_template: Option<String>,
+    /// Seconds between redraws; `charts.every_secs` of the signal when unset.
+    pub every_secs: Option<u64>,
+}
+
+/// Ends of a value axis; an unset end follows the data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AxisRange {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// How timestamps and numbers are shown on chart axes and in CSV exports.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FormatConfig {
+    /// strftime pattern for the time axis, e.g. `%H:%M`.
+    #[serde(default = "default_time_format")]
+    pub time_format: String,
+    /// strftime pattern for date breaks and exported timestamps.
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    #[serde(default)]
+    pub locale: NumberLocale,
+    #[serde(default)]
+    pub group_thousands: bool,
+    /// Machine-readable timestamps in CSV exports, REST responses, MQTT JSON
+    /// payloads and logs; GraphQL keeps epoch milliseconds.
+    #[serde(default)]
+    pub timestamps: TimestampStyle,
+    /// Zone of `location.timezone`, set when the configuration is loaded.
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub zone: location::Zone,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        FormatConfig {
+            time_format: default_time_format(),
+            date_format: default_date_format(),
+            locale: NumberLocale::default(),
+            group_thousands: false,
+            timestamps: TimestampStyle::default(),
+            zone: location::Zone::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampStyle {
+    /// Milliseconds since the Unix epoch, e.g. `1700000000000`.
+    #[default]
+    EpochMs,
+    /// RFC 3339 in local time with offset, e.g. `2023-11-14T23:13:20.000+01:00`.
+    Rfc3339,
+}
+
+/// Decimal and grouping separators, by language.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberLocale {
+    #[default]
+    En,
+    De,
+    Es,
+    Fr,
+    It,
+    Nl,
+    Pl,
+    Sv,
+}
+
+/// High-limit alarm thresholds per signal. Unset thresholds are derived
+/// from `[motor]` unless `auto = false` (see `limits`).
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    #[serde(default = "default_true")]
+    pub auto: bool,
+    /// How long a limit must be exceeded before alarming.
+    #[serde(default = "default_limit_duration_secs")]
+    pub duration_secs: i64,
+    /// Seconds after a motor start during which limits do not alarm, for
+    /// inrush and start-up transients; overridden per signal.
+    #[serde(default)]
+    pub startup_grace_secs: i64,
+    /// Speed above which the motor counts as started; a start is the first
+    /// sample above it after one at or below it.
+    #[serde(default = "default_start_speed_rpm")]
+    pub start_speed_rpm: f64,
+    pub power: Option<Threshold>,
+    pub torque: Option<Threshold>,
+    pub speed: Option<Threshold>,
+    pub heat: Option<Threshold>,
+    pub cycles: Option<Threshold>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            auto: true,
+            duration_secs: default_limit_duration_secs(),
+            startup_grace_secs: 0,
+            start_speed_rpm: default_start_speed_rpm(),
+            power: None,
+            torque: None,
+            speed: None,
+            heat: None,
+            cycles: None,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn threshold(&self, signal: Signal) -> Option<&Threshold> {
+        match signal {
+            Signal::Power => self.power.as_ref(),
+            Signal::Torque => self.torque.as_ref(),
+            Signal::Speed => self.speed.as_ref(),
+            Signal::Heat => self.heat.as_ref(),
+            Signal::Cycles => self.cycles.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    pub warn: Option<f64>,
+    pub alarm: Option<f64>,
+    /// Overrides `limits.startup_grace_secs` for this signal.
+    pub startup_grace_secs: Option<i64>,
+}
+
+/// CPU and memory budgets; unset budgets are not enforced.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GovernorConfig {
+    pub cpu_percent: Option<f64>,
+    pub memory_mb: Option<f64>,
+    #[serde(default = "default_governor_check_interval_secs")]
+    pub check_interval_secs: u64,
+    /// Render interval, in poll ticks, at the highest degradation level.
+    #[serde(default = "default_governor_max_render_every")]
+    pub max_render_every: u64,
+    /// Storage batch size, in samples, at the highest degradation level.
+    #[serde(default = "default_governor_max_batch_size")]
+    pub max_batch_size: u32,
+}
+
+/// Queues between the poller and each sink, and how each sink recovers
+/// from failures; see `pipeline` and `sink`.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SinksConfig {
+    /// Sample batches on their way to the database.
+    #[serde(default)]
+    pub storage: SinkConfig,
+    /// Closed statistics windows on their way to the database.
+    #[serde(default)]
+    pub stats: SinkConfig,
+    /// Samples on their way to the MQTT broker.
+    #[serde(default)]
+    pub mqtt: SinkConfig,
+    /// Wake-ups of the NATS sink, which publishes from the database; drops
+    /// the oldest when the section is absent.
+    #[serde(default = "default_nats_sink")]
+    pub nats: SinkConfig,
+    /// Samples on their way to the charts; drops the oldest and skips a
+    /// failed render when the section is absent.
+    #[serde(default = "default_charts_sink")]
+    pub charts: SinkConfig,
+    /// Samples printed as JSON lines on standard output; disabled when
+    /// absent.
+    pub stdout: Option<SinkConfig>,
+}
+
+impl Default for SinksConfig {
+    fn default() -> Self {
+        SinksConfig {
+            storage: SinkConfig::default(),
+            stats: SinkConfig::default(),
+            mqtt: SinkConfig::default(),
+            nats: default_nats_sink(),
+            charts: default_charts_sink(),
+            stdout: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SinkConfig {
+    /// Items (batches, for storage) queued before `overflow` applies.
+    #[serde(default = "default_sink_capacity")]
+    pub capacity: usize,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+    /// Directory for `spill_to_disk` files.
+    #[serde(default = "default_spill_dir")]
+    pub spill_dir: String,
+    /// What happens to an item the sink failed to write.
+    #[serde(default)]
+    pub on_error: RestartPolicy,
+    /// Longest wait between retries; the wait doubles from 1 s.
+    #[serde(default = "default_sink_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+}
+
+impl Default for SinkConfig {
+    fn default() -> Self {
+        SinkConfig {
+            capacity: default_sink_capacity(),
+            overflow: OverflowPolicy::default(),
+            spill_dir: default_spill_dir(),
+            on_error: RestartPolicy::default(),
+            max_backoff_secs: default_sink_max_backoff_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    /// Reopen the sink and write the item again, backing off between tries.
+    #[default]
+    Retry,
+    /// Reopen the sink and drop the item.
+    Skip,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait for room; polling stalls until the sink catches up.
+    Block,
+    /// Discard the oldest queued item.
+    DropOldest,
+    /// Append to a file and replay it once the sink catches up.
+    #[default]
+    SpillToDisk,
+}
+
+/// Aggregations computed for one signal in every statistics window, on top
+/// of min/max/avg/stddev.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RollupConfig {
+    /// Percentiles (0–100), stored as `p<n>`, e.g. `p95`.
+    #[serde(default)]
+    pub percentiles: Vec<f64>,
+    /// Thresholds; seconds above each are stored as `time_above_<threshold>`.
+    #[serde(default)]
+    pub time_above: Vec<f64>,
+    /// Energy integral in kWh (`energy_kwh`); power only.
+    #[serde(default)]
+    pub energy: bool,
+}
+
+/// HTTP API settings; the API is disabled unless `listen` is set.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ApiConfig {
+    pub listen: Option<SocketAddr>,
+    /// Arrow Flight (gRPC) server for analytic clients; disabled when absent.
+    pub flight_listen: Option<SocketAddr>,
+    /// Certificate of both listeners; plain HTTP and gRPC when absent.
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub exports: ExportJobsConfig,
+    #[serde(default)]
+    pub queries: QueryGateConfig,
+}
+
+/// Server certificate; see `tls`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first.
+    pub cert: PathBuf,
+    /// PEM private key of the certificate.
+    pub key: PathBuf,
+}
+
+/// TLS towards a broker; see `tls`.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ClientTlsConfig {
+    /// PEM certificates the broker is verified against; the system roots
+    /// when absent.
+    pub ca: Option<PathBuf>,
+    /// PEM client certificate, for brokers that require one; needs `key`.
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+}
+
+/// Limits of `POST /exports` background jobs; see `export_jobs`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ExportJobsConfig {
+    /// Exports reading the database at once, inline `GET /export` included.
+    #[serde(default = "default_exports_max_concurrent")]
+    pub max_concurrent: usize,
+    /// Jobs queued or running at once; further submissions are refused.
+    #[serde(default = "default_exports_max_queued")]
+    pub max_queued: usize,
+    /// Directory the results are written to.
+    #[serde(default = "default_exports_dir")]
+    pub dir: String,
+    /// How long a finished job and its file are kept.
+    #[serde(default = "default_exports_keep_minutes")]
+    pub keep_minutes: u64,
+}
+
+/// Limits of API reads on the database; see `query_gate`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct QueryGateConfig {
+    /// Read-only connections the API reads through, apart from the
+    /// connections of the poll loop.
+    #[serde(default = "default_query_connections")]
+    pub connections: u32,
+    /// Requests a client may make per minute; 0 disables the limit.
+    #[serde(default = "default_query_requests_per_minute")]
+    pub requests_per_minute: u32,
+    /// Requests a client may make at once on top of the rate, e.g. when a
+    /// dashboard loads all its panels.
+    #[serde(default = "default_query_burst")]
+    pub burst: u32,
+    /// How long the latest sample is answered from memory.
+    #[serde(default = "default_query_latest_cache_ms")]
+    pub latest_cache_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RedundancyRole {
+    Primary,
+    Standby,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RedundancyConfig {
+    pub role: RedundancyRole,
+    /// Local UDP address for heartbeats and mirrored samples.
+    pub listen: SocketAddr,
+    /// The other monitor's `listen` address.
+    pub peer: SocketAddr,
+    #[serde(default = "default_node_name")]
+    pub node_name: String,
+    #[serde(default = "default_heartbeat_ms")]
+    pub heartbeat_ms: u64,
+    /// Silence after which the peer is considered dead.
+    #[serde(default = "default_failover_ms")]
+    pub failover_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReportConfig {
+    /// Length of each reporting period; periods are aligned to UTC midnight.
+    #[serde(default = "default_report_interval_hours")]
+    pub interval_hours: u32,
+    /// Output path; see `output` for the available placeholders.
+    #[serde(default = "default_report_path_template")]
+    pub path_template: String,
+}
+
+/// An alarm rule: raises `name` once `condition` has held for
+/// `duration_secs`, and clears it when the condition goes away.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AlarmRuleConfig {
+    pub name: String,
+    pub condition: Condition,
+    #[serde(default)]
+    pub duration_secs: i64,
+    #[serde(default = "default_warning")]
+    pub severity: Severity,
+    /// Alarm message; `{<name>}` is replaced by the current value of a
+    /// signal, `speed_reference` or temperature channel, `{rule}` by the
+    /// rule name. Describes the condition when absent.
+    pub message: Option<String>,
+    /// Taken when the alarm is raised; by default a snapshot when
+    /// `[snapshots]` is configured, nothing otherwise.
+    #[serde(default)]
+    pub actions: Option<Vec<RuleAction>>,
+    /// Seconds after a motor start during which the rule does not alarm;
+    /// see `limits.start_speed_rpm`.
+    #[serde(default)]
+    pub startup_grace_secs: i64,
+}
+
+impl AlarmRuleConfig {
+    /// `actions`, or the default for whether `[snapshots]` is configured.
+    pub fn actions(&self, snapshots: bool) -> Vec<RuleAction> {
+        match &self.actions {
+            Some(actions) => actions.clone(),
+            None if snapshots => vec![RuleAction::Snapshot],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// A comparison of one value, or all / any of several conditions.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Condition {
+    All { all: Vec<Condition> },
+    Any { any: Vec<Condition> },
+    /// `signal` is a signal, `speed_reference` or temperature channel.
+    Compare { signal: String, op: Comparator, value: f64 },
+}
+
+impl Condition {
+    /// Every value name the condition reads.
+    pub fn signals(&self) -> Vec<&str> {
+        match self {
+            Condition::All { all: conditions } | Condition::Any { any: conditions } => {
+                conditions.iter().flat_map(Condition::signals).collect()
+            }
+            Condition::Compare { signal, .. } => vec![signal.as_str()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub enum Comparator {
+    #[serde(rename = ">")]
+    Above,
+    #[serde(rename = ">=")]
+    AtLeast,
+    #[serde(rename = "<")]
+    Below,
+    #[serde(rename = "<=")]
+    AtMost,
+    #[serde(rename = "==")]
+    Equal,
+    #[serde(rename = "!=")]
+    NotEqual,
+}
+
+impl Comparator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Comparator::Above => ">",
+            Comparator::AtLeast => ">=",
+            Comparator::Below => "<",
+            Comparator::AtMost => "<=",
+            Comparator::Equal => "==",
+            Comparator::NotEqual => "!=",
+        }
+    }
+
+    pub fn holds(&self, left: f64, right: f64) -> bool {
+        match self {
+            Comparator::Above => left > right,
+            Comparator::AtLeast => left >= right,
+            Comparator::Below => left < right,
+            Comparator::AtMost => left <= right,
+            Comparator::Equal => left == right,
+            Comparator::NotEqual => left != right,
+        }
+    }
+}
+
+/// What happens besides recording the alarm when a rule raises it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    /// Capture incident charts of the rule's signals; needs `[snapshots]`.
+    Snapshot,
+    /// Also record an `alarm_rule` event.
+    Event,
+}
+
+/// Alarm when |speed reference - actual speed| stays above `max_error_rpm`
+/// for `duration_secs`, a sign of overload, belt slip or drive limits.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TrackingConfig {
+    pub max_error_rpm: f64,
+    #[serde(default = "default_tracking_duration_secs")]
+    pub duration_secs: i64,
+    #[serde(default = "default_warning")]
+    pub severity: Severity,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotConfig {
+    /// How far back each incident chart reaches.
+    #[serde(default = "default_snapshot_minutes")]
+    pub minutes: i64,
+    /// Parent folder of the per-alarm incident folders.
+    #[serde(default = "default_snapshot_dir_template")]
+    pub dir_template: String,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveConfig {
+    /// Raw samples older than this are compacted into the `archive` table.
+    #[serde(default = "default_archive_after_hours")]
+    pub after_hours: i64,
+    #[serde(default = "default_archive_check_interval_minutes")]
+    pub check_interval_minutes: u64,
+    /// zstd compression level (1-22).
+    #[serde(default = "default_archive_level")]
+    pub level: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceConfig {
+    #[serde(default = "default_integrity_check_hours")]
+    pub integrity_check_hours: u64,
+    /// WAL checkpoint interval.
+    #[serde(default = "default_checkpoint_minutes")]
+    pub checkpoint_minutes: u64,
+    #[serde(default = "default_vacuum_hours")]
+    pub vacuum_hours: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BackupConfig {
+    #[serde(default = "default_backup_dir")]
+    pub dir: String,
+    #[serde(default = "default_backup_interval_hours")]
+    pub interval_hours: u64,
+    /// Backups kept in `dir`; older ones are deleted.
+    #[serde(default = "default_backup_keep")]
+    pub keep: usize,
+    /// Program and arguments run after each backup, e.g.
+    /// `["rclone", "copyto", "{file}", "remote:rustsys/{name}"]`.
+    #[serde(default)]
+    pub upload_command: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DiskConfig {
+    /// Where free space is measured; normally the volume holding the database.
+    #[serde(default = "default_disk_path")]
+    pub path: String,
+    #[serde(default = "default_disk_min_free_mb")]
+    pub min_free_mb: u64,
+    /// Size limit for everything under `prune_dirs`.
+    pub quota_mb: Option<u64>,
+    /// Directories of generated files (charts, incidents, reports) whose
+    /// oldest files may be deleted. Nothing is pruned when empty.
+    #[serde(default)]
+    pub prune_dirs: Vec<String>,
+    #[serde(default = "default_disk_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    /// Defaults to `rustsys-<motor_id>`.
+    pub client_id: Option<String>,
+    /// Broker login; see `secrets` to keep the password out of this file.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default = "default_mqtt_keep_alive_secs")]
+    pub keep_alive_secs: u64,
+    #[serde(default)]
+    pub mode: MqttMode,
+    /// Topic for `json` mode; `{site}` and `{motor}` are replaced by the site
+    /// and motor id. Defaults to `rustsys/{site}/{motor}/samples`, or
+    /// `rustsys/{motor}/samples` without a site.
+    pub topic: Option<String>,
+    /// Sparkplug group; defaults to the site, and is required in
+    /// `sparkplug_b` mode without one.
+    pub group_id: Option<String>,
+    /// Sparkplug edge node; defaults to `rustsys`.
+    pub edge_node_id: Option<String>,
+    /// Sparkplug device; defaults to the motor id.
+    pub device_id: Option<String>,
+    /// Connects over TLS (usually port 8883); plain when absent.
+    pub tls: Option<ClientTlsConfig>,
+}
+
+/// JetStream publisher; see `nats`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NatsConfig {
+    /// Server URL, e.g. `nats://nats.local:4222`; may carry a user and
+    /// password, see `secrets`.
+    pub url: String,
+    /// `.creds` file for JWT/NKey authentication.
+    pub credentials_file: Option<PathBuf>,
+    /// `{site}` and `{motor}` are replaced by the site and motor id.
+    /// Defaults to `rustsys.{site}.{motor}.samples`, or
+    /// `rustsys.{motor}.samples` without a site.
+    pub samples_subject: Option<String>,
+    /// Like `samples_subject`, plus `{kind}` for the event kind. Defaults to
+    /// `rustsys.{site}.{motor}.events.{kind}`.
+    pub events_subject: Option<String>,
+    /// Samples or events read from the database per round.
+    #[serde(default = "default_nats_batch_size")]
+    pub batch_size: i64,
+    /// Requires TLS; a `tls://` URL without it uses the system roots.
+    pub tls: Option<ClientTlsConfig>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MqttMode {
+    /// One JSON sample per message.
+    #[default]
+    Json,
+    /// Sparkplug B births and data (protobuf).
+    SparkplugB,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct InterlockConfig {
+    /// Without this the interlock only logs, alarms and audits (dry run).
+    #[serde(default)]
+    pub writes_enabled: bool,
+    /// Holding register (or drive parameter) the stop command is written to.
+    pub stop_register: RegisterDef,
+    pub stop_value: u16,
+    /// How long torque may stay above `motor.peak_torque` before stopping.
+    #[serde(default = "default_overload_secs")]
+    pub overload_secs: i64,
+    /// Hard limit (°C) for heat and every temperature channel.
+    pub max_temperature: Option<f64>,
+}
+
+/// Thermal image of a motor protection relay; see `thermal`.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ThermalConfig {
+    /// Nameplate full-load current I_B (A).
+    pub rated_current: f64,
+    /// Permissible continuous current as a multiple of `rated_current`.
+    #[serde(default = "default_service_factor")]
+    pub service_factor: f64,
+    /// Heating time constant; derived from `trip_class` when absent.
+    pub time_constant_secs: Option<f64>,
+    /// Relay trip class: seconds to trip from cold at 7.2 × the permissible current.
+    #[serde(default = "default_trip_class")]
+    pub trip_class: u32,
+    /// Cooling time constant of a stopped motor, as a multiple of the heating one.
+    #[serde(default = "default_standstill_cooling_factor")]
+    pub standstill_cooling_factor: f64,
+    /// Thermal level (1.0 = trip) that raises a warning.
+    #[serde(default = "default_thermal_warn_level")]
+    pub warn_level: f64,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SetpointConfig {
+    /// Without this, requests are ramped and audited but never written.
+    #[serde(default)]
+    pub writes_enabled: bool,
+    pub speed: Option<SetpointChannel>,
+    pub torque: Option<SetpointChannel>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SetpointChannel {
+    /// Holding register / drive parameter receiving the setpoint.
+    pub register: RegisterDef,
+    /// Engineering units (rpm, Nm) per register count.
+    #[serde(default = "default_setpoint_scale")]
+    pub scale: f64,
+    /// At least 0: the register holds 0 to 65535 counts.
+    #[serde(default)]
+    pub min: f64,
+    /// Defaults to `motor.max_speed` or `motor.peak_torque`; capped at
+    /// 65535 counts times `scale`.
+    pub max: Option<f64>,
+    /// Ramp rate away from zero, in units per second; jumps when unset.
+    pub accel_per_sec: Option<f64>,
+    /// Ramp rate towards zero, in units per second; jumps when unset.
+    pub decel_per_sec: Option<f64>,
+}
+
+/// Operator control panel; see `control`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ControlConfig {
+    /// Without this, confirmed commands are audited but never written.
+    #[serde(default)]
+    pub writes_enabled: bool,
+    /// Operator name → bearer token; holders have the control role. Tokens
+    /// may be secret references, see `secrets`.
+    pub operators: BTreeMap<String, String>,
+    /// Seconds a confirmation token stays valid.
+    #[serde(default = "default_control_confirm_secs")]
+    pub confirm_secs: u64,
+    pub start: Option<CommandRegister>,
+    pub stop: Option<CommandRegister>,
+    pub reset_fault: Option<CommandRegister>,
+    /// Registers written together or not at all, by name; see
+    /// `parameter_sets`.
+    #[serde(default)]
+    pub parameter_sets: BTreeMap<String, ParameterSet>,
+}
+
+/// A command written as a fixed value to a holding register / drive parameter.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CommandRegister {
+    pub register: RegisterDef,
+    pub value: u16,
+}
+
+/// A group of related registers, e.g. a setpoint and its enable bit.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ParameterSet {
+    /// Holding registers and their values, in the order they are written.
+    pub writes: Vec<ParameterWrite>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ParameterWrite {
+    pub address: u16,
+    pub value: u16,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HeatmapConfig {
+    /// Trailing window covered by the heatmap.
+    #[serde(default = "default_heatmap_window_hours")]
+    pub window_hours: i64,
+    #[serde(default = "default_heatmap_every_minutes")]
+    pub every_minutes: u64,
+    #[serde(default = "default_heatmap_load_bins")]
+    pub load_bins: usize,
+    #[serde(default = "default_heatmap_temperature_bins")]
+    pub temperature_bins: usize,
+    #[serde(default = "default_heatmap_path_template")]
+    pub path_template: String,
+}
+
+/// A chart per signal overlaying the trailing `window_hours` with the same
+/// window `offset_hours` earlier; see `comparison`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ComparisonConfig {
+    #[serde(default = "default_comparison_signals")]
+    pub signals: Vec<String>,
+    #[serde(default = "default_comparison_hours")]
+    pub window_hours: i64,
+    /// 24 compares with yesterday, 168 with the same weekday last week, the
+    /// shift length with the last shift.
+    #[serde(default = "default_comparison_hours")]
+    pub offset_hours: i64,
+    #[serde(default = "default_comparison_every_minutes")]
+    pub every_minutes: u64,
+    /// Must contain `{signal}` and differ between entries.
+    #[serde(default = "default_comparison_path_template")]
+    pub path_template: String,
+}
+
+/// Charts per signal at fixed horizons from the window statistics; see
+/// `zoom`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ZoomConfig {
+    #[serde(default = "default_zoom_signals")]
+    pub signals: Vec<String>,
+    /// Horizons such as `15m`, `2h`, `24h` or `7d`.
+    #[serde(default = "default_zoom_horizons")]
+    pub horizons: Vec<String>,
+    #[serde(default = "default_zoom_every_minutes")]
+    pub every_minutes: u64,
+    /// Must contain `{signal}` and `{zoom}`, which is replaced by the horizon.
+    #[serde(default = "default_zoom_path_template")]
+    pub path_template: String,
+}
+
+/// Poll intervals per motor state; see `state`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptivePollingConfig {
+    /// Interval while running or ramping.
+    #[serde(default = "default_running_ms")]
+    pub running_ms: u64,
+    /// Interval while stopped.
+    #[serde(default = "default_stopped_ms")]
+    pub stopped_ms: u64,
+    /// Speed (and speed reference) at or below which the motor is at standstill.
+    #[serde(default = "default_stop_speed_rpm")]
+    pub stop_speed_rpm: f64,
+    /// Standstill time before switching to the stopped interval.
+    #[serde(default = "default_stop_delay_secs")]
+    pub stop_delay_secs: u64,
+    /// Speed change rate above which the motor is ramping.
+    #[serde(default = "default_ramp_rate_rpm_per_sec")]
+    pub ramp_rate_rpm_per_sec: f64,
+    /// Tracking error above which the motor is ramping (needs `registers.speed_reference`).
+    #[serde(default = "default_ramp_error_rpm")]
+    pub ramp_error_rpm: f64,
+}
+
+/// Flatline detection: a watched signal or temperature channel that changes
+/// by no more than `tolerance` for `window_secs` is reported as stale.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StaleConfig {
+    #[serde(default = "default_stale_window_secs")]
+    pub window_secs: i64,
+    /// Signal names (`power`, `speed`, ...) or temperature channel names.
+    #[serde(default = "default_stale_signals")]
+    pub signals: Vec<String>,
+    #[serde(default)]
+    pub tolerance: f64,
+    /// Don't treat a value stuck at exactly 0 as stale (motor stopped).
+    #[serde(default = "default_true")]
+    pub ignore_zero: bool,
+    #[serde(default = "default_warning")]
+    pub severity: Severity,
+}
+
+/// When the motor is expected to run; see `schedule`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub windows: Vec<ScheduleWindow>,
+    /// Speed above which the motor counts as running.
+    #[serde(default = "default_schedule_running_speed_rpm")]
+    pub running_speed_rpm: f64,
+    /// How long actual and expected state must differ before alarming.
+    #[serde(default = "default_schedule_delay_secs")]
+    pub delay_secs: i64,
+    #[serde(default = "default_warning")]
+    pub severity: Severity,
+}
+
+/// Kind of machine the motor drives; sets the affinity-law exponent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EquipmentKind {
+    /// Centrifugal pump: power grows with the cube of the speed.
+    Pump,
+    /// Fan or blower: power grows with the cube of the speed.
+    Fan,
+    /// Positive-displacement compressor: power grows with the speed.
+    Compressor,
+}
+
+/// Expected power of the driven equipment at a given speed; see `equipment`.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EquipmentConfig {
+    pub kind: EquipmentKind,
+    /// Speed of the reference point; `motor.rated_speed` when absent.
+    pub reference_speed_rpm: Option<f64>,
+    /// Power at the reference speed of the healthy machine, in the unit of
+    /// `motor.rated_power`; `motor.rated_power` when absent.
+    pub reference_power: Option<f64>,
+    /// Power grows with the speed to this power; 3 for pumps and fans, 1
+    /// for compressors when absent.
+    pub exponent: Option<f64>,
+    /// Deviation from the expected power, in percent, before alarming.
+    #[serde(default = "default_equipment_tolerance_percent")]
+    pub tolerance_percent: f64,
+    /// Speed below which power is not checked.
+    #[serde(default = "default_equipment_min_speed_rpm")]
+    pub min_speed_rpm: f64,
+    /// How long the deviation must last before alarming.
+    #[serde(default = "default_equipment_delay_secs")]
+    pub delay_secs: i64,
+    #[serde(default = "default_warning")]
+    pub severity: Severity,
+}
+
+/// Scheduled runtime on `days` from `start` to `end` (`HH:MM`, local time).
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleWindow {
+    /// `mon` ... `sun`.
+    pub days: Vec<String>,
+    pub start: String,
+    /// Not after `start`: the window ends on the following day.
+    pub end: String,
+}
+
+/// Utility demand windows and the daily demand profile; see `demand`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DemandConfig {
+    /// Length of a demand window; must divide an hour.
+    #[serde(default = "default_demand_window_minutes")]
+    pub window_minutes: u32,
+    /// Demand profile chart of the day.
+    #[serde(default = "default_demand_path_template")]
+    pub path_template: String,
+}
+
+/// Where the motor runs; see `location`.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LocationConfig {
+    /// IANA time zone, e.g. `Europe/Berlin`; the host's when absent.
+    pub timezone: Option<String>,
+    /// Site or plant name, e.g. `Plant Nord`.
+    pub name: Option<String>,
+    pub line: Option<String>,
+    pub area: Option<String>,
+}
+
+/// Shift definitions and where their summaries go; see `shifts`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ShiftsConfig {
+    pub shift: Vec<ShiftDef>,
+    /// Output path of each summary; see `output` for the placeholders.
+    #[serde(default = "default_shift_path_template")]
+    pub path_template: String,
+}
+
+/// A shift from `start` to `end` (`HH:MM`, local time), every day.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ShiftDef {
+    pub name: String,
+    pub start: String,
+    /// Not after `start`: the shift ends on the following day.
+    pub end: String,
+}
+
+/// Drift fitting for reports; see `trends`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TrendsConfig {
+    /// Trailing window the trends are fitted over.
+    #[serde(default = "default_trends_window_days")]
+    pub window_days: u32,
+    /// Load (percent of rated power) at which temperature is compared.
+    #[serde(default = "default_reference_load_percent")]
+    pub reference_load_percent: f64,
+    /// Hours within this many percent of rated power of the reference load count.
+    #[serde(default = "default_load_band_percent")]
+    pub load_band_percent: f64,
+    /// Efficiency (0–1) treated as end of life; no efficiency RUL when absent.
+    pub min_efficiency: Option<f64>,
+    /// Days with data needed before a trend is fitted.
+    #[serde(default = "default_trends_min_points")]
+    pub min_points: usize,
+}
+
+/// Signal correlation for reports; see `correlation`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CorrelationConfig {
+    /// Signals, `speed_reference` and temperature channels in the matrix.
+    /// Power, torque, speed, heat and every temperature channel when empty.
+    #[serde(default)]
+    pub signals: Vec<String>,
+    /// Pairs whose lag is analysed, leader first: `["power", "heat"]` asks
+    /// how long heat takes to follow power.
+    #[serde(default = "default_lag_pairs")]
+    pub lag_pairs: Vec<[String; 2]>,
+    /// Samples are averaged over buckets of this length before correlating.
+    #[serde(default = "default_correlation_bucket_secs")]
+    pub bucket_secs: u64,
+    /// Longest lag tried, either way.
+    #[serde(default = "default_max_lag_minutes")]
+    pub max_lag_minutes: u64,
+}
+
+/// Register blocks served to a second master over Modbus TCP; see
+/// `modbus_proxy`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ModbusProxyConfig {
+    pub listen: SocketAddr,
+    /// Unit identifier answered; requests for other units are refused.
+    /// Any unit when absent.
+    pub unit_id: Option<u8>,
+    pub blocks: Vec<ProxyBlock>,
+    /// Cached words older than this are refused instead of served.
+    #[serde(default = "default_proxy_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+/// Unix domain socket of the running monitor; see `ipc`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct IpcConfig {
+    /// Path of the socket, e.g. `/run/rustsys/motor-1.sock`.
+    pub socket: PathBuf,
+}
+
+/// Consecutive registers read every poll and served by the proxy.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyBlock {
+    pub address: u16,
+    pub count: u16,
+    /// 0x04 (input registers) or 0x03 (holding registers).
+    #[serde(default = "default_read_function")]
+    pub function: u8,
+}
+
+/// Current waveform capture and fault sideband analysis; see `mcsa`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct McsaConfig {
+    /// First register of the captured waveform (signed 16-bit samples);
+    /// `count` is ignored, `function` selects the read function.
+    pub waveform: RegisterDef,
+    /// Waveform length in registers.
+    #[serde(default = "default_mcsa_samples")]
+    pub samples: u16,
+    /// Rate at which the device sampled the waveform.
+    pub sample_rate_hz: f64,
+    /// Amperes per register count.
+    #[serde(default = "default_mcsa_scale")]
+    pub scale: f64,
+    #[serde(default = "default_line_frequency_hz")]
+    pub line_frequency_hz: f64,
+    /// Derived from `motor.rated_speed` when unset.
+    pub poles: Option<u32>,
+    /// Bearing defect frequencies as multiples of the shaft frequency.
+    #[serde(default)]
+    pub bearing_orders: Vec<f64>,
+    #[serde(default = "default_mcsa_every_minutes")]
+    pub every_minutes: u64,
+    /// Captures are skipped below this load (percent of rated power).
+    #[serde(default = "default_mcsa_min_load_percent")]
+    pub min_load_percent: f64,
+    /// Rotor bar sideband level (dB) that raises `mcsa_rotor_bar`.
+    pub rotor_bar_alarm_db: Option<f64>,
+    /// Bearing sideband level (dB) that raises `mcsa_bearing`.
+    pub bearing_alarm_db: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    /// Path to the plugin's dynamic library (`.so`, `.dylib` or `.dll`).
+    pub path: PathBuf,
+    /// Passed to the plugin's constructor as is.
+    #[serde(default)]
+    #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
+    pub settings: toml::Table,
+}
+
+/// Register addresses for each raw signal read from the controller (input
+/// registers unless an entry sets `function`). With EtherNet/IP, `address`
+/// is the CIP instance (drive parameter number).
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterMap {
+    #[serde(default = "RegisterDef::voltage")]
+    pub voltage: RegisterDef,
+    #[serde(default = "RegisterDef::current")]
+    pub current: RegisterDef,
+    #[serde(default = "RegisterDef::heat")]
+    pub heat: RegisterDef,
+    #[serde(default = "RegisterDef::speed")]
+    pub speed: RegisterDef,
+    /// Commanded speed reference (rpm); optional, not every drive exposes it.
+    pub speed_reference: Option<RegisterDef>,
+    /// Speed estimated from the output frequency, for drives without a
+    /// speed register; `speed` is not read when set. See `soft_sensors`.
+    pub speed_estimate: Option<SpeedEstimate>,
+    /// Named temperature channels (e.g. `winding_u`, `de_bearing`, `ambient`),
+    /// configured as `[registers.temperatures.<name>]`.
+    #[serde(default)]
+    pub temperatures: BTreeMap<String, TemperatureChannel>,
+    /// Pulse counters of auxiliary IO modules (encoders, flow meters),
+    /// read as rates; configured as `[registers.pulses.<name>]`, see `pulses`.
+    #[serde(default)]
+    pub pulses: BTreeMap<String, PulseInput>,
+    /// Display units of signals, temperature channels and pulse inputs,
+    /// configured as `[registers.units.<name>]`; see `units`.
+    #[serde(default)]
+    pub units: BTreeMap<String, UnitConfig>,
+    /// Raw values meaning a sensor fault, per register, configured as
+    /// `[registers.sentinels.<name>]`; see `sentinels`.
+    #[serde(default)]
+    pub sentinels: BTreeMap<String, SentinelConfig>,
+}
+
+/// What to do when a register reads a fault sentinel; see `sentinels`.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SentinelConfig {
+    /// Raw register values meaning a sensor fault.
+    #[serde(default = "default_sentinel_values")]
+    pub values: Vec<u32>,
+    #[serde(default)]
+    pub action: SentinelAction,
+    /// Substitute with `action = "default"`.
+    pub default: Option<f64>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SentinelAction {
+    /// Repeat the last value that was not a sentinel.
+    #[default]
+    LastGood,
+    /// Use `default`.
+    Default,
+    /// Fail the poll, as if the register could not be read.
+    CommFail,
+}
+
+/// Induction motor speed from the drive's output frequency; see `soft_sensors`.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SpeedEstimate {
+    /// Output frequency (Hz).
+    pub frequency: RegisterDef,
+    /// Number of poles, e.g. 4 for 1500 rpm at 50 Hz.
+    pub poles: u32,
+    /// Synchronous speed at rated frequency minus the nameplate speed.
+    #[serde(default)]
+    pub rated_slip_rpm: f64,
+    /// Nameplate current; scales the slip with the load when set.
+    pub rated_current_a: Option<f64>,
+}
+
+/// Unit a signal is shown in: the stored base-unit value times `scale`.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UnitConfig {
+    pub unit: String,
+    #[serde(default = "default_unit_scale")]
+    pub scale: f64,
+}
+
+/// A temperature input (°C) with optional warning and alarm thresholds.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TemperatureChannel {
+    pub address: u16,
+    #[serde(default = "default_register_count")]
+    pub count: u16,
+    /// Modbus read function; see `RegisterDef::function`.
+    #[serde(default = "default_read_function")]
+    pub function: u8,
+    pub warn: Option<f64>,
+    pub alarm: Option<f64>,
+    /// How long a threshold must be exceeded before alarming.
+    #[serde(default = "default_temperature_duration_secs")]
+    pub duration_secs: i64,
+    /// Seconds after a motor start during which the thresholds do not
+    /// alarm; see `limits.start_speed_rpm`.
+    #[serde(default)]
+    pub startup_grace_secs: i64,
+    /// Former names of this channel; values stored under them read under
+    /// this name. See `aliases`.
+    #[serde(default)]
+    pub renamed_from: Vec<String>,
+}
+
+impl TemperatureChannel {
+    pub fn register(&self) -> RegisterDef {
+        RegisterDef { address: self.address, count: self.count, function: self.function }
+    }
+}
+
+/// A wrapping pulse counter whose rate is a signal.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PulseInput {
+    /// Modbus unit id of the IO module; the drive's when absent.
+    pub unit_id: Option<u8>,
+    pub address: u16,
+    /// 1 for a 16-bit counter, 2 for a 32-bit one.
+    #[serde(default = "default_register_count")]
+    pub count: u16,
+    /// Modbus read function; see `RegisterDef::function`.
+    #[serde(default = "default_read_function")]
+    pub function: u8,
+    /// Engineering units per Hz, e.g. 0.06 for m/min from 1000 pulses per metre.
+    #[serde(default = "default_unit_scale")]
+    pub scale: f64,
+    /// Unit of the scaled rate, e.g. `m/min`.
+    #[serde(default = "default_pulse_unit")]
+    pub unit: String,
+    /// Largest increase, in pulses, a drop in the raw value may stand for
+    /// before it counts as a reset rather than a wrap; half the register
+    /// range when absent.
+    pub max_step: Option<u64>,
+    /// Former names of this input; values stored under them read under
+    /// this name. See `aliases`.
+    #[serde(default)]
+    pub renamed_from: Vec<String>,
+}
+
+impl PulseInput {
+    pub fn register(&self) -> RegisterDef {
+        RegisterDef { address: self.address, count: self.count, function: self.function }
+    }
+
+    pub fn bits(&self) -> u32 {
+        16 * self.count as u32
+    }
+
+    pub fn max_step(&self) -> u64 {
+        self.max_step.unwrap_or(1 << (self.bits() - 1))
+    }
+}
+
+/// The drive's fault queue; see `faults`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FaultHistoryConfig {
+    /// First register of the queue, one fault code per register.
+    pub address: u16,
+    /// Number of registers in the queue.
+    pub entries: u16,
+    /// Modbus read function; see `RegisterDef::function`.
+    #[serde(default = "default_read_function")]
+    pub function: u8,
+    /// Whether the first register holds the latest fault; false for drives
+    /// that fill the queue from the end.
+    #[serde(default = "default_true")]
+    pub newest_first: bool,
+    /// Built-in fault texts of a drive family.
+    #[serde(default)]
+    pub profile: FaultProfile,
+    /// Fault texts by code, e.g. `{ "7" = "Motor Overload" }`; take
+    /// precedence over the profile's.
+    #[serde(default)]
+    pub codes: BTreeMap<String, String>,
+    #[serde(default = "default_fault_history_every_secs")]
+    pub every_secs: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultProfile {
+    /// No built-in texts; only `codes`.
+    #[default]
+    Generic,
+    /// Allen-Bradley PowerFlex 525.
+    #[serde(rename = "powerflex_525")]
+    Powerflex525,
+}
+
+/// Wrapping cumulative registers; see `counters`.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CountersConfig {
+    /// Configured as `[counters.registers.<name>]`.
+    pub registers: BTreeMap<String, CounterRegister>,
+    #[serde(default = "default_counters_every_secs")]
+    pub every_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CounterRegister {
+    pub address: u16,
+    /// 1 for a 16-bit counter, 2 for a 32-bit one.
+    #[serde(default = "default_register_count")]
+    pub count: u16,
+    /// Modbus read function; see `RegisterDef::function`.
+    #[serde(default = "default_read_function")]
+    pub function: u8,
+    /// Units per count, e.g. 0.1 for a counter in tenths of a kWh.
+    #[serde(default = "default_unit_scale")]
+    pub scale: f64,
+    /// Unit of the total, e.g. `kWh` or `h`; for display only.
+    #[serde(default)]
+    pub unit: String,
+    /// Largest increase, in counts, a drop in the raw value may stand for
+    /// before it counts as a reset rather than a wrap; half the register
+    /// range when absent.
+    pub max_step: Option<u64>,
+}
+
+impl CounterRegister {
+    pub fn register(&self) -> RegisterDef {
+        RegisterDef { address: self.address, count: self.count, function: self.function }
+    }
+
+    pub fn bits(&self) -> u32 {
+        16 * self.count as u32
+    }
+
+    pub fn max_step(&self) -> u64 {
+        self.max_step.unwrap_or(1 << (self.bits() - 1))
+    }
+}
+
+/// Auxiliary sensors read over the bus; see `environment`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentConfig {
+    /// Configured as `[environment.sensors.<name>]`.
+    pub sensors: BTreeMap<String, EnvironmentSensor>,
+    #[serde(default = "default_environment_every_secs")]
+    pub every_secs: u64,
+    /// Temperature sensor the motor's temperature rise is measured against;
+    /// the first temperature sensor when absent.
+    pub ambient: Option<String>,
+}
+
+impl EnvironmentConfig {
+    pub fn ambient_sensor(&self) -> Option<&str> {
+        self.ambient.as_deref().or_else(|| {
+            self.sensors
+                .iter()
+                .find(|(_, sensor)| sensor.quantity == EnvironmentQuantity::Temperature)
+                .map(|(name, _)| name.as_str())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentSensor {
+    /// Modbus unit id of the sensor; must differ from `serial.slave_id`.
+    pub unit_id: u8,
+    pub quantity: EnvironmentQuantity,
+    pub address: u16,
+    #[serde(default = "default_register_count")]
+    pub count: u16,
+    /// Modbus read function; see `RegisterDef::function`.
+    #[serde(default = "default_read_function")]
+    pub function: u8,
+    /// Factor from the register value, e.g. 0.1 for tenths of a degree.
+    #[serde(default = "default_unit_scale")]
+    pub scale: f64,
+}
+
+impl EnvironmentSensor {
+    pub fn register(&self) -> RegisterDef {
+        RegisterDef { address: self.address, count: self.count, function: self.function }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvironmentQuantity {
+    Temperature,
+    Humidity,
+}
+
+impl EnvironmentQuantity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnvironmentQuantity::Temperature => "temperature",
+            EnvironmentQuantity::Humidity => "humidity",
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            EnvironmentQuantity::Temperature => "°C",
+            EnvironmentQuantity::Humidity => "%RH",
+        }
+    }
+}
+
+/// How written registers are checked; see `transport::VerifiedWrites`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WriteVerifyConfig {
+    /// Largest accepted difference between the written and the read-back
+    /// register value, in counts, for drives that store a scaled setpoint
+    /// with their own rounding.
+    #[serde(default)]
+    pub tolerance: u16,
+    /// Wait between the write and the read-back, for drives that apply
+    /// writes with a delay.
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterDef {
+    pub address: u16,
+    /// Number of consecutive 16-bit registers; 2 reads a big-endian 32-bit value.
+    #[serde(default = "default_register_count")]
+    pub count: u16,
+    /// Modbus function used to read the value: 0x04 (input registers), 0x03
+    /// (holding registers), or a user-defined code (0x41–0x48, 0x64–0x6E)
+    /// for vendor functions; see `transport::ModbusRtu`. Modbus RTU only;
+    /// single writes use 0x06, parameter sets 0x10.
+    #[serde(default = "default_read_function")]
+    pub function: u8,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        SerialConfig {
+            path: default_serial_path(),
+            baud_rate: default_baud_rate(),
+            slave_id: default_slave_id(),
+            parity: Parity::None,
+            usb: None,
+        }
+    }
+}
+
+impl Default for ChartsConfig {
+    fn default() -> Self {
+        ChartsConfig {
+            window_secs: default_chart_window_secs(),
+            path_template: default_chart_path_template(),
+            preload_minutes: None,
+            y_padding: 0.0,
+            y_range: BTreeMap::new(),
+            every_secs: BTreeMap::new(),
+            incremental: false,
+            chart: Vec::new(),
+        }
+    }
+}
+
+impl Default for ExportJobsConfig {
+    fn default() -> Self {
+        ExportJobsConfig {
+            max_concurrent: default_exports_max_concurrent(),
+            max_queued: default_exports_max_queued(),
+            dir: default_exports_dir(),
+            keep_minutes: default_exports_keep_minutes(),
+        }
+    }
+}
+
+impl Default for QueryGateConfig {
+    fn default() -> Self {
+        QueryGateConfig {
+            connections: default_query_connections(),
+            requests_per_minute: default_query_requests_per_minute(),
+            burst: default_query_burst(),
+            latest_cache_ms: default_query_latest_cache_ms(),
+        }
+    }
+}
+
+impl Default for GovernorConfig {
+    fn default() -> Self {
+        GovernorConfig {
+            cpu_percent: None,
+            memory_mb: None,
+            check_interval_secs: default_governor_check_interval_secs(),
+            max_render_every: default_governor_max_render_every(),
+            max_batch_size: default_governor_max_batch_size(),
+        }
+    }
+}
+
+impl Default for RegisterMap {
+    fn default() -> Self {
+        RegisterMap {
+            voltage: RegisterDef::voltage(),
+            current: RegisterDef::current(),
+            heat: RegisterDef::heat(),
+            speed: RegisterDef::speed(),
+            speed_reference: None,
+            speed_estimate: None,
+            temperatures: BTreeMap::new(),
+            pulses: BTreeMap::new(),
+            units: BTreeMap::new(),
+            sentinels: BTreeMap::new(),
+        }
+    }
+}
+
+impl RegisterMap {
+    /// All register definitions paired with their dotted config key.
+    pub fn entries(&self) -> Vec<(String, RegisterDef)> {
+        let mut entries = vec![
+            ("registers.voltage".to_string(), self.voltage),
+            ("registers.current".to_string(), self.current),
+            ("registers.heat".to_string(), self.heat),
+        ];
+        match &self.speed_estimate {
+            Some(estimate) => entries.push(("registers.speed_estimate.frequency".to_string(), estimate.frequency)),
+            None => entries.push(("registers.speed".to_string(), self.speed)),
+        }
+        if let Some(def) = self.speed_reference {
+            entries.push(("registers.speed_reference".to_string(), def));
+        }
+        for (name, channel) in &self.temperatures {
+            entries.push((format!("registers.temperatures.{}", name), channel.register()));
+        }
+        entries
+    }
+}
+
+impl RegisterDef {
+    fn at(address: u16) -> Self {
+        RegisterDef { address, count: 1, function: transport::READ_INPUT_REGISTERS }
+    }
+
+    fn voltage() -> Self {
+        Self::at(0)
+    }
+
+    fn current() -> Self {
+        Self::at(1)
+    }
+
+    fn heat() -> Self {
+        Self::at(2)
+    }
+
+    fn speed() -> Self {
+        Self::at(3)
+    }
+
+    /// Exclusive end address of the block this definition occupies.
+    pub fn end(&self) -> u32 {
+        self.address as u32 + self.count as u32
+    }
+
+    /// Whether reads of `self` and `other` address the same register table
+    /// (holding and input registers are separate; vendor functions are
+    /// assumed to share one per code).
+    pub fn same_table(&self, other: &RegisterDef) -> bool {
+        self.function == other.function
+    }
+}
+
+fn default_database_url() -> String {
+    "sqlite://motor_data.db".to_string()
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_motor_id() -> String {
+    "motor-1".to_string()
+}
+
+fn default_serial_path() -> String {
+    "/dev/ttyUSB0".to_string()
+}
+
+fn default_baud_rate() -> u32 {
+    9600
+}
+
+fn default_slave_id() -> u8 {
+    1
+}
+
+fn default_cip_class() -> u16 {
+    0x0F
+}
+
+fn default_cip_attribute() -> u8 {
+    1
+}
+
+fn default_ethernet_ip_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_node_name() -> String {
+    "rustsys".to_string()
+}
+
+fn default_heartbeat_ms() -> u64 {
+    500
+}
+
+fn default_failover_ms() -> u64 {
+    3000
+}
+
+fn default_report_interval_hours() -> u32 {
+    24
+}
+
+fn default_report_path_template() -> String {
+    "reports/{motor}/report_{date}_{time}.md".to_string()
+}
+
+fn default_shift_path_template() -> String {
+    "shifts/{motor}/shift_{date}_{time}.md".to_string()
+}
+
+fn default_sentinel_values() -> Vec<u32> {
+    vec![0xFFFF, 0x8000]
+}
+
+fn default_demand_window_minutes() -> u32 {
+    15
+}
+
+fn default_demand_path_template() -> String {
+    "charts/{motor}/demand_{date}.png".to_string()
+}
+
+fn default_tracking_duration_secs() -> i64 {
+    10
+}
+
+fn default_warning() -> Severity {
+    Severity::Warning
+}
+
+fn default_archive_after_hours() -> i64 {
+    24 * 7
+}
+
+fn default_archive_check_interval_minutes() -> u64 {
+    60
+}
+
+fn default_archive_level() -> i32 {
+    9
+}
+
+fn default_integrity_check_hours() -> u64 {
+    24
+}
+
+fn default_checkpoint_minutes() -> u64 {
+    15
+}
+
+fn default_vacuum_hours() -> u64 {
+    24 * 7
+}
+
+fn default_service_factor() -> f64 {
+    1.0
+}
+
+fn default_trip_class() -> u32 {
+    10
+}
+
+fn default_standstill_cooling_factor() -> f64 {
+    3.0
+}
+
+fn default_thermal_warn_level() -> f64 {
+    0.9
+}
+
+fn default_proxy_max_age_secs() -> u64 {
+    10
+}
+
+fn default_unit_scale() -> f64 {
+    1.0
+}
+
+fn default_chart_size() -> (u32, u32) {
+    super::CHART_SIZE
+}
+
+fn default_counters_every_secs() -> u64 {
+    60
+}
+
+fn default_fault_history_every_secs() -> u64 {
+    30
+}
+
+fn default_environment_every_secs() -> u64 {
+    60
+}
+
+fn default_backup_dir() -> String {
+    "backups".to_string()
+}
+
+fn default_backup_interval_hours() -> u64 {
+    24
+}
+
+fn default_backup_keep() -> usize {
+    7
+}
+
+fn default_overload_secs() -> i64 {
+    5
+}
+
+fn default_time_format() -> String {
+    "%H:%M:%S".to_string()
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_string()
+}
+
+fn default_heatmap_window_hours() -> i64 {
+    24
+}
+
+fn default_heatmap_every_minutes() -> u64 {
+    15
+}
+
+fn default_heatmap_load_bins() -> usize {
+    15
+}
+
+fn default_heatmap_temperature_bins() -> usize {
+    20
+}
+
+fn default_heatmap_path_template() -> String {
+    "heatmap_{motor}.png".to_string()
+}
+
+fn default_comparison_signals() -> Vec<String> {
+    ["power", "heat"].iter().map(|s| s.to_string()).collect()
+}
+
+fn default_comparison_hours() -> i64 {
+    24
+}
+
+fn default_comparison_every_minutes() -> u64 {
+    15
+}
+
+fn default_comparison_path_template() -> String {
+    "compare_{motor}_{signal}.png".to_string()
+}
+
+fn default_zoom_signals() -> Vec<String> {
+    Signal::ALL.iter().map(|s| s.name().to_string()).collect()
+}
+
+fn default_zoom_horizons() -> Vec<String> {
+    ["15m", "2h", "24h", "7d"].iter().map(|s| s.to_string()).collect()
+}
+
+fn default_zoom_every_minutes() -> u64 {
+    1
+}
+
+fn default_zoom_path_template() -> String {
+    "charts/{motor}/{signal}_{zoom}.png".to_string()
+}
+
+fn default_running_ms() -> u64 {
+    200
+}
+
+fn default_stopped_ms() -> u64 {
+    10_000
+}
+
+fn default_stop_speed_rpm() -> f64 {
+    5.0
+}
+
+fn default_stop_delay_secs() -> u64 {
+    10
+}
+
+fn default_ramp_rate_rpm_per_sec() -> f64 {
+    20.0
+}
+
+fn default_ramp_error_rpm() -> f64 {
+    30.0
+}
+
+fn default_exports_max_concurrent() -> usize {
+    2
+}
+
+fn default_exports_max_queued() -> usize {
+    8
+}
+
+fn default_exports_dir() -> String {
+    "exports".to_string()
+}
+
+fn default_exports_keep_minutes() -> u64 {
+    60
+}
+
+fn default_query_connections() -> u32 {
+    2
+}
+
+fn default_query_requests_per_minute() -> u32 {
+    600
+}
+
+fn default_query_burst() -> u32 {
+    30
+}
+
+fn default_query_latest_cache_ms() -> u64 {
+    1000
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_nats_batch_size() -> i64 {
+    500
+}
+
+fn default_mqtt_keep_alive_secs() -> u64 {
+    30
+}
+
+fn default_disk_path() -> String {
+    ".".to_string()
+}
+
+fn default_disk_min_free_mb() -> u64 {
+    500
+}
+
+fn default_disk_check_interval_secs() -> u64 {
+    60
+}
+
+fn default_setpoint_scale() -> f64 {
+    1.0
+}
+
+fn default_pulse_unit() -> String {
+    "Hz".to_string()
+}
+
+fn default_control_confirm_secs() -> u64 {
+    30
+}
+
+fn default_sink_capacity() -> usize {
+    64
+}
+
+fn default_spill_dir() -> String {
+    "spill".to_string()
+}
+
+fn default_sink_max_backoff_secs() -> u64 {
+    60
+}
+
+fn default_nats_sink() -> SinkConfig {
+    SinkConfig { overflow: OverflowPolicy::DropOldest, ..SinkConfig::default() }
+}
+
+fn default_charts_sink() -> SinkConfig {
+    SinkConfig { overflow: OverflowPolicy::DropOldest, on_error: RestartPolicy::Skip, ..SinkConfig::default() }
+}
+
+fn default_stale_window_secs() -> i64 {
+    60
+}
+
+fn default_stale_signals() -> Vec<String> {
+    ["power", "speed", "heat"].iter().map(|s| s.to_string()).collect()
+}
+
+fn default_schedule_running_speed_rpm() -> f64 {
+    5.0
+}
+
+fn default_schedule_delay_secs() -> i64 {
+    300
+}
+
+fn default_equipment_tolerance_percent() -> f64 {
+    15.0
+}
+
+fn default_equipment_min_speed_rpm() -> f64 {
+    300.0
+}
+
+fn default_equipment_delay_secs() -> i64 {
+    120
+}
+
+fn default_trends_window_days() -> u32 {
+    28
+}
+
+fn default_reference_load_percent() -> f64 {
+    80.0
+}
+
+fn default_load_band_percent() -> f64 {
+    10.0
+}
+
+fn default_trends_min_points() -> usize {
+    7
+}
+
+fn default_lag_pairs() -> Vec<[String; 2]> {
+    vec![["power".to_string(), "heat".to_string()], ["speed".to_string(), "power".to_string()]]
+}
+
+fn default_correlation_bucket_secs() -> u64 {
+    60
+}
+
+fn default_max_lag_minutes() -> u64 {
+    60
+}
+
+fn default_mcsa_samples() -> u16 {
+    2048
+}
+
+fn default_mcsa_scale() -> f64 {
+    0.01
+}
+
+fn default_line_frequency_hz() -> f64 {
+    50.0
+}
+
+fn default_mcsa_every_minutes() -> u64 {
+    60
+}
+
+fn default_mcsa_min_load_percent() -> f64 {
+    50.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_limit_duration_secs() -> i64 {
+    5
+}
+
+fn default_start_speed_rpm() -> f64 {
+    5.0
+}
+
+fn default_snapshot_minutes() -> i64 {
+    10
+}
+
+fn default_snapshot_dir_template() -> String {
+    "incidents/{motor}/{date}".to_string()
+}
+
+fn default_register_count() -> u16 {
+    1
+}
+
+fn default_read_function() -> u8 {
+    transport::READ_INPUT_REGISTERS
+}
+
+fn default_temperature_duration_secs() -> i64 {
+    5
+}
+
+fn default_chart_window_secs() -> i64 {
+    600
+}
+
+fn default_chart_path_template() -> String {
+    "current_{signal}.png".to_string()
+}
+
+fn default_governor_check_interval_secs() -> u64 {
+    10
+}
+
+fn default_governor_max_render_every() -> u64 {
+    30
+}
+
+fn default_governor_max_batch_size() -> u32 {
+    60
+}
+
+/// A single problem found in the configuration file.
+#[derive(Debug)]
+pub struct ConfigIssue {
+    /// Dotted key path, e.g. `registers.speed.address`.
+    pub key: String,
+    pub message: String,
+    /// 1-based line and column, when the key can be located in the source.
+    pub location: Option<(usize, usize)>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Invalid(String, Vec<ConfigIssue>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "cannot read config file {}: {}", path, err),
+            ConfigError::Invalid(path, issues) => {
+                writeln!(f, "invalid config file {} ({} problem(s)):", path, issues.len())?;
+                for issue in issues {
+                    match issue.location {
+                        Some((line, column)) => write!(f, "  {}:{}:{}: ", path, line, column)?,
+                        None => write!(f, "  {}: ", path)?,
+                    }
+                    if issue.key.is_empty() {
+                        writeln!(f, "{}", issue.message)?;
+                    } else {
+                        writeln!(f, "`{}`: {}", issue.key, issue.message)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Reads, parses and validates the configuration file at `path`.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<MonitorConfig, ConfigError> {
+    let display = path.as_ref().display().to_string();
+    let source = fs::read_to_string(&path).map_err(|e| ConfigError::Io(display.clone(), e))?;
+    let dir = path.as_ref().parent().unwrap_or(Path::new("."));
+    let mut config = parse_in(&source, Some(dir)).map_err(|issues| ConfigError::Invalid(display, issues))?;
+    config.source = Some(path.as_ref().to_path_buf());
+    Ok(config)
+}
+
+/// Parses and validates configuration from TOML source text. Templates are
+/// applied, but `extends` is rejected since there is no file to resolve
+/// paths against (and a signed bundle must not pull in unsigned files).
+pub fn parse(source: &str) -> Result<MonitorConfig, Vec<ConfigIssue>> {
+    parse_in(source, None)
+}
+
+fn parse_in(source: &str, dir: Option<&Path>) -> Result<MonitorConfig, Vec<ConfigIssue>> {
+    let syntax_issue = |e: toml::de::Error| {
+        vec![ConfigIssue {
+            key: String::new(),
+            message: e.message().to_string(),
+            location: e.span().map(|span| line_column(source, span.start)),
+        }]
+    };
+    let table: toml::Table = toml::from_str(source).map_err(syntax_issue)?;
+    let composed = ["extends", "template", "templates"].iter().any(|key| table.contains_key(*key));
+    let (mut config, table): (MonitorConfig, toml::Table) = if composed {
+        let document = toml_edit::ImDocument::parse(source).ok();
+        let issue = |(key, message): (String, String)| {
+            let location = document
+                .as_ref()
+                .and_then(|doc| locate(doc.as_item(), &key))
+                .map(|offset| line_column(source, offset));
+            vec![ConfigIssue { key, message, location }]
+        };
+        let merged = match dir {
+            Some(dir) => resolve_extends(table, dir, &mut Vec::new()),
+            None if table.contains_key("extends") => {
+                Err(("extends".to_string(), "is only supported when loading a config file".to_string()))
+            }
+            None => Ok(table),
+        }
+        .and_then(apply_templates)
+        .map_err(issue)?;
+        // Spans are lost once files are merged, so errors carry no location.
+        let config = toml::Value::Table(merged.clone()).try_into().map_err(|e: toml::de::Error| {
+            vec![ConfigIssue { key: String::new(), message: e.message().to_string(), location: None }]
+        })?;
+        (config, merged)
+    } else {
+        (toml::from_str(source).map_err(syntax_issue)?, table)
+    };
+    config.config_hash = lineage::config_hash(&table);
+
+    let mut issues = secrets::resolve_config(&mut config, dir);
+    issues.extend(validate(&config));
+    if issues.is_empty() {
+        config.format.zone = location::Zone::from_config(&config);
+        return Ok(config);
+    }
+
+    let document = toml_edit::ImDocument::parse(source).ok();
+    Err(issues
+        .into_iter()
+        .map(|(key, message)| {
+            let location = document
+                .as_ref()
+                .and_then(|doc| locate(doc.as_item(), &key))
+                .map(|offset| line_column(source, offset));
+            ConfigIssue { key, message, location }
+        })
+        .collect())
+}
+
+/// Merges the files named by `extends` (relative to `dir`) beneath `table`.
+/// `chain` holds the files being resolved, to detect cycles.
+fn resolve_extends(
+    mut table: toml::Table,
+    dir: &Path,
+    chain: &mut Vec<PathBuf>,
+) -> Result<toml::Table, (String, String)> {
+    let key = || "extends".to_string();
+    let paths = match table.remove("extends") {
+        None => return Ok(table),
+        Some(value) => string_list(value).ok_or_else(|| (key(), "must be a path or a list of paths".to_string()))?,
+    };
+
+    let mut merged = toml::Table::new();
+    for relative in paths {
+        let path = dir.join(&relative);
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if chain.contains(&canonical) {
+            return Err((key(), format!("{} extends itself", path.display())));
+        }
+        let source =
+            fs::read_to_string(&path).map_err(|e| (key(), format!("cannot read {}: {}", path.display(), e)))?;
+        let base: toml::Table =
+            toml::from_str(&source).map_err(|e| (key(), format!("{}: {}", path.display(), e.message())))?;
+        chain.push(canonical);
+        let base = resolve_extends(base, path.parent().unwrap_or(dir), chain)?;
+        chain.pop();
+        merge_tables(&mut merged, base);
+    }
+    merge_tables(&mut merged, table);
+    Ok(merged)
+}
+
+/// Applies the templates named by `template` beneath the rest of `table`
+/// and drops the `templates` definitions.
+fn apply_templates(mut table: toml::Table) -> Result<toml::Table, (String, String)> {
+    let templates = match table.remove("templates") {
+        None => toml::Table::new(),
+        Some(toml::Value::Table(templates)) => templates,
+        Some(_) => return Err(("templates".to_string(), "must be a table of templates".to_string())),
+    };
+    let names = match table.remove("template") {
+        None => return Ok(table),
+        Some(value) => string_list(value)
+            .ok_or_else(|| ("template".to_string(), "must be a template name or a list of names".to_string()))?,
+    };
+
+    let mut merged = toml::Table::new();
+    for name in names {
+        match templates.get(&name) {
+            Some(toml::Value::Table(template)) => merge_tables(&mut merged, template.clone()),
+            _ => {
+                let known: Vec<&String> = templates.keys().collect();
+                return Err(("template".to_string(), format!("unknown template {:?} (defined: {:?})", name, known)));
+            }
+        }
+    }
+    merge_tables(&mut merged, table);
+    Ok(merged)
+}
+
+fn string_list(value: toml::Value) -> Option<Vec<String>> {
+    match value {
+        toml::Value::String(s) => Some(vec![s]),
+        toml::Value::Array(items) => items.into_iter().map(|v| v.as_str().map(str::to_string)).collect(),
+        _ => None,
+    }
+}
+
+/// Deep-merges `overrides` into `base`: tables merge, other values replace.
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(value)) => merge_tables(existing, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Semantic checks that serde cannot express. Returns `(key, message)` pairs.
+pub fn validate(config: &MonitorConfig) -> Vec<(String, String)> {
+    let mut issues = Vec::new();
+    let mut check = |ok: bool, key: &str, message: String| {
+        if !ok {
+            issues.push((key.to_string(), message));
+        }
+    };
+
+    check(
+        (50..=3_600_000).contains(&config.poll_interval_ms),
+        "poll_interval_ms",
+        format!("must be between 50 and 3600000, got {}", config.poll_interval_ms),
+    );
+    check(
+        config.database_url.starts_with("sqlite:"),
+        "database_url",
+        format!("must be a sqlite: URL, got {:?}", secrets::redact_url(&config.database_url)),
+    );
+
+    check(
+        !config.motor_id.is_empty() && config.motor_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+        "motor_id",
+        format!("must be non-empty and contain only letters, digits, '-' or '_', got {:?}", config.motor_id),
+    );
+
+    if let Some(group) = &config.group {
+        check(!group.trim().is_empty(), "group", "must not be empty".to_string());
+    }
+
+    if let Some(location) = &config.location {
+        if let Some(timezone) = &location.timezone {
+            if let Err(message) = location::Zone::parse(timezone) {
+                check(false, "location.timezone", message);
+            }
+        }
+        for (key, label) in [("name", &location.name), ("line", &location.line), ("area", &location.area)] {
+            if let Some(label) = label {
+                check(!label.trim().is_empty(), &format!("location.{}", key), "must not be empty".to_string());
+            }
+        }
+    }
+
+    if let Some(site) = &config.site {
+        check(
+            !site.is_empty() && site.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+            "site",
+            format!("must be non-empty and contain only letters, digits, '-' or '_', got {:?}", site),
+        );
+    }
+
+    let serial = &config.serial;
+    check(!serial.path.is_empty(), "serial.path", "must not be empty".to_string());
+    if let Some(usb) = &serial.usb {
+        check(
+            usb.serial_number.as_ref().is_none_or(|serial| !serial.is_empty()),
+            "serial.usb.serial_number",
+            "must not be empty".to_string(),
+        );
+    }
+    check(
+        STANDARD_BAUD_RATES.contains(&serial.baud_rate),
+        "serial.baud_rate",
+        format!("must be one of {:?}, got {}", STANDARD_BAUD_RATES, serial.baud_rate),
+    );
+    check(
+        (1..=247).contains(&serial.slave_id),
+        "serial.slave_id",
+        format!("must be between 1 and 247, got {}", serial.slave_id),
+    );
+
+    match (&config.transport, &config.ethernet_ip) {
+        (TransportKind::EthernetIp, None) => check(
+            false,
+            "transport",
+            "is \"ethernet_ip\" but the [ethernet_ip] section is missing".to_string(),
+        ),
+        (_, Some(eip)) => {
+            check(
+                eip.address.parse::<SocketAddr>().is_ok() || eip.address.rsplit_once(':').is_some(),
+                "ethernet_ip.address",
+                format!("must be host:port, got {:?}", eip.address),
+            );
+            check(
+                (100..=60_000).contains(&eip.timeout_ms),
+                "ethernet_ip.timeout_ms",
+                format!("must be between 100 and 60000, got {}", eip.timeout_ms),
+            );
+        }
+        _ => {}
+    }
+
+    let motor = &config.motor;
+    for (key, value) in [
+        ("motor.rated_power", motor.rated_power),
+        ("motor.rated_torque", motor.rated_torque),
+        ("motor.rated_speed", motor.rated_speed),
+        ("motor.peak_torque", motor.peak_torque),
+        ("motor.max_speed", motor.max_speed),
+    ] {
+        check(value.is_finite() && value > 0.0, key, format!("must be a positive number, got {}", value));
+    }
+    check(
+        motor.peak_torque >= motor.rated_torque,
+        "motor.peak_torque",
+        format!("must not be below rated_torque ({})", motor.rated_torque),
+    );
+    check(
+        motor.max_speed >= motor.rated_speed,
+        "motor.max_speed",
+        format!("must not be below rated_speed ({})", motor.rated_speed),
+    );
+
+    check(
+        (10..=7 * 24 * 3600).contains(&config.charts.window_secs),
+        "charts.window_secs",
+        format!("must be between 10 and 604800, got {}", config.charts.window_secs),
+    );
+    check_template(&mut check, "charts.path_template", &config.charts.path_template, true);
+    check(
+        (0.0..=1.0).contains(&config.charts.y_padding),
+        "charts.y_padding",
+        format!("must be between 0 and 1, got {}", config.charts.y_padding),
+    );
+    for (name, range) in &config.charts.y_range {
+        let key = format!("charts.y_range.{}", name);
+        check(Signal::from_name(name).is_some(), &key, format!("unknown signal {:?}", name));
+        check(
+            range.min.is_none_or(f64::is_finite) && range.max.is_none_or(f64::is_finite),
+            &key,
+            "min and max must be finite".to_string(),
+        );
+        if let (Some(min), Some(max)) = (range.min, range.max) {
+            check(min < max, &format!("{}.min", key), format!("must be below max ({})", max));
+        }
+    }
+    for (name, every) in &config.charts.every_secs {
+        let key = format!("charts.every_secs.{}", name);
+        check(Signal::from_name(name).is_some(), &key, format!("unknown signal {:?}", name));
+        check((1..=3600).contains(every), &key, format!("must be between 1 and 3600, got {}", every));
+    }
+    if let Some(minutes) = config.charts.preload_minutes {
+        check(
+            minutes >= 0 && minutes * 60 <= config.charts.window_secs,
+            "charts.preload_minutes",
+            format!("must be between 0 and the chart window ({} s), got {} min", config.charts.window_secs, minutes),
+        );
+    }
+    for (i, chart) in config.charts.chart.iter().enumerate() {
+        let key = |field: &str| format!("charts.chart[{}].{}", i, field);
+        check(!chart.name.trim().is_empty(), &key("name"), "must not be empty".to_string());
+        check(
+            config.charts.chart[..i].iter().all(|other| other.name != chart.name),
+            &key("name"),
+            format!("duplicate chart {:?}", chart.name),
+        );
+        check(Signal::from_name(&chart.signal).is_some(), &key("signal"), format!("unknown signal {:?}", chart.signal));
+        if let Some(secs) = chart.window_secs {
+            check(
+                (10..=config.charts.window_secs).contains(&secs),
+                &key("window_secs"),
+                format!("must be between 10 and charts.window_secs ({}), got {}", config.charts.window_secs, secs),
+            );
+        }
+        let (width, height) = chart.size;
+        check(
+            (100..=4096).contains(&width) && (100..=4096).contains(&height),
+            &key("size"),
+            format!("width and height must be between 100 and 4096, got {}x{}", width, height),
+        );
+        if let Some(template) = &chart.path_template {
+            check_template(&mut check, &key("path_template"), template, false);
+        }
+        let output = |chart: &ChartConfig| {
+            chart.path_template.as_deref().unwrap_or(&config.charts.path_template).replace("{signal}", &chart.name)
+        };
+        check(
+            config.charts.chart[..i].iter().all(|other| output(other) != output(chart)),
+            &key("path_template"),
+            format!("chart {:?} writes to the same file as another chart: {:?}", chart.name, output(chart)),
+        );
+        if let Some(every) = chart.every_secs {
+            let message = format!("must be between 1 and 3600, got {}", every);
+            check((1..=3600).contains(&every), &key("every_secs"), message);
+        }
+    }
+
+    for (key, pattern) in [("format.time_format", &config.format.time_format), ("format.date_format", &config.format.date_format)] {
+        let valid = !pattern.is_empty()
+            && chrono::format::StrftimeItems::new(pattern).all(|item| !matches!(item, chrono::format::Item::Error));
+        check(valid, key, format!("is not a valid strftime pattern: {:?}", pattern));
+    }
+
+    check(
+        (0..=3600).contains(&config.limits.duration_secs),
+        "limits.duration_secs",
+        format!("must be between 0 and 3600, got {}", config.limits.duration_secs),
+    );
+    for signal in Signal::ALL {
+        let Some(threshold) = config.limits.threshold(signal) else {
+            continue;
+        };
+        if let (Some(warn), Some(alarm)) = (threshold.warn, threshold.alarm) {
+            check(
+                warn < alarm,
+                &format!("limits.{}.warn", signal.name()),
+                format!("must be below alarm ({})", alarm),
+            );
+        }
+        if let Some(grace) = threshold.startup_grace_secs {
+            check(
+                (0..=3600).contains(&grace),
+                &format!("limits.{}.startup_grace_secs", signal.name()),
+                format!("must be between 0 and 3600, got {}", grace),
+            );
+        }
+    }
+    check(
+        (0..=3600).contains(&config.limits.startup_grace_secs),
+        "limits.startup_grace_secs",
+        format!("must be between 0 and 3600, got {}", config.limits.startup_grace_secs),
+    );
+    check(
+        config.limits.start_speed_rpm >= 0.0,
+        "limits.start_speed_rpm",
+        format!("must not be negative, got {}", config.limits.start_speed_rpm),
+    );
+
+    let governor = &config.governor;
+    if let Some(cpu) = governor.cpu_percent {
+        check(cpu > 0.0 && cpu <= 400.0, "governor.cpu_percent", format!("must be between 0 and 400, got {}", cpu));
+    }
+    if let Some(memory) = governor.memory_mb {
+        check(memory > 0.0, "governor.memory_mb", format!("must be positive, got {}", memory));
+    }
+    check(
+        governor.check_interval_secs >= 1,
+        "governor.check_interval_secs",
+        "must be at least 1".to_string(),
+    );
+    check(
+        (1..=3600).contains(&governor.max_render_every),
+        "governor.max_render_every",
+        format!("must be between 1 and 3600, got {}", governor.max_render_every),
+    );
+    check(
+        (1..=10_000).contains(&governor.max_batch_size),
+        "governor.max_batch_size",
+        format!("must be between 1 and 10000, got {}", governor.max_batch_size),
+    );
+
+    if let Some(redundancy) = &config.redundancy {
+        check(
+            (50..=10_000).contains(&redundancy.heartbeat_ms),
+            "redundancy.heartbeat_ms",
+            format!("must be between 50 and 10000, got {}", redundancy.heartbeat_ms),
+        );
+        check(
+            redundancy.failover_ms >= 3 * redundancy.heartbeat_ms,
+            "redundancy.failover_ms",
+            format!("must be at least three heartbeats ({} ms)", 3 * redundancy.heartbeat_ms),
+        );
+        check(
+            redundancy.listen != redundancy.peer,
+            "redundancy.peer",
+            "must differ from redundancy.listen".to_string(),
+        );
+    }
+
+    if let Some(report) = &config.report {
+        check(
+            (1..=24 * 31).contains(&report.interval_hours),
+            "report.interval_hours",
+            format!("must be between 1 and 744, got {}", report.interval_hours),
+        );
+        check_template(&mut check, "report.path_template", &report.path_template, false);
+    }
+
+    if let Some(tracking) = &config.tracking {
+        check(
+            config.registers.speed_reference.is_some(),
+            "tracking",
+            "requires registers.speed_reference to be configured".to_string(),
+        );
+        check(
+            tracking.max_error_rpm > 0.0,
+            "tracking.max_error_rpm",
+            format!("must be positive, got {}", tracking.max_error_rpm),
+        );
+        check(
+            (1..=3600).contains(&tracking.duration_secs),
+            "tracking.duration_secs",
+            format!("must be between 1 and 3600, got {}", tracking.duration_secs),
+        );
+    }
+
+    let value_names: Vec<&str> = Signal::ALL
+        .iter()
+        .map(|s| s.name())
+        .chain(std::iter::once("speed_reference"))
+        .chain(config.registers.temperatures.keys().map(String::as_str))
+        .chain(config.registers.pulses.keys().map(String::as_str))
+        .collect();
+    let generated: Vec<String> = limits::limit_rules(&config.limits, &config.motor)
+        .into_iter()
+        .chain(rules::temperature_rules(&config.registers))
+        .map(|rule| rule.name)
+        .collect();
+    for (i, rule) in config.alarm_rules.iter().enumerate() {
+        let key = |field: &str| format!("alarm_rules[{}].{}", i, field);
+        check(
+            !rule.name.is_empty() && rule.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            &key("name"),
+            format!("must be letters, digits and underscores, got {:?}", rule.name),
+        );
+        check(
+            config.alarm_rules.iter().filter(|other| other.name == rule.name).count() == 1,
+            &key("name"),
+            format!("{:?} is used by more than one rule", rule.name),
+        );
+        check(
+            !generated.contains(&rule.name),
+            &key("name"),
+            format!("{:?} is the name of a limit or temperature channel rule", rule.name),
+        );
+        check_condition(&mut check, &key("condition"), &rule.condition, &value_names);
+        check(
+            (0..=86_400).contains(&rule.duration_secs),
+            &key("duration_secs"),
+            format!("must be between 0 and 86400, got {}", rule.duration_secs),
+        );
+        check(
+            (0..=3600).contains(&rule.startup_grace_secs),
+            &key("startup_grace_secs"),
+            format!("must be between 0 and 3600, got {}", rule.startup_grace_secs),
+        );
+        if let Some(message) = &rule.message {
+            let unknown: Vec<&str> = rules::placeholders(message)
+                .into_iter()
+                .filter(|name| *name != "rule" && !value_names.contains(name))
+                .collect();
+            check(unknown.is_empty(), &key("message"), format!("unknown placeholder(s) {:?}", unknown));
+        }
+        check(
+            !rule.actions.as_ref().is_some_and(|actions| actions.contains(&RuleAction::Snapshot))
+                || config.snapshots.is_some(),
+            &key("actions"),
+            "\"snapshot\" needs a [snapshots] section".to_string(),
+        );
+    }
+
+    if let Some(snapshots) = &config.snapshots {
+        check(
+            (1..=24 * 60).contains(&snapshots.minutes),
+            "snapshots.minutes",
+            format!("must be between 1 and 1440, got {}", snapshots.minutes),
+        );
+        check_template(&mut check, "snapshots.dir_template", &snapshots.dir_template, false);
+    }
+    if let Some(archive) = &config.archive {
+        check(
+            archive.after_hours >= 1,
+            "archive.after_hours",
+            format!("must be at least 1, got {}", archive.after_hours),
+        );
+        check(
+            (1..=24 * 60).contains(&archive.check_interval_minutes),
+            "archive.check_interval_minutes",
+            format!("must be between 1 and 1440, got {}", archive.check_interval_minutes),
+        );
+        check(
+            (1..=22).contains(&archive.level),
+            "archive.level",
+            format!("must be between 1 and 22, got {}", archive.level),
+        );
+    }
+    if let Some(thermal) = &config.thermal {
+        check(
+            thermal.rated_current.is_finite() && thermal.rated_current > 0.0,
+            "thermal.rated_current",
+            format!("must be positive, got {}", thermal.rated_current),
+        );
+        check(
+            (1.0..=1.5).contains(&thermal.service_factor),
+            "thermal.service_factor",
+            format!("must be between 1.0 and 1.5, got {}", thermal.service_factor),
+        );
+        if let Some(tau) = thermal.time_constant_secs {
+            check(tau.is_finite() && tau > 0.0, "thermal.time_constant_secs", format!("must be positive, got {}", tau));
+        }
+        check(
+            [5, 10, 20, 30, 40].contains(&thermal.trip_class),
+            "thermal.trip_class",
+            format!("must be 5, 10, 20, 30 or 40, got {}", thermal.trip_class),
+        );
+        check(
+            (1.0..=10.0).contains(&thermal.standstill_cooling_factor),
+            "thermal.standstill_cooling_factor",
+            format!("must be between 1 and 10, got {}", thermal.standstill_cooling_factor),
+        );
+        check(
+            thermal.warn_level > 0.05 && thermal.warn_level < 1.0,
+            "thermal.warn_level",
+            format!("must be between 0.05 and 1 (trip), got {}", thermal.warn_level),
+        );
+    }
+    if let Some(verify) = &config.write_verify {
+        check(
+            verify.delay_ms <= 5000,
+            "write_verify.delay_ms",
+            format!("must be at most 5000, got {}", verify.delay_ms),
+        );
+    }
+    if let Some(interlock) = &config.interlock {
+        check(
+            (0..=600).contains(&interlock.overload_secs),
+            "interlock.overload_secs",
+            format!("must be between 0 and 600, got {}", interlock.overload_secs),
+        );
+        if let Some(limit) = interlock.max_temperature {
+            check(
+                (0.0..=300.0).contains(&limit),
+                "interlock.max_temperature",
+                format!("must be between 0 and 300 °C, got {}", limit),
+            );
+        }
+        check(
+            (1..=2).contains(&interlock.stop_register.count),
+            "interlock.stop_register.count",
+            format!("must be 1 or 2, got {}", interlock.stop_register.count),
+        );
+    }
+    if let Some(setpoints) = &config.setpoints {
+        for (name, channel) in [("speed", &setpoints.speed), ("torque", &setpoints.torque)] {
+            let Some(channel) = channel else {
+                continue;
+            };
+            let key = |field: &str| format!("setpoints.{}.{}", name, field);
+            check(
+                channel.register.count == 1,
+                &key("register.count"),
+                format!("setpoints are written as a single register, got count {}", channel.register.count),
+            );
+            check(channel.scale > 0.0, &key("scale"), format!("must be positive, got {}", channel.scale));
+            check(
+                channel.min >= 0.0,
+                &key("min"),
+                format!("registers hold no negative setpoints, got {}", channel.min),
+            );
+            if let Some(max) = channel.max {
+                check(max > channel.min, &key("max"), format!("must be above min ({}), got {}", channel.min, max));
+            }
+            for (field, rate) in [("accel_per_sec", channel.accel_per_sec), ("decel_per_sec", channel.decel_per_sec)] {
+                if let Some(rate) = rate {
+                    check(rate > 0.0, &key(field), format!("must be positive, got {}", rate));
+                }
+            }
+        }
+    }
+    if let Some(control) = &config.control {
+        check(!control.operators.is_empty(), "control.operators", "must list at least one operator".to_string());
+        for (name, token) in &control.operators {
+            check(
+                token.len() >= 16,
+                &format!("control.operators.{}", name),
+                format!("token must be at least 16 characters, got {}", token.len()),
+            );
+        }
+        check(
+            (5..=600).contains(&control.confirm_secs),
+            "control.confirm_secs",
+            format!("must be between 5 and 600, got {}", control.confirm_secs),
+        );
+        let commands = [&control.start, &control.stop, &control.reset_fault];
+        check(
+            commands.iter().any(|c| c.is_some()) || config.setpoints.is_some() || !control.parameter_sets.is_empty(),
+            "control",
+            "needs start, stop, reset_fault, parameter_sets or [setpoints] to control".to_string(),
+        );
+        for (name, command) in [("start", &control.start), ("stop", &control.stop), ("reset_fault", &control.reset_fault)] {
+            if let Some(command) = command {
+                check(
+                    command.register.count == 1,
+                    &format!("control.{}.register.count", name),
+                    format!("commands are written as a single register, got count {}", command.register.count),
+                );
+            }
+        }
+        for (name, set) in &control.parameter_sets {
+            let key = format!("control.parameter_sets.{}.writes", name);
+            check(
+                (1..=transport::MAX_WRITE_REGISTERS as usize).contains(&set.writes.len()),
+                &key,
+                format!("must list 1 to {} registers, got {}", transport::MAX_WRITE_REGISTERS, set.writes.len()),
+            );
+            for (i, write) in set.writes.iter().enumerate() {
+                check(
+                    set.writes[..i].iter().all(|other| other.address != write.address),
+                    &key,
+                    format!("register {} is written more than once", write.address),
+                );
+            }
+        }
+    }
+    if let Some(heatmap) = &config.heatmap {
+        check(
+            (1..=24 * 31).contains(&heatmap.window_hours),
+            "heatmap.window_hours",
+            format!("must be between 1 and 744, got {}", heatmap.window_hours),
+        );
+        check(
+            (1..=24 * 60).contains(&heatmap.every_minutes),
+            "heatmap.every_minutes",
+            format!("must be between 1 and 1440, got {}", heatmap.every_minutes),
+        );
+        for (key, bins) in [("heatmap.load_bins", heatmap.load_bins), ("heatmap.temperature_bins", heatmap.temperature_bins)] {
+            check((2..=100).contains(&bins), key, format!("must be between 2 and 100, got {}", bins));
+        }
+        check_template(&mut check, "heatmap.path_template", &heatmap.path_template, false);
+    }
+    for (i, comparison) in config.comparisons.iter().enumerate() {
+        let key = |field: &str| format!("comparisons[{}].{}", i, field);
+        for (field, hours) in [("window_hours", comparison.window_hours), ("offset_hours", comparison.offset_hours)] {
+            check(
+                (1..=24 * 366).contains(&hours),
+                &key(field),
+                format!("must be between 1 and 8784, got {}", hours),
+            );
+        }
+        check(
+            (1..=24 * 60).contains(&comparison.every_minutes),
+            &key("every_minutes"),
+            format!("must be between 1 and 1440, got {}", comparison.every_minutes),
+        );
+        for name in &comparison.signals {
+            check(Signal::from_name(name).is_some(), &key("signals"), format!("unknown signal {:?}", name));
+        }
+        check_template(&mut check, &key("path_template"), &comparison.path_template, true);
+        check(
+            config.comparisons[..i].iter().all(|other| other.path_template != comparison.path_template),
+            &key("path_template"),
+            "is used by another comparison".to_string(),
+        );
+    }
+    if let Some(zoom) = &config.zoom {
+        check(!zoom.horizons.is_empty(), "zoom.horizons", "must list at least one horizon".to_string());
+        for (i, label) in zoom.horizons.iter().enumerate() {
+            check(
+                zoom::horizon_secs(label).is_some_and(|secs| secs <= 31 * 86_400),
+                "zoom.horizons",
+                format!("must be a number followed by m, h or d, at most 31d, got {:?}", label),
+            );
+            check(!zoom.horizons[..i].contains(label), "zoom.horizons", format!("lists {:?} twice", label));
+        }
+        for name in &zoom.signals {
+            check(Signal::from_name(name).is_some(), "zoom.signals", format!("unknown signal {:?}", name));
+        }
+        check(
+            (1..=24 * 60).contains(&zoom.every_minutes),
+            "zoom.every_minutes",
+            format!("must be between 1 and 1440, got {}", zoom.every_minutes),
+        );
+        check_template(&mut check, "zoom.path_template", &zoom.path_template.replace("{zoom}", ""), true);
+        check(
+            zoom.path_template.contains("{zoom}"),
+            "zoom.path_template",
+            "must contain {zoom}, otherwise every horizon writes the same file".to_string(),
+        );
+    }
+    if let Some(adaptive) = &config.adaptive_polling {
+        for (key, value) in [
+            ("adaptive_polling.running_ms", adaptive.running_ms),
+            ("adaptive_polling.stopped_ms", adaptive.stopped_ms),
+        ] {
+            check((50..=3_600_000).contains(&value), key, format!("must be between 50 and 3600000, got {}", value));
+        }
+        check(
+            adaptive.stopped_ms >= adaptive.running_ms,
+            "adaptive_polling.stopped_ms",
+            format!("must not be shorter than running_ms ({}), got {}", adaptive.running_ms, adaptive.stopped_ms),
+        );
+        for (key, value) in [
+            ("adaptive_polling.stop_speed_rpm", adaptive.stop_speed_rpm),
+            ("adaptive_polling.ramp_rate_rpm_per_sec", adaptive.ramp_rate_rpm_per_sec),
+            ("adaptive_polling.ramp_error_rpm", adaptive.ramp_error_rpm),
+        ] {
+            check(value >= 0.0, key, format!("must not be negative, got {}", value));
+        }
+    }
+    for (name, step) in &config.precision {
+        let key = format!("precision.{}", name);
+        check(
+            Signal::from_name(name).is_some()
+                || name == "speed_reference"
+                || config.registers.temperatures.contains_key(name)
+                || config.registers.pulses.contains_key(name),
+            &key,
+            "unknown signal, temperature channel or pulse input".to_string(),
+        );
+        check(step.is_finite() && *step > 0.0, &key, format!("must be positive, got {}", step));
+    }
+    for (name, unit) in &config.registers.units {
+        let key = |field: &str| format!("registers.units.{}.{}", name, field);
+        check(
+            Signal::from_name(name).is_some()
+                || name == "speed_reference"
+                || config.registers.temperatures.contains_key(name)
+                || config.registers.pulses.contains_key(name),
+            &format!("registers.units.{}", name),
+            "unknown signal, temperature channel or pulse input".to_string(),
+        );
+        check(!unit.unit.trim().is_empty(), &key("unit"), "must not be empty".to_string());
+        check(unit.scale.is_finite() && unit.scale != 0.0, &key("scale"), format!("must be non-zero, got {}", unit.scale));
+    }
+    for (name, rollup) in &config.rollups {
+        check(Signal::from_name(name).is_some(), &format!("rollups.{}", name), "unknown signal".to_string());
+        for p in &rollup.percentiles {
+            check(
+                *p > 0.0 && *p <= 100.0,
+                &format!("rollups.{}.percentiles", name),
+                format!("must be between 0 and 100, got {}", p),
+            );
+        }
+        check(
+            rollup.time_above.iter().all(|t| t.is_finite()),
+            &format!("rollups.{}.time_above", name),
+            "must be finite numbers".to_string(),
+        );
+        check(
+            !rollup.energy || name == "power",
+            &format!("rollups.{}.energy", name),
+            "is only available for power".to_string(),
+        );
+    }
+    if let Some(stale) = &config.stale {
+        check(
+            (1..=24 * 3600).contains(&stale.window_secs),
+            "stale.window_secs",
+            format!("must be between 1 and 86400, got {}", stale.window_secs),
+        );
+        check(
+            stale.tolerance >= 0.0,
+            "stale.tolerance",
+            format!("must not be negative, got {}", stale.tolerance),
+        );
+        for name in &stale.signals {
+            check(
+                Signal::from_name(name).is_some() || config.registers.temperatures.contains_key(name),
+                "stale.signals",
+                format!("unknown signal or temperature channel {:?}", name),
+            );
+        }
+    }
+    if let Some(schedule) = &config.schedule {
+        check(!schedule.windows.is_empty(), "schedule.windows", "must list at least one window".to_string());
+        for (i, window) in schedule.windows.iter().enumerate() {
+            let key = |field: &str| format!("schedule.windows[{}].{}", i, field);
+            check(!window.days.is_empty(), &key("days"), "must list at least one day".to_string());
+            for day in &window.days {
+                check(
+                    schedule::parse_day(day).is_some(),
+                    &key("days"),
+                    format!("unknown day {:?}; use mon, tue, wed, thu, fri, sat or sun", day),
+                );
+            }
+            for (field, time) in [("start", &window.start), ("end", &window.end)] {
+                check(
+                    schedule::parse_time(time).is_some(),
+                    &key(field),
+                    format!("expected HH:MM, got {:?}", time),
+                );
+            }
+        }
+        check(
+            schedule.running_speed_rpm >= 0.0,
+            "schedule.running_speed_rpm",
+            format!("must not be negative, got {}", schedule.running_speed_rpm),
+        );
+        check(
+            (0..=24 * 3600).contains(&schedule.delay_secs),
+            "schedule.delay_secs",
+            format!("must be between 0 and 86400, got {}", schedule.delay_secs),
+        );
+    }
+    if let Some(equipment) = &config.equipment {
+        for (field, value) in [
+            ("reference_speed_rpm", equipment.reference_speed_rpm),
+            ("reference_power", equipment.reference_power),
+            ("exponent", equipment.exponent),
+        ] {
+            if let Some(value) = value {
+                check(value > 0.0, &format!("equipment.{}", field), format!("must be positive, got {}", value));
+            }
+        }
+        check(
+            equipment.reference_speed_rpm.is_some() || config.motor.rated_speed > 0.0,
+            "equipment.reference_speed_rpm",
+            "must be set while motor.rated_speed is 0".to_string(),
+        );
+        check(
+            equipment.reference_power.is_some() || config.motor.rated_power > 0.0,
+            "equipment.reference_power",
+            "must be set while motor.rated_power is 0".to_string(),
+        );
+        check(
+            equipment.tolerance_percent > 0.0 && equipment.tolerance_percent < 100.0,
+            "equipment.tolerance_percent",
+            format!("must be between 0 and 100, got {}", equipment.tolerance_percent),
+        );
+        check(
+            equipment.min_speed_rpm >= 0.0,
+            "equipment.min_speed_rpm",
+            format!("must not be negative, got {}", equipment.min_speed_rpm),
+        );
+        check(
+            (0..=24 * 3600).contains(&equipment.delay_secs),
+            "equipment.delay_secs",
+            format!("must be between 0 and 86400, got {}", equipment.delay_secs),
+        );
+    }
+    if let Some(shifts) = &config.shifts {
+        check(!shifts.shift.is_empty(), "shifts.shift", "must list at least one shift".to_string());
+        for (i, shift) in shifts.shift.iter().enumerate() {
+            let key = |field: &str| format!("shifts.shift[{}].{}", i, field);
+            check(!shift.name.is_empty(), &key("name"), "must not be empty".to_string());
+            check(
+                shifts.shift[..i].iter().all(|other| other.name != shift.name),
+                &key("name"),
+                format!("duplicate shift {:?}", shift.name),
+            );
+            for (field, time) in [("start", &shift.start), ("end", &shift.end)] {
+                check(schedule::parse_time(time).is_some(), &key(field), format!("expected HH:MM, got {:?}", time));
+            }
+        }
+        check_template(&mut check, "shifts.path_template", &shifts.path_template, false);
+    }
+    if let Some(demand) = &config.demand {
+        check(
+            (1..=60).contains(&demand.window_minutes) && 60 % demand.window_minutes == 0,
+            "demand.window_minutes",
+            format!("must divide 60, got {}", demand.window_minutes),
+        );
+        check_template(&mut check, "demand.path_template", &demand.path_template, false);
+    }
+    if let Some(trends) = &config.trends {
+        check(
+            (2..=3650).contains(&trends.window_days),
+            "trends.window_days",
+            format!("must be between 2 and 3650, got {}", trends.window_days),
+        );
+        check(
+            trends.reference_load_percent > 0.0 && trends.reference_load_percent <= 150.0,
+            "trends.reference_load_percent",
+            format!("must be above 0 and at most 150, got {}", trends.reference_load_percent),
+        );
+        check(
+            trends.load_band_percent > 0.0,
+            "trends.load_band_percent",
+            format!("must be positive, got {}", trends.load_band_percent),
+        );
+        if let Some(min) = trends.min_efficiency {
+            check(
+                min > 0.0 && min < 1.0,
+                "trends.min_efficiency",
+                format!("must be between 0 and 1, got {}", min),
+            );
+        }
+        check(
+            trends.min_points >= 2 && trends.min_points <= trends.window_days as usize,
+            "trends.min_points",
+            format!("must be between 2 and window_days, got {}", trends.min_points),
+        );
+    }
+    if let Some(correlation) = &config.correlation {
+        check(config.report.is_some(), "correlation", "is reported through [report], which is missing".to_string());
+        let known = |name: &String| {
+            Signal::from_name(name).is_some()
+                || (name == "speed_reference" && config.registers.speed_reference.is_some())
+                || config.registers.temperatures.contains_key(name)
+        };
+        for name in &correlation.signals {
+            check(known(name), "correlation.signals", format!("unknown signal or temperature channel {:?}", name));
+        }
+        for (i, [leader, follower]) in correlation.lag_pairs.iter().enumerate() {
+            let key = format!("correlation.lag_pairs[{}]", i);
+            for name in [leader, follower] {
+                check(known(name), &key, format!("unknown signal or temperature channel {:?}", name));
+            }
+            check(leader != follower, &key, "must name two different signals".to_string());
+        }
+        check(
+            (1..=3600).contains(&correlation.bucket_secs),
+            "correlation.bucket_secs",
+            format!("must be between 1 and 3600, got {}", correlation.bucket_secs),
+        );
+        check(
+            correlation.max_lag_minutes * 60 >= correlation.bucket_secs && correlation.max_lag_minutes <= 24 * 60,
+            "correlation.max_lag_minutes",
+            format!("must cover at least one bucket and at most 1440, got {}", correlation.max_lag_minutes),
+        );
+    }
+    if let Some(proxy) = &config.modbus_proxy {
+        check(
+            config.transport == TransportKind::ModbusRtu,
+            "modbus_proxy",
+            "needs block reads, which only transport = \"modbus_rtu\" supports".to_string(),
+        );
+        check(!proxy.blocks.is_empty(), "modbus_proxy.blocks", "must list at least one block".to_string());
+        check(
+            (1..=3600).contains(&proxy.max_age_secs),
+            "modbus_proxy.max_age_secs",
+            format!("must be between 1 and 3600, got {}", proxy.max_age_secs),
+        );
+        check(
+            Some(proxy.listen) != config.api.listen && Some(proxy.listen) != config.api.flight_listen,
+            "modbus_proxy.listen",
+            "must differ from api.listen and api.flight_listen".to_string(),
+        );
+        for (i, block) in proxy.blocks.iter().enumerate() {
+            let key = |field: &str| format!("modbus_proxy.blocks[{}].{}", i, field);
+            check(
+                (1..=1000).contains(&block.count),
+                &key("count"),
+                format!("must be between 1 and 1000, got {}", block.count),
+            );
+            check(
+                block.address.checked_add(block.count.saturating_sub(1)).is_some(),
+                &key("address"),
+                format!("block of {} register(s) at {} exceeds the address space", block.count, block.address),
+            );
+            check(
+                block.function == transport::READ_INPUT_REGISTERS || block.function == transport::READ_HOLDING_REGISTERS,
+                &key("function"),
+                format!("must be 0x03 or 0x04, got {:#04x}", block.function),
+            );
+        }
+    }
+    if let Some(environment) = &config.environment {
+        check(
+            config.transport == TransportKind::ModbusRtu,
+            "environment",
+            "reads other bus devices, which only transport = \"modbus_rtu\" supports".to_string(),
+        );
+        check(!environment.sensors.is_empty(), "environment.sensors", "must list at least one sensor".to_string());
+        for (name, sensor) in &environment.sensors {
+            let key = |field: &str| format!("environment.sensors.{}.{}", name, field);
+            check(
+                (1..=247).contains(&sensor.unit_id) && sensor.unit_id != config.serial.slave_id,
+                &key("unit_id"),
+                format!("must be between 1 and 247 and not the drive's serial.slave_id, got {}", sensor.unit_id),
+            );
+            check(
+                transport::is_read_function(sensor.function),
+                &key("function"),
+                format!("must be a read function, got {:#04x}", sensor.function),
+            );
+            check((1..=2).contains(&sensor.count), &key("count"), format!("must be 1 or 2, got {}", sensor.count));
+            check(
+                sensor.scale.is_finite() && sensor.scale != 0.0,
+                &key("scale"),
+                format!("must be non-zero, got {}", sensor.scale),
+            );
+        }
+        check(
+            (1..=3600).contains(&environment.every_secs),
+            "environment.every_secs",
+            format!("must be between 1 and 3600, got {}", environment.every_secs),
+        );
+        if let Some(ambient) = &environment.ambient {
+            check(
+                environment.sensors.get(ambient).is_some_and(|s| s.quantity == EnvironmentQuantity::Temperature),
+                "environment.ambient",
+                format!("must name a temperature sensor, got {:?}", ambient),
+            );
+        }
+    }
+    if let Some(counters) = &config.counters {
+        check(!counters.registers.is_empty(), "counters.registers", "must list at least one counter".to_string());
+        for (name, counter) in &counters.registers {
+            let key = |field: &str| format!("counters.registers.{}.{}", name, field);
+            check(
+                transport::is_read_function(counter.function),
+                &key("function"),
+                format!("must be a read function, got {:#04x}", counter.function),
+            );
+            check((1..=2).contains(&counter.count), &key("count"), format!("must be 1 or 2, got {}", counter.count));
+            check(
+                counter.scale.is_finite() && counter.scale > 0.0,
+                &key("scale"),
+                format!("must be positive, got {}", counter.scale),
+            );
+            if let (Some(max_step), true) = (counter.max_step, (1..=2).contains(&counter.count)) {
+                check(
+                    max_step >= 1 && max_step < 1 << counter.bits(),
+                    &key("max_step"),
+                    format!("must be between 1 and {}, got {}", (1u64 << counter.bits()) - 1, max_step),
+                );
+            }
+        }
+        check(
+            (1..=3600).contains(&counters.every_secs),
+            "counters.every_secs",
+            format!("must be between 1 and 3600, got {}", counters.every_secs),
+        );
+    }
+    if let Some(ipc) = &config.ipc {
+        // sockaddr_un holds at most 107 bytes of path plus the terminating NUL,
+        // and the socket is bound under a longer path first; see `ipc`.
+        let (len, max) = (ipc.socket.as_os_str().len(), 107 - super::ipc::STAGING_BYTES);
+        check(len > 0 && len <= max, "ipc.socket", format!("must be a path of 1 to {} bytes, got {}", max, len));
+    }
+    if let Some(faults) = &config.fault_history {
+        check(
+            config.transport == TransportKind::ModbusRtu,
+            "fault_history",
+            "needs block reads, which only transport = \"modbus_rtu\" supports".to_string(),
+        );
+        check(
+            transport::is_read_function(faults.function),
+            "fault_history.function",
+            format!("must be a read function, got {:#04x}", faults.function),
+        );
+        check(
+            (1..=32).contains(&faults.entries),
+            "fault_history.entries",
+            format!("must be between 1 and 32, got {}", faults.entries),
+        );
+        check(
+            faults.address as u32 + faults.entries as u32 <= 0x10000,
+            "fault_history.entries",
+            format!("runs past the last register from address {}", faults.address),
+        );
+        for code in faults.codes.keys() {
+            check(
+                code.parse::<u16>().is_ok_and(|code| code != 0),
+                &format!("fault_history.codes.{}", code),
+                "must be keyed by a fault code between 1 and 65535".to_string(),
+            );
+        }
+        check(
+            (1..=3600).contains(&faults.every_secs),
+            "fault_history.every_secs",
+            format!("must be between 1 and 3600, got {}", faults.every_secs),
+        );
+    }
+    if let Some(mcsa) = &config.mcsa {
+        check(
+            config.transport == TransportKind::ModbusRtu,
+            "mcsa",
+            "needs block reads, which only transport = \"modbus_rtu\" supports".to_string(),
+        );
+        check(
+            (256..=16384).contains(&mcsa.samples),
+            "mcsa.samples",
+            format!("must be between 256 and 16384, got {}", mcsa.samples),
+        );
+        check(
+            (mcsa.waveform.address as u32 + mcsa.samples as u32) <= 0x1_0000,
+            "mcsa.waveform.address",
+            "waveform runs past the end of the address space".to_string(),
+        );
+        check(
+            transport::is_read_function(mcsa.waveform.function),
+            "mcsa.waveform.function",
+            format!("must be 3, 4 or a user-defined function code, got {}", mcsa.waveform.function),
+        );
+        check(
+            (1.0..=400.0).contains(&mcsa.line_frequency_hz),
+            "mcsa.line_frequency_hz",
+            format!("must be between 1 and 400, got {}", mcsa.line_frequency_hz),
+        );
+        check(
+            mcsa.sample_rate_hz >= 4.0 * mcsa.line_frequency_hz,
+            "mcsa.sample_rate_hz",
+            format!("must be at least 4 × line_frequency_hz, got {}", mcsa.sample_rate_hz),
+        );
+        check(mcsa.scale > 0.0, "mcsa.scale", format!("must be positive, got {}", mcsa.scale));
+        if let Some(poles) = mcsa.poles {
+            check(
+                (2..=48).contains(&poles) && poles % 2 == 0,
+                "mcsa.poles",
+                format!("must be an even number between 2 and 48, got {}", poles),
+            );
+        }
+        check(
+            mcsa.bearing_orders.iter().all(|order| *order > 0.0),
+            "mcsa.bearing_orders",
+            "must all be positive".to_string(),
+        );
+        check(
+            (1..=7 * 24 * 60).contains(&mcsa.every_minutes),
+            "mcsa.every_minutes",
+            format!("must be between 1 and 10080, got {}", mcsa.every_minutes),
+        );
+        check(
+            (0.0..=150.0).contains(&mcsa.min_load_percent),
+            "mcsa.min_load_percent",
+            format!("must be between 0 and 150, got {}", mcsa.min_load_percent),
+        );
+        for (key, db) in [("mcsa.rotor_bar_alarm_db", mcsa.rotor_bar_alarm_db), ("mcsa.bearing_alarm_db", mcsa.bearing_alarm_db)] {
+            if let Some(db) = db {
+                check((-100.0..0.0).contains(&db), key, format!("must be between -100 and 0 dB, got {}", db));
+            }
+        }
+    }
+    if let (Some(flight), Some(listen)) = (config.api.flight_listen, config.api.listen) {
+        check(flight != listen, "api.flight_listen", "must differ from api.listen".to_string());
+    }
+    let exports = &config.api.exports;
+    check(
+        (1..=16).contains(&exports.max_concurrent),
+        "api.exports.max_concurrent",
+        format!("must be between 1 and 16, got {}", exports.max_concurrent),
+    );
+    check(
+        (1..=1000).contains(&exports.max_queued),
+        "api.exports.max_queued",
+        format!("must be between 1 and 1000, got {}", exports.max_queued),
+    );
+    check(!exports.dir.is_empty(), "api.exports.dir", "must not be empty".to_string());
+    check(
+        (1..=7 * 24 * 60).contains(&exports.keep_minutes),
+        "api.exports.keep_minutes",
+        format!("must be between 1 and 10080, got {}", exports.keep_minutes),
+    );
+    let queries = &config.api.queries;
+    check(
+        (1..=8).contains(&queries.connections),
+        "api.queries.connections",
+        format!("must be between 1 and 8, got {}", queries.connections),
+    );
+    check(
+        queries.requests_per_minute == 0 || queries.burst >= 1,
+        "api.queries.burst",
+        "must be at least 1 while requests_per_minute is set".to_string(),
+    );
+    check(
+        queries.latest_cache_ms <= 60_000,
+        "api.queries.latest_cache_ms",
+        format!("must be at most 60000, got {}", queries.latest_cache_ms),
+    );
+    let sinks = [
+        ("storage", Some(&config.sinks.storage)),
+        ("stats", Some(&config.sinks.stats)),
+        ("mqtt", Some(&config.sinks.mqtt)),
+        ("nats", Some(&config.sinks.nats)),
+        ("charts", Some(&config.sinks.charts)),
+        ("stdout", config.sinks.stdout.as_ref()),
+    ];
+    for (name, sink) in sinks.into_iter().filter_map(|(name, sink)| Some((name, sink?))) {
+        check(
+            (1..=100_000).contains(&sink.capacity),
+            &format!("sinks.{}.capacity", name),
+            format!("must be between 1 and 100000, got {}", sink.capacity),
+        );
+        check(
+            sink.overflow != OverflowPolicy::SpillToDisk || !sink.spill_dir.is_empty(),
+            &format!("sinks.{}.spill_dir", name),
+            "must not be empty with overflow = \"spill_to_disk\"".to_string(),
+        );
+        check(
+            (1..=3600).contains(&sink.max_backoff_secs),
+            &format!("sinks.{}.max_backoff_secs", name),
+            format!("must be between 1 and 3600, got {}", sink.max_backoff_secs),
+        );
+    }
+    for (i, plugin) in config.plugins.iter().enumerate() {
+        check(
+            !plugin.path.as_os_str().is_empty(),
+            &format!("plugins[{}].path", i),
+            "must not be empty".to_string(),
+        );
+    }
+    if let Some(mqtt) = &config.mqtt {
+        check(!mqtt.host.is_empty(), "mqtt.host", "must not be empty".to_string());
+        check(
+            mqtt.password.is_none() || mqtt.username.is_some(),
+            "mqtt.password",
+            "needs mqtt.username".to_string(),
+        );
+        check(
+            (1..=3600).contains(&mqtt.keep_alive_secs),
+            "mqtt.keep_alive_secs",
+            format!("must be between 1 and 3600, got {}", mqtt.keep_alive_secs),
+        );
+        if mqtt.mode == MqttMode::SparkplugB {
+            check(
+                mqtt.group_id.is_some() || config.site.is_some(),
+                "mqtt.group_id",
+                "is required with mode = \"sparkplug_b\" unless `site` is set".to_string(),
+            );
+            for (key, id) in [
+                ("mqtt.group_id", &mqtt.group_id),
+                ("mqtt.edge_node_id", &mqtt.edge_node_id),
+                ("mqtt.device_id", &mqtt.device_id),
+            ] {
+                let Some(id) = id else {
+                    continue;
+                };
+                check(
+                    !id.is_empty() && !id.contains(['/', '+', '#']),
+                    key,
+                    format!("must be non-empty without '/', '+' or '#', got {:?}", id),
+                );
+            }
+        } else if let Some(topic) = &mqtt.topic {
+            check(
+                !topic.is_empty() && !topic.contains(['+', '#']),
+                "mqtt.topic",
+                format!("must be non-empty without wildcards, got {:?}", topic),
+            );
+            check(
+                config.site.is_some() || !topic.contains("{site}"),
+                "mqtt.topic",
+                "uses {site} but `site` is not set".to_string(),
+            );
+        }
+    }
+    if let Some(nats) = &config.nats {
+        check(
+            nats.url.starts_with("nats://") || nats.url.starts_with("tls://"),
+            "nats.url",
+            format!("must start with nats:// or tls://, got {:?}", secrets::redact_url(&nats.url)),
+        );
+        let subjects = [("nats.samples_subject", &nats.samples_subject), ("nats.events_subject", &nats.events_subject)];
+        for (key, subject) in subjects {
+            let Some(subject) = subject else {
+                continue;
+            };
+            check(
+                !subject.is_empty() && !subject.contains(['*', '>', ' ']) && !subject.split('.').any(str::is_empty),
+                key,
+                format!("must be a subject without wildcards or empty tokens, got {:?}", subject),
+            );
+            check(
+                config.site.is_some() || !subject.contains("{site}"),
+                key,
+                "uses {site} but `site` is not set".to_string(),
+            );
+        }
+        check(
+            nats.samples_subject.as_ref().is_none_or(|s| !s.contains("{kind}")),
+            "nats.samples_subject",
+            "cannot use {kind}, which only events have".to_string(),
+        );
+        check(
+            (1..=10_000).contains(&nats.batch_size),
+            "nats.batch_size",
+            format!("must be between 1 and 10000, got {}", nats.batch_size),
+        );
+    }
+    let client_tls = [
+        ("mqtt.tls", config.mqtt.as_ref().and_then(|m| m.tls.as_ref())),
+        ("nats.tls", config.nats.as_ref().and_then(|n| n.tls.as_ref())),
+    ];
+    for (key, tls) in client_tls {
+        let Some(tls) = tls else {
+            continue;
+        };
+        check(tls.cert.is_some() == tls.key.is_some(), key, "needs both `cert` and `key`, or neither".to_string());
+        check(
+            key != "mqtt.tls" || tls.cert.is_none() || tls.ca.is_some(),
+            "mqtt.tls.ca",
+            "is required with a client certificate".to_string(),
+        );
+    }
+    // A file not yet in place is waited for at startup; see `startup`.
+    let mut pem_files = Vec::new();
+    if let Some(tls) = &config.api.tls {
+        pem_files.push(("api.tls.cert".to_string(), &tls.cert, tls::Pem::Certificates));
+        pem_files.push(("api.tls.key".to_string(), &tls.key, tls::Pem::PrivateKey));
+    }
+    for (key, tls) in client_tls {
+        let Some(tls) = tls else {
+            continue;
+        };
+        let files = [
+            ("ca", &tls.ca, tls::Pem::Certificates),
+            ("cert", &tls.cert, tls::Pem::Certificates),
+            ("key", &tls.key, tls::Pem::PrivateKey),
+        ];
+        for (field, path, what) in files {
+            if let Some(path) = path {
+                pem_files.push((format!("{}.{}", key, field), path, what));
+            }
+        }
+    }
+    for (key, path, what) in pem_files {
+        if let Err(err) = tls::check_pem(path, what) {
+            check(err.kind() == io::ErrorKind::NotFound, &key, err.to_string());
+        }
+    }
+    if config.require_tls {
+        let plain = |listen: Option<SocketAddr>| listen.is_some_and(tls::is_exposed) && config.api.tls.is_none();
+        check(
+            !plain(config.api.listen),
+            "api.tls",
+            "is required by require_tls for a non-loopback api.listen".to_string(),
+        );
+        check(
+            !plain(config.api.flight_listen),
+            "api.tls",
+            "is required by require_tls for a non-loopback api.flight_listen".to_string(),
+        );
+        check(
+            config.mqtt.as_ref().is_none_or(|m| m.tls.is_some()),
+            "mqtt.tls",
+            "is required by require_tls".to_string(),
+        );
+        check(
+            config.nats.as_ref().is_none_or(|n| n.tls.is_some() || n.url.starts_with("tls://")),
+            "nats.tls",
+            "is required by require_tls".to_string(),
+        );
+        check(
+            config.modbus_proxy.as_ref().is_none_or(|p| !tls::is_exposed(p.listen)),
+            "modbus_proxy.listen",
+            "has no TLS; require_tls allows it on a loopback address only".to_string(),
+        );
+        check(
+            config.redundancy.is_none(),
+            "redundancy",
+            "exchanges samples in plain UDP, which require_tls does not allow".to_string(),
+        );
+    }
+    if let Some(disk) = &config.disk {
+        check(
+            (1..=24 * 3600).contains(&disk.check_interval_secs),
+            "disk.check_interval_secs",
+            format!("must be between 1 and 86400, got {}", disk.check_interval_secs),
+        );
+        let database = config.database_url.trim_start_matches("sqlite:").trim_start_matches("//");
+        for dir in &disk.prune_dirs {
+            let trimmed = dir.trim_end_matches('/');
+            check(
+                !trimmed.is_empty() && trimmed != "." && !Path::new(database).starts_with(trimmed),
+                "disk.prune_dirs",
+                format!("{:?} would prune the database", dir),
+            );
+        }
+    }
+    if let Some(maintenance) = &config.maintenance {
+        for (key, value, max) in [
+            ("maintenance.integrity_check_hours", maintenance.integrity_check_hours, 24 * 31),
+            ("maintenance.checkpoint_minutes", maintenance.checkpoint_minutes, 24 * 60),
+            ("maintenance.vacuum_hours", maintenance.vacuum_hours, 24 * 365),
+        ] {
+            check((1..=max).contains(&value), key, format!("must be between 1 and {}, got {}", max, value));
+        }
+    }
+    if let Some(backup) = &config.backup {
+        check(!backup.dir.is_empty(), "backup.dir", "must not be empty".to_string());
+        check(
+            (1..=24 * 31).contains(&backup.interval_hours),
+            "backup.interval_hours",
+            format!("must be between 1 and 744, got {}", backup.interval_hours),
+        );
+        check((1..=1000).contains(&backup.keep), "backup.keep", format!("must be between 1 and 1000, got {}", backup.keep));
+        check(
+            backup.upload_command.first().is_none_or(|program| !program.is_empty()),
+            "backup.upload_command",
+            "must start with a program".to_string(),
+        );
+    }
+
+    for (name, channel) in &config.registers.temperatures {
+        let key = format!("registers.temperatures.{}", name);
+        check(
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+            &key,
+            "channel names may only contain lowercase letters, digits and '_'".to_string(),
+        );
+        check(
+            !sentinels::REGISTERS.contains(&name.as_str()),
+            &key,
+            format!("{:?} names a register; `[registers.sentinels.{}]` could not tell them apart", name, name),
+        );
+        for (field, value) in [("warn", channel.warn), ("alarm", channel.alarm)] {
+            if let Some(value) = value {
+                check(
+                    (-50.0..=300.0).contains(&value),
+                    &format!("{}.{}", key, field),
+                    format!("must be between -50 and 300 °C, got {}", value),
+                );
+            }
+        }
+        if let (Some(warn), Some(alarm)) = (channel.warn, channel.alarm) {
+            check(warn < alarm, &format!("{}.warn", key), format!("must be below alarm ({})", alarm));
+        }
+        check(
+            (0..=3600).contains(&channel.duration_secs),
+            &format!("{}.duration_secs", key),
+            format!("must be between 0 and 3600, got {}", channel.duration_secs),
+        );
+        check(
+            (0..=3600).contains(&channel.startup_grace_secs),
+            &format!("{}.startup_grace_secs", key),
+            format!("must be between 0 and 3600, got {}", channel.startup_grace_secs),
+        );
+        check_renamed_from(&mut check, &key, &channel.renamed_from, &config.registers.temperatures);
+    }
+    for (name, input) in &config.registers.pulses {
+        let key = |field: &str| format!("registers.pulses.{}.{}", name, field);
+        check(
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+            &format!("registers.pulses.{}", name),
+            "input names may only contain lowercase letters, digits and '_'".to_string(),
+        );
+        check(
+            Signal::from_name(name).is_none()
+                && name != "speed_reference"
+                && !config.registers.temperatures.contains_key(name),
+            &format!("registers.pulses.{}", name),
+            "is already the name of a signal or temperature channel".to_string(),
+        );
+        if let Some(unit_id) = input.unit_id {
+            check(
+                config.transport == TransportKind::ModbusRtu,
+                &key("unit_id"),
+                "reads other bus devices, which only transport = \"modbus_rtu\" supports".to_string(),
+            );
+            check((1..=247).contains(&unit_id), &key("unit_id"), format!("must be between 1 and 247, got {}", unit_id));
+        }
+        check(
+            transport::is_read_function(input.function),
+            &key("function"),
+            format!("must be a read function, got {:#04x}", input.function),
+        );
+        check((1..=2).contains(&input.count), &key("count"), format!("must be 1 or 2, got {}", input.count));
+        check(
+            input.scale.is_finite() && input.scale > 0.0,
+            &key("scale"),
+            format!("must be positive, got {}", input.scale),
+        );
+        check(!input.unit.trim().is_empty(), &key("unit"), "must not be empty".to_string());
+        if let (Some(max_step), true) = (input.max_step, (1..=2).contains(&input.count)) {
+            check(
+                max_step >= 1 && max_step < 1 << input.bits(),
+                &key("max_step"),
+                format!("must be between 1 and {}, got {}", (1u64 << input.bits()) - 1, max_step),
+            );
+        }
+        let pulses = &config.registers.pulses;
+        check_renamed_from(&mut check, &format!("registers.pulses.{}", name), &input.renamed_from, pulses);
+    }
+
+    for (name, sentinel) in &config.registers.sentinels {
+        let key = |field: &str| format!("registers.sentinels.{}.{}", name, field);
+        let registers = &config.registers;
+        let read = match name.as_str() {
+            "voltage" | "current" | "heat" => true,
+            "speed" => registers.speed_estimate.is_none(),
+            "speed_reference" => registers.speed_reference.is_some(),
+            sentinels::FREQUENCY => registers.speed_estimate.is_some(),
+            channel => registers.temperatures.contains_key(channel),
+        };
+        check(
+            read,
+            &format!("registers.sentinels.{}", name),
+            "must name a register that is read: voltage, current, heat, speed, speed_reference, frequency (of \
+             speed_estimate) or a temperature channel"
+                .to_string(),
+        );
+        check(!sentinel.values.is_empty(), &key("values"), "must list at least one value".to_string());
+        check(
+            sentinel.action != SentinelAction::Default || sentinel.default.is_some_and(f64::is_finite),
+            &key("default"),
+            "must be set to a number with action = \"default\"".to_string(),
+        );
+    }
+
+    if let Some(estimate) = &config.registers.speed_estimate {
+        check(
+            estimate.poles >= 2 && estimate.poles % 2 == 0 && estimate.poles <= 64,
+            "registers.speed_estimate.poles",
+            format!("must be an even number between 2 and 64, got {}", estimate.poles),
+        );
+        check(
+            estimate.rated_slip_rpm.is_finite() && estimate.rated_slip_rpm >= 0.0,
+            "registers.speed_estimate.rated_slip_rpm",
+            format!("must not be negative, got {}", estimate.rated_slip_rpm),
+        );
+        if let Some(rated) = estimate.rated_current_a {
+            check(
+                rated.is_finite() && rated > 0.0,
+                "registers.speed_estimate.rated_current_a",
+                format!("must be positive, got {}", rated),
+            );
+        }
+    }
+
+    let registers = config.registers.entries();
+    for (name, def) in &registers {
+        check(
+            (1..=2).contains(&def.count),
+            &format!("{}.count", name),
+            format!("must be 1 or 2, got {}", def.count),
+        );
+        check(
+            def.end() <= 0x1_0000,
+            &format!("{}.address", name),
+            format!("block {}..{} runs past the end of the address space", def.address, def.end()),
+        );
+        check(
+            transport::is_read_function(def.function),
+            &format!("{}.function", name),
+            format!(
+                "must be 3, 4 or a user-defined function code (65-72, 100-110), got {}",
+                def.function
+            ),
+        );
+        check(
+            config.transport == TransportKind::ModbusRtu || def.function == transport::READ_INPUT_REGISTERS,
+            &format!("{}.function", name),
+            "only applies to transport = \"modbus_rtu\"".to_string(),
+        );
+    }
+    // Over EtherNet/IP, `address` is a parameter instance, which holds a whole
+    // value whatever its `count`, so neighbouring instances cannot overlap.
+    let modbus_registers: &[_] = if config.transport == TransportKind::ModbusRtu { &registers[..] } else { &[] };
+    for (i, (name, def)) in modbus_registers.iter().enumerate() {
+        for (other_name, other) in registers[..i].iter().filter(|(_, other)| other.same_table(def)) {
+            check(
+                def.end() <= other.address as u32 || other.end() <= def.address as u32,
+                name,
+                format!(
+                    "registers {}..{} overlap `{}` ({}..{})",
+                    def.address,
+                    def.end(),
+                    other_name,
+                    other.address,
+                    other.end()
+                ),
+            );
+        }
+    }
+
+    issues
+}
+
+fn check_condition(check: &mut impl FnMut(bool, &str, String), key: &str, condition: &Condition, names: &[&str]) {
+    match condition {
+        Condition::All { all: conditions } | Condition::Any { any: conditions } => {
+            let list = if matches!(condition, Condition::All { .. }) { "all" } else { "any" };
+            check(!conditions.is_empty(), &format!("{}.{}", key, list), "must list at least one condition".to_string());
+            for (i, condition) in conditions.iter().enumerate() {
+                check_condition(check, &format!("{}.{}[{}]", key, list, i), condition, names);
+            }
+        }
+        Condition::Compare { signal, value, .. } => {
+            check(
+                names.contains(&signal.as_str()),
+                key,
+                format!("unknown signal {:?}; available: {:?}", signal, names),
+            );
+            check(value.is_finite(), key, format!("value must be finite, got {}", value));
+        }
+    }
+}
+
+fn check_template(check: &mut impl FnMut(bool, &str, String), key: &str, template: &str, per_signal: bool) {
+    let unknown = output::unknown_placeholders(template);
+    check(
+        unknown.is_empty(),
+        key,
+        format!("unknown placeholder(s) {:?}; available: {:?}", unknown, output::PLACEHOLDERS),
+    );
+    check(!template.trim().is_empty(), key, "must not be empty".to_string());
+    if per_signal {
+        check(
+            template.contains("{signal}"),
+            key,
+            "must contain {signal}, otherwise every chart writes the same file".to_string(),
+        );
+    }
+}
+
+/// Former names must not be configured themselves, or both would claim the
+/// same history.
+fn check_renamed_from<T>(
+    check: &mut impl FnMut(bool, &str, String),
+    key: &str,
+    renamed_from: &[String],
+    configured: &BTreeMap<String, T>,
+) {
+    for former in renamed_from {
+        check(
+            !former.is_empty() && !configured.contains_key(former),
+            &format!("{}.renamed_from", key),
+            format!("{:?} must not be empty or the name of a configured channel", former),
+        );
+    }
+}
+
+/// Finds the byte offset of a dotted key in the parsed document, falling back
+/// to the closest parent that exists (e.g. a defaulted table).
+fn locate(root: &toml_edit::Item, key: &str) -> Option<usize> {
+    let mut item = root;
+    let mut found = None;
+    for part in key.split('.') {
+        match item.get(part) {
+            Some(next) => {
+                item = next;
+                found = next.span().map(|span| span.start).or(found);
+            }
+            None => break,
+        }
+    }
+    found
+}
+
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rfind('\n').map_or(before.len(), |nl| before.len() - nl - 1) + 1;
+    (line, column)
+}
@@ -0,0 +1,167 @@
+//! Snapshots of the configuration the data was recorded under.
+//!
+//! Stored values only make sense together with the settings in force when
+//! they were taken: the nameplate data of `[motor]`, the register map with
+//! its read functions, units, pulse scales and sentinels, the alarm limits
+//! and `[[alarm_rules]]`, the `[counters]`, and the `[equipment]` and
+//! `[thermal]` models behind their alarms. The lineage hash (see `lineage`)
+//! tells whether two exports were made under the same configuration, but
+//! not what it was. So the monitor keeps those settings, with the charts in
+//! effect, in the `config_history` table of its database, recorded whenever
+//! their hash differs from the last one, with the time they came into
+//! force: at startup, and when reloaded charts (see `chart_defs`) change.
+//!
+//! The history travels with the data: archived hour blocks (`archive`),
+//! backups and synced copies live in the same database. Exports carry the
+//! snapshots in force over their range as `config.snapshots` metadata, a
+//! JSON array oldest first: the one in force at the start of the range,
+//! then any recorded during it. Secrets are not part of a snapshot.
+
+use super::chart_defs::ChartDef;
+use super::config::{
+    AlarmRuleConfig, CountersConfig, EquipmentConfig, LimitsConfig, MonitorConfig, RegisterMap, ThermalConfig,
+};
+use super::lineage;
+use super::units::{UnitInfo, Units};
+use super::MotorSpecs;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+
+/// The settings that only change with a restart.
+#[derive(Debug, Serialize)]
+struct Sections<'a> {
+    motor: &'a MotorSpecs,
+    registers: &'a RegisterMap,
+    limits: &'a LimitsConfig,
+    alarm_rules: &'a [AlarmRuleConfig],
+    counters: &'a Option<CountersConfig>,
+    equipment: &'a Option<EquipmentConfig>,
+    thermal: &'a Option<ThermalConfig>,
+    /// Stored and display unit of every value.
+    units: Vec<UnitInfo>,
+}
+
+/// The settings needed to interpret stored values, taken once; the charts
+/// in effect are added by `json`.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    sections: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Snapshot {
+    pub fn new(config: &MonitorConfig) -> Self {
+        let sections = Sections {
+            motor: &config.motor,
+            registers: &config.registers,
+            limits: &config.limits,
+            alarm_rules: &config.alarm_rules,
+            counters: &config.counters,
+            equipment: &config.equipment,
+            thermal: &config.thermal,
+            units: Units::new(&config.registers).all(),
+        };
+        match serde_json::to_value(sections).expect("snapshot serializes") {
+            serde_json::Value::Object(sections) => Snapshot { sections },
+            _ => unreachable!("sections serialize to an object"),
+        }
+    }
+
+    /// The snapshot with `charts` in effect.
+    pub fn json(&self, charts: &[ChartDef]) -> String {
+        let mut snapshot = self.sections.clone();
+        snapshot.insert("charts".to_string(), serde_json::to_value(charts).expect("charts serialize"));
+        serde_json::Value::Object(snapshot).to_string()
+    }
+}
+
+/// A snapshot as stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recorded {
+    /// Epoch milliseconds from which the snapshot was in force.
+    pub taken_at: i64,
+    pub sha256: String,
+    pub json: String,
+}
+
+pub async fn setup_config_history_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS config_history (
+            taken_at INTEGER NOT NULL,
+            sha256 TEXT NOT NULL,
+            snapshot TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Records `snapshot` with `charts` as in force from `now` (milliseconds),
+/// unless it is the latest one recorded. Returns whether it was recorded.
+pub async fn record(pool: &SqlitePool, snapshot: &Snapshot, charts: &[ChartDef], now: i64) -> bool {
+    let json = snapshot.json(charts);
+    let sha256 = lineage::hex(&Sha256::digest(json.as_bytes()));
+    let latest: Option<(String,)> =
+        sqlx::query_as("SELECT sha256 FROM config_history ORDER BY taken_at DESC, rowid DESC LIMIT 1")
+            .fetch_optional(pool)
+            .await
+            .unwrap();
+    if latest.is_some_and(|(latest,)| latest == sha256) {
+        return false;
+    }
+    sqlx::query("INSERT INTO config_history (taken_at, sha256, snapshot) VALUES (?, ?, ?)")
+        .bind(now)
+        .bind(&sha256)
+        .bind(&json)
+        .execute(pool)
+        .await
+        .unwrap();
+    log::info!("recorded configuration snapshot {}", &sha256[..12]);
+    true
+}
+
+/// The snapshots in force over `[from, to)`, oldest first. Empty for a
+/// database without the table, e.g. one from an older version.
+pub async fn in_force(pool: &SqlitePool, from: i64, to: i64) -> Vec<Recorded> {
+    let rows: Vec<(i64, String, String)> = sqlx::query_as(
+        r#"
+        SELECT taken_at, sha256, snapshot FROM config_history
+        WHERE taken_at >= COALESCE((SELECT MAX(taken_at) FROM config_history WHERE taken_at <= ?), ?)
+            AND taken_at < ?
+        ORDER BY taken_at, rowid
+        "#,
+    )
+    .bind(from)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    rows.into_iter().map(|(taken_at, sha256, json)| Recorded { taken_at, sha256, json }).collect()
+}
+
+/// The `config.snapshots` metadata entry of an export of `[from, to)`; no
+/// entry when no snapshot was recorded.
+pub async fn metadata(pool: &SqlitePool, from: i64, to: i64) -> BTreeMap<String, String> {
+    let snapshots: Vec<serde_json::Value> = in_force(pool, from, to)
+        .await
+        .into_iter()
+        .filter_map(|recorded| {
+            let snapshot: serde_json::Value = serde_json::from_str(&recorded.json).ok()?;
+            Some(serde_json::json!({
+                "in_force_from": lineage::rfc3339(recorded.taken_at),
+                "sha256": recorded.sha256,
+                "snapshot": snapshot,
+            }))
+        })
+        .collect();
+    let mut metadata = BTreeMap::new();
+    if !snapshots.is_empty() {
+        metadata.insert("config.snapshots".to_string(), serde_json::Value::from(snapshots).to_string());
+    }
+    metadata
+}
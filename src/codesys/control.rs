@@ -0,0 +1,406 @@
+//! Operator control panel: start, stop, setpoint, fault reset and parameter
+//! sets over HTTP.
+//!
+//! Commands take two steps. `POST /control/<action>` checks the request and
+//! the interlock and answers with a confirmation token; `POST
+//! /control/confirm/<token>` within `confirm_secs` carries it out. Only
+//! operators listed in `[control.operators]` hold the control role: every
+//! request must carry one of their tokens as `Authorization: Bearer`, and a
+//! confirmation token is only accepted from the operator it was issued to.
+//!
+//! Start, setpoint and fault reset are refused while the interlock is
+//! tripped, both when requested and when confirmed; stop always goes
+//! through. Start, stop and fault reset write their `value` to their
+//! register from the polling loop, like the interlock, and parameter sets
+//! their registers (see `parameter_sets`): only with `writes_enabled` and
+//! outside a global `dry_run`. Setpoints are handed to
+//! the setpoint ramps. Every confirmed command is recorded in the audit log
+//! under the operator's name. `GET /control` serves a minimal HTML panel.
+
+use super::audit;
+use super::config::{CommandRegister, ControlConfig, ParameterSet};
+use super::events;
+use super::lineage;
+use super::parameter_sets::{self, SetError};
+use super::setpoint::{SetpointCommand, SetpointHandle, SetpointKind};
+use super::transport::{Transport, TransportError};
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Start,
+    Stop,
+    ResetFault,
+    Setpoint { kind: SetpointKind, value: f64 },
+    ParameterSet(String),
+}
+
+impl Action {
+    /// Name in the audit log and in `/control/<action>`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Start => "start",
+            Action::Stop => "stop",
+            Action::ResetFault => "reset_fault",
+            Action::Setpoint { .. } => "setpoint",
+            Action::ParameterSet(_) => "parameter_set",
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Action::Start => "start the motor".to_string(),
+            Action::Stop => "stop the motor".to_string(),
+            Action::ResetFault => "reset the drive fault".to_string(),
+            Action::Setpoint { kind, value } => format!("set the {} setpoint to {}", kind.name(), value),
+            Action::ParameterSet(name) => format!("write the parameter set {}", name),
+        }
+    }
+}
+
+/// Why a control request was refused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Refusal {
+    /// The action is not configured or its value is out of range.
+    Invalid(String),
+    Interlocked,
+    /// Unknown, expired or issued to another operator.
+    UnknownToken,
+    /// The command queue is full.
+    Busy,
+}
+
+impl Refusal {
+    pub fn message(&self) -> String {
+        match self {
+            Refusal::Invalid(message) => message.clone(),
+            Refusal::Interlocked => "refused: the interlock is tripped".to_string(),
+            Refusal::UnknownToken => "unknown or expired confirmation token".to_string(),
+            Refusal::Busy => "control queue is full, try again".to_string(),
+        }
+    }
+}
+
+/// Answer to a control request: the token confirming it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Prepared {
+    pub token: String,
+    pub action: String,
+    pub expires_in_secs: u64,
+}
+
+#[derive(Debug)]
+struct Pending {
+    action: Action,
+    operator: String,
+    expires: Instant,
+}
+
+#[derive(Debug)]
+struct Command {
+    action: Action,
+    by: String,
+}
+
+/// API side: authorises, checks and queues commands.
+#[derive(Debug)]
+pub struct ControlHandle {
+    config: ControlConfig,
+    setpoints: Option<SetpointHandle>,
+    tx: mpsc::Sender<Command>,
+    tripped: Arc<AtomicBool>,
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+/// Polling loop side: writes confirmed commands.
+#[derive(Debug)]
+pub struct ControlExecutor {
+    config: ControlConfig,
+    rx: mpsc::Receiver<Command>,
+    tripped: Arc<AtomicBool>,
+    /// Global `dry_run`.
+    dry_run: bool,
+}
+
+pub fn controller(
+    config: ControlConfig,
+    setpoints: Option<SetpointHandle>,
+    dry_run: bool,
+) -> (ControlHandle, ControlExecutor) {
+    let (tx, rx) = mpsc::channel(16);
+    let tripped = Arc::new(AtomicBool::new(false));
+    let handle = ControlHandle {
+        config: config.clone(),
+        setpoints,
+        tx,
+        tripped: Arc::clone(&tripped),
+        pending: Mutex::new(HashMap::new()),
+    };
+    (handle, ControlExecutor { config, rx, tripped, dry_run })
+}
+
+/// Compares without stopping at the first differing byte.
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// 128 random bits from the operating system, as hex.
+fn new_token() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("the OS random number generator is available");
+    lineage::hex(&bytes)
+}
+
+/// What a command writes.
+enum Write<'a> {
+    Register(&'a CommandRegister),
+    Set(&'a ParameterSet),
+}
+
+impl Write<'_> {
+    fn describe(&self) -> String {
+        match self {
+            Write::Register(register) => format!("{} to register {}", register.value, register.register.address),
+            Write::Set(set) => parameter_sets::describe(set),
+        }
+    }
+
+    async fn send(&self, transport: &mut dyn Transport) -> Result<(), SendError> {
+        match self {
+            Write::Register(register) => transport.write(&register.register, register.value).await.map_err(|err| {
+                match err {
+                    TransportError::Unverified(err) => SendError::Unverified(err.to_string()),
+                    err => {
+                        let changed = matches!(err, TransportError::Rejected { .. });
+                        SendError::Failed { error: err.to_string(), changed }
+                    }
+                }
+            }),
+            Write::Set(set) => parameter_sets::write(transport, set).await.map_err(|err| {
+                let changed = !matches!(err, SetError::NotWritten(_));
+                SendError::Failed { error: err.to_string(), changed }
+            }),
+        }
+    }
+}
+
+/// Why a command's write did not go through as meant.
+enum SendError {
+    /// The write failed; `changed` when it may have changed the drive
+    /// anyway, i.e. a rejected or rolled back one.
+    Failed { error: String, changed: bool },
+    /// The write was acknowledged, but reading it back failed.
+    Unverified(String),
+}
+
+fn command_write<'a>(config: &'a ControlConfig, action: &Action) -> Option<Write<'a>> {
+    match action {
+        Action::Start => config.start.as_ref().map(Write::Register),
+        Action::Stop => config.stop.as_ref().map(Write::Register),
+        Action::ResetFault => config.reset_fault.as_ref().map(Write::Register),
+        Action::Setpoint { .. } => None,
+        Action::ParameterSet(name) => config.parameter_sets.get(name).map(Write::Set),
+    }
+}
+
+impl ControlHandle {
+    /// The operator holding `bearer`, if any.
+    pub fn operator(&self, bearer: &str) -> Option<String> {
+        let mut found = None;
+        for (name, token) in &self.config.operators {
+            if same_token(token, bearer) {
+                found = Some(name.clone());
+            }
+        }
+        found
+    }
+
+    fn check(&self, action: &Action) -> Result<(), Refusal> {
+        match action {
+            Action::Setpoint { kind, value } => {
+                let setpoints =
+                    self.setpoints.as_ref().ok_or_else(|| Refusal::Invalid("setpoints are not configured".to_string()))?;
+                setpoints.check(*kind, *value).map_err(Refusal::Invalid)?;
+            }
+            Action::ParameterSet(name) => {
+                if !self.config.parameter_sets.contains_key(name) {
+                    return Err(Refusal::Invalid(format!("control.parameter_sets.{} is not configured", name)));
+                }
+            }
+            _ => {
+                if command_write(&self.config, action).is_none() {
+                    return Err(Refusal::Invalid(format!("control.{} is not configured", action.name())));
+                }
+            }
+        }
+        if *action != Action::Stop && self.tripped.load(Ordering::Relaxed) {
+            return Err(Refusal::Interlocked);
+        }
+        Ok(())
+    }
+
+    /// Checks `action` and issues the token confirming it.
+    pub fn prepare(&self, operator: &str, action: Action) -> Result<Prepared, Refusal> {
+        self.check(&action)?;
+        let token = new_token();
+        let expires = Instant::now() + Duration::from_secs(self.config.confirm_secs);
+        let mut pending = self.pending.lock().unwrap();
+        let now = Instant::now();
+        pending.retain(|_, p| p.expires > now);
+        let described = action.describe();
+        pending.insert(token.clone(), Pending { action, operator: operator.to_string(), expires });
+        Ok(Prepared { token, action: described, expires_in_secs: self.config.confirm_secs })
+    }
+
+    /// Carries out the action `token` confirms; the token is used up either way.
+    pub fn confirm(&self, operator: &str, token: &str) -> Result<Action, Refusal> {
+        let pending = {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.get(token) {
+                Some(p) if p.operator == operator => pending.remove(token),
+                _ => None,
+            }
+        };
+        let Some(pending) = pending.filter(|p| p.expires > Instant::now()) else {
+            return Err(Refusal::UnknownToken);
+        };
+        let action = pending.action;
+        self.check(&action)?;
+        let by = operator.to_string();
+        match action {
+            Action::Setpoint { kind, value } => {
+                let setpoints = self.setpoints.as_ref().expect("checked above");
+                setpoints.submit(SetpointCommand { kind, value, by, dry_run: false }).map_err(|_| Refusal::Busy)?;
+            }
+            _ => self.tx.try_send(Command { action: action.clone(), by }).map_err(|_| Refusal::Busy)?,
+        }
+        Ok(action)
+    }
+}
+
+impl ControlExecutor {
+    /// Mirrors the interlock's latch for the API's checks.
+    pub fn set_interlock_tripped(&self, tripped: bool) {
+        self.tripped.store(tripped, Ordering::Relaxed);
+    }
+
+    /// Writes confirmed commands; returns whether a stop was among them.
+    pub async fn step(
+        &mut self,
+        pool: &SqlitePool,
+        mut transport: Option<&mut (dyn Transport + '_)>,
+        motor_id: &str,
+        now: i64,
+    ) -> bool {
+        let mut stopped = false;
+        while let Ok(command) = self.rx.try_recv() {
+            let Some(write) = command_write(&self.config, &command.action) else {
+                continue;
+            };
+            let would_write = format!("would write {}", write.describe());
+            let writes = self.config.writes_enabled && !self.dry_run;
+            let outcome = if command.action != Action::Stop && self.tripped.load(Ordering::Relaxed) {
+                "refused: the interlock tripped after confirmation".to_string()
+            } else {
+                match (writes, transport.as_deref_mut()) {
+                    (false, _) if self.dry_run => format!("dry run (dry_run = true); {}", would_write),
+                    (false, _) => format!("dry run (writes_enabled = false); {}", would_write),
+                    (true, None) => "not written: no field-bus connection".to_string(),
+                    (true, Some(transport)) => match write.send(transport).await {
+                        Ok(()) => {
+                            stopped |= command.action == Action::Stop;
+                            format!("wrote {}", write.describe())
+                        }
+                        Err(SendError::Unverified(err)) => {
+                            stopped |= command.action == Action::Stop;
+                            let message = format!("{}: {}", command.action.describe(), err);
+                            events::record_event(pool, motor_id, now, "write_unverified", &message).await;
+                            format!("wrote {}, read-back failed: {}", write.describe(), err)
+                        }
+                        Err(SendError::Failed { error, changed }) => {
+                            if changed {
+                                let message = format!("{}: {}", command.action.describe(), error);
+                                events::record_event(pool, motor_id, now, "write_failed", &message).await;
+                            }
+                            format!("write failed: {}", error)
+                        }
+                    },
+                }
+            };
+            let detail = format!("{}; {}", command.action.describe(), outcome);
+            audit::record(pool, motor_id, now, &command.by, command.action.name(), &detail).await;
+        }
+        stopped
+    }
+}
+
+/// The control panel page; `{base}` is replaced with the routes' prefix.
+pub const PANEL_HTML: &str = r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Motor control</title>
+<style>
+body { font-family: sans-serif; max-width: 32em; margin: 2em auto; }
+button, input, select { font-size: 1em; margin: 0.2em; }
+#status { white-space: pre-wrap; color: #444; }
+</style>
+</head>
+<body>
+<h1>Motor control</h1>
+<p><label>Operator token <input id="token" type="password" size="32"></label></p>
+<p>
+<button data-action="start">Start</button>
+<button data-action="stop">Stop</button>
+<button data-action="reset_fault">Reset fault</button>
+</p>
+<p>
+<select id="kind"><option>speed</option><option>torque</option></select>
+<input id="value" type="number" step="any">
+<button data-action="setpoint">Set</button>
+</p>
+<p>
+<input id="set" placeholder="parameter set">
+<button data-action="parameter_set">Write</button>
+</p>
+<p id="status"></p>
+<script>
+const base = "{base}/control";
+const status = text => document.getElementById("status").textContent = text;
+async function post(path) {
+  const token = document.getElementById("token").value;
+  const response = await fetch(base + path, { method: "POST", headers: { Authorization: "Bearer " + token } });
+  const body = await response.text();
+  if (!response.ok) throw new Error(body);
+  return JSON.parse(body);
+}
+for (const button of document.querySelectorAll("button")) {
+  button.onclick = async () => {
+    let path = "/" + button.dataset.action;
+    if (button.dataset.action === "setpoint") {
+      const kind = document.getElementById("kind").value;
+      path += "?kind=" + kind + "&value=" + encodeURIComponent(document.getElementById("value").value);
+    }
+    if (button.dataset.action === "parameter_set") {
+      path += "?name=" + encodeURIComponent(document.getElementById("set").value);
+    }
+    try {
+      const prepared = await post(path);
+      if (!confirm("Confirm: " + prepared.action + "?")) return status("cancelled");
+      const done = await post("/confirm/" + prepared.token);
+      status("sent: " + done.action);
+    } catch (err) {
+      status(err.message);
+    }
+  };
+}
+</script>
+</body>
+</html>
+"#;
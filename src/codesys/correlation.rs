@@ -0,0 +1,213 @@
+//! Cross-signal correlation for reports.
+//!
+//! With `[correlation]`, reports get a *Signal correlation* section. The
+//! signals are averaged over `bucket_secs` buckets of the report period and
+//! their Pearson correlation is tabulated as a matrix. For each of
+//! `lag_pairs` the second signal is shifted against the first by up to
+//! `max_lag_minutes` either way; the lag with the strongest correlation is
+//! how long the second takes to follow, e.g. how far winding temperature
+//! trails a load change. Each lag curve is plotted next to the report.
+//!
+//! A correlation needs both signals to vary: one that stayed constant over
+//! the period (torque, while it is a fixed value) shows no value.
+
+use super::config::{CorrelationConfig, MonitorConfig};
+use super::export::PAGE_SIZE;
+use super::output::{add_png_text, write_atomically};
+use super::{motor_data_range, Signal};
+use plotters::prelude::*;
+use sqlx::sqlite::SqlitePool;
+use std::error::Error;
+use std::path::Path;
+
+/// Fewest buckets with both values a correlation is computed from.
+const MIN_POINTS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct CorrelationSettings {
+    pub config: CorrelationConfig,
+    /// Names in the matrix, in order.
+    pub names: Vec<String>,
+}
+
+impl CorrelationSettings {
+    pub fn from_config(config: &MonitorConfig) -> Option<CorrelationSettings> {
+        config.correlation.as_ref().map(|correlation| {
+            let names = if correlation.signals.is_empty() {
+                [Signal::Power, Signal::Torque, Signal::Speed, Signal::Heat]
+                    .iter()
+                    .map(|s| s.name().to_string())
+                    .chain(config.registers.temperatures.keys().cloned())
+                    .collect()
+            } else {
+                correlation.signals.clone()
+            };
+            CorrelationSettings { config: correlation.clone(), names }
+        })
+    }
+}
+
+/// Correlation of `follower` with `leader` by lag.
+#[derive(Debug, Clone)]
+pub struct LagCurve {
+    pub leader: String,
+    pub follower: String,
+    /// `(lag in minutes, correlation)`; at a positive lag `follower` trails
+    /// `leader`. Lags without enough data are left out.
+    pub points: Vec<(f64, f64)>,
+    /// Longest lag tried, either way.
+    pub max_lag_minutes: f64,
+}
+
+impl LagCurve {
+    /// The lag with the strongest correlation of either sign.
+    pub fn peak(&self) -> Option<(f64, f64)> {
+        self.points.iter().copied().max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+    }
+
+    /// File name suffix of the lag plot.
+    pub fn name(&self) -> String {
+        format!("lag_{}_{}", self.leader, self.follower)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Correlation {
+    pub names: Vec<String>,
+    /// `matrix[i][j]` correlates `names[i]` with `names[j]`.
+    pub matrix: Vec<Vec<Option<f64>>>,
+    pub lags: Vec<LagCurve>,
+}
+
+/// Pearson correlation of `a[i]` with `b[i + lag]` over the indices where
+/// both are known; `None` with fewer than `MIN_POINTS` pairs or when either
+/// side is constant.
+pub fn pearson(a: &[f64], b: &[f64], lag: isize) -> Option<f64> {
+    let pairs: Vec<(f64, f64)> = a
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &x)| {
+            let y = *b.get(usize::try_from(i as isize + lag).ok()?)?;
+            (x.is_finite() && y.is_finite()).then_some((x, y))
+        })
+        .collect();
+    if pairs.len() < MIN_POINTS
+        || pairs.iter().all(|p| p.0 == pairs[0].0)
+        || pairs.iter().all(|p| p.1 == pairs[0].1)
+    {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut sxy, mut sxx, mut syy) = (0.0, 0.0, 0.0);
+    for (x, y) in &pairs {
+        let (dx, dy) = (x - mean_x, y - mean_y);
+        sxy += dx * dy;
+        sxx += dx * dx;
+        syy += dy * dy;
+    }
+    Some((sxy / (sxx * syy).sqrt()).clamp(-1.0, 1.0))
+}
+
+/// Means of each of `names` per `bucket_secs` bucket of `[from, to)`
+/// (seconds); `NaN` for a bucket without values.
+pub async fn resample(pool: &SqlitePool, names: &[String], from: i64, to: i64, bucket_secs: i64) -> Vec<Vec<f64>> {
+    let buckets = ((to - from).max(0) + bucket_secs - 1) / bucket_secs;
+    let mut sums = vec![vec![(0.0, 0u32); buckets as usize]; names.len()];
+    let mut cursor = from * 1000;
+    loop {
+        let page = motor_data_range(pool, cursor, to * 1000, Some(PAGE_SIZE)).await;
+        for data in &page {
+            let bucket = (data.seconds() - from) / bucket_secs;
+            if !(0..buckets).contains(&bucket) {
+                continue;
+            }
+            for (series, name) in sums.iter_mut().zip(names) {
+                if let Some(value) = data.named_value(name).filter(|v| v.is_finite()) {
+                    let (sum, count) = &mut series[bucket as usize];
+                    *sum += value;
+                    *count += 1;
+                }
+            }
+        }
+        match page.last() {
+            Some(last) if page.len() as i64 == PAGE_SIZE => cursor = last.timestamp + 1,
+            _ => break,
+        }
+    }
+    let mean = |(sum, count): (f64, u32)| if count == 0 { f64::NAN } else { sum / count as f64 };
+    sums.into_iter().map(|series| series.into_iter().map(mean).collect()).collect()
+}
+
+/// Correlation matrix and lag curves over `[from, to)` (seconds).
+pub async fn analyze(pool: &SqlitePool, settings: &CorrelationSettings, from: i64, to: i64) -> Correlation {
+    let config = &settings.config;
+    let mut names = settings.names.clone();
+    for name in config.lag_pairs.iter().flatten() {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    let bucket_secs = config.bucket_secs as i64;
+    let series = resample(pool, &names, from, to, bucket_secs).await;
+    let index = |name: &String| names.iter().position(|n| n == name).unwrap();
+
+    let matrix = (0..settings.names.len())
+        .map(|i| (0..settings.names.len()).map(|j| pearson(&series[i], &series[j], 0)).collect())
+        .collect();
+    let max_lag = (config.max_lag_minutes as i64 * 60 / bucket_secs) as isize;
+    let lags = config
+        .lag_pairs
+        .iter()
+        .map(|[leader, follower]| {
+            let (a, b) = (&series[index(leader)], &series[index(follower)]);
+            let points = (-max_lag..=max_lag)
+                .filter_map(|lag| {
+                    let minutes = lag as f64 * bucket_secs as f64 / 60.0;
+                    pearson(a, b, lag).map(|r| (minutes, r))
+                })
+                .collect();
+            LagCurve {
+                leader: leader.clone(),
+                follower: follower.clone(),
+                points,
+                max_lag_minutes: config.max_lag_minutes as f64,
+            }
+        })
+        .collect();
+    Correlation { names: settings.names.clone(), matrix, lags }
+}
+
+/// Plots `curve` with its peak marked, with `text` as PNG text chunks; the
+/// file is replaced atomically.
+pub fn draw_lag_plot(path: &Path, curve: &LagCurve, text: &[(&str, String)]) -> Result<(), Box<dyn Error>> {
+    write_atomically(path, |temp| {
+        render_lag_plot(temp, curve)?;
+        add_png_text(temp, text)
+    })
+}
+
+fn render_lag_plot(path: &Path, curve: &LagCurve) -> Result<(), Box<dyn Error>> {
+    let max_lag_minutes = curve.max_lag_minutes;
+    let root = BitMapBackend::new(path, (640, 480)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let title = format!("{} following {}", curve.follower, curve.leader);
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 30).into_font())
+        .margin(5)
+        .x_label_area_size(30)
+        .y_label_area_size(40)
+        .build_cartesian_2d(-max_lag_minutes..max_lag_minutes, -1.0..1.0)?;
+    chart.configure_mesh().x_desc("Lag (min)").y_desc("Correlation").draw()?;
+
+    let grey = BLACK.mix(0.35);
+    chart.draw_series(LineSeries::new([(-max_lag_minutes, 0.0), (max_lag_minutes, 0.0)], grey))?;
+    chart.draw_series(LineSeries::new([(0.0, -1.0), (0.0, 1.0)], grey))?;
+    chart.draw_series(LineSeries::new(curve.points.iter().copied(), &BLUE))?;
+    if let Some(peak) = curve.peak() {
+        chart.draw_series(std::iter::once(Circle::new(peak, 4, RED.filled())))?;
+    }
+    root.present()?;
+    Ok(())
+}
@@ -0,0 +1,154 @@
+//! Cumulative counters (energy, run hours) read from wrapping registers.
+//!
+//! Drives expose totals such as kWh and run hours as 16- or 32-bit
+//! registers that roll over to zero. `[counters.registers.<name>]` lists
+//! them; every `every_secs` each is read and the increase since the last
+//! read, taking a wrap into account, is added to a running total. The last
+//! raw value and the total are kept in the `counter_state` table, so the
+//! total carries on across wraps and restarts. A counter seen for the first
+//! time starts its total at the register's value.
+//!
+//! A drop in the raw value is a wrap if the increase it implies is at most
+//! `max_step` counts (half the register range by default); otherwise the
+//! device was reset or replaced and counting restarts from zero. Both are
+//! recorded as events (`counter_wrap`, `counter_reset`).
+
+use super::config::CountersConfig;
+use super::events;
+use super::transport::Transport;
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+
+pub async fn setup_counters_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS counter_state (
+            name TEXT PRIMARY KEY,
+            raw INTEGER NOT NULL,
+            total REAL NOT NULL,
+            updated_at INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// How a counter moved between two reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Counted up by this many, without wrapping.
+    Up(u64),
+    /// Rolled over; counted up by this many in total.
+    Wrapped(u64),
+    /// Restarted from zero; counted this many since.
+    Reset(u64),
+}
+
+impl Step {
+    pub fn counts(self) -> u64 {
+        match self {
+            Step::Up(n) | Step::Wrapped(n) | Step::Reset(n) => n,
+        }
+    }
+}
+
+/// The step from `previous` to `current` of a `bits`-wide counter.
+pub fn counter_step(previous: u64, current: u64, bits: u32, max_step: u64) -> Step {
+    if current >= previous {
+        return Step::Up(current - previous);
+    }
+    // A previous value beyond the range (the register was narrowed) cannot wrap.
+    match (1u64 << bits).checked_sub(previous).map(|rest| rest + current) {
+        Some(wrapped) if wrapped <= max_step => Step::Wrapped(wrapped),
+        _ => Step::Reset(current),
+    }
+}
+
+/// A counter's persisted state.
+#[derive(Debug, Clone)]
+pub struct CounterState {
+    pub name: String,
+    pub raw: i64,
+    /// In the counter's unit, i.e. scaled.
+    pub total: f64,
+    pub updated_at: i64,
+}
+
+pub async fn load_states(pool: &SqlitePool) -> Vec<CounterState> {
+    let rows: Vec<(String, i64, f64, i64)> =
+        sqlx::query_as("SELECT name, raw, total, updated_at FROM counter_state ORDER BY name")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+    rows.into_iter().map(|(name, raw, total, updated_at)| CounterState { name, raw, total, updated_at }).collect()
+}
+
+async fn save_state(pool: &SqlitePool, state: &CounterState) {
+    sqlx::query("INSERT OR REPLACE INTO counter_state (name, raw, total, updated_at) VALUES (?, ?, ?, ?)")
+        .bind(&state.name)
+        .bind(state.raw)
+        .bind(state.total)
+        .bind(state.updated_at)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// The polling loop's side: reads the counters when due.
+pub struct CounterReader {
+    config: CountersConfig,
+    states: HashMap<String, CounterState>,
+    last_read: Option<i64>,
+}
+
+impl CounterReader {
+    pub async fn load(pool: &SqlitePool, config: CountersConfig) -> Self {
+        let states = load_states(pool).await.into_iter().map(|s| (s.name.clone(), s)).collect();
+        CounterReader { config, states, last_read: None }
+    }
+
+    /// Reads every counter if `every_secs` have passed since the last round
+    /// and adds its increase to the total. A counter that cannot be read is
+    /// skipped until the next round.
+    pub async fn maybe_read(&mut self, pool: &SqlitePool, motor_id: &str, transport: &mut dyn Transport, now: i64) {
+        if self.last_read.is_some_and(|last| now - last < self.config.every_secs as i64) {
+            return;
+        }
+        self.last_read = Some(now);
+        for (name, register) in &self.config.registers {
+            let raw = match transport.read(&register.register()).await {
+                Ok(raw) => raw as u64,
+                Err(err) => {
+                    log::warn!("cannot read counter {}: {}", name, err);
+                    continue;
+                }
+            };
+            let total = match self.states.get(name) {
+                Some(previous) => {
+                    let step = counter_step(previous.raw as u64, raw, register.bits(), register.max_step());
+                    record_step(pool, motor_id, name, previous.raw, raw, step, now).await;
+                    previous.total + step.counts() as f64 * register.scale
+                }
+                None => raw as f64 * register.scale,
+            };
+            let state = CounterState { name: name.clone(), raw: raw as i64, total, updated_at: now };
+            save_state(pool, &state).await;
+            self.states.insert(name.clone(), state);
+        }
+    }
+}
+
+/// Records a wrap or reset as an event.
+async fn record_step(pool: &SqlitePool, motor_id: &str, name: &str, previous: i64, raw: u64, step: Step, now: i64) {
+    let (kind, message) = match step {
+        Step::Up(_) => return,
+        Step::Wrapped(_) => ("counter_wrap", format!("counter {} wrapped from {} to {}", name, previous, raw)),
+        Step::Reset(_) => {
+            ("counter_reset", format!("counter {} dropped from {} to {}, counting on from zero", name, previous, raw))
+        }
+    };
+    log::info!("{}", message);
+    events::record_event(pool, motor_id, now, kind, &message).await;
+}
@@ -0,0 +1,336 @@
+//! Peak demand: average power over the utility's demand windows.
+//!
+//! Utilities bill many sites for their highest average power over any
+//! demand window of the month, typically 15 minutes. With a `[demand]`
+//! section the monitor closes a window every `window_minutes`, counted from
+//! midnight in the motor's time zone (see `location`), and stores its
+//! average power in the `demand` table. Each sample counts for the time
+//! until the next one, up to `MAX_GAP_MS`, and the average is taken over the
+//! time covered, so an outage does not pass for zero demand. A window
+//! without samples is not stored.
+//!
+//! The highest window of each calendar month is kept in `demand_peaks`; a
+//! window that beats it is recorded as a `demand_peak` event. After every
+//! window the day's demand profile is drawn to `path_template`, one bar per
+//! window with the month's peak as a line. Windows the monitor was down for
+//! are filled in from the stored samples on the next start.
+//!
+//! GraphQL serves the windows, the monthly peaks and the rolling average
+//! over the trailing window (`demand`). The fleet report adds up the
+//! motors of each `site` window by window for the site's coincident peak.
+
+use super::config::{DemandConfig, FormatConfig, MonitorConfig};
+use super::disk;
+use super::events;
+use super::location::Zone;
+use super::output::{add_png_text, chart_text, ensure_parent, render_path, write_atomically, OutputContext};
+use super::{motor_data_range, MotorData};
+use chrono::prelude::*;
+use plotters::prelude::*;
+use sqlx::sqlite::SqlitePool;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+
+/// A sample counts for the time until the next one, up to this (ms).
+const MAX_GAP_MS: i64 = 60_000;
+/// Wait after a window closes, so its last samples are stored.
+const SETTLE_SECS: u64 = 30;
+/// How far back windows are filled in on start-up.
+const BACKFILL_MS: i64 = 24 * 3_600_000;
+
+pub async fn setup_demand_tables(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS demand (
+            motor_id TEXT NOT NULL,
+            window_start INTEGER NOT NULL,
+            kw REAL NOT NULL,
+            PRIMARY KEY (motor_id, window_start)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS demand_peaks (
+            motor_id TEXT NOT NULL,
+            month TEXT NOT NULL,
+            window_start INTEGER NOT NULL,
+            kw REAL NOT NULL,
+            PRIMARY KEY (motor_id, month)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// What demand tracking needs from the monitor configuration.
+#[derive(Debug, Clone)]
+pub struct DemandSettings {
+    pub config: DemandConfig,
+    pub motor_id: String,
+    pub zone: Zone,
+    pub format: FormatConfig,
+}
+
+impl DemandSettings {
+    /// `None` without a `[demand]` section.
+    pub fn from_config(config: &MonitorConfig) -> Option<DemandSettings> {
+        config.demand.as_ref().map(|demand| DemandSettings {
+            config: demand.clone(),
+            motor_id: config.motor_id.clone(),
+            zone: config.format.zone,
+            format: config.format.clone(),
+        })
+    }
+
+    pub fn window_ms(&self) -> i64 {
+        self.config.window_minutes as i64 * 60_000
+    }
+
+    /// Start of the window holding `ms`.
+    pub fn window_start(&self, ms: i64) -> i64 {
+        let offset = self.zone.offset_secs(ms.div_euclid(1000)) * 1000;
+        ms - (ms + offset).rem_euclid(self.window_ms())
+    }
+
+    /// Calendar month of `ms` in the motor's zone, e.g. `2024-03`.
+    pub fn month(&self, ms: i64) -> String {
+        self.zone.at_millis(ms).map_or_else(String::new, |t| t.format("%Y-%m").to_string())
+    }
+}
+
+/// Time-weighted mean power (kW) of `samples` (oldest first) up to `end`.
+pub fn average_kw(samples: &[MotorData], end: i64) -> Option<f64> {
+    let (mut energy, mut covered) = (0.0, 0i64);
+    for (i, data) in samples.iter().enumerate() {
+        let next = samples.get(i + 1).map_or(end, |next| next.timestamp);
+        let held = (next - data.timestamp).clamp(0, MAX_GAP_MS);
+        if data.current_power.is_finite() && held > 0 {
+            energy += data.current_power * held as f64;
+            covered += held;
+        }
+    }
+    (covered > 0).then(|| energy / covered as f64)
+}
+
+/// Average power over the window ending at `now`.
+pub async fn rolling_kw(pool: &SqlitePool, window_ms: i64, now: i64) -> Option<f64> {
+    average_kw(&motor_data_range(pool, now - window_ms, now, None).await, now)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DemandWindow {
+    /// Epoch ms.
+    pub start: i64,
+    pub kw: f64,
+}
+
+/// Stored windows starting in `[from, to)`, oldest first; none for a
+/// database without the table.
+pub async fn windows(pool: &SqlitePool, motor_id: &str, from: i64, to: i64) -> Vec<DemandWindow> {
+    let rows: Vec<(i64, f64)> = sqlx::query_as(
+        "SELECT window_start, kw FROM demand WHERE motor_id = ? AND window_start >= ? AND window_start < ? \
+         ORDER BY window_start",
+    )
+    .bind(motor_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    rows.into_iter().map(|(start, kw)| DemandWindow { start, kw }).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct MonthlyPeak {
+    pub month: String,
+    pub window: DemandWindow,
+}
+
+/// Peak of every month on record, newest first.
+pub async fn monthly_peaks(pool: &SqlitePool, motor_id: &str) -> Vec<MonthlyPeak> {
+    let rows: Vec<(String, i64, f64)> =
+        sqlx::query_as("SELECT month, window_start, kw FROM demand_peaks WHERE motor_id = ? ORDER BY month DESC")
+            .bind(motor_id)
+            .fetch_all(pool)
+            .await
+            .unwrap_or_default();
+    rows.into_iter().map(|(month, start, kw)| MonthlyPeak { month, window: DemandWindow { start, kw } }).collect()
+}
+
+async fn month_peak(pool: &SqlitePool, motor_id: &str, month: &str) -> Option<DemandWindow> {
+    let row: Option<(i64, f64)> =
+        sqlx::query_as("SELECT window_start, kw FROM demand_peaks WHERE motor_id = ? AND month = ?")
+            .bind(motor_id)
+            .bind(month)
+            .fetch_optional(pool)
+            .await
+            .unwrap();
+    row.map(|(start, kw)| DemandWindow { start, kw })
+}
+
+/// Stores `window` and raises the month's peak if it beats it.
+async fn record_window(pool: &SqlitePool, settings: &DemandSettings, window: DemandWindow) {
+    let motor_id = &settings.motor_id;
+    sqlx::query("INSERT OR REPLACE INTO demand (motor_id, window_start, kw) VALUES (?, ?, ?)")
+        .bind(motor_id)
+        .bind(window.start)
+        .bind(window.kw)
+        .execute(pool)
+        .await
+        .unwrap();
+    let month = settings.month(window.start);
+    if month_peak(pool, motor_id, &month).await.is_some_and(|peak| peak.kw >= window.kw) {
+        return;
+    }
+    sqlx::query("INSERT OR REPLACE INTO demand_peaks (motor_id, month, window_start, kw) VALUES (?, ?, ?, ?)")
+        .bind(motor_id)
+        .bind(&month)
+        .bind(window.start)
+        .bind(window.kw)
+        .execute(pool)
+        .await
+        .unwrap();
+    let message = format!(
+        "new peak demand for {}: {:.1} kW over the {} min window from {}",
+        month,
+        window.kw,
+        settings.config.window_minutes,
+        settings.format.datetime(window.start)
+    );
+    log::info!("{}", message);
+    let end = (window.start + settings.window_ms()).div_euclid(1000);
+    events::record_event(pool, motor_id, end, "demand_peak", &message).await;
+}
+
+/// Midnight to midnight, in the motor's zone, of the day holding `ms`.
+fn day_of(zone: Zone, ms: i64) -> Option<(i64, i64)> {
+    let day = zone.at_millis(ms)?.date_naive();
+    let start = zone.from_local(&day.and_hms_opt(0, 0, 0)?)?;
+    let end = zone.from_local(&day.succ_opt()?.and_hms_opt(0, 0, 0)?)?;
+    Some((start.timestamp_millis(), end.timestamp_millis()))
+}
+
+/// Draws the demand profile of `day` (epoch ms) with `text` as PNG text
+/// chunks; the file is replaced atomically.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_profile(
+    filename: &str,
+    day: (i64, i64),
+    windows: &[DemandWindow],
+    window_ms: i64,
+    peak: Option<f64>,
+    format: &FormatConfig,
+    title: &str,
+    text: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    write_atomically(Path::new(filename), |path| {
+        render_profile(path, day, windows, window_ms, peak, format, title)?;
+        add_png_text(path, text)
+    })
+}
+
+fn render_profile(
+    path: &Path,
+    day: (i64, i64),
+    windows: &[DemandWindow],
+    window_ms: i64,
+    peak: Option<f64>,
+    format: &FormatConfig,
+    title: &str,
+) -> Result<(), Box<dyn Error>> {
+    let top = windows.iter().map(|w| w.kw).chain(peak).fold(0.0, f64::max);
+    let top = if top > 0.0 { top * 1.1 } else { 1.0 };
+    let root = BitMapBackend::new(path, (640, 480)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 30).into_font())
+        .margin(5)
+        .x_label_area_size(30)
+        .y_label_area_size(40)
+        .build_cartesian_2d(day.0..day.1, 0.0..top)?;
+
+    let time_label = |ms: &i64| format.time(*ms);
+    let decimals = super::format::axis_decimals(top);
+    let value_label = |v: &f64| format.number(*v, decimals);
+    chart
+        .configure_mesh()
+        .x_desc("Time")
+        .y_desc("Demand (kW)")
+        .x_label_formatter(&time_label)
+        .y_label_formatter(&value_label)
+        .draw()?;
+    chart.draw_series(windows.iter().map(|w| {
+        Rectangle::new([(w.start, 0.0), ((w.start + window_ms).min(day.1), w.kw)], BLUE.mix(0.6).filled())
+    }))?;
+    if let Some(peak) = peak {
+        chart.draw_series(std::iter::once(PathElement::new(vec![(day.0, peak), (day.1, peak)], RED)))?;
+        let label = format!("month peak {} kW", format.number(peak, 1));
+        chart.draw_series(std::iter::once(Text::new(label, (day.0, peak), ("sans-serif", 12).into_font())))?;
+    }
+    root.present()?;
+    Ok(())
+}
+
+/// Draws the profile of the day holding `ms`.
+async fn render_day(pool: &SqlitePool, settings: &DemandSettings, ms: i64, space: &disk::Space) {
+    let Some(day) = day_of(settings.zone, ms) else {
+        return;
+    };
+    let windows = windows(pool, &settings.motor_id, day.0, day.1).await;
+    let peak = month_peak(pool, &settings.motor_id, &settings.month(ms)).await.map(|peak| peak.kw);
+    let ctx = OutputContext { motor: &settings.motor_id, signal: "demand", timestamp: ms.div_euclid(1000) };
+    let path = render_path(&settings.config.path_template, &ctx);
+    let title = format!("Demand, {} min windows, {}", settings.config.window_minutes, settings.format.date(ms));
+    let text = chart_text(&settings.motor_id, day.0, day.1);
+    let (window_ms, format) = (settings.window_ms(), &settings.format);
+    let result = ensure_parent(&path, space)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|_| draw_profile(&path.to_string_lossy(), day, &windows, window_ms, peak, format, &title, &text));
+    if let Err(err) = result {
+        log::error!("cannot write demand profile {}: {}", path.display(), err);
+    }
+}
+
+/// Start of the window after the newest stored one, at most `BACKFILL_MS`
+/// back.
+async fn next_open_window(pool: &SqlitePool, settings: &DemandSettings, now: i64) -> i64 {
+    let (latest,): (Option<i64>,) = sqlx::query_as("SELECT MAX(window_start) FROM demand WHERE motor_id = ?")
+        .bind(&settings.motor_id)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    let backfill = settings.window_start(now - BACKFILL_MS);
+    latest.map_or(backfill, |latest| (latest + settings.window_ms()).max(backfill))
+}
+
+/// Closes every demand window as it ends, forever.
+pub async fn run_demand_tracker(pool: Arc<SqlitePool>, settings: DemandSettings, space: disk::Space) {
+    let window_ms = settings.window_ms();
+    let mut start = next_open_window(&pool, &settings, Utc::now().timestamp_millis()).await;
+    loop {
+        let now = Utc::now().timestamp_millis();
+        let open = settings.window_start(now);
+        if start < open {
+            while start < open {
+                let end = start + window_ms;
+                let samples = motor_data_range(&pool, start, end, None).await;
+                if let Some(kw) = average_kw(&samples, end) {
+                    record_window(&pool, &settings, DemandWindow { start, kw }).await;
+                }
+                start = end;
+            }
+            render_day(&pool, &settings, open - 1, &space).await;
+        }
+        let wait = (open + window_ms - now).max(0) as u64;
+        time::sleep(Duration::from_millis(wait) + Duration::from_secs(SETTLE_SECS)).await;
+    }
+}
@@ -0,0 +1,163 @@
+//! Device identification: vendor, product and firmware as reported by the
+//! drive or controller behind each motor.
+//!
+//! The transport is asked once per connection (EtherNet/IP drives answer
+//! through the CIP Identity object; Modbus RTU devices are not queried).
+//! The answer is cached in the `devices` table, so a failed or unsupported
+//! read keeps the last known identity, and is attached to exports as
+//! metadata so exported data says where it came from.
+
+use super::transport::Transport;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceInfo {
+    pub vendor: String,
+    pub product_code: String,
+    pub product_name: Option<String>,
+    /// Firmware revision, e.g. `2.11`.
+    pub firmware: String,
+    pub serial_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Device {
+    pub motor_id: String,
+    pub info: DeviceInfo,
+    /// First and latest identification with these values (epoch seconds).
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+pub async fn setup_devices_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS devices (
+            motor_id TEXT PRIMARY KEY,
+            vendor TEXT NOT NULL,
+            product_code TEXT NOT NULL,
+            product_name TEXT,
+            firmware TEXT NOT NULL,
+            serial_number TEXT,
+            first_seen INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Stores `info` for `motor_id`. `first_seen` restarts when any field
+/// changed, e.g. after a firmware update or a drive swap; the change is
+/// also recorded as a `device_changed` event.
+pub async fn save_device(pool: &SqlitePool, motor_id: &str, info: &DeviceInfo, now: i64) {
+    let previous = load_device(pool, motor_id).await;
+    let first_seen = match &previous {
+        Some(device) if device.info == *info => device.first_seen,
+        _ => now,
+    };
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO devices
+            (motor_id, vendor, product_code, product_name, firmware, serial_number, first_seen, last_seen)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(motor_id)
+    .bind(&info.vendor)
+    .bind(&info.product_code)
+    .bind(&info.product_name)
+    .bind(&info.firmware)
+    .bind(&info.serial_number)
+    .bind(first_seen)
+    .bind(now)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    if let Some(device) = previous.filter(|d| d.info != *info) {
+        let message = format!("{} replaced by {}", describe(&device.info), describe(info));
+        super::events::record_event(pool, motor_id, now, "device_changed", &message).await;
+    }
+}
+
+pub async fn load_device(pool: &SqlitePool, motor_id: &str) -> Option<Device> {
+    sqlx::query(
+        r#"
+        SELECT motor_id, vendor, product_code, product_name, firmware, serial_number, first_seen, last_seen
+        FROM devices WHERE motor_id = ?
+        "#,
+    )
+    .bind(motor_id)
+    .fetch_optional(pool)
+    .await
+    .unwrap()
+    .map(|row| Device {
+        motor_id: row.get("motor_id"),
+        info: DeviceInfo {
+            vendor: row.get("vendor"),
+            product_code: row.get("product_code"),
+            product_name: row.get("product_name"),
+            firmware: row.get("firmware"),
+            serial_number: row.get("serial_number"),
+        },
+        first_seen: row.get("first_seen"),
+        last_seen: row.get("last_seen"),
+    })
+}
+
+/// One-line description, e.g. `vendor 1 product 55 (PowerFlex 525) firmware 5.1`.
+pub fn describe(info: &DeviceInfo) -> String {
+    let mut text = format!("vendor {} product {}", info.vendor, info.product_code);
+    if let Some(name) = &info.product_name {
+        text.push_str(&format!(" ({})", name));
+    }
+    text.push_str(&format!(" firmware {}", info.firmware));
+    text
+}
+
+/// Key/value metadata describing the series of `motor_id`, for export headers.
+pub fn metadata(motor_id: &str, device: Option<&DeviceInfo>) -> BTreeMap<String, String> {
+    let mut metadata = BTreeMap::new();
+    metadata.insert("motor_id".to_string(), motor_id.to_string());
+    if let Some(info) = device {
+        metadata.insert("device_vendor".to_string(), info.vendor.clone());
+        metadata.insert("device_product_code".to_string(), info.product_code.clone());
+        metadata.insert("device_firmware".to_string(), info.firmware.clone());
+        if let Some(name) = &info.product_name {
+            metadata.insert("device_product_name".to_string(), name.clone());
+        }
+        if let Some(serial) = &info.serial_number {
+            metadata.insert("device_serial_number".to_string(), serial.clone());
+        }
+    }
+    metadata
+}
+
+/// `metadata` for the device cached for `motor_id`, plus `site` when set.
+pub async fn export_metadata(pool: &SqlitePool, motor_id: &str, site: Option<&str>) -> BTreeMap<String, String> {
+    let device = load_device(pool, motor_id).await;
+    let mut metadata = metadata(motor_id, device.as_ref().map(|d| &d.info));
+    if let Some(site) = site {
+        metadata.insert("site".to_string(), site.to_string());
+    }
+    metadata
+}
+
+/// Asks a freshly connected transport for its identity and caches it.
+/// Failures are logged; the cached identity stays in place.
+pub async fn identify(pool: &SqlitePool, motor_id: &str, transport: &mut dyn Transport, now: i64) {
+    match transport.identify().await {
+        Ok(Some(info)) => {
+            log::info!("connected to {}", describe(&info));
+            save_device(pool, motor_id, &info, now).await;
+        }
+        Ok(None) => {}
+        Err(err) => log::warn!("cannot read device identification: {}", err),
+    }
+}
@@ -0,0 +1,138 @@
+//! Disk-space guard for generated files.
+//!
+//! Every `check_interval_secs` the guard measures free space under `path`
+//! and the size of the files under `prune_dirs`. Beyond `quota_mb`, or below
+//! `min_free_mb`, the oldest files are deleted first. If free space is still
+//! below `min_free_mb` afterwards, a critical `storage_low` alarm is raised
+//! and writers of charts, snapshots, reports, heatmaps and archive blocks
+//! stand down (see `Space`) instead of failing halfway through a write.
+//! Samples keep being stored. Each monitor has a guard of its own, so
+//! monitors sharing a process (see `blocking`) only pause for their own
+//! volume.
+
+use super::alarms::{Severity, SustainedAlarm};
+use super::config::DiskConfig;
+use chrono::prelude::*;
+use sqlx::sqlite::SqlitePool;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::time;
+
+const MB: u64 = 1024 * 1024;
+
+/// A monitor's view of its disk guard, shared with its writers of optional
+/// outputs. Never low when the monitor has no `[disk]` guard.
+#[derive(Debug, Clone, Default)]
+pub struct Space(Arc<AtomicBool>);
+
+impl Space {
+    /// True while free space is below the guard's minimum; optional outputs
+    /// should not be written.
+    pub fn is_low(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails while space is low, so the output is not written.
+    pub fn check(&self) -> std::io::Result<()> {
+        if self.is_low() {
+            return Err(std::io::Error::other("disk space low, output paused"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Artifact {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+}
+
+fn collect(dir: &Path, out: &mut Vec<Artifact>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            collect(&entry.path(), out);
+        } else if metadata.is_file() {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            out.push(Artifact { path: entry.path(), modified, size: metadata.len() });
+        }
+    }
+}
+
+/// Deletes the oldest files under `dirs` until they fit `quota` bytes and
+/// at least `min_free` bytes are available under `path`. Returns the number
+/// of files and bytes removed.
+pub fn prune(dirs: &[String], quota: Option<u64>, path: &Path, min_free: u64) -> (usize, u64) {
+    let mut artifacts = Vec::new();
+    for dir in dirs {
+        collect(Path::new(dir), &mut artifacts);
+    }
+    artifacts.sort_by_key(|a| a.modified);
+    let mut total: u64 = artifacts.iter().map(|a| a.size).sum();
+    let (mut files, mut bytes) = (0, 0);
+    for artifact in artifacts {
+        let over_quota = quota.is_some_and(|quota| total > quota);
+        let short_of_space = fs2::available_space(path).is_ok_and(|free| free < min_free);
+        if !over_quota && !short_of_space {
+            break;
+        }
+        match fs::remove_file(&artifact.path) {
+            Ok(()) => {
+                total -= artifact.size;
+                files += 1;
+                bytes += artifact.size;
+            }
+            Err(err) => log::warn!("cannot prune {}: {}", artifact.path.display(), err),
+        }
+    }
+    (files, bytes)
+}
+
+pub async fn run_disk_guard(pool: Arc<SqlitePool>, motor_id: String, config: DiskConfig, space: Space) {
+    let mut interval = time::interval(Duration::from_secs(config.check_interval_secs));
+    let mut alarm = SustainedAlarm::new("storage_low", None, Severity::Critical, 0);
+    let path = PathBuf::from(&config.path);
+    let min_free = config.min_free_mb * MB;
+    loop {
+        interval.tick().await;
+        let (files, bytes) = prune(&config.prune_dirs, config.quota_mb.map(|mb| mb * MB), &path, min_free);
+        if files > 0 {
+            log::info!("pruned {} old file(s), {} MB", files, bytes / MB);
+        }
+
+        let free = match fs2::available_space(&path) {
+            Ok(free) => free,
+            Err(err) => {
+                log::warn!("cannot check free space on {}: {}", path.display(), err);
+                continue;
+            }
+        };
+        let low = free < min_free;
+        if low != space.0.swap(low, Ordering::Relaxed) {
+            if low {
+                log::error!("disk space low: {} MB free on {}, pausing optional outputs", free / MB, path.display());
+            } else {
+                log::info!("disk space recovered: {} MB free on {}", free / MB, path.display());
+            }
+        }
+        alarm
+            .update(&pool, &motor_id, low, Local::now().timestamp(), || {
+                format!(
+                    "only {} MB free on {} (minimum {} MB); charts, snapshots, reports and archiving paused",
+                    free / MB,
+                    path.display(),
+                    config.min_free_mb
+                )
+            })
+            .await;
+    }
+}
@@ -0,0 +1,150 @@
+//! Ambient sensors on the field bus.
+//!
+//! `[environment.sensors.<name>]` lists auxiliary devices (ambient
+//! temperature, humidity) sharing the RTU bus with the drive, each at its
+//! own unit id. They are read every `every_secs` between motor polls and
+//! stored in the `environment` table, apart from the motor samples, since
+//! they change slowly and belong to the room rather than the motor. The bus
+//! is the polling loop's, so a round reads one sensor after each poll rather
+//! than all at once, and readings are stored by a task of their own.
+//!
+//! Reports get an *Environment* section with each sensor's range over the
+//! period and the motor's temperature rise above ambient: the hourly mean
+//! heat minus the mean of the `ambient` sensor over the same hour. A motor
+//! that runs hot on a hot day has a normal rise; one whose rise grows at the
+//! same load is cooling worse.
+
+use super::config::{EnvironmentConfig, EnvironmentQuantity};
+use super::site;
+use super::transport::Transport;
+use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+
+pub async fn setup_environment_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS environment (
+            timestamp INTEGER NOT NULL,
+            sensor TEXT NOT NULL,
+            quantity TEXT NOT NULL,
+            value REAL NOT NULL,
+            PRIMARY KEY (sensor, timestamp)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// The polling loop's side: reads the sensors when due.
+pub struct EnvironmentPoller {
+    config: EnvironmentConfig,
+    last_read: Option<i64>,
+    /// Sensors still to read in the current round, last first.
+    pending: Vec<String>,
+}
+
+impl EnvironmentPoller {
+    pub fn new(config: EnvironmentConfig) -> Self {
+        EnvironmentPoller { config, last_read: None, pending: Vec::new() }
+    }
+
+    /// Starts a round if `every_secs` have passed since the last one, and
+    /// reads the next sensor of the round; the reading is stored in the
+    /// background. A sensor that does not answer is skipped until the next
+    /// round.
+    pub async fn maybe_read(&mut self, pool: &Arc<SqlitePool>, transport: &mut dyn Transport, now: i64) {
+        if self.pending.is_empty() {
+            if self.last_read.is_some_and(|last| now - last < self.config.every_secs as i64) {
+                return;
+            }
+            self.last_read = Some(now);
+            self.pending = self.config.sensors.keys().rev().cloned().collect();
+        }
+        let Some(name) = self.pending.pop() else {
+            return;
+        };
+        let Some(sensor) = self.config.sensors.get(&name) else {
+            return;
+        };
+        match transport.read_unit(sensor.unit_id, &sensor.register()).await {
+            Ok(raw) => {
+                let (pool, quantity, value) = (Arc::clone(pool), sensor.quantity, raw * sensor.scale);
+                tokio::spawn(async move { insert_reading(&pool, now, &name, quantity, value).await });
+            }
+            Err(err) => log::warn!("cannot read environment sensor {} (unit {}): {}", name, sensor.unit_id, err),
+        }
+    }
+}
+
+async fn insert_reading(pool: &SqlitePool, timestamp: i64, sensor: &str, quantity: EnvironmentQuantity, value: f64) {
+    sqlx::query("INSERT OR REPLACE INTO environment (timestamp, sensor, quantity, value) VALUES (?, ?, ?, ?)")
+        .bind(timestamp)
+        .bind(sensor)
+        .bind(quantity.name())
+        .bind(value)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// Range of one sensor over a period.
+#[derive(Debug, Clone)]
+pub struct SensorSummary {
+    pub sensor: String,
+    pub quantity: String,
+    pub readings: i64,
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+/// Per-sensor summaries of the readings in `[from, to)` (epoch seconds).
+pub async fn sensor_summaries(pool: &SqlitePool, from: i64, to: i64) -> Vec<SensorSummary> {
+    let rows: Vec<(String, String, i64, f64, f64, f64)> = sqlx::query_as(
+        r#"
+        SELECT sensor, quantity, COUNT(*), MIN(value), AVG(value), MAX(value) FROM environment
+        WHERE timestamp >= ? AND timestamp < ?
+        GROUP BY sensor, quantity
+        ORDER BY sensor
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+    rows.into_iter()
+        .map(|(sensor, quantity, readings, min, mean, max)| SensorSummary {
+            sensor,
+            quantity,
+            readings,
+            min,
+            mean,
+            max,
+        })
+        .collect()
+}
+
+/// Hourly temperature rise of the motor above `sensor`: `(hour start, mean
+/// heat minus mean ambient)` for each hour in `[from, to)` with both.
+pub async fn hourly_rise(pool: &SqlitePool, sensor: &str, from: i64, to: i64) -> Vec<(i64, f64)> {
+    sqlx::query_as(&format!(
+        r#"
+        SELECT s.window_start, s.avg - AVG(e.value) FROM signal_stats s
+        JOIN environment e
+            ON e.sensor = ? AND e.timestamp >= s.window_start AND e.timestamp < s.window_start + 3600
+        WHERE s.{} AND s.signal = 'heat' AND s.window_size = '1h' AND s.window_start >= ? AND s.window_start < ?
+        GROUP BY s.window_start
+        ORDER BY s.window_start
+        "#,
+        site::SAMPLES
+    ))
+    .bind(sensor)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
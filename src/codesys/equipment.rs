@@ -0,0 +1,139 @@
+//! Expected power of the driven equipment.
+//!
+//! The power a centrifugal pump or a fan takes follows the affinity laws:
+//! it grows with the cube of the speed, so a machine that took
+//! `reference_power` at `reference_speed_rpm` should take
+//! `reference_power · (speed / reference_speed_rpm)³` at any other speed. A
+//! positive-displacement compressor moves a fixed volume per turn and its
+//! power grows with the speed itself. `[equipment]` names the `kind` (or an
+//! `exponent` of its own) and a reference point of the healthy machine, and
+//! each sample above `min_speed_rpm` is compared with the power expected at
+//! its speed. Two alarms fire after `delay_secs`:
+//!
+//! - `equipment_power_low`: more than `tolerance_percent` below the
+//!   expected power; the machine moves less than it should, e.g. a clogged
+//!   filter or suction strainer, a worn or damaged impeller, a slipping belt;
+//! - `equipment_power_high`: more than `tolerance_percent` above; e.g.
+//!   rubbing, failing bearings or a fouled impeller.
+//!
+//! Without a reference point the nameplate is taken, `motor.rated_power` at
+//! `motor.rated_speed`, which only fits a machine that loads the motor fully
+//! at rated speed. A point measured on the healthy machine is better.
+
+use super::alarms::SustainedAlarm;
+use super::config::{EquipmentConfig, EquipmentKind};
+use super::{MotorData, MotorSpecs};
+use sqlx::sqlite::SqlitePool;
+
+impl EquipmentKind {
+    /// Affinity-law exponent of power over speed.
+    pub fn exponent(&self) -> f64 {
+        match self {
+            EquipmentKind::Pump | EquipmentKind::Fan => 3.0,
+            EquipmentKind::Compressor => 1.0,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EquipmentKind::Pump => "pump",
+            EquipmentKind::Fan => "fan",
+            EquipmentKind::Compressor => "compressor",
+        }
+    }
+}
+
+/// The power-over-speed law of the equipment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Profile {
+    pub reference_speed_rpm: f64,
+    pub reference_power: f64,
+    pub exponent: f64,
+}
+
+impl Profile {
+    pub fn new(config: &EquipmentConfig, specs: &MotorSpecs) -> Self {
+        Profile {
+            reference_speed_rpm: config.reference_speed_rpm.unwrap_or(specs.rated_speed),
+            reference_power: config.reference_power.unwrap_or(specs.rated_power),
+            exponent: config.exponent.unwrap_or_else(|| config.kind.exponent()),
+        }
+    }
+
+    /// Power expected at `speed_rpm`.
+    pub fn expected_power(&self, speed_rpm: f64) -> f64 {
+        self.reference_power * (speed_rpm.abs() / self.reference_speed_rpm).powf(self.exponent)
+    }
+
+    /// Deviation of `power` at `speed_rpm` from the expected power, in
+    /// percent of it.
+    pub fn deviation_percent(&self, speed_rpm: f64, power: f64) -> Option<f64> {
+        let expected = self.expected_power(speed_rpm);
+        (expected > 0.0).then(|| (power - expected) / expected * 100.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct EquipmentMonitor {
+    config: EquipmentConfig,
+    profile: Profile,
+    power_low: SustainedAlarm,
+    power_high: SustainedAlarm,
+}
+
+impl EquipmentMonitor {
+    pub fn new(config: EquipmentConfig, specs: &MotorSpecs) -> Self {
+        let alarm = |rule| SustainedAlarm::new(rule, Some("power"), config.severity, config.delay_secs);
+        let profile = Profile::new(&config, specs);
+        log::info!(
+            "{} profile: {:.2} at {:.0} rpm, power ∝ speed^{}",
+            config.kind.name(),
+            profile.reference_power,
+            profile.reference_speed_rpm,
+            profile.exponent
+        );
+        EquipmentMonitor {
+            power_low: alarm("equipment_power_low"),
+            power_high: alarm("equipment_power_high"),
+            profile,
+            config,
+        }
+    }
+
+    /// Returns `(alarm id, rule)` for each alarm raised by `data`.
+    pub async fn update(&mut self, pool: &SqlitePool, motor_id: &str, data: &MotorData) -> Vec<(i64, String)> {
+        let speed = data.current_speed;
+        let deviation = (speed.abs() >= self.config.min_speed_rpm)
+            .then(|| self.profile.deviation_percent(speed, data.current_power))
+            .flatten();
+        let tolerance = self.config.tolerance_percent;
+        let expected = self.profile.expected_power(speed);
+        let power = data.current_power;
+        let kind = self.config.kind.name();
+        let now = data.seconds();
+        let mut raised = Vec::new();
+        let checks = [
+            (&mut self.power_low, deviation.is_some_and(|d| d < -tolerance), "below"),
+            (&mut self.power_high, deviation.is_some_and(|d| d > tolerance), "above"),
+        ];
+        for (alarm, condition, text) in checks {
+            let id = alarm
+                .update(pool, motor_id, condition, now, || {
+                    format!(
+                        "{} power {:.2} is {:.0}% {} the {:.2} expected at {:.0} rpm",
+                        kind,
+                        power,
+                        deviation.unwrap_or_default().abs(),
+                        text,
+                        expected,
+                        speed
+                    )
+                })
+                .await;
+            if let Some(id) = id {
+                raised.push((id, alarm.rule.clone()));
+            }
+        }
+        raised
+    }
+}
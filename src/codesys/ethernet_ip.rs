@@ -0,0 +1,225 @@
+//! Minimal EtherNet/IP explicit-messaging client for drives without Modbus.
+//!
+//! Registers a session with the drive and reads values with unconnected CIP
+//! `Get_Attribute_Single` requests sent via `SendRRData` (writes use
+//! `Set_Attribute_Single`). For a register
+//! definition, `address` is the instance number (the drive parameter number
+//! when reading the Parameter object) and `count` selects a 16-bit (1) or
+//! 32-bit (2) value. The drive identifies itself through the Identity
+//! object (`Get_Attributes_All` on class 0x01, instance 1).
+
+use super::config::{EthernetIpConfig, RegisterDef};
+use super::devices::DeviceInfo;
+use super::transport::{Transport, TransportError};
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time;
+
+const CMD_REGISTER_SESSION: u16 = 0x0065;
+const CMD_SEND_RR_DATA: u16 = 0x006F;
+const ITEM_NULL_ADDRESS: u16 = 0x0000;
+const ITEM_UNCONNECTED_DATA: u16 = 0x00B2;
+const SERVICE_GET_ATTRIBUTES_ALL: u8 = 0x01;
+const SERVICE_GET_ATTRIBUTE_SINGLE: u8 = 0x0E;
+const SERVICE_SET_ATTRIBUTE_SINGLE: u8 = 0x10;
+const HEADER_LEN: usize = 24;
+/// Identity object (class 0x01), instance 1.
+const IDENTITY_PATH: [u8; 4] = [0x20, 0x01, 0x24, 0x01];
+
+pub struct EthernetIpClient {
+    stream: TcpStream,
+    session: u32,
+    class: u16,
+    attribute: u8,
+    timeout: Duration,
+}
+
+impl EthernetIpClient {
+    pub async fn connect(config: &EthernetIpConfig) -> Result<Self, TransportError> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let stream = time::timeout(timeout, TcpStream::connect(config.address.as_str()))
+            .await
+            .map_err(|_| TransportError::Protocol(format!("timed out connecting to {}", config.address)))??;
+        stream.set_nodelay(true)?;
+
+        let mut client = EthernetIpClient {
+            stream,
+            session: 0,
+            class: config.class,
+            attribute: config.attribute,
+            timeout,
+        };
+        // Protocol version 1, no options.
+        let reply = client.request(CMD_REGISTER_SESSION, &[1, 0, 0, 0]).await?;
+        client.session = reply.session;
+        log::info!("EtherNet/IP session 0x{:08x} registered with {}", client.session, config.address);
+        Ok(client)
+    }
+
+    async fn request(&mut self, command: u16, data: &[u8]) -> Result<Encapsulation, TransportError> {
+        let mut frame = Vec::with_capacity(HEADER_LEN + data.len());
+        frame.extend_from_slice(&command.to_le_bytes());
+        frame.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        frame.extend_from_slice(&self.session.to_le_bytes());
+        frame.extend_from_slice(&0u32.to_le_bytes()); // status
+        frame.extend_from_slice(&[0; 8]); // sender context
+        frame.extend_from_slice(&0u32.to_le_bytes()); // options
+        frame.extend_from_slice(data);
+
+        let stream = &mut self.stream;
+        let exchange = async {
+            stream.write_all(&frame).await?;
+            let mut header = [0u8; HEADER_LEN];
+            stream.read_exact(&mut header).await?;
+            let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+            let mut body = vec![0u8; length];
+            stream.read_exact(&mut body).await?;
+            Ok::<_, std::io::Error>((header, body))
+        };
+        let (header, body) = time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| TransportError::Protocol("request timed out".to_string()))??;
+
+        let status = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        if status != 0 {
+            return Err(TransportError::Protocol(format!("encapsulation status 0x{:08x}", status)));
+        }
+        Ok(Encapsulation {
+            session: u32::from_le_bytes([header[4], header[5], header[6], header[7]]),
+            body,
+        })
+    }
+
+    /// CIP request path for class / instance / attribute.
+    fn path(&self, instance: u16) -> Vec<u8> {
+        let mut path = Vec::with_capacity(8);
+        if self.class <= 0xFF {
+            path.extend_from_slice(&[0x20, self.class as u8]);
+        } else {
+            path.extend_from_slice(&[0x21, 0x00]);
+            path.extend_from_slice(&self.class.to_le_bytes());
+        }
+        if instance <= 0xFF {
+            path.extend_from_slice(&[0x24, instance as u8]);
+        } else {
+            path.extend_from_slice(&[0x25, 0x00]);
+            path.extend_from_slice(&instance.to_le_bytes());
+        }
+        path.extend_from_slice(&[0x30, self.attribute]);
+        path
+    }
+
+    /// Sends one unconnected CIP request and returns the response data.
+    async fn cip_request(&mut self, service: u8, path: &[u8], payload: &[u8]) -> Result<Vec<u8>, TransportError> {
+        let mut cip = vec![service, (path.len() / 2) as u8];
+        cip.extend_from_slice(path);
+        cip.extend_from_slice(payload);
+
+        let mut data = Vec::with_capacity(16 + cip.len());
+        data.extend_from_slice(&0u32.to_le_bytes()); // interface handle
+        data.extend_from_slice(&(self.timeout.as_secs().max(1) as u16).to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes()); // item count
+        data.extend_from_slice(&ITEM_NULL_ADDRESS.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&ITEM_UNCONNECTED_DATA.to_le_bytes());
+        data.extend_from_slice(&(cip.len() as u16).to_le_bytes());
+        data.extend_from_slice(&cip);
+
+        let reply = self.request(CMD_SEND_RR_DATA, &data).await?;
+        let response = unconnected_item(&reply.body)?;
+        Ok(cip_response_data(response)?.to_vec())
+    }
+}
+
+struct Encapsulation {
+    session: u32,
+    body: Vec<u8>,
+}
+
+#[async_trait]
+impl Transport for EthernetIpClient {
+    async fn read(&mut self, def: &RegisterDef) -> Result<f64, TransportError> {
+        let path = self.path(def.address);
+        let value = self.cip_request(SERVICE_GET_ATTRIBUTE_SINGLE, &path, &[]).await?;
+        decode_value(&value, def.count)
+    }
+
+    async fn write(&mut self, def: &RegisterDef, value: u16) -> Result<(), TransportError> {
+        let payload = match def.count {
+            2 => (value as u32).to_le_bytes().to_vec(),
+            _ => value.to_le_bytes().to_vec(),
+        };
+        let path = self.path(def.address);
+        self.cip_request(SERVICE_SET_ATTRIBUTE_SINGLE, &path, &payload).await?;
+        Ok(())
+    }
+
+    async fn identify(&mut self) -> Result<Option<DeviceInfo>, TransportError> {
+        let data = self.cip_request(SERVICE_GET_ATTRIBUTES_ALL, &IDENTITY_PATH, &[]).await?;
+        decode_identity(&data).map(Some)
+    }
+}
+
+/// Finds the unconnected data item in a SendRRData reply.
+fn unconnected_item(body: &[u8]) -> Result<&[u8], TransportError> {
+    let short = || TransportError::Protocol("truncated SendRRData reply".to_string());
+    let count = u16::from_le_bytes([*body.get(6).ok_or_else(short)?, *body.get(7).ok_or_else(short)?]);
+    let mut offset = 8;
+    for _ in 0..count {
+        let item = body.get(offset..offset + 4).ok_or_else(short)?;
+        let kind = u16::from_le_bytes([item[0], item[1]]);
+        let len = u16::from_le_bytes([item[2], item[3]]) as usize;
+        let payload = body.get(offset + 4..offset + 4 + len).ok_or_else(short)?;
+        if kind == ITEM_UNCONNECTED_DATA {
+            return Ok(payload);
+        }
+        offset += 4 + len;
+    }
+    Err(TransportError::Protocol("reply has no unconnected data item".to_string()))
+}
+
+/// Checks the CIP general status and returns the attribute data.
+fn cip_response_data(response: &[u8]) -> Result<&[u8], TransportError> {
+    if response.len() < 4 {
+        return Err(TransportError::Protocol("truncated CIP response".to_string()));
+    }
+    let general_status = response[2];
+    let extra_words = response[3] as usize;
+    if general_status != 0 {
+        return Err(TransportError::Protocol(format!("CIP general status 0x{:02x}", general_status)));
+    }
+    response
+        .get(4 + extra_words * 2..)
+        .ok_or_else(|| TransportError::Protocol("truncated CIP response".to_string()))
+}
+
+/// Decodes Identity object attributes 1-7: vendor ID, device type, product
+/// code, revision, status, serial number and product name.
+fn decode_identity(data: &[u8]) -> Result<DeviceInfo, TransportError> {
+    let short = || TransportError::Protocol(format!("identity data too short ({} bytes)", data.len()));
+    let word = |at: usize| data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or_else(short);
+    let vendor = word(0)?;
+    let product_code = word(4)?;
+    let (major, minor) = (*data.get(6).ok_or_else(short)?, *data.get(7).ok_or_else(short)?);
+    let serial = data.get(10..14).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(short)?;
+    let product_name = data.get(14).and_then(|&len| data.get(15..15 + len as usize)).map(|name| {
+        String::from_utf8_lossy(name).trim().to_string()
+    });
+    Ok(DeviceInfo {
+        vendor: vendor.to_string(),
+        product_code: product_code.to_string(),
+        product_name: product_name.filter(|name| !name.is_empty()),
+        firmware: format!("{}.{}", major, minor),
+        serial_number: Some(format!("{:08X}", serial)),
+    })
+}
+
+fn decode_value(data: &[u8], count: u16) -> Result<f64, TransportError> {
+    match (count, data) {
+        (1, [a, b, ..]) => Ok(u16::from_le_bytes([*a, *b]) as f64),
+        (2, [a, b, c, d, ..]) => Ok(u32::from_le_bytes([*a, *b, *c, *d]) as f64),
+        _ => Err(TransportError::Protocol(format!("attribute data too short ({} bytes)", data.len()))),
+    }
+}
@@ -0,0 +1,102 @@
+//! Event log: timestamped, non-alarm occurrences such as state changes,
+//! commands and communication problems.
+
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub id: i64,
+    pub motor_id: String,
+    pub timestamp: i64,
+    /// Short machine-readable category, e.g. `comm_failure`.
+    pub kind: String,
+    pub message: String,
+}
+
+/// Filter for `list_events`; unset fields match everything.
+#[derive(Debug, Default, Clone)]
+pub struct EventFilter {
+    pub motor_id: Option<String>,
+    pub kind: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+pub async fn setup_events_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS events (
+            id INTEGER PRIMARY KEY,
+            motor_id TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            message TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+pub async fn record_event(pool: &SqlitePool, motor_id: &str, timestamp: i64, kind: &str, message: &str) -> i64 {
+    sqlx::query("INSERT INTO events (motor_id, timestamp, kind, message) VALUES (?, ?, ?, ?)")
+        .bind(motor_id)
+        .bind(timestamp)
+        .bind(kind)
+        .bind(message)
+        .execute(pool)
+        .await
+        .unwrap()
+        .last_insert_rowid()
+}
+
+/// Up to `limit` events with an id above `after`, oldest first.
+pub async fn events_after(pool: &SqlitePool, after: i64, limit: i64) -> Vec<Event> {
+    sqlx::query_as::<_, (i64, String, i64, String, String)>(
+        "SELECT id, motor_id, timestamp, kind, message FROM events WHERE id > ? ORDER BY id LIMIT ?",
+    )
+    .bind(after)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|(id, motor_id, timestamp, kind, message)| Event { id, motor_id, timestamp, kind, message })
+    .collect()
+}
+
+pub async fn list_events(pool: &SqlitePool, filter: &EventFilter) -> Vec<Event> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, motor_id, timestamp, kind, message FROM events
+        WHERE (?1 IS NULL OR motor_id = ?1)
+          AND (?2 IS NULL OR kind = ?2)
+          AND (?3 IS NULL OR timestamp >= ?3)
+          AND (?4 IS NULL OR timestamp < ?4)
+        ORDER BY timestamp DESC
+        LIMIT ?5
+        "#,
+    )
+    .bind(filter.motor_id.as_deref())
+    .bind(filter.kind.as_deref())
+    .bind(filter.from)
+    .bind(filter.to)
+    .bind(filter.limit.unwrap_or(-1))
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    rows.iter()
+        .map(|row| Event {
+            id: row.get("id"),
+            motor_id: row.get("motor_id"),
+            timestamp: row.get("timestamp"),
+            kind: row.get("kind"),
+            message: row.get("message"),
+        })
+        .collect()
+}
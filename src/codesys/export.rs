@@ -0,0 +1,249 @@
+//! Export of samples as CSV (formatted according to `[format]`) or Arrow IPC.
+//!
+//! Decimal separators follow the configured locale; where the decimal
+//! separator is `,` fields are delimited with `;` so the file opens cleanly
+//! in a spreadsheet set to that locale. `stream_samples` produces either
+//! format page by page for the HTTP export endpoint and for background
+//! export jobs (`export_jobs`); the Arrow Flight server (`flight`) sends the
+//! same record batches.
+//!
+//! Streamed exports carry metadata (motor id and, once identified, the
+//! device's vendor, product and firmware; see `devices::metadata`): as
+//! `# key: value` lines before the CSV header, or as Arrow schema metadata.
+//! Values are exported in the display units of `units`, named in the
+//! metadata (`unit.<column>`), in the CSV header of a column with a
+//! configured unit (`power (W)`) and as the `unit` metadata of each Arrow
+//! field. The `estimated` column names the
+//! values of a sample that were estimated rather than read (`soft_sensors`).
+
+use super::config::FormatConfig;
+use super::units::Units;
+use super::{motor_data_range, MotorData};
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use futures::stream::{self, Stream};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// Samples fetched from the database per streamed chunk.
+pub const PAGE_SIZE: i64 = 5000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// Arrow IPC streaming format.
+    Arrow,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Arrow => "application/vnd.apache.arrow.stream",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Arrow => "arrows",
+        }
+    }
+}
+
+/// Fixed columns after the timestamp; temperature channels follow in name order.
+const COLUMNS: [&str; 9] =
+    ["time", "power", "torque", "speed", "heat", "cycles", "speed_reference", "sequence", "estimated"];
+
+/// Writes the header row for samples carrying `channels`. The first column
+/// is `timestamp_ms` or `timestamp` depending on `format.timestamps`; value
+/// columns with a configured unit carry it.
+pub fn write_header<W: Write>(out: &mut W, format: &FormatConfig, units: &Units, channels: &[String]) -> io::Result<()> {
+    let delimiter = format.locale.csv_delimiter().to_string();
+    let header: Vec<String> = std::iter::once(format.timestamps.column().to_string())
+        .chain(COLUMNS.iter().map(|&column| match column {
+            "time" | "sequence" | "estimated" => column.to_string(),
+            signal => units.header(signal),
+        }))
+        .chain(channels.iter().map(|channel| units.header(channel)))
+        .collect();
+    writeln!(out, "{}", header.join(&delimiter))
+}
+
+pub fn write_row<W: Write>(out: &mut W, format: &FormatConfig, channels: &[String], data: &MotorData) -> io::Result<()> {
+    let delimiter = format.locale.csv_delimiter().to_string();
+    let number = |value: f64| format.plain_number(value, 3);
+    let mut fields = vec![
+        format.timestamp(data.timestamp),
+        format.datetime(data.timestamp),
+        number(data.current_power),
+        number(data.current_torque),
+        number(data.current_speed),
+        number(data.current_heat),
+        number(data.current_cycles),
+        data.speed_reference.map(number).unwrap_or_default(),
+        data.sequence.map(|s| s.to_string()).unwrap_or_default(),
+        data.estimated.join(" "),
+    ];
+    fields.extend(channels.iter().map(|c| data.temperatures.get(c).copied().map(number).unwrap_or_default()));
+    writeln!(out, "{}", fields.join(&delimiter))
+}
+
+/// Writes `samples` as a complete CSV document.
+pub fn write_csv<W: Write>(out: &mut W, format: &FormatConfig, units: &Units, samples: &[MotorData]) -> io::Result<()> {
+    let channels = channels_of(samples);
+    write_header(out, format, units, &channels)?;
+    for data in samples {
+        write_row(out, format, &channels, &units.sample(data))?;
+    }
+    Ok(())
+}
+
+pub fn channels_of(samples: &[MotorData]) -> Vec<String> {
+    let mut channels: Vec<String> = samples.iter().flat_map(|d| d.temperatures.keys().cloned()).collect();
+    channels.sort();
+    channels.dedup();
+    channels
+}
+
+/// Writes `# key: value` lines, which most CSV readers can skip as comments.
+pub fn write_metadata<W: Write>(out: &mut W, metadata: &BTreeMap<String, String>) -> io::Result<()> {
+    for (key, value) in metadata {
+        writeln!(out, "# {}: {}", key, value)?;
+    }
+    Ok(())
+}
+
+/// Schema of exported samples; `record_batch` expects samples already in
+/// display units (`Units::sample`).
+pub fn arrow_schema(channels: &[String], units: &Units, metadata: &BTreeMap<String, String>) -> SchemaRef {
+    let value = |name: &str, nullable: bool| {
+        let unit = HashMap::from([("unit".to_string(), units.unit(name).to_string())]);
+        Field::new(name, DataType::Float64, nullable).with_metadata(unit)
+    };
+    let mut fields = vec![
+        Field::new("timestamp", DataType::Timestamp(TimeUnit::Millisecond, None), false),
+        value("power", false),
+        value("torque", false),
+        value("speed", false),
+        value("heat", false),
+        value("cycles", false),
+        value("speed_reference", true),
+        Field::new("sequence", DataType::Int64, true),
+        Field::new("estimated", DataType::Utf8, true),
+    ];
+    fields.extend(channels.iter().map(|c| value(c, true)));
+    let metadata: HashMap<String, String> = metadata.clone().into_iter().collect();
+    Arc::new(Schema::new(fields).with_metadata(metadata))
+}
+
+pub fn record_batch(schema: &SchemaRef, channels: &[String], samples: &[MotorData]) -> Result<RecordBatch, ArrowError> {
+    let values = |f: fn(&MotorData) -> f64| -> ArrayRef { Arc::new(Float64Array::from_iter_values(samples.iter().map(f))) };
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(TimestampMillisecondArray::from_iter_values(samples.iter().map(|d| d.timestamp))),
+        values(|d| d.current_power),
+        values(|d| d.current_torque),
+        values(|d| d.current_speed),
+        values(|d| d.current_heat),
+        values(|d| d.current_cycles),
+        Arc::new(samples.iter().map(|d| d.speed_reference).collect::<Float64Array>()),
+        Arc::new(samples.iter().map(|d| d.sequence).collect::<Int64Array>()),
+        Arc::new(
+            samples.iter().map(|d| (!d.estimated.is_empty()).then(|| d.estimated.join(" "))).collect::<StringArray>(),
+        ),
+    ];
+    for channel in channels {
+        columns.push(Arc::new(samples.iter().map(|d| d.temperatures.get(channel).copied()).collect::<Float64Array>()));
+    }
+    RecordBatch::try_new(Arc::clone(schema), columns)
+}
+
+/// Column layout is fixed by the first page: temperature channels that only
+/// appear later in the range are not exported.
+enum Encoder {
+    Csv { format: FormatConfig, units: Units, metadata: BTreeMap<String, String>, channels: Option<Vec<String>> },
+    Arrow {
+        units: Units,
+        metadata: BTreeMap<String, String>,
+        writer: Option<(StreamWriter<Vec<u8>>, SchemaRef, Vec<String>)>,
+    },
+}
+
+impl Encoder {
+    fn encode(&mut self, page: &[MotorData], last: bool) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        match self {
+            Encoder::Csv { format, units, metadata, channels } => {
+                let channels = match channels {
+                    Some(channels) => channels,
+                    None => {
+                        let found = channels_of(page);
+                        metadata.extend(units.metadata(&found));
+                        write_metadata(&mut out, metadata)?;
+                        write_header(&mut out, format, units, &found)?;
+                        channels.insert(found)
+                    }
+                };
+                for data in page {
+                    write_row(&mut out, format, channels, &units.sample(data))?;
+                }
+            }
+            Encoder::Arrow { units, metadata, writer } => {
+                let to_io = |e: ArrowError| io::Error::other(e);
+                if writer.is_none() {
+                    let channels = channels_of(page);
+                    metadata.extend(units.metadata(&channels));
+                    let schema = arrow_schema(&channels, units, metadata);
+                    *writer = Some((StreamWriter::try_new(Vec::new(), &schema).map_err(to_io)?, schema, channels));
+                }
+                let (stream, schema, channels) = writer.as_mut().unwrap();
+                if !page.is_empty() {
+                    let page: Vec<MotorData> = page.iter().map(|d| units.sample(d)).collect();
+                    stream.write(&record_batch(schema, channels, &page).map_err(to_io)?).map_err(to_io)?;
+                }
+                if last {
+                    stream.finish().map_err(to_io)?;
+                }
+                out = std::mem::take(stream.get_mut());
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Streams samples with `from <= timestamp < to` (milliseconds), one page
+/// at a time, so memory use stays flat regardless of the range.
+pub fn stream_samples(
+    pool: Arc<SqlitePool>,
+    format: FormatConfig,
+    units: Units,
+    metadata: BTreeMap<String, String>,
+    kind: ExportFormat,
+    from: i64,
+    to: i64,
+) -> impl Stream<Item = io::Result<Vec<u8>>> + Send {
+    let encoder = match kind {
+        ExportFormat::Csv => Encoder::Csv { format, units, metadata, channels: None },
+        ExportFormat::Arrow => Encoder::Arrow { units, metadata, writer: None },
+    };
+    stream::unfold(Some((encoder, from)), move |state| {
+        let pool = Arc::clone(&pool);
+        async move {
+            let (mut encoder, cursor) = state?;
+            let page = motor_data_range(&pool, cursor, to, Some(PAGE_SIZE)).await;
+            let last = (page.len() as i64) < PAGE_SIZE;
+            let next = page.last().map_or(to, |d| d.timestamp + 1);
+            match encoder.encode(&page, last) {
+                Ok(chunk) => Some((Ok(chunk), (!last).then_some((encoder, next)))),
+                Err(err) => Some((Err(err), None)),
+            }
+        }
+    })
+}
@@ -0,0 +1,210 @@
+//! Background export jobs.
+//!
+//! `POST /exports` queues an export instead of streaming it inline; the
+//! result is written to `api.exports.dir` and downloaded once the job is
+//! done. At most `max_concurrent` exports (jobs and inline `GET /export`
+//! downloads together) read the database at a time and at most `max_queued`
+//! jobs wait, so a burst of large exports cannot starve the polling loop of
+//! an edge device. Finished jobs and their files are removed after
+//! `keep_minutes`. Jobs live in memory; a restart forgets them.
+
+use super::config::{ExportJobsConfig, FormatConfig};
+use super::disk;
+use super::export::{stream_samples, ExportFormat};
+use super::output::{ensure_parent, temp_path};
+use super::units::Units;
+use chrono::prelude::*;
+use futures::StreamExt;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportJob {
+    pub id: u64,
+    pub format: ExportFormat,
+    /// Requested range, epoch milliseconds.
+    pub from: i64,
+    pub to: i64,
+    pub status: JobStatus,
+    /// Epoch milliseconds.
+    pub submitted_at: i64,
+    pub finished_at: Option<i64>,
+    /// Size of the result once done.
+    pub bytes: Option<u64>,
+    /// Set when the job failed.
+    pub error: Option<String>,
+}
+
+impl ExportJob {
+    pub fn filename(&self) -> String {
+        format!("export_{}.{}", self.id, self.format.extension())
+    }
+}
+
+#[derive(Debug)]
+pub struct ExportQueue {
+    config: ExportJobsConfig,
+    jobs: Mutex<BTreeMap<u64, ExportJob>>,
+    next_id: AtomicU64,
+    permits: Arc<Semaphore>,
+    space: disk::Space,
+}
+
+impl ExportQueue {
+    pub fn new(config: ExportJobsConfig, space: disk::Space) -> Self {
+        let permits = Arc::new(Semaphore::new(config.max_concurrent));
+        ExportQueue { config, jobs: Mutex::new(BTreeMap::new()), next_id: AtomicU64::new(1), permits, space }
+    }
+
+    /// A slot for an inline export, held until the download ends; `None`
+    /// while `max_concurrent` exports are running.
+    pub fn try_inline(&self) -> Option<OwnedSemaphorePermit> {
+        Arc::clone(&self.permits).try_acquire_owned().ok()
+    }
+
+    pub fn job(&self, id: u64) -> Option<ExportJob> {
+        self.prune();
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Where the result of `job` is written.
+    pub fn result_path(&self, job: &ExportJob) -> PathBuf {
+        Path::new(&self.config.dir).join(job.filename())
+    }
+
+    /// Queues an export of `[from, to)` and returns the new job, or an error
+    /// when `max_queued` jobs are already waiting or running.
+    #[allow(clippy::too_many_arguments)]
+    pub fn submit(
+        self: &Arc<Self>,
+        pool: Arc<SqlitePool>,
+        format: FormatConfig,
+        units: Units,
+        metadata: BTreeMap<String, String>,
+        kind: ExportFormat,
+        from: i64,
+        to: i64,
+    ) -> Result<ExportJob, String> {
+        self.prune();
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let pending = jobs.values().filter(|j| matches!(j.status, JobStatus::Queued | JobStatus::Running)).count();
+            if pending >= self.config.max_queued {
+                return Err(format!("{} export(s) already pending; retry later", pending));
+            }
+            let job = ExportJob {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                format: kind,
+                from,
+                to,
+                status: JobStatus::Queued,
+                submitted_at: Local::now().timestamp_millis(),
+                finished_at: None,
+                bytes: None,
+                error: None,
+            };
+            jobs.insert(job.id, job.clone());
+            job
+        };
+
+        let queue = Arc::clone(self);
+        let id = job.id;
+        let path = self.result_path(&job);
+        tokio::spawn(async move {
+            let _permit = Arc::clone(&queue.permits).acquire_owned().await.unwrap();
+            queue.update(id, |job| job.status = JobStatus::Running);
+            let result = write_export(&path, &queue.space, pool, format, units, metadata, kind, from, to).await;
+            queue.update(id, |job| {
+                job.finished_at = Some(Local::now().timestamp_millis());
+                match result {
+                    Ok(bytes) => {
+                        job.status = JobStatus::Done;
+                        job.bytes = Some(bytes);
+                    }
+                    Err(err) => {
+                        log::error!("export job {} failed: {}", job.id, err);
+                        job.status = JobStatus::Failed;
+                        job.error = Some(err.to_string());
+                    }
+                }
+            });
+        });
+        Ok(job)
+    }
+
+    fn update(&self, id: u64, change: impl FnOnce(&mut ExportJob)) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            change(job);
+        }
+    }
+
+    /// Forgets finished jobs older than `keep_minutes` and deletes their files.
+    fn prune(&self) {
+        let cutoff = Local::now().timestamp_millis() - self.config.keep_minutes as i64 * 60_000;
+        let mut jobs = self.jobs.lock().unwrap();
+        let expired: Vec<u64> =
+            jobs.values().filter(|j| j.finished_at.is_some_and(|t| t < cutoff)).map(|j| j.id).collect();
+        for id in expired {
+            if let Some(job) = jobs.remove(&id) {
+                let _ = std::fs::remove_file(self.result_path(&job));
+            }
+        }
+    }
+}
+
+/// Streams the export into a temporary file next to `path`, then renames it
+/// into place. Returns the file size.
+#[allow(clippy::too_many_arguments)]
+async fn write_export(
+    path: &Path,
+    space: &disk::Space,
+    pool: Arc<SqlitePool>,
+    format: FormatConfig,
+    units: Units,
+    metadata: BTreeMap<String, String>,
+    kind: ExportFormat,
+    from: i64,
+    to: i64,
+) -> io::Result<u64> {
+    ensure_parent(path, space)?;
+    let temp = temp_path(path);
+    let result = async {
+        let mut file = tokio::fs::File::create(&temp).await?;
+        let mut bytes = 0;
+        let mut chunks = std::pin::pin!(stream_samples(pool, format, units, metadata, kind, from, to));
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            bytes += chunk.len() as u64;
+        }
+        file.flush().await?;
+        Ok::<_, io::Error>(bytes)
+    }
+    .await;
+    match result {
+        Ok(bytes) => {
+            tokio::fs::rename(&temp, path).await?;
+            Ok(bytes)
+        }
+        Err(err) => {
+            let _ = tokio::fs::remove_file(&temp).await;
+            Err(err)
+        }
+    }
+}
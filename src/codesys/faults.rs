@@ -0,0 +1,191 @@
+//! Drive fault history read from the drive's fault queue registers.
+//!
+//! Many drives keep their last N fault codes in consecutive registers, one
+//! code per register, and shift the list along when a new fault trips. With
+//! a `[fault_history]` section the block is read every `every_secs`, in one
+//! request, so the list cannot shift between two registers read. The list
+//! is compared with the one read before: the codes that were pushed on since
+//! are recorded as `drive_fault` events, oldest first, with the code's text
+//! from the vendor `profile` or from `codes`. The last list read is kept in
+//! the `fault_history_state` table, so a restart neither loses nor repeats
+//! faults; the very first read only records the list as it stands.
+//!
+//! A zero code is an empty slot. The registers carry no fault times, so a
+//! new fault is only told apart from the list it pushed along by the codes:
+//! a fault repeating while every slot holds that same code goes unnoticed.
+
+use super::config::{FaultHistoryConfig, FaultProfile, RegisterDef};
+use super::events;
+use super::transport::Transport;
+use sqlx::sqlite::SqlitePool;
+
+pub async fn setup_fault_history_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS fault_history_state (
+            motor_id TEXT PRIMARY KEY,
+            codes TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Fault texts of the PowerFlex 525 (`F<code>` on its display).
+const POWERFLEX_525: &[(u16, &str)] = &[
+    (2, "Auxiliary Input"),
+    (3, "Power Loss"),
+    (4, "UnderVoltage"),
+    (5, "OverVoltage"),
+    (6, "Motor Stalled"),
+    (7, "Motor Overload"),
+    (8, "Heatsink OvrTmp"),
+    (9, "CC OvrTmp"),
+    (12, "HW OverCurrent"),
+    (13, "Ground Fault"),
+    (15, "Load Loss"),
+    (21, "Output Ph Loss"),
+    (29, "Analog In Loss"),
+    (33, "Auto Rstrt Tries"),
+    (38, "Phase U to Gnd"),
+    (39, "Phase V to Gnd"),
+    (40, "Phase W to Gnd"),
+    (41, "Phase UV Short"),
+    (42, "Phase UW Short"),
+    (43, "Phase VW Short"),
+    (48, "Params Defaulted"),
+    (59, "Safety Open"),
+    (63, "SW OverCurrent"),
+    (64, "Drive Overload"),
+    (70, "Power Unit"),
+    (71, "DSI Net Loss"),
+    (72, "Opt Net Loss"),
+    (73, "EN Net Loss"),
+    (80, "AutoTune Failure"),
+    (81, "DSI Comm Loss"),
+    (82, "Opt Comm Loss"),
+    (83, "EN Comm Loss"),
+    (91, "Encoder Loss"),
+    (100, "Parameter Chksum"),
+    (101, "External Storage"),
+    (114, "uC Failure"),
+    (122, "I/O Board Fail"),
+];
+
+/// Text of `code` under `config`: `codes` first, then the profile.
+pub fn describe(config: &FaultHistoryConfig, code: u16) -> Option<String> {
+    if let Some(text) = config.codes.get(&code.to_string()) {
+        return Some(text.clone());
+    }
+    let table: &[(u16, &str)] = match config.profile {
+        FaultProfile::Generic => &[],
+        FaultProfile::Powerflex525 => POWERFLEX_525,
+    };
+    table.iter().find(|(c, _)| *c == code).map(|(_, text)| text.to_string())
+}
+
+/// Codes at the head of `current` that were pushed on since `previous`;
+/// both newest first. The shortest shift that lines the rest of `current`
+/// up with `previous` is taken, so an unchanged list has no new entries and
+/// one sharing nothing with `previous` is new throughout.
+pub fn new_entries<'a>(previous: &[u16], current: &'a [u16]) -> &'a [u16] {
+    let shift = (0..=current.len())
+        .find(|&k| {
+            let rest = &current[k..];
+            let n = rest.len().min(previous.len());
+            rest[..n] == previous[..n] && (n > 0 || k == current.len())
+        })
+        .unwrap_or(current.len());
+    &current[..shift]
+}
+
+fn encode(codes: &[u16]) -> String {
+    codes.iter().map(u16::to_string).collect::<Vec<_>>().join(",")
+}
+
+fn decode(codes: &str) -> Vec<u16> {
+    codes.split(',').filter_map(|code| code.parse().ok()).collect()
+}
+
+async fn load_state(pool: &SqlitePool, motor_id: &str) -> Option<Vec<u16>> {
+    let row: Option<(String,)> = sqlx::query_as("SELECT codes FROM fault_history_state WHERE motor_id = ?")
+        .bind(motor_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap();
+    row.map(|(codes,)| decode(&codes))
+}
+
+async fn save_state(pool: &SqlitePool, motor_id: &str, codes: &[u16], now: i64) {
+    sqlx::query("INSERT OR REPLACE INTO fault_history_state (motor_id, codes, updated_at) VALUES (?, ?, ?)")
+        .bind(motor_id)
+        .bind(encode(codes))
+        .bind(now)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// The polling loop's side: reads the fault queue when due.
+pub struct FaultHistoryReader {
+    config: FaultHistoryConfig,
+    /// Last list read, newest first; `None` before the first read.
+    previous: Option<Vec<u16>>,
+    last_read: Option<i64>,
+}
+
+impl FaultHistoryReader {
+    pub async fn load(pool: &SqlitePool, motor_id: &str, config: FaultHistoryConfig) -> Self {
+        let previous = load_state(pool, motor_id).await;
+        FaultHistoryReader { config, previous, last_read: None }
+    }
+
+    /// Reads the block in one request, newest first and without empty
+    /// slots; `None` if it cannot be read.
+    async fn read(&self, transport: &mut dyn Transport) -> Option<Vec<u16>> {
+        let block = RegisterDef { address: self.config.address, count: 1, function: self.config.function };
+        let mut codes = match transport.read_block(&block, self.config.entries).await {
+            Ok(codes) => codes,
+            Err(err) => {
+                log::warn!("cannot read fault history registers from {}: {}", block.address, err);
+                return None;
+            }
+        };
+        if !self.config.newest_first {
+            codes.reverse();
+        }
+        codes.retain(|&code| code != 0);
+        Some(codes)
+    }
+
+    /// Reads the fault queue if `every_secs` have passed since the last round
+    /// and records the faults that are new since the previous read.
+    pub async fn maybe_read(&mut self, pool: &SqlitePool, motor_id: &str, transport: &mut dyn Transport, now: i64) {
+        if self.last_read.is_some_and(|last| now - last < self.config.every_secs as i64) {
+            return;
+        }
+        self.last_read = Some(now);
+        let Some(codes) = self.read(transport).await else {
+            return;
+        };
+        match &self.previous {
+            Some(previous) if *previous == codes => return,
+            Some(previous) => {
+                for &code in new_entries(previous, &codes).iter().rev() {
+                    let message = match describe(&self.config, code) {
+                        Some(text) => format!("drive fault {}: {}", code, text),
+                        None => format!("drive fault {}", code),
+                    };
+                    log::warn!("{}", message);
+                    events::record_event(pool, motor_id, now, "drive_fault", &message).await;
+                }
+            }
+            None => log::info!("fault history holds {} entries; recording faults from now on", codes.len()),
+        }
+        save_state(pool, motor_id, &codes, now).await;
+        self.previous = Some(codes);
+    }
+}
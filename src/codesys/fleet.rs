@@ -0,0 +1,340 @@
+//! Fleet comparison report.
+//!
+//! Ranks several motors, each with its own configuration and database, by
+//! energy per run-hour, average load, temperature margin and alarm count
+//! over a period. Values far from the fleet median (robust z-score above
+//! `OUTLIER_Z`, using the median absolute deviation) are flagged so
+//! maintenance can look at those motors first. Availability, performance,
+//! quality and OEE (see `oee`) follow per motor and per `group`, then the
+//! peak demand (see `demand`) per motor and, adding up the motors of each
+//! `site` window by window, per site. The report ends with the lineage of
+//! every motor's figures (see `lineage`).
+
+use super::analytics::format_duration;
+use super::config::MonitorConfig;
+use super::demand::{self, DemandWindow};
+use super::lineage::{self, rfc3339};
+use super::oee::{self, percent, KpiSettings, Kpis};
+use super::{motor_data_range, open_database_read_only, MotorData, Signal};
+use chrono::prelude::*;
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+
+/// Samples read per query while scanning the period.
+const PAGE_SIZE: i64 = 5000;
+/// Gaps longer than this (ms) are outages and don't count as run time.
+const MAX_GAP_MS: i64 = 60_000;
+/// Robust z-score above which a value is flagged.
+const OUTLIER_Z: f64 = 3.0;
+
+#[derive(Debug, Clone)]
+pub struct MotorMetrics {
+    pub motor_id: String,
+    pub run_secs: f64,
+    pub energy_kwh: f64,
+    /// `None` when the motor did not run.
+    pub energy_per_run_hour: Option<f64>,
+    /// Mean power while running, as % of rated power.
+    pub avg_load_percent: Option<f64>,
+    /// Heat limit minus the highest heat seen; `None` without a heat limit.
+    pub temperature_margin: Option<f64>,
+    pub alarm_count: i64,
+    /// `group` of the motor's configuration.
+    pub group: Option<String>,
+    pub kpis: Kpis,
+    /// `site` of the motor's configuration.
+    pub site: Option<String>,
+    /// Demand windows of the period; empty without `[demand]`.
+    pub demand: Vec<DemandWindow>,
+    pub lineage: lineage::Source,
+}
+
+/// The hottest acceptable heat: the configured heat limit (warning first),
+/// else the interlock's hard limit.
+pub fn heat_limit(config: &MonitorConfig) -> Option<f64> {
+    config
+        .limits
+        .threshold(Signal::Heat)
+        .and_then(|t| t.warn.or(t.alarm))
+        .or_else(|| config.interlock.as_ref().and_then(|i| i.max_temperature))
+}
+
+/// What a motor did over a period: run time, energy and the hottest it got.
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    pub run_secs: f64,
+    pub energy_kwh: f64,
+    /// Time integral of the load while running, in %·s of `rated_power`.
+    pub load_secs: f64,
+    /// `None` without samples.
+    pub max_heat: Option<f64>,
+    /// Highest reading of each temperature channel with samples.
+    pub max_temperatures: BTreeMap<String, f64>,
+}
+
+/// Scans the samples in `[from, to)` (milliseconds). The motor runs while
+/// its speed is above zero; gaps over `MAX_GAP_MS` don't count.
+pub async fn usage(pool: &SqlitePool, rated_power: f64, from: i64, to: i64) -> Usage {
+    let (mut run_secs, mut energy_kwh, mut load_secs) = (0.0, 0.0, 0.0);
+    let mut max_heat = f64::NEG_INFINITY;
+    let mut max_temperatures: BTreeMap<String, f64> = BTreeMap::new();
+    let mut previous: Option<MotorData> = None;
+    let mut cursor = from;
+    loop {
+        let page = motor_data_range(pool, cursor, to, Some(PAGE_SIZE)).await;
+        for data in &page {
+            max_heat = max_heat.max(data.current_heat);
+            for (channel, &value) in data.temperatures.iter().filter(|(_, v)| v.is_finite()) {
+                let max = max_temperatures.entry(channel.clone()).or_insert(value);
+                *max = max.max(value);
+            }
+            if let Some(prev) = &previous {
+                let dt_ms = data.timestamp - prev.timestamp;
+                if prev.current_speed > 0.0 && dt_ms <= MAX_GAP_MS {
+                    let secs = dt_ms as f64 / 1000.0;
+                    run_secs += secs;
+                    energy_kwh += prev.current_power * secs / 3600.0;
+                    load_secs += prev.current_power / rated_power * 100.0 * secs;
+                }
+            }
+            previous = Some(data.clone());
+        }
+        match page.last() {
+            Some(last) if page.len() as i64 == PAGE_SIZE => cursor = last.timestamp + 1,
+            _ => break,
+        }
+    }
+    Usage { run_secs, energy_kwh, load_secs, max_heat: max_heat.is_finite().then_some(max_heat), max_temperatures }
+}
+
+/// Metrics for one motor over `[from, to)` (milliseconds).
+pub async fn motor_metrics(pool: &SqlitePool, config: &MonitorConfig, from: i64, to: i64) -> MotorMetrics {
+    let rated_power = config.motor.rated_power;
+    let Usage { run_secs, energy_kwh, load_secs, max_heat, .. } = usage(pool, rated_power, from, to).await;
+
+    let (alarm_count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM alarms WHERE motor_id = ? AND raised_at >= ? AND raised_at < ?")
+            .bind(&config.motor_id)
+            .bind(from.div_euclid(1000))
+            .bind(to.div_euclid(1000))
+            .fetch_one(pool)
+            .await
+            .unwrap();
+
+    let ran = run_secs > 0.0;
+    MotorMetrics {
+        motor_id: config.motor_id.clone(),
+        run_secs,
+        energy_kwh,
+        energy_per_run_hour: ran.then(|| energy_kwh / (run_secs / 3600.0)),
+        avg_load_percent: (ran && rated_power > 0.0).then(|| load_secs / run_secs),
+        temperature_margin: heat_limit(config).zip(max_heat).map(|(limit, max_heat)| limit - max_heat),
+        alarm_count,
+        group: config.group.clone(),
+        kpis: oee::kpis(pool, &KpiSettings::from_config(config), from, to).await,
+        site: config.site.clone(),
+        demand: demand::windows(pool, &config.motor_id, from, to).await,
+        lineage: lineage::Source::load(pool, config).await,
+    }
+}
+
+/// Opens every motor's database read-only and collects its metrics.
+pub async fn collect(configs: &[MonitorConfig], from: i64, to: i64) -> Result<Vec<MotorMetrics>, Box<dyn Error>> {
+    let mut metrics = Vec::new();
+    for config in configs {
+        let pool = open_database_read_only(&config.database_url).await?;
+        metrics.push(motor_metrics(&pool, config, from, to).await);
+        pool.close().await;
+    }
+    Ok(metrics)
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Indices of values whose robust z-score exceeds `OUTLIER_Z`. Needs at
+/// least three values; with no spread at all nothing is flagged.
+pub fn outliers(values: &[Option<f64>]) -> Vec<usize> {
+    let mut present: Vec<f64> = values.iter().flatten().copied().collect();
+    if present.len() < 3 {
+        return Vec::new();
+    }
+    present.sort_by(f64::total_cmp);
+    let center = median(&present);
+    let mut deviations: Vec<f64> = present.iter().map(|v| (v - center).abs()).collect();
+    deviations.sort_by(f64::total_cmp);
+    // 1.4826 scales the MAD to the standard deviation of a normal distribution.
+    let spread = 1.4826 * median(&deviations);
+    if spread == 0.0 {
+        return Vec::new();
+    }
+    values
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| v.is_some_and(|v| ((v - center) / spread).abs() > OUTLIER_Z))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Markdown report, motors ranked by energy per run-hour (highest first).
+pub fn render_markdown(metrics: &[MotorMetrics], from: i64, to: i64) -> String {
+    let mut ranked: Vec<&MotorMetrics> = metrics.iter().collect();
+    ranked.sort_by(|a, b| {
+        let key = |m: &MotorMetrics| m.energy_per_run_hour.unwrap_or(f64::NEG_INFINITY);
+        key(b).total_cmp(&key(a)).then_with(|| a.motor_id.cmp(&b.motor_id))
+    });
+
+    let columns: [(&str, Vec<Option<f64>>); 4] = [
+        ("energy per run-hour", ranked.iter().map(|m| m.energy_per_run_hour).collect()),
+        ("average load", ranked.iter().map(|m| m.avg_load_percent).collect()),
+        ("temperature margin", ranked.iter().map(|m| m.temperature_margin).collect()),
+        ("alarm count", ranked.iter().map(|m| Some(m.alarm_count as f64)).collect()),
+    ];
+    let flagged: Vec<Vec<usize>> = columns.iter().map(|(_, values)| outliers(values)).collect();
+    let cell = |column: usize, row: usize, text: String| {
+        if flagged[column].contains(&row) {
+            format!("**{}** ⚠", text)
+        } else {
+            text
+        }
+    };
+    let number = |value: Option<f64>, unit: &str| value.map_or("–".to_string(), |v| format!("{:.1}{}", v, unit));
+
+    let mut out = String::new();
+    writeln!(out, "# Fleet comparison\n").unwrap();
+    writeln!(out, "Period: {} – {}\n", format_timestamp(from), format_timestamp(to)).unwrap();
+    out.push_str("| Rank | Motor | Run time | Energy | Energy per run-hour | Avg. load | Temperature margin | Alarms |\n");
+    out.push_str("|---:|---|---:|---:|---:|---:|---:|---:|\n");
+    for (row, m) in ranked.iter().enumerate() {
+        writeln!(
+            out,
+            "| {} | {} | {} | {:.1} kWh | {} | {} | {} | {} |",
+            row + 1,
+            m.motor_id,
+            format_duration(m.run_secs),
+            m.energy_kwh,
+            cell(0, row, number(m.energy_per_run_hour, " kW")),
+            cell(1, row, number(m.avg_load_percent, " %")),
+            cell(2, row, number(m.temperature_margin, " °C")),
+            cell(3, row, m.alarm_count.to_string()),
+        )
+        .unwrap();
+    }
+
+    out.push_str("\n## Availability\n\n");
+    out.push_str("| Motor | Group | Availability | Performance | Quality | OEE | Quality events |\n");
+    out.push_str("|---|---|---:|---:|---:|---:|---:|\n");
+    let mut groups: BTreeMap<&str, Kpis> = BTreeMap::new();
+    for m in &ranked {
+        let group = m.group.as_deref().unwrap_or("–");
+        writeln!(out, "| {} | {} | {} |", m.motor_id, group, kpi_cells(&m.kpis)).unwrap();
+        if let Some(group) = &m.group {
+            groups.entry(group).or_default().add(&m.kpis);
+        }
+    }
+    if !groups.is_empty() {
+        out.push_str("\n| Group | Availability | Performance | Quality | OEE | Quality events |\n");
+        out.push_str("|---|---:|---:|---:|---:|---:|\n");
+        for (group, kpis) in &groups {
+            writeln!(out, "| {} | {} |", group, kpi_cells(kpis)).unwrap();
+        }
+    }
+
+    if ranked.iter().any(|m| !m.demand.is_empty()) {
+        out.push_str(&demand_section(&ranked));
+    }
+
+    out.push_str("\n## Outliers\n\n");
+    let mut any = false;
+    for ((name, values), rows) in columns.iter().zip(&flagged) {
+        for &row in rows {
+            any = true;
+            let value = values[row].unwrap_or_default();
+            writeln!(out, "- {}: {} of {:.1} is far from the fleet median", ranked[row].motor_id, name, value).unwrap();
+        }
+    }
+    if ranked.len() < 3 {
+        out.push_str("Outlier detection needs at least three motors.\n");
+    } else if !any {
+        out.push_str("No outliers.\n");
+    }
+
+    out.push_str("\n## Lineage\n\n");
+    let generated_at = rfc3339(Local::now().timestamp_millis());
+    writeln!(out, "- Generator: {}", lineage::GENERATOR).unwrap();
+    writeln!(out, "- Range: {} – {} (exclusive)", rfc3339(from), rfc3339(to)).unwrap();
+    writeln!(out, "- Generated: {}\n", generated_at).unwrap();
+    out.push_str("| Motor | Database | Database id | Configuration SHA-256 |\n");
+    out.push_str("|---|---|---|---|\n");
+    for m in metrics {
+        let source = &m.lineage;
+        let (database, id, hash) = (&source.database, source.database_id(), &source.config_hash);
+        writeln!(out, "| {} | {} | `{}` | `{}` |", m.motor_id, database, id, hash).unwrap();
+    }
+    out
+}
+
+/// Highest demand window of `windows`.
+fn peak(windows: &[DemandWindow]) -> Option<DemandWindow> {
+    windows.iter().copied().max_by(|a, b| a.kw.total_cmp(&b.kw))
+}
+
+/// Peak demand per motor and per site. A site's coincident peak is its
+/// highest window with the motors added up; it is at most the sum of the
+/// motors' own peaks, which rarely coincide.
+fn demand_section(ranked: &[&MotorMetrics]) -> String {
+    let mut out = String::from("\n## Peak demand\n\n");
+    out.push_str("| Motor | Site | Peak demand | Window start |\n");
+    out.push_str("|---|---|---:|---|\n");
+    let mut sites: BTreeMap<&str, (BTreeMap<i64, f64>, f64)> = BTreeMap::new();
+    for m in ranked {
+        let site = m.site.as_deref().unwrap_or("–");
+        let Some(top) = peak(&m.demand) else {
+            writeln!(out, "| {} | {} | – | – |", m.motor_id, site).unwrap();
+            continue;
+        };
+        writeln!(out, "| {} | {} | {:.1} kW | {} |", m.motor_id, site, top.kw, format_timestamp(top.start)).unwrap();
+        let (windows, sum_of_peaks) = sites.entry(site).or_default();
+        for window in &m.demand {
+            *windows.entry(window.start).or_default() += window.kw;
+        }
+        *sum_of_peaks += top.kw;
+    }
+    out.push_str("\n| Site | Coincident peak | Window start | Sum of motor peaks |\n");
+    out.push_str("|---|---:|---|---:|\n");
+    for (site, (windows, sum_of_peaks)) in &sites {
+        let windows: Vec<DemandWindow> = windows.iter().map(|(&start, &kw)| DemandWindow { start, kw }).collect();
+        if let Some(top) = peak(&windows) {
+            let at = format_timestamp(top.start);
+            writeln!(out, "| {} | {:.1} kW | {} | {:.1} kW |", site, top.kw, at, sum_of_peaks).unwrap();
+        }
+    }
+    out
+}
+
+/// Availability, performance, quality, OEE and quality events as table cells.
+fn kpi_cells(kpis: &Kpis) -> String {
+    [
+        percent(kpis.availability()),
+        percent(kpis.performance()),
+        percent(kpis.quality()),
+        percent(kpis.oee()),
+        kpis.quality_events.to_string(),
+    ]
+    .join(" | ")
+}
+
+fn format_timestamp(ms: i64) -> String {
+    Local
+        .timestamp_millis_opt(ms)
+        .single()
+        .map_or_else(|| ms.to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string())
+}
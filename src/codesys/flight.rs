@@ -0,0 +1,224 @@
+//! Arrow Flight server for analytic clients.
+//!
+//! Serves the samples of `GET /export?format=arrow` over Arrow Flight
+//! (gRPC), so pyarrow, polars or R's arrow package pull them straight into
+//! a dataframe, batch by batch, without a CSV round trip. A ticket (or the
+//! command of a flight descriptor) is the JSON query
+//! `{"from": <ms>, "to": <ms>}`; `from` is inclusive, `to` exclusive.
+//!
+//! Flight downloads count against `api.exports.max_concurrent` like inline
+//! HTTP exports and are refused with `RESOURCE_EXHAUSTED` while all slots
+//! are taken.
+
+// The service's errors are tonic's `Status`, large or not.
+#![allow(clippy::result_large_err)]
+
+use super::config::TlsConfig;
+use super::config_history;
+use super::devices;
+use super::export::{self, PAGE_SIZE};
+use super::export_jobs::ExportQueue;
+use super::lineage;
+use super::location::Location;
+use super::motor_data_range;
+use super::startup;
+use super::tls;
+use super::units::Units;
+use arrow_flight::encode::FlightDataEncoderBuilder;
+use arrow_flight::error::FlightError;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo, HandshakeRequest,
+    HandshakeResponse, PollInfo, PutResult, SchemaAsIpc, SchemaResult, Ticket,
+};
+use arrow_ipc::writer::IpcWriteOptions;
+use arrow_schema::SchemaRef;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::Deserialize;
+use sqlx::sqlite::SqlitePool;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status, Streaming};
+
+/// Time range requested by a ticket or flight descriptor.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FlightQuery {
+    from: i64,
+    to: i64,
+}
+
+impl FlightQuery {
+    fn parse(bytes: &[u8]) -> Result<Self, Status> {
+        let query: FlightQuery = serde_json::from_slice(bytes).map_err(|err| {
+            Status::invalid_argument(format!("expected {{\"from\": <ms>, \"to\": <ms>}}: {}", err))
+        })?;
+        if query.from >= query.to {
+            return Err(Status::invalid_argument("`from` must be before `to`"));
+        }
+        Ok(query)
+    }
+}
+
+#[derive(Debug)]
+pub struct FlightServer {
+    pub pool: Arc<SqlitePool>,
+    pub motor_id: String,
+    pub site: Option<String>,
+    /// Shared with the HTTP API, so both count against the same limit.
+    pub exports: Arc<ExportQueue>,
+    pub units: Units,
+    /// Stamped into the schema metadata of every flight.
+    pub lineage: lineage::Source,
+    pub location: Location,
+}
+
+impl FlightServer {
+    /// Schema of the samples in `[from, to)`: the temperature columns are
+    /// taken from the first page, as for HTTP exports.
+    async fn schema(&self, query: &FlightQuery, first_page: &[super::MotorData]) -> (SchemaRef, Vec<String>) {
+        let mut metadata = devices::export_metadata(&self.pool, &self.motor_id, self.site.as_deref()).await;
+        metadata.extend(self.location.metadata());
+        metadata.extend(lineage::Stamp::new(&self.lineage, query.from, query.to).metadata());
+        metadata.extend(config_history::metadata(&self.pool, query.from, query.to).await);
+        let channels = export::channels_of(first_page);
+        metadata.extend(self.units.metadata(&channels));
+        (export::arrow_schema(&channels, &self.units, &metadata), channels)
+    }
+}
+
+fn unsupported<T>(what: &str) -> Result<T, Status> {
+    Err(Status::unimplemented(format!("{} is not supported; use get_flight_info and do_get", what)))
+}
+
+#[async_trait]
+impl FlightService for FlightServer {
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+    type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        unsupported("handshake")
+    }
+
+    async fn list_flights(&self, _request: Request<Criteria>) -> Result<Response<Self::ListFlightsStream>, Status> {
+        unsupported("list_flights")
+    }
+
+    /// Describes the flight for a descriptor whose command is a query; its
+    /// single endpoint's ticket is that query.
+    async fn get_flight_info(&self, request: Request<FlightDescriptor>) -> Result<Response<FlightInfo>, Status> {
+        let descriptor = request.into_inner();
+        let query = FlightQuery::parse(&descriptor.cmd)?;
+        let first_page = motor_data_range(&self.pool, query.from, query.to, Some(PAGE_SIZE)).await;
+        let (schema, _) = self.schema(&query, &first_page).await;
+        let info = FlightInfo::new()
+            .try_with_schema(&schema)
+            .map_err(|err| Status::internal(err.to_string()))?
+            .with_endpoint(FlightEndpoint::new().with_ticket(Ticket::new(descriptor.cmd.clone())))
+            .with_descriptor(descriptor);
+        Ok(Response::new(info))
+    }
+
+    async fn poll_flight_info(&self, _request: Request<FlightDescriptor>) -> Result<Response<PollInfo>, Status> {
+        unsupported("poll_flight_info")
+    }
+
+    async fn get_schema(&self, request: Request<FlightDescriptor>) -> Result<Response<SchemaResult>, Status> {
+        let query = FlightQuery::parse(&request.get_ref().cmd)?;
+        let first_page = motor_data_range(&self.pool, query.from, query.to, Some(PAGE_SIZE)).await;
+        let (schema, _) = self.schema(&query, &first_page).await;
+        let result = SchemaAsIpc::new(&schema, &IpcWriteOptions::default())
+            .try_into()
+            .map_err(|err: arrow_schema::ArrowError| Status::internal(err.to_string()))?;
+        Ok(Response::new(result))
+    }
+
+    /// Streams the samples of the ticket's range, one record batch per
+    /// database page.
+    async fn do_get(&self, request: Request<Ticket>) -> Result<Response<Self::DoGetStream>, Status> {
+        let query = FlightQuery::parse(&request.get_ref().ticket)?;
+        let Some(permit) = self.exports.try_inline() else {
+            return Err(Status::resource_exhausted("too many exports running; retry later"));
+        };
+        let first_page = motor_data_range(&self.pool, query.from, query.to, Some(PAGE_SIZE)).await;
+        let (schema, channels) = self.schema(&query, &first_page).await;
+
+        let (pool, to, units) = (Arc::clone(&self.pool), query.to, self.units.clone());
+        let batch_schema = Arc::clone(&schema);
+        let batches = stream::unfold(Some(first_page), move |page| {
+            let (pool, schema, channels, units) =
+                (Arc::clone(&pool), Arc::clone(&batch_schema), channels.clone(), units.clone());
+            async move {
+                let page = page.filter(|page| !page.is_empty())?;
+                let next = match page.len() as i64 {
+                    n if n < PAGE_SIZE => None,
+                    _ => Some(motor_data_range(&pool, page[page.len() - 1].timestamp + 1, to, Some(PAGE_SIZE)).await),
+                };
+                let page: Vec<super::MotorData> = page.iter().map(|d| units.sample(d)).collect();
+                Some((export::record_batch(&schema, &channels, &page).map_err(FlightError::from), next))
+            }
+        });
+        let data = FlightDataEncoderBuilder::new()
+            .with_schema(schema)
+            .build(batches)
+            .map(move |data| {
+                // The slot is released when the client stops reading.
+                let _permit = &permit;
+                data.map_err(Status::from)
+            })
+            .boxed();
+        Ok(Response::new(data))
+    }
+
+    async fn do_put(&self, _request: Request<Streaming<FlightData>>) -> Result<Response<Self::DoPutStream>, Status> {
+        unsupported("do_put")
+    }
+
+    async fn do_action(&self, _request: Request<Action>) -> Result<Response<Self::DoActionStream>, Status> {
+        unsupported("do_action")
+    }
+
+    async fn list_actions(&self, _request: Request<Empty>) -> Result<Response<Self::ListActionsStream>, Status> {
+        Ok(Response::new(stream::empty().boxed()))
+    }
+
+    async fn do_exchange(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        unsupported("do_exchange")
+    }
+}
+
+/// Serves Arrow Flight on `listen`, over TLS with `tls`. Waits until the
+/// certificate is in place and the port is free; see `startup`.
+pub async fn serve(listen: SocketAddr, server: FlightServer, tls: Option<TlsConfig>) {
+    let component = format!("Arrow Flight on {}", listen);
+    let mut builder = tonic::transport::Server::builder();
+    if let Some(tls) = &tls {
+        let Ok(config) = startup::retry(&component, || async move { tls::flight_config(tls) }).await else {
+            return;
+        };
+        builder = builder.tls_config(config).unwrap();
+    }
+    let Ok(listener) = startup::retry(&component, || tokio::net::TcpListener::bind(listen)).await else {
+        return;
+    };
+    let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+    log::info!("Arrow Flight listening on {}{}", listen, if tls.is_some() { " (TLS)" } else { "" });
+    builder
+        .add_service(FlightServiceServer::new(server))
+        .serve_with_incoming(incoming)
+        .await
+        .unwrap();
+}
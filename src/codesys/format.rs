@@ -0,0 +1,172 @@
+//! Human-facing formatting of timestamps and numbers, shared by chart axes
+//! and CSV export so both follow the `[format]` settings, plus the
+//! machine-readable timestamp style (`format.timestamps`) used by CSV
+//! exports, the REST API's JSON, MQTT JSON payloads and logs. GraphQL keeps
+//! epoch milliseconds: its timestamp fields are typed `Int` in the schema.
+
+use super::config::{FormatConfig, NumberLocale, TimestampStyle};
+use super::location::Zone;
+use chrono::prelude::*;
+
+impl TimestampStyle {
+    /// Renders an epoch-millisecond timestamp in this style.
+    pub fn render(&self, ms: i64) -> String {
+        match self {
+            TimestampStyle::EpochMs => ms.to_string(),
+            TimestampStyle::Rfc3339 => Local
+                .timestamp_millis_opt(ms)
+                .single()
+                .map_or_else(|| ms.to_string(), |t| t.to_rfc3339_opts(SecondsFormat::Millis, false)),
+        }
+    }
+
+    /// Renders the epoch-millisecond `fields` of the JSON object `value` in
+    /// this style; other fields and `null`s are left alone.
+    pub fn restyle(&self, value: &mut serde_json::Value, fields: &[&str]) {
+        if *self == TimestampStyle::EpochMs {
+            return;
+        }
+        for field in fields {
+            if let Some(slot) = value.get_mut(*field) {
+                if let Some(ms) = slot.as_i64() {
+                    *slot = serde_json::Value::String(self.render(ms));
+                }
+            }
+        }
+    }
+
+    /// Header for a timestamp column in this style.
+    pub fn column(&self) -> &'static str {
+        match self {
+            TimestampStyle::EpochMs => "timestamp_ms",
+            TimestampStyle::Rfc3339 => "timestamp",
+        }
+    }
+}
+
+/// Parses a timestamp given as epoch milliseconds or RFC 3339, whichever
+/// style is configured, into epoch milliseconds.
+pub fn parse_timestamp(text: &str) -> Option<i64> {
+    let text = text.trim();
+    text.parse::<i64>()
+        .ok()
+        .or_else(|| DateTime::parse_from_rfc3339(text).ok().map(|t| t.timestamp_millis()))
+}
+
+impl NumberLocale {
+    pub fn decimal_separator(&self) -> char {
+        match self {
+            NumberLocale::En => '.',
+            _ => ',',
+        }
+    }
+
+    pub fn group_separator(&self) -> char {
+        match self {
+            NumberLocale::En => ',',
+            NumberLocale::De | NumberLocale::Es | NumberLocale::It | NumberLocale::Nl => '.',
+            NumberLocale::Fr | NumberLocale::Pl | NumberLocale::Sv => ' ',
+        }
+    }
+
+    /// Field delimiter for CSV: `;` where `,` is the decimal separator.
+    pub fn csv_delimiter(&self) -> char {
+        if self.decimal_separator() == ',' {
+            ';'
+        } else {
+            ','
+        }
+    }
+}
+
+impl FormatConfig {
+    /// Formats `value` with `decimals` places, grouping thousands if enabled.
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        self.render_number(value, decimals, self.group_thousands)
+    }
+
+    /// Like `number` but never groups thousands, for machine-read output.
+    pub fn plain_number(&self, value: f64, decimals: usize) -> String {
+        self.render_number(value, decimals, false)
+    }
+
+    fn render_number(&self, value: f64, decimals: usize, group: bool) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        let plain = format!("{:.*}", decimals, value.abs());
+        let (int_part, frac_part) = plain.split_once('.').unwrap_or((&plain, ""));
+
+        let mut out = String::with_capacity(plain.len() + 4);
+        if value < 0.0 && plain.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            out.push('-');
+        }
+        for (i, digit) in int_part.chars().enumerate() {
+            if group && i > 0 && (int_part.len() - i) % 3 == 0 {
+                out.push(self.locale.group_separator());
+            }
+            out.push(digit);
+        }
+        if !frac_part.is_empty() {
+            out.push(self.locale.decimal_separator());
+            out.push_str(frac_part);
+        }
+        out
+    }
+
+    /// Time of day in `location.timezone` for an epoch-millisecond timestamp.
+    pub fn time(&self, ms: i64) -> String {
+        self.local(ms, &self.time_format)
+    }
+
+    pub fn date(&self, ms: i64) -> String {
+        self.local(ms, &self.date_format)
+    }
+
+    pub fn datetime(&self, ms: i64) -> String {
+        self.local(ms, &format!("{} {}", self.date_format, self.time_format))
+    }
+
+    /// Machine-readable timestamp in the configured `timestamps` style.
+    pub fn timestamp(&self, ms: i64) -> String {
+        self.timestamps.render(ms)
+    }
+
+    fn local(&self, ms: i64, pattern: &str) -> String {
+        self.zone.at_millis(ms).map_or_else(|| ms.to_string(), |t| t.format(pattern).to_string())
+    }
+}
+
+/// Decimal places for axis labels spanning `range`.
+pub fn axis_decimals(range: f64) -> usize {
+    match range.abs() {
+        r if r < 10.0 => 2,
+        r if r < 1000.0 => 1,
+        _ => 0,
+    }
+}
+
+/// Midnights in `zone` (epoch milliseconds) strictly inside `(from, to)`,
+/// where charts draw a date break.
+pub fn day_boundaries(zone: Zone, from: i64, to: i64) -> Vec<i64> {
+    let Some(start) = zone.at_millis(from) else {
+        return Vec::new();
+    };
+    let mut boundaries = Vec::new();
+    let mut day = start.date_naive();
+    loop {
+        day = match day.succ_opt() {
+            Some(next) => next,
+            None => break,
+        };
+        let Some(midnight) = zone.from_local(&day.and_hms_opt(0, 0, 0).unwrap()) else {
+            continue;
+        };
+        let ms = midnight.timestamp_millis();
+        if ms >= to {
+            break;
+        }
+        boundaries.push(ms);
+    }
+    boundaries
+}
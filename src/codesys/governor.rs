@@ -0,0 +1,148 @@
+//! Resource governor for small edge devices.
+//!
+//! Samples the process's CPU and resident memory from `/proc` and compares
+//! them against the configured budgets. While over budget the governor steps
+//! up a degradation level, which stretches the chart render interval and
+//! grows storage batches; once usage falls comfortably below budget it steps
+//! back down. Every level change is logged.
+
+use super::config::GovernorConfig;
+use std::fs;
+use std::time::Instant;
+
+/// Highest degradation level; at this level the configured maxima apply.
+const MAX_LEVEL: u32 = 3;
+/// Usage must fall below this fraction of the budget before recovering.
+const RECOVERY_RATIO: f64 = 0.8;
+/// Clock ticks per second assumed when `sysconf` cannot tell; Linux's
+/// usual `USER_HZ`.
+const DEFAULT_CLOCK_TICKS: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ResourceUsage {
+    pub cpu_percent: f64,
+    pub memory_mb: f64,
+}
+
+#[derive(Debug)]
+pub struct ResourceGovernor {
+    config: GovernorConfig,
+    level: u32,
+    last_sample: Option<(Instant, f64)>,
+    last_usage: Option<ResourceUsage>,
+    /// Unit of the CPU times in `/proc/self/stat`, per second.
+    clock_ticks: f64,
+}
+
+impl ResourceGovernor {
+    pub fn new(config: GovernorConfig) -> Self {
+        ResourceGovernor { config, level: 0, last_sample: None, last_usage: None, clock_ticks: clock_ticks() }
+    }
+
+    pub fn check_interval_secs(&self) -> u64 {
+        self.config.check_interval_secs
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn last_usage(&self) -> Option<ResourceUsage> {
+        self.last_usage
+    }
+
+    /// Render charts only on every n-th poll tick.
+    pub fn render_every(&self) -> u64 {
+        scale(self.config.max_render_every, self.level)
+    }
+
+    /// Number of samples collected before they are written to the database.
+    pub fn batch_size(&self) -> usize {
+        scale(self.config.max_batch_size as u64, self.level) as usize
+    }
+
+    /// Samples resource usage and adjusts the degradation level.
+    pub fn check(&mut self) {
+        let Some(usage) = self.sample() else {
+            return;
+        };
+        self.last_usage = Some(usage);
+
+        let over = self.config.cpu_percent.is_some_and(|budget| usage.cpu_percent > budget)
+            || self.config.memory_mb.is_some_and(|budget| usage.memory_mb > budget);
+        let relaxed = self.config.cpu_percent.is_none_or(|budget| usage.cpu_percent < budget * RECOVERY_RATIO)
+            && self.config.memory_mb.is_none_or(|budget| usage.memory_mb < budget * RECOVERY_RATIO);
+
+        let previous = self.level;
+        if over && self.level < MAX_LEVEL {
+            self.level += 1;
+        } else if relaxed && self.level > 0 {
+            self.level -= 1;
+        }
+
+        if self.level > previous {
+            log::warn!(
+                "resource budget exceeded (cpu {:.1}%, rss {:.1} MB): degrading to level {}, rendering every {} ticks, batching {} samples",
+                usage.cpu_percent,
+                usage.memory_mb,
+                self.level,
+                self.render_every(),
+                self.batch_size()
+            );
+        } else if self.level < previous {
+            log::info!(
+                "resource usage back within budget (cpu {:.1}%, rss {:.1} MB): recovering to level {}",
+                usage.cpu_percent,
+                usage.memory_mb,
+                self.level
+            );
+        }
+    }
+
+    fn sample(&mut self) -> Option<ResourceUsage> {
+        let now = Instant::now();
+        let cpu_seconds = process_cpu_seconds(self.clock_ticks)?;
+        let memory_mb = process_rss_mb()?;
+        let previous = self.last_sample.replace((now, cpu_seconds));
+        let (then, then_cpu) = previous?;
+        let elapsed = now.duration_since(then).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        Some(ResourceUsage { cpu_percent: (cpu_seconds - then_cpu) / elapsed * 100.0, memory_mb })
+    }
+}
+
+fn scale(max: u64, level: u32) -> u64 {
+    1 + max.saturating_sub(1) * level as u64 / MAX_LEVEL as u64
+}
+
+/// Clock ticks per second of the kernel, `sysconf(_SC_CLK_TCK)`.
+fn clock_ticks() -> f64 {
+    // SAFETY: sysconf only reads a system setting.
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks > 0 {
+        ticks as f64
+    } else {
+        log::warn!("cannot read the clock tick rate; assuming {} per second", DEFAULT_CLOCK_TICKS);
+        DEFAULT_CLOCK_TICKS
+    }
+}
+
+/// User plus system CPU time consumed by this process, in seconds, with
+/// `clock_ticks` ticks per second.
+fn process_cpu_seconds(clock_ticks: f64) -> Option<f64> {
+    let stat = fs::read_to_string("/proc/self/stat").ok()?;
+    // The command name may contain spaces, so split after its closing paren.
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let utime: f64 = fields.get(11)?.parse().ok()?;
+    let stime: f64 = fields.get(12)?.parse().ok()?;
+    Some((utime + stime) / clock_ticks)
+}
+
+fn process_rss_mb() -> Option<f64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: f64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb / 1024.0)
+}
@@ -0,0 +1,768 @@
+//! GraphQL schema over motors, samples, window statistics, alarms and events.
+//!
+//! Served at `/graphql` (see `api`), the one route for reading monitor data
+//! besides the sample exports (`/export`, `/exports`), so dashboards can
+//! fetch exactly the fields they need in a single request. Mutations cover
+//! alarm handling (acknowledging, shelving and unshelving), notes and
+//! setpoints; all are rejected on a `--read-only` viewer.
+
+use super::alarms::{self, AlarmFilter, Severity};
+use super::api::ApiState;
+use super::audit;
+use super::counters;
+use super::demand;
+use super::devices;
+use super::events::{self, EventFilter};
+use super::jitter::JitterSummary;
+use super::maintenance;
+use super::notes;
+use super::oee;
+use super::plugins;
+use super::setpoint::{self, SetpointCommand};
+use super::sink::SinkHealth;
+use super::stats::{self, StatsWindow};
+use super::thermal;
+use super::units::UnitInfo;
+use super::{motor_data_range, MotorData, Signal};
+use async_graphql::{Context, EmptySubscription, Enum, Error, Object, Result, Schema, SimpleObject};
+use chrono::prelude::*;
+use std::sync::Arc;
+
+pub type MonitorSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+pub fn build_schema(state: Arc<ApiState>) -> MonitorSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription).data(state).finish()
+}
+
+#[derive(SimpleObject)]
+struct Motor {
+    id: String,
+    site: Option<String>,
+    location: MotorLocation,
+    specs: Specs,
+    /// Identification reported by the drive or controller, if any.
+    device: Option<Device>,
+    /// Thermal image of the `[thermal]` model (1.0 = trip level), as last
+    /// saved; saved once a minute.
+    thermal_level: Option<f64>,
+}
+
+/// `[location]` of the motor.
+#[derive(SimpleObject)]
+struct MotorLocation {
+    /// IANA time zone; null when the host's zone is used.
+    timezone: Option<String>,
+    name: Option<String>,
+    line: Option<String>,
+    area: Option<String>,
+}
+
+#[derive(SimpleObject)]
+struct Device {
+    vendor: String,
+    product_code: String,
+    product_name: Option<String>,
+    firmware: String,
+    serial_number: Option<String>,
+    first_seen: i64,
+    last_seen: i64,
+}
+
+impl From<devices::Device> for Device {
+    fn from(d: devices::Device) -> Self {
+        Device {
+            vendor: d.info.vendor,
+            product_code: d.info.product_code,
+            product_name: d.info.product_name,
+            firmware: d.info.firmware,
+            serial_number: d.info.serial_number,
+            first_seen: d.first_seen,
+            last_seen: d.last_seen,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct Specs {
+    rated_power: f64,
+    rated_torque: f64,
+    rated_speed: f64,
+    peak_torque: f64,
+    max_speed: f64,
+}
+
+#[derive(SimpleObject)]
+struct Sample {
+    timestamp: i64,
+    power: f64,
+    torque: f64,
+    speed: f64,
+    heat: f64,
+    cycles: f64,
+    speed_reference: Option<f64>,
+    tracking_error: Option<f64>,
+    temperatures: Vec<Temperature>,
+    /// Per-motor sample sequence number; gaps mean lost samples.
+    sequence: Option<i64>,
+    /// Values estimated rather than read, e.g. `speed`.
+    estimated: Vec<String>,
+}
+
+impl From<MotorData> for Sample {
+    fn from(d: MotorData) -> Self {
+        Sample {
+            timestamp: d.timestamp,
+            power: d.current_power,
+            torque: d.current_torque,
+            speed: d.current_speed,
+            heat: d.current_heat,
+            cycles: d.current_cycles,
+            speed_reference: d.speed_reference,
+            tracking_error: d.tracking_error(),
+            temperatures: d
+                .temperatures
+                .iter()
+                .map(|(channel, &value)| Temperature { channel: channel.clone(), value })
+                .collect(),
+            sequence: d.sequence,
+            estimated: d.estimated,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct Temperature {
+    channel: String,
+    value: f64,
+}
+
+#[derive(SimpleObject)]
+struct Aggregation {
+    signal: String,
+    window: String,
+    window_start: i64,
+    count: i64,
+    min: f64,
+    max: f64,
+    avg: f64,
+    stddev: f64,
+    /// `[rollups]` aggregations of the signal, e.g. `p95` or `energy_kwh`.
+    aggregates: Vec<AggregateValue>,
+}
+
+#[derive(SimpleObject)]
+struct AggregateValue {
+    name: String,
+    value: f64,
+}
+
+#[derive(SimpleObject)]
+struct Alarm {
+    id: i64,
+    motor_id: String,
+    rule: String,
+    signal: Option<String>,
+    severity: String,
+    message: String,
+    raised_at: i64,
+    cleared_at: Option<i64>,
+    snapshot_path: Option<String>,
+    acknowledged_at: Option<i64>,
+    acknowledged_by: Option<String>,
+    shelved: bool,
+}
+
+#[derive(SimpleObject)]
+struct Shelf {
+    id: i64,
+    motor_id: String,
+    rule: String,
+    reason: String,
+    shelved_by: String,
+    shelved_at: i64,
+    shelved_until: i64,
+    unshelved_at: Option<i64>,
+}
+
+impl From<alarms::Shelf> for Shelf {
+    fn from(s: alarms::Shelf) -> Self {
+        Shelf {
+            id: s.id,
+            motor_id: s.motor_id,
+            rule: s.rule,
+            reason: s.reason,
+            shelved_by: s.shelved_by,
+            shelved_at: s.shelved_at,
+            shelved_until: s.shelved_until,
+            unshelved_at: s.unshelved_at,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct Event {
+    id: i64,
+    motor_id: String,
+    timestamp: i64,
+    kind: String,
+    message: String,
+}
+
+#[derive(SimpleObject)]
+struct MaintenanceRun {
+    task: String,
+    started_at: i64,
+    duration_ms: i64,
+    ok: bool,
+    detail: String,
+    size_bytes: Option<i64>,
+}
+
+#[derive(SimpleObject)]
+struct Note {
+    id: i64,
+    motor_id: String,
+    timestamp: i64,
+    author: String,
+    text: String,
+    created_at: i64,
+}
+
+impl From<notes::Note> for Note {
+    fn from(n: notes::Note) -> Self {
+        Note { id: n.id, motor_id: n.motor_id, timestamp: n.timestamp, author: n.author, text: n.text, created_at: n.created_at }
+    }
+}
+
+#[derive(SimpleObject)]
+struct PluginValue {
+    timestamp: i64,
+    value: f64,
+}
+
+#[derive(SimpleObject)]
+struct AuditEntry {
+    id: i64,
+    motor_id: String,
+    timestamp: i64,
+    actor: String,
+    action: String,
+    detail: String,
+}
+
+#[derive(SimpleObject)]
+struct Counter {
+    name: String,
+    /// Last register value read.
+    raw: i64,
+    /// Accumulated across wraps and restarts, in the counter's unit.
+    total: f64,
+    updated_at: i64,
+}
+
+#[derive(SimpleObject)]
+struct Sink {
+    name: String,
+    /// `idle`, `running`, `backoff` or `stopped`.
+    state: String,
+    written: u64,
+    failures: u64,
+    skipped: u64,
+    last_error: Option<String>,
+    last_error_at: Option<i64>,
+}
+
+impl Sink {
+    fn new(name: String, h: SinkHealth) -> Self {
+        Sink {
+            name,
+            state: h.state.name().to_string(),
+            written: h.written,
+            failures: h.failures,
+            skipped: h.skipped,
+            last_error: h.last_error,
+            last_error_at: h.last_error_at,
+        }
+    }
+}
+
+/// Time between samples against the poll interval, in milliseconds; jitter
+/// is the actual minus the nominal interval.
+#[derive(SimpleObject)]
+struct Sampling {
+    nominal_ms: i64,
+    /// Intervals measured; gaps are not among them.
+    intervals: u64,
+    /// Intervals over 1.5 poll intervals.
+    late: u64,
+    /// Intervals over 3 poll intervals, counted as outages.
+    gaps: u64,
+    mean_jitter_ms: f64,
+    std_dev_ms: f64,
+    max_jitter_ms: f64,
+    /// Over the last 1000 intervals.
+    p99_jitter_ms: Option<f64>,
+    last_interval_ms: Option<i64>,
+}
+
+/// How a value is stored and shown; see `units`.
+#[derive(SimpleObject)]
+struct Unit {
+    name: String,
+    /// Unit of the stored value, as served by the API.
+    stored: String,
+    /// Unit in exports, charts and the dashboard.
+    display: String,
+    /// Factor from the stored to the display unit.
+    scale: f64,
+}
+
+impl From<UnitInfo> for Unit {
+    fn from(u: UnitInfo) -> Self {
+        Unit { name: u.name, stored: u.stored, display: u.display, scale: u.scale }
+    }
+}
+
+impl From<JitterSummary> for Sampling {
+    fn from(j: JitterSummary) -> Self {
+        Sampling {
+            nominal_ms: j.nominal_ms,
+            intervals: j.intervals,
+            late: j.late,
+            gaps: j.gaps,
+            mean_jitter_ms: j.mean_ms,
+            std_dev_ms: j.std_dev_ms,
+            max_jitter_ms: j.max_ms,
+            p99_jitter_ms: j.p99_ms,
+            last_interval_ms: j.last_interval_ms,
+        }
+    }
+}
+
+/// Demand windows of a period; see `demand`.
+#[derive(SimpleObject)]
+struct Demand {
+    window_minutes: u32,
+    /// Average power (kW) over the window ending now.
+    rolling_kw: Option<f64>,
+    windows: Vec<DemandWindow>,
+    /// Highest window of every month on record, newest first.
+    monthly_peaks: Vec<MonthlyPeak>,
+}
+
+#[derive(SimpleObject)]
+struct DemandWindow {
+    /// Epoch milliseconds.
+    start: i64,
+    kw: f64,
+}
+
+impl From<demand::DemandWindow> for DemandWindow {
+    fn from(w: demand::DemandWindow) -> Self {
+        DemandWindow { start: w.start, kw: w.kw }
+    }
+}
+
+#[derive(SimpleObject)]
+struct MonthlyPeak {
+    /// `YYYY-MM` in the motor's time zone.
+    month: String,
+    start: i64,
+    kw: f64,
+}
+
+/// OEE-style KPIs of a period; ratios are fractions, `null` when undefined.
+#[derive(SimpleObject)]
+struct Kpis {
+    from: i64,
+    to: i64,
+    scheduled_secs: f64,
+    run_secs: f64,
+    availability: Option<f64>,
+    /// Mean speed while running over rated speed.
+    performance: Option<f64>,
+    /// Share of the run time without an active alarm.
+    quality: Option<f64>,
+    oee: Option<f64>,
+    /// Alarms raised in the period.
+    quality_events: i64,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Motors known to this monitor.
+    async fn motors(&self, ctx: &Context<'_>) -> Vec<Motor> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        let specs = &state.specs;
+        let device = devices::load_device(state.queries.pool(), &state.motor_id).await;
+        let thermal = thermal::stored_level(state.queries.pool(), &state.motor_id).await;
+        vec![Motor {
+            id: state.motor_id.clone(),
+            site: state.site.clone(),
+            location: MotorLocation {
+                timezone: state.location.zone.name().map(str::to_string),
+                name: state.location.name.clone(),
+                line: state.location.line.clone(),
+                area: state.location.area.clone(),
+            },
+            specs: Specs {
+                rated_power: specs.rated_power,
+                rated_torque: specs.rated_torque,
+                rated_speed: specs.rated_speed,
+                peak_torque: specs.peak_torque,
+                max_speed: specs.max_speed,
+            },
+            device: device.map(Device::from),
+            thermal_level: thermal.map(|(level, _)| level),
+        }]
+    }
+
+    /// Raw samples with `from <= timestamp < to` (epoch milliseconds), oldest first.
+    async fn samples(&self, ctx: &Context<'_>, from: i64, to: i64, limit: Option<i64>) -> Vec<Sample> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        motor_data_range(state.queries.pool(), from, to, limit).await.into_iter().map(Sample::from).collect()
+    }
+
+    /// The latest stored sample, with the latest value of every signal;
+    /// cached for `api.queries.latest_cache_ms`.
+    async fn latest(&self, ctx: &Context<'_>) -> Option<Sample> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        state.queries.latest().await.map(Sample::from)
+    }
+
+    /// Persisted window statistics. `window` is one of `1m`, `15m`, `1h`.
+    async fn aggregations(
+        &self,
+        ctx: &Context<'_>,
+        signal: String,
+        window: String,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Aggregation>> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        let signal = Signal::from_name(&signal).ok_or_else(|| Error::new(format!("unknown signal {:?}", signal)))?;
+        let window =
+            StatsWindow::from_label(&window).ok_or_else(|| Error::new(format!("unknown window {:?}", window)))?;
+        Ok(stats::window_summaries(state.queries.pool(), signal, window, from, to)
+            .await
+            .into_iter()
+            .map(|s| Aggregation {
+                signal: s.signal.name().to_string(),
+                window: s.window.label().to_string(),
+                window_start: s.window_start,
+                count: s.count as i64,
+                min: s.min,
+                max: s.max,
+                avg: s.avg,
+                stddev: s.stddev,
+                aggregates: s.aggregates.into_iter().map(|(name, value)| AggregateValue { name, value }).collect(),
+            })
+            .collect())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn alarms(
+        &self,
+        ctx: &Context<'_>,
+        motor_id: Option<String>,
+        rule: Option<String>,
+        severity: Option<String>,
+        #[graphql(default)] active_only: bool,
+        #[graphql(default)] unacknowledged_only: bool,
+        #[graphql(default)] include_shelved: bool,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Alarm>> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        let severity = match severity {
+            Some(name) => {
+                Some(Severity::from_name(&name).ok_or_else(|| Error::new(format!("unknown severity {:?}", name)))?)
+            }
+            None => None,
+        };
+        let filter =
+            AlarmFilter { motor_id, rule, severity, active_only, unacknowledged_only, include_shelved, from, to, limit };
+        Ok(alarms::list_alarms(state.queries.pool(), &filter)
+            .await
+            .into_iter()
+            .map(|a| Alarm {
+                id: a.id,
+                motor_id: a.motor_id,
+                rule: a.rule,
+                signal: a.signal,
+                severity: a.severity.name().to_string(),
+                message: a.message,
+                raised_at: a.raised_at,
+                cleared_at: a.cleared_at,
+                snapshot_path: a.snapshot_path,
+                acknowledged_at: a.acknowledged_at,
+                acknowledged_by: a.acknowledged_by,
+                shelved: a.shelved,
+            })
+            .collect())
+    }
+
+    /// Alarm shelves; by default only those currently in place.
+    async fn shelves(&self, ctx: &Context<'_>, #[graphql(default = true)] active_only: bool) -> Vec<Shelf> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        let now = Local::now().timestamp();
+        alarms::list_shelves(state.queries.pool(), active_only, now).await.into_iter().map(Shelf::from).collect()
+    }
+
+    /// Operator notes with `from <= timestamp < to` (epoch seconds), oldest first.
+    async fn notes(&self, ctx: &Context<'_>, from: i64, to: i64) -> Vec<Note> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        notes::list_notes(state.queries.pool(), &state.motor_id, from, to).await.into_iter().map(Note::from).collect()
+    }
+
+    /// Values derived by a plugin with `from <= timestamp < to` (epoch milliseconds), oldest first.
+    async fn plugin_values(&self, ctx: &Context<'_>, plugin: String, name: String, from: i64, to: i64) -> Vec<PluginValue> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        plugins::list_values(state.queries.pool(), &plugin, &name, from, to)
+            .await
+            .into_iter()
+            .map(|v| PluginValue { timestamp: v.timestamp, value: v.value })
+            .collect()
+    }
+
+    /// Commands written to the drive and other audited actions, newest first.
+    async fn audit_log(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<i64>,
+    ) -> Vec<AuditEntry> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        audit::list_entries(state.queries.pool(), from, to, limit)
+            .await
+            .into_iter()
+            .map(|e| AuditEntry {
+                id: e.id,
+                motor_id: e.motor_id,
+                timestamp: e.timestamp,
+                actor: e.actor,
+                action: e.action,
+                detail: e.detail,
+            })
+            .collect()
+    }
+
+    /// Recent database maintenance runs (integrity check, checkpoint, vacuum), newest first.
+    async fn maintenance_runs(&self, ctx: &Context<'_>, limit: Option<i64>) -> Vec<MaintenanceRun> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        maintenance::list_runs(state.queries.pool(), limit)
+            .await
+            .into_iter()
+            .map(|r| MaintenanceRun {
+                task: r.task,
+                started_at: r.started_at,
+                duration_ms: r.duration_ms,
+                ok: r.ok,
+                detail: r.detail,
+                size_bytes: r.size_bytes,
+            })
+            .collect()
+    }
+
+    /// Availability, performance, quality and OEE over `[from, to)` (epoch
+    /// milliseconds), at most `oee::MAX_RANGE_MS` long; see `oee`.
+    async fn kpis(&self, ctx: &Context<'_>, from: i64, to: i64) -> Result<Kpis> {
+        if from >= to {
+            return Err(Error::new("from must be before to"));
+        }
+        if to.checked_sub(from).is_none_or(|range| range > oee::MAX_RANGE_MS) {
+            return Err(Error::new(format!("the range must be at most {} days", oee::MAX_RANGE_MS / 86_400_000)));
+        }
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        let kpis = oee::kpis(state.queries.pool(), &state.kpis, from, to).await;
+        Ok(Kpis {
+            from,
+            to,
+            scheduled_secs: kpis.scheduled_secs,
+            run_secs: kpis.run_secs,
+            availability: kpis.availability(),
+            performance: kpis.performance(),
+            quality: kpis.quality(),
+            oee: kpis.oee(),
+            quality_events: kpis.quality_events,
+        })
+    }
+
+    /// Demand windows starting in `[from, to)` (epoch milliseconds) and the
+    /// monthly peaks; `null` without `[demand]`.
+    async fn demand(&self, ctx: &Context<'_>, from: i64, to: i64) -> Result<Option<Demand>> {
+        if from >= to {
+            return Err(Error::new("from must be before to"));
+        }
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        let Some(settings) = &state.demand else {
+            return Ok(None);
+        };
+        let now = Utc::now().timestamp_millis();
+        let windows = demand::windows(state.queries.pool(), &state.motor_id, from, to).await;
+        Ok(Some(Demand {
+            window_minutes: settings.config.window_minutes,
+            rolling_kw: demand::rolling_kw(state.queries.pool(), settings.window_ms(), now).await,
+            windows: windows.into_iter().map(Into::into).collect(),
+            monthly_peaks: demand::monthly_peaks(state.queries.pool(), &state.motor_id)
+                .await
+                .into_iter()
+                .map(|peak| MonthlyPeak { month: peak.month, start: peak.window.start, kw: peak.window.kw })
+                .collect(),
+        }))
+    }
+
+    /// Totals of the `[counters]` registers.
+    async fn counters(&self, ctx: &Context<'_>) -> Vec<Counter> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        counters::load_states(state.queries.pool())
+            .await
+            .into_iter()
+            .map(|c| Counter { name: c.name, raw: c.raw, total: c.total, updated_at: c.updated_at })
+            .collect()
+    }
+
+    /// Health of each sink the polling loop feeds; empty for a `--read-only` viewer.
+    async fn sinks(&self, ctx: &Context<'_>) -> Vec<Sink> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        let health = state.sinks.lock().unwrap().clone();
+        health.into_iter().map(|(name, h)| Sink::new(name, h)).collect()
+    }
+
+    /// Stored and display unit of every signal, `speed_reference`,
+    /// temperature channel and pulse input. Values served by the other
+    /// queries are in the stored units.
+    async fn units(&self, ctx: &Context<'_>) -> Vec<Unit> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        state.units.all().into_iter().map(Unit::from).collect()
+    }
+
+    /// Sampling jitter of the polling loop; nothing measured for a `--read-only` viewer.
+    async fn sampling(&self, ctx: &Context<'_>) -> Sampling {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        let summary = state.sampling.lock().unwrap().summary();
+        summary.into()
+    }
+
+    async fn events(
+        &self,
+        ctx: &Context<'_>,
+        motor_id: Option<String>,
+        kind: Option<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<i64>,
+    ) -> Vec<Event> {
+        let state = ctx.data_unchecked::<Arc<ApiState>>();
+        let filter = EventFilter { motor_id, kind, from, to, limit };
+        events::list_events(state.queries.pool(), &filter)
+            .await
+            .into_iter()
+            .map(|e| Event { id: e.id, motor_id: e.motor_id, timestamp: e.timestamp, kind: e.kind, message: e.message })
+            .collect()
+    }
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+enum SetpointKind {
+    Speed,
+    Torque,
+}
+
+pub struct MutationRoot;
+
+/// The API state, unless the server is a `--read-only` viewer.
+fn writable<'a>(ctx: &Context<'a>) -> Result<&'a Arc<ApiState>> {
+    let state = ctx.data_unchecked::<Arc<ApiState>>();
+    if state.read_only {
+        return Err(Error::new("this server is read-only"));
+    }
+    Ok(state)
+}
+
+#[Object]
+impl MutationRoot {
+    /// Acknowledges an alarm. Fails if it is unknown or already acknowledged.
+    async fn acknowledge_alarm(&self, ctx: &Context<'_>, id: i64, by: String) -> Result<bool> {
+        let state = writable(ctx)?;
+        if !alarms::acknowledge_alarm(&state.pool, id, &by, Local::now().timestamp()).await {
+            return Err(Error::new(format!("alarm {} not found or already acknowledged", id)));
+        }
+        Ok(true)
+    }
+
+    /// Suppresses alarms of `rule` for `hours`; a reason is mandatory.
+    async fn shelve_rule(&self, ctx: &Context<'_>, rule: String, hours: f64, reason: String, by: String) -> Result<Shelf> {
+        let state = writable(ctx)?;
+        if !(hours > 0.0 && hours <= 24.0 * 7.0) {
+            return Err(Error::new("hours must be between 0 and 168"));
+        }
+        if reason.trim().is_empty() {
+            return Err(Error::new("a reason is required to shelve an alarm"));
+        }
+        let now = Local::now().timestamp();
+        let until = now + (hours * 3600.0) as i64;
+        alarms::shelve_rule(&state.pool, &state.motor_id, &rule, &reason, &by, now, until).await;
+        let shelf = alarms::list_shelves(&state.pool, true, now).await.into_iter().find(|s| s.rule == rule);
+        shelf.map(Shelf::from).ok_or_else(|| Error::new("shelf not found after insert"))
+    }
+
+    /// Attaches a note to the motor at `timestamp` (epoch seconds; now when omitted).
+    async fn add_note(&self, ctx: &Context<'_>, text: String, author: String, timestamp: Option<i64>) -> Result<Note> {
+        let state = writable(ctx)?;
+        if text.trim().is_empty() {
+            return Err(Error::new("note text must not be empty"));
+        }
+        let now = Local::now().timestamp();
+        let timestamp = timestamp.unwrap_or(now);
+        let id = notes::add_note(&state.pool, &state.motor_id, timestamp, &author, &text, now).await;
+        Ok(Note { id, motor_id: state.motor_id.clone(), timestamp, author, text, created_at: now })
+    }
+
+    async fn delete_note(&self, ctx: &Context<'_>, id: i64) -> Result<bool> {
+        let state = writable(ctx)?;
+        if !notes::delete_note(&state.pool, id).await {
+            return Err(Error::new(format!("note {} not found", id)));
+        }
+        Ok(true)
+    }
+
+    /// Requests a new speed (rpm) or torque (Nm) setpoint. The monitor ramps
+    /// towards it at the configured rates; the request is audited. With
+    /// `dryRun` only the ramp it would start is audited. Disabled when
+    /// `[control]` is configured: setpoints then take an operator token and a
+    /// confirmation, through `POST /control/setpoint`.
+    async fn set_setpoint(
+        &self,
+        ctx: &Context<'_>,
+        kind: SetpointKind,
+        value: f64,
+        by: String,
+        #[graphql(default)] dry_run: bool,
+    ) -> Result<bool> {
+        let state = writable(ctx)?;
+        if state.control.is_some() {
+            return Err(Error::new("with [control] configured, setpoints go through POST /control/setpoint"));
+        }
+        let handle = state.setpoints.as_ref().ok_or_else(|| Error::new("setpoints are not configured"))?;
+        let kind = match kind {
+            SetpointKind::Speed => setpoint::SetpointKind::Speed,
+            SetpointKind::Torque => setpoint::SetpointKind::Torque,
+        };
+        handle.submit(SetpointCommand { kind, value, by, dry_run }).map_err(Error::new)?;
+        Ok(true)
+    }
+
+    /// Lifts a shelf before it expires.
+    async fn unshelve_rule(&self, ctx: &Context<'_>, rule: String) -> Result<bool> {
+        let state = writable(ctx)?;
+        if !alarms::unshelve_rule(&state.pool, &state.motor_id, &rule, Local::now().timestamp()).await {
+            return Err(Error::new(format!("rule {:?} is not shelved", rule)));
+        }
+        Ok(true)
+    }
+}
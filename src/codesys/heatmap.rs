@@ -0,0 +1,173 @@
+//! Dwell-time heatmap of temperature versus load.
+//!
+//! Each cell shows how long the motor spent at a given (load %, heat)
+//! combination over the window. A cooling problem shows up as high
+//! temperatures at partial load, which line charts of either signal alone
+//! tend to hide.
+
+use super::config::{FormatConfig, HeatmapConfig};
+use super::disk;
+use super::output::{add_png_text, chart_text, ensure_parent, render_path, write_atomically, OutputContext};
+use super::{motor_data_range, MotorData, MotorSpecs};
+use chrono::prelude::*;
+use plotters::prelude::*;
+use sqlx::sqlite::SqlitePool;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+
+/// Load axis upper bound (% of rated power); higher loads land in the last bin.
+const MAX_LOAD_PERCENT: f64 = 150.0;
+
+#[derive(Debug, Clone)]
+pub struct DwellGrid {
+    pub load_bins: usize,
+    pub temperature_bins: usize,
+    pub temperature_min: f64,
+    pub temperature_max: f64,
+    /// Seconds spent per cell, row-major by temperature bin.
+    pub seconds: Vec<f64>,
+}
+
+impl DwellGrid {
+    pub fn get(&self, load_bin: usize, temperature_bin: usize) -> f64 {
+        self.seconds[temperature_bin * self.load_bins + load_bin]
+    }
+
+    pub fn total_seconds(&self) -> f64 {
+        self.seconds.iter().sum()
+    }
+}
+
+/// Bins `samples` (oldest first) by load and heat. Each sample accounts for
+/// the time until the next one, capped at `max_gap_ms` so outages don't
+/// count as dwell time.
+pub fn dwell_grid(
+    samples: &[MotorData],
+    specs: &MotorSpecs,
+    load_bins: usize,
+    temperature_bins: usize,
+    max_gap_ms: i64,
+) -> Option<DwellGrid> {
+    let (min, max) = samples
+        .iter()
+        .map(|d| d.current_heat)
+        .filter(|t| t.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), t| (lo.min(t), hi.max(t)));
+    if !min.is_finite() || specs.rated_power <= 0.0 {
+        return None;
+    }
+    let (temperature_min, temperature_max) = (min.floor(), (max.ceil()).max(min.floor() + 1.0));
+
+    let mut grid = DwellGrid {
+        load_bins,
+        temperature_bins,
+        temperature_min,
+        temperature_max,
+        seconds: vec![0.0; load_bins * temperature_bins],
+    };
+    for pair in samples.windows(2) {
+        let (data, next) = (&pair[0], &pair[1]);
+        let dwell_ms = (next.timestamp - data.timestamp).clamp(0, max_gap_ms);
+        let load = data.current_power / specs.rated_power * 100.0;
+        let load_bin = bin(load, 0.0, MAX_LOAD_PERCENT, load_bins);
+        let temperature_bin = bin(data.current_heat, temperature_min, temperature_max, temperature_bins);
+        grid.seconds[temperature_bin * load_bins + load_bin] += dwell_ms as f64 / 1000.0;
+    }
+    Some(grid)
+}
+
+fn bin(value: f64, min: f64, max: f64, bins: usize) -> usize {
+    let position = ((value - min) / (max - min) * bins as f64).floor();
+    if position.is_nan() {
+        return 0;
+    }
+    (position.max(0.0) as usize).min(bins - 1)
+}
+
+/// Draws the heatmap with `text` as PNG text chunks; the file is replaced
+/// atomically.
+pub fn draw_heatmap(
+    filename: &str,
+    grid: &DwellGrid,
+    format: &FormatConfig,
+    title: &str,
+    text: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    write_atomically(Path::new(filename), |path| {
+        render_heatmap(path, grid, format, title)?;
+        add_png_text(path, text)
+    })
+}
+
+fn render_heatmap(path: &Path, grid: &DwellGrid, format: &FormatConfig, title: &str) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(path, (640, 480)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 30).into_font())
+        .margin(5)
+        .x_label_area_size(30)
+        .y_label_area_size(40)
+        .build_cartesian_2d(0.0..MAX_LOAD_PERCENT, grid.temperature_min..grid.temperature_max)?;
+
+    let value_label = |v: &f64| format.number(*v, 0);
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_desc("Load (% of rated power)")
+        .y_desc("Heat (°C)")
+        .x_label_formatter(&value_label)
+        .y_label_formatter(&value_label)
+        .draw()?;
+
+    let load_step = MAX_LOAD_PERCENT / grid.load_bins as f64;
+    let temperature_step = (grid.temperature_max - grid.temperature_min) / grid.temperature_bins as f64;
+    let peak = grid.seconds.iter().copied().fold(0.0, f64::max);
+    let cells = (0..grid.temperature_bins).flat_map(|t| (0..grid.load_bins).map(move |l| (l, t)));
+    chart.draw_series(cells.filter(|&(l, t)| grid.get(l, t) > 0.0).map(|(l, t)| {
+        // Log scale so short excursions stay visible next to long steady states.
+        let intensity = (1.0 + grid.get(l, t)).ln() / (1.0 + peak).ln();
+        let color = HSLColor(0.66 * (1.0 - intensity), 1.0, 0.5);
+        let x = l as f64 * load_step;
+        let y = grid.temperature_min + t as f64 * temperature_step;
+        Rectangle::new([(x, y), (x + load_step, y + temperature_step)], color.filled())
+    }))?;
+
+    root.present()?;
+    Ok(())
+}
+
+/// Renders the heatmap for the trailing window every `every_minutes`.
+pub async fn run_heatmap_renderer(
+    pool: Arc<SqlitePool>,
+    motor_id: String,
+    specs: MotorSpecs,
+    config: HeatmapConfig,
+    format: FormatConfig,
+    poll_interval_ms: u64,
+    space: disk::Space,
+) {
+    let mut interval = time::interval(Duration::from_secs(config.every_minutes * 60));
+    loop {
+        interval.tick().await;
+        let now = Local::now().timestamp_millis();
+        let samples = motor_data_range(&pool, now - config.window_hours * 3_600_000, now, None).await;
+        let max_gap_ms = (poll_interval_ms as i64 * 3).max(1000);
+        let Some(grid) = dwell_grid(&samples, &specs, config.load_bins, config.temperature_bins, max_gap_ms) else {
+            continue;
+        };
+
+        let ctx = OutputContext { motor: &motor_id, signal: "heatmap", timestamp: now.div_euclid(1000) };
+        let path = render_path(&config.path_template, &ctx);
+        let title = format!("Heat vs. load, last {} h", config.window_hours);
+        let text = chart_text(&motor_id, now - config.window_hours * 3_600_000, now);
+        let result = ensure_parent(&path, &space)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|_| draw_heatmap(&path.to_string_lossy(), &grid, &format, &title, &text));
+        if let Err(err) = result {
+            log::error!("cannot write heatmap {}: {}", path.display(), err);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Load-based auto-stop interlock.
+//!
+//! Trips when torque stays above the motor's peak torque for
+//! `overload_secs`, when heat or any temperature channel exceeds
+//! `max_temperature`, or when the thermal model (`thermal`) reaches its
+//! trip level. A trip writes `stop_value` to `stop_register` (only
+//! with `writes_enabled`; otherwise it is a dry run), raises a critical
+//! alarm and is recorded in the audit log. The interlock stays latched
+//! until the readings are back within limits.
+
+use super::alarms::{self, Severity};
+use super::audit;
+use super::config::InterlockConfig;
+use super::events;
+use super::transport::{Transport, TransportError};
+use super::{MotorData, MotorSpecs};
+use sqlx::sqlite::SqlitePool;
+
+/// After a thermal trip the interlock resets only once the thermal level
+/// has cooled this far below the trip level, like a relay's restart
+/// inhibit.
+const THERMAL_RESET_MARGIN: f64 = 0.1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trip {
+    Overload { torque: f64, peak: f64, secs: i64 },
+    OverTemperature { source: String, value: f64, limit: f64 },
+    ThermalOverload { level: f64 },
+}
+
+impl Trip {
+    pub fn describe(&self) -> String {
+        match self {
+            Trip::Overload { torque, peak, secs } => {
+                format!("torque {:.1} Nm above peak {:.1} Nm for {} s", torque, peak, secs)
+            }
+            Trip::OverTemperature { source, value, limit } => {
+                format!("{} at {:.1} °C above hard limit {:.1} °C", source, value, limit)
+            }
+            Trip::ThermalOverload { level } => format!("thermal image at {:.0} % of trip level", level * 100.0),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Interlock {
+    config: InterlockConfig,
+    overload_since: Option<i64>,
+    tripped: bool,
+}
+
+impl Interlock {
+    pub fn new(config: InterlockConfig) -> Self {
+        Interlock { config, overload_since: None, tripped: false }
+    }
+
+    /// Whether the interlock is latched after a trip.
+    pub fn is_tripped(&self) -> bool {
+        self.tripped
+    }
+
+    /// Feeds a sample and the thermal level, if a thermal model runs;
+    /// returns the trip reason the first time a limit is violated. Further
+    /// violations are ignored until the interlock resets.
+    pub fn check(&mut self, data: &MotorData, specs: &MotorSpecs, thermal_level: Option<f64>) -> Option<Trip> {
+        let now = data.seconds();
+        let overloaded = data.current_torque > specs.peak_torque;
+        if overloaded {
+            self.overload_since.get_or_insert(now);
+        } else {
+            self.overload_since = None;
+        }
+
+        let over_temperature = self.config.max_temperature.and_then(|limit| {
+            std::iter::once(("heat".to_string(), data.current_heat))
+                .chain(data.temperatures.iter().map(|(name, value)| (name.clone(), *value)))
+                .find(|(_, value)| *value > limit)
+                .map(|(source, value)| Trip::OverTemperature { source, value, limit })
+        });
+        let overload = self
+            .overload_since
+            .filter(|since| now - since >= self.config.overload_secs)
+            .map(|since| Trip::Overload { torque: data.current_torque, peak: specs.peak_torque, secs: now - since });
+
+        let thermal = thermal_level.filter(|level| *level >= 1.0).map(|level| Trip::ThermalOverload { level });
+
+        let trip = over_temperature.or(overload).or(thermal);
+        let hot = thermal_level.is_some_and(|level| level > 1.0 - THERMAL_RESET_MARGIN);
+        if trip.is_none() && !overloaded && !hot && self.tripped {
+            log::info!("interlock reset: readings back within limits");
+            self.tripped = false;
+        }
+        if self.tripped {
+            return None;
+        }
+        self.tripped = trip.is_some();
+        trip
+    }
+}
+
+/// Carries out a trip: writes the stop command when enabled, not in a global
+/// `dry_run`, and a transport is available (only the active node has one),
+/// then records the action.
+pub async fn execute(
+    pool: &SqlitePool,
+    config: &InterlockConfig,
+    transport: Option<&mut (dyn Transport + '_)>,
+    motor_id: &str,
+    trip: &Trip,
+    now: i64,
+    dry_run: bool,
+) {
+    let reason = trip.describe();
+    let would_write = format!("would write {} to register {}", config.stop_value, config.stop_register.address);
+    let outcome = match (config.writes_enabled && !dry_run, transport) {
+        (false, _) if dry_run => format!("dry run, stop not written (dry_run = true); {}", would_write),
+        (false, _) => format!("dry run, stop not written (writes_enabled = false); {}", would_write),
+        (true, None) => "stop not written: no field-bus connection".to_string(),
+        (true, Some(transport)) => match transport.write(&config.stop_register, config.stop_value).await {
+            Ok(()) => format!("wrote {} to register {}", config.stop_value, config.stop_register.address),
+            Err(TransportError::Unverified(err)) => {
+                let message = format!("interlock stop: read-back failed: {}", err);
+                events::record_event(pool, motor_id, now, "write_unverified", &message).await;
+                format!(
+                    "wrote {} to register {}, read-back failed: {}",
+                    config.stop_value, config.stop_register.address, err
+                )
+            }
+            Err(err) => {
+                if let TransportError::Rejected { .. } = err {
+                    let message = format!("interlock stop: {}", err);
+                    events::record_event(pool, motor_id, now, "write_failed", &message).await;
+                }
+                format!("stop command failed: {}", err)
+            }
+        },
+    };
+    log::error!("interlock tripped: {} ({})", reason, outcome);
+    audit::record(pool, motor_id, now, "interlock", "stop", &format!("{}; {}", reason, outcome)).await;
+    alarms::raise_alarm(pool, motor_id, "interlock_stop", None, Severity::Critical, &reason, now).await;
+}
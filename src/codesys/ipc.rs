@@ -0,0 +1,309 @@
+//! Local JSON-RPC socket of the running monitor.
+//!
+//! With an `[ipc]` section the monitor listens on a Unix domain socket, so
+//! `rustsys status`, `rustsys tail` and `rustsys setpoint` talk to the live
+//! instance instead of opening the serial port or the database themselves.
+//! Every line on the socket is one JSON-RPC 2.0 message. Methods:
+//!
+//! - `status`: motor id, process id, start time, latest sample and the
+//!   health of the sinks.
+//! - `tail`: answers `true`, then sends every new sample as a `sample`
+//!   notification until the client hangs up. A client too slow to keep up
+//!   misses samples, which shows as a gap in their sequence numbers.
+//! - `setpoint` (`kind`, `value`, `by`, `dry_run`): hands the request to the
+//!   setpoint ramps, like the GraphQL `setSetpoint` mutation. `by` is taken
+//!   from the client as is, so with `[control]` configured the method is
+//!   refused: setpoints then take an operator token and a confirmation,
+//!   through `POST /control/setpoint`.
+//!
+//! The socket is created with mode `0660`, so its owner and group, and the
+//! permissions of its directory, decide who may talk to the monitor. It is
+//! bound in a directory only the monitor's user may enter, `.<name>.tmp`
+//! next to it, and moved into place once its mode is set, so nobody can
+//! connect while it still has the default mode. A socket left behind by a
+//! monitor that crashed is replaced; one another monitor still answers on is
+//! left alone.
+
+use super::config::IpcConfig;
+use super::setpoint::{SetpointCommand, SetpointHandle, SetpointKind};
+use super::sink::SinkHealthMap;
+use super::startup;
+use super::MotorData;
+use chrono::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Bytes the socket's path grows by while it is bound in the staging
+/// directory: `.`, `.tmp` and `/s`.
+pub const STAGING_BYTES: usize = 7;
+/// Understood but refused, e.g. setpoints are not configured.
+const REFUSED: i64 = -32000;
+
+/// Samples buffered per following client.
+const TAIL_BUFFER: usize = 256;
+
+type RpcError = (i64, String);
+
+#[derive(Debug)]
+struct Shared {
+    motor_id: String,
+    /// Epoch ms.
+    started_at: i64,
+    setpoints: Option<SetpointHandle>,
+    /// `[control]` is configured: `setpoint` is refused.
+    controlled: bool,
+    sinks: SinkHealthMap,
+    latest: Mutex<Option<MotorData>>,
+    samples: broadcast::Sender<MotorData>,
+}
+
+/// The polling loop's side: hands samples to the socket's clients.
+#[derive(Debug)]
+pub struct IpcServer {
+    shared: Arc<Shared>,
+}
+
+impl IpcServer {
+    pub fn start(
+        config: IpcConfig,
+        motor_id: String,
+        setpoints: Option<SetpointHandle>,
+        controlled: bool,
+        sinks: SinkHealthMap,
+    ) -> Self {
+        let (samples, _) = broadcast::channel(TAIL_BUFFER);
+        let shared = Arc::new(Shared {
+            motor_id,
+            started_at: Local::now().timestamp_millis(),
+            setpoints,
+            controlled,
+            sinks,
+            latest: Mutex::new(None),
+            samples,
+        });
+        tokio::spawn(serve(config, Arc::clone(&shared)));
+        IpcServer { shared }
+    }
+
+    /// Makes `data` the latest sample and sends it to following clients.
+    pub fn publish(&self, data: &MotorData) {
+        *self.shared.latest.lock().unwrap() = Some(data.clone());
+        if self.shared.samples.receiver_count() > 0 {
+            let _ = self.shared.samples.send(data.clone());
+        }
+    }
+}
+
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another monitor is listening on it"));
+        }
+        fs::remove_file(path)?;
+    }
+    let private = staging_dir(path);
+    if private.exists() {
+        fs::remove_dir_all(&private)?;
+    }
+    DirBuilder::new().mode(0o700).create(&private)?;
+    let staged = private.join("s");
+    let bound = UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o660))?;
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    if let Err(err) = fs::remove_dir_all(&private) {
+        log::warn!("cannot remove {}: {}", private.display(), err);
+    }
+    bound
+}
+
+/// `.<name>.tmp` next to the socket `path`; the socket is bound in it as
+/// `s`.
+fn staging_dir(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp", name))
+}
+
+async fn serve(config: IpcConfig, shared: Arc<Shared>) {
+    let socket = &config.socket;
+    let component = format!("IPC socket {}", socket.display());
+    let Ok(listener) = startup::retry(&component, || async move { bind(socket) }).await else {
+        return;
+    };
+    log::info!("IPC socket on {}", config.socket.display());
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle(stream, Arc::clone(&shared)));
+            }
+            Err(err) => log::warn!("IPC accept failed: {}", err),
+        }
+    }
+}
+
+async fn send(writer: &mut OwnedWriteHalf, message: &Value) -> io::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+fn response(id: Value, outcome: Result<Value, RpcError>) -> Value {
+    match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+    }
+}
+
+async fn handle(stream: UnixStream, shared: Arc<Shared>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                if send(&mut writer, &response(Value::Null, Err((PARSE_ERROR, err.to_string())))).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let method = request.get("method").and_then(Value::as_str).unwrap_or_default();
+        if method == "tail" {
+            let samples = shared.samples.subscribe();
+            if send(&mut writer, &response(id, Ok(Value::Bool(true)))).await.is_ok() {
+                follow(&mut writer, samples).await;
+            }
+            return;
+        }
+        let outcome = match method {
+            "status" => Ok(status(&shared)),
+            "setpoint" => setpoint(&shared, params),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {:?}", method))),
+        };
+        if send(&mut writer, &response(id, outcome)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends samples as `sample` notifications until the client hangs up.
+async fn follow(writer: &mut OwnedWriteHalf, mut samples: broadcast::Receiver<MotorData>) {
+    loop {
+        match samples.recv().await {
+            Ok(data) => {
+                let notification = json!({ "jsonrpc": "2.0", "method": "sample", "params": data });
+                if send(writer, &notification).await.is_err() {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+fn status(shared: &Shared) -> Value {
+    json!({
+        "motor_id": shared.motor_id,
+        "pid": std::process::id(),
+        "started_at": shared.started_at,
+        "last_sample": *shared.latest.lock().unwrap(),
+        "sinks": *shared.sinks.lock().unwrap(),
+        "setpoints": shared.setpoints.is_some(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct SetpointParams {
+    kind: String,
+    value: f64,
+    by: String,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+fn setpoint(shared: &Shared, params: Value) -> Result<Value, RpcError> {
+    let params: SetpointParams = serde_json::from_value(params).map_err(|err| (INVALID_PARAMS, err.to_string()))?;
+    let kind = match params.kind.as_str() {
+        "speed" => SetpointKind::Speed,
+        "torque" => SetpointKind::Torque,
+        other => return Err((INVALID_PARAMS, format!("unknown setpoint {:?}; expected speed or torque", other))),
+    };
+    if shared.controlled {
+        return Err((REFUSED, "with [control] configured, setpoints go through POST /control/setpoint".to_string()));
+    }
+    let handle = shared.setpoints.as_ref().ok_or((REFUSED, "setpoints are not configured".to_string()))?;
+    let command = SetpointCommand { kind, value: params.value, by: params.by, dry_run: params.dry_run };
+    handle.submit(command).map_err(|err| (REFUSED, err))?;
+    Ok(Value::Bool(true))
+}
+
+/// A connection to a running monitor's socket.
+#[derive(Debug)]
+pub struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+}
+
+impl Client {
+    pub async fn connect(path: &Path) -> io::Result<Self> {
+        let (reader, writer) = UnixStream::connect(path).await?.into_split();
+        Ok(Client { lines: BufReader::new(reader).lines(), writer, next_id: 1 })
+    }
+
+    async fn next_message(&mut self) -> Result<Value, String> {
+        match self.lines.next_line().await.map_err(|err| err.to_string())? {
+            Some(line) => serde_json::from_str(&line).map_err(|err| format!("invalid message from the monitor: {}", err)),
+            None => Err("the monitor closed the connection".to_string()),
+        }
+    }
+
+    /// Calls `method` and waits for its result.
+    pub async fn call(&mut self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        send(&mut self.writer, &request).await.map_err(|err| err.to_string())?;
+        loop {
+            let mut message = self.next_message().await?;
+            if message.get("id").and_then(Value::as_u64) != Some(id) {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                return Err(error.get("message").and_then(Value::as_str).unwrap_or("unknown error").to_string());
+            }
+            return Ok(message.get_mut("result").map(Value::take).unwrap_or(Value::Null));
+        }
+    }
+
+    /// Next sample after a `tail` call; waits for the monitor to poll.
+    pub async fn next_sample(&mut self) -> Result<MotorData, String> {
+        loop {
+            let mut message = self.next_message().await?;
+            if message.get("method").and_then(Value::as_str) == Some("sample") {
+                let params = message.get_mut("params").map(Value::take).unwrap_or(Value::Null);
+                return serde_json::from_value(params).map_err(|err| format!("invalid sample from the monitor: {}", err));
+            }
+        }
+    }
+}
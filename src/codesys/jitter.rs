@@ -0,0 +1,143 @@
+//! Sampling jitter: actual time between samples against the poll interval.
+//!
+//! Polls run late when the bus is slow, a read is retried or the machine is
+//! busy, so samples are rarely exactly one poll interval apart. `SampleClock`
+//! measures every interval between successful samples and hands back the
+//! time each sample stands for, which integrations such as the load cycles
+//! use instead of the nominal interval. An interval of more than
+//! `MAX_GAP_FACTOR` poll intervals is an outage rather than jitter; it is
+//! counted as a gap and the sample after it stands for one nominal interval,
+//! as does one taken when the clock was set back.
+//!
+//! The statistics, jitter being the actual minus the nominal interval, are
+//! served by GraphQL `sampling`.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Intervals longer than this many poll intervals are gaps.
+const MAX_GAP_FACTOR: i64 = 3;
+/// Intervals over 1.5 poll intervals count as late.
+const LATE_FACTOR: f64 = 1.5;
+/// Recent intervals kept for the percentile.
+const RECENT: usize = 1000;
+
+/// Jitter statistics since the monitor started.
+#[derive(Debug, Default)]
+pub struct Jitter {
+    nominal_ms: i64,
+    intervals: u64,
+    late: u64,
+    gaps: u64,
+    /// Running mean and sum of squared deviations of the jitter (Welford).
+    mean: f64,
+    m2: f64,
+    max_abs: f64,
+    last_interval_ms: Option<i64>,
+    /// Absolute jitter of the latest intervals, for the percentile.
+    recent: VecDeque<f64>,
+}
+
+/// A snapshot of `Jitter`, in milliseconds.
+#[derive(Debug, Clone, Default)]
+pub struct JitterSummary {
+    pub nominal_ms: i64,
+    /// Intervals measured; gaps are not among them.
+    pub intervals: u64,
+    pub late: u64,
+    pub gaps: u64,
+    pub mean_ms: f64,
+    pub std_dev_ms: f64,
+    /// Largest deviation from the poll interval, either way.
+    pub max_ms: f64,
+    /// 99th percentile of the deviation over the last `RECENT` intervals.
+    pub p99_ms: Option<f64>,
+    pub last_interval_ms: Option<i64>,
+}
+
+impl Jitter {
+    fn record(&mut self, nominal_ms: i64, interval_ms: i64) {
+        self.nominal_ms = nominal_ms;
+        self.last_interval_ms = Some(interval_ms);
+        if interval_ms <= 0 || interval_ms > nominal_ms * MAX_GAP_FACTOR {
+            self.gaps += 1;
+            return;
+        }
+        if interval_ms as f64 > nominal_ms as f64 * LATE_FACTOR {
+            self.late += 1;
+        }
+        let jitter = (interval_ms - nominal_ms) as f64;
+        self.intervals += 1;
+        let delta = jitter - self.mean;
+        self.mean += delta / self.intervals as f64;
+        self.m2 += delta * (jitter - self.mean);
+        self.max_abs = self.max_abs.max(jitter.abs());
+        if self.recent.len() == RECENT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(jitter.abs());
+    }
+
+    pub fn summary(&self) -> JitterSummary {
+        let mut recent: Vec<f64> = self.recent.iter().copied().collect();
+        recent.sort_by(f64::total_cmp);
+        let p99 = (!recent.is_empty()).then(|| recent[((recent.len() - 1) as f64 * 0.99).round() as usize]);
+        JitterSummary {
+            nominal_ms: self.nominal_ms,
+            intervals: self.intervals,
+            late: self.late,
+            gaps: self.gaps,
+            mean_ms: self.mean,
+            std_dev_ms: if self.intervals > 1 { (self.m2 / (self.intervals - 1) as f64).sqrt() } else { 0.0 },
+            max_ms: self.max_abs,
+            p99_ms: p99,
+            last_interval_ms: self.last_interval_ms,
+        }
+    }
+}
+
+/// Jitter statistics shared with the API.
+pub type JitterHandle = Arc<Mutex<Jitter>>;
+
+/// The polling loop's side: times successive samples.
+#[derive(Debug)]
+pub struct SampleClock {
+    nominal_ms: i64,
+    last: Option<i64>,
+    jitter: JitterHandle,
+}
+
+impl SampleClock {
+    pub fn new(nominal: Duration) -> Self {
+        let jitter = JitterHandle::default();
+        jitter.lock().unwrap().nominal_ms = nominal.as_millis() as i64;
+        SampleClock { nominal_ms: nominal.as_millis() as i64, last: None, jitter }
+    }
+
+    pub fn jitter(&self) -> JitterHandle {
+        Arc::clone(&self.jitter)
+    }
+
+    /// Follows a change of the poll interval, e.g. by adaptive polling.
+    pub fn set_nominal(&mut self, nominal: Duration) {
+        self.nominal_ms = nominal.as_millis() as i64;
+    }
+
+    /// Records a sample taken at `now_ms` and returns the seconds it stands
+    /// for: the time since the previous sample, or the poll interval for the
+    /// first sample and after a gap.
+    pub fn tick(&mut self, now_ms: i64) -> f64 {
+        let nominal_secs = self.nominal_ms as f64 / 1000.0;
+        let Some(last) = self.last.replace(now_ms) else {
+            return nominal_secs;
+        };
+        let interval_ms = now_ms - last;
+        self.jitter.lock().unwrap().record(self.nominal_ms, interval_ms);
+        if interval_ms <= 0 || interval_ms > self.nominal_ms * MAX_GAP_FACTOR {
+            nominal_secs
+        } else {
+            interval_ms as f64 / 1000.0
+        }
+    }
+}
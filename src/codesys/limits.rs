@@ -0,0 +1,105 @@
+//! High-limit alarms on power, torque, speed and heat.
+//!
+//! Thresholds come from `[limits]`; any that are not configured are derived
+//! from `MotorSpecs` (unless `limits.auto = false`), so a new install gets
+//! sensible alarms from the nameplate data alone:
+//!
+//! | signal | warning            | critical       |
+//! |--------|--------------------|----------------|
+//! | torque | 90% rated torque   | peak torque    |
+//! | speed  | 95% max speed      | max speed      |
+//! | power  | 110% rated power   | –              |
+//!
+//! Each limit becomes a rule of `rules::RuleEngine`. A signal's limits are
+//! ignored for `startup_grace_secs` after a motor start
+//! (`limits.startup_grace_secs`, or the signal's own setting).
+
+use super::alarms::Severity;
+use super::config::{AlarmRuleConfig, Comparator, Condition, LimitsConfig, Threshold};
+use super::{MotorSpecs, Signal};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limit {
+    pub signal: Signal,
+    pub severity: Severity,
+    pub threshold: f64,
+    /// Derived from `MotorSpecs` rather than configured.
+    pub derived: bool,
+    pub startup_grace_secs: i64,
+}
+
+fn derived_threshold(signal: Signal, specs: &MotorSpecs) -> Option<Threshold> {
+    match signal {
+        Signal::Torque => Some(Threshold {
+            warn: Some(0.9 * specs.rated_torque),
+            alarm: Some(specs.peak_torque),
+            startup_grace_secs: None,
+        }),
+        Signal::Speed => Some(Threshold {
+            warn: Some(0.95 * specs.max_speed),
+            alarm: Some(specs.max_speed),
+            startup_grace_secs: None,
+        }),
+        Signal::Power => Some(Threshold { warn: Some(1.1 * specs.rated_power), alarm: None, startup_grace_secs: None }),
+        Signal::Heat | Signal::Cycles => None,
+    }
+}
+
+/// The limits in force: configured thresholds, completed by derived ones.
+pub fn effective_limits(config: &LimitsConfig, specs: &MotorSpecs) -> Vec<Limit> {
+    let mut limits = Vec::new();
+    for signal in Signal::ALL {
+        let (threshold, derived) = match (config.threshold(signal), config.auto) {
+            (Some(threshold), _) => (Some(threshold.clone()), false),
+            (None, true) => (derived_threshold(signal, specs), true),
+            (None, false) => (None, false),
+        };
+        let Some(threshold) = threshold else {
+            continue;
+        };
+        let startup_grace_secs = threshold.startup_grace_secs.unwrap_or(config.startup_grace_secs);
+        for (value, severity) in [(threshold.warn, Severity::Warning), (threshold.alarm, Severity::Critical)] {
+            if let Some(threshold) = value {
+                limits.push(Limit { signal, severity, threshold, derived, startup_grace_secs });
+            }
+        }
+    }
+    limits
+}
+
+/// The rules of the effective limits, evaluated by `rules::RuleEngine`.
+pub fn limit_rules(config: &LimitsConfig, specs: &MotorSpecs) -> Vec<AlarmRuleConfig> {
+    effective_limits(config, specs)
+        .into_iter()
+        .map(|limit| {
+            log::info!(
+                "{} {} limit: {:.1}{}",
+                limit.signal.name(),
+                limit.severity.name(),
+                limit.threshold,
+                if limit.derived { " (derived from motor specs)" } else { "" }
+            );
+            let signal = limit.signal.name();
+            AlarmRuleConfig {
+                name: format!("{}_high_{}", signal, limit.severity.name()),
+                condition: Condition::Compare {
+                    signal: signal.to_string(),
+                    op: Comparator::Above,
+                    value: limit.threshold,
+                },
+                duration_secs: config.duration_secs,
+                severity: limit.severity,
+                message: Some(format!(
+                    "{} {{{}}} above {} limit {:.1}{}",
+                    signal,
+                    signal,
+                    limit.severity.name(),
+                    limit.threshold,
+                    if limit.derived { " (derived from motor specs)" } else { "" }
+                )),
+                actions: None,
+                startup_grace_secs: limit.startup_grace_secs,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,146 @@
+//! Lineage stamps of exports and reports.
+//!
+//! Every export and report records where its numbers came from, so a figure
+//! can be traced back during an audit: the program and version that
+//! generated it, the SHA-256 of the configuration in effect, the identity
+//! of the source database and the time range covered. The configuration
+//! hash is taken over the parsed configuration (after `extends` and
+//! templates), so comments and formatting do not change it. The database
+//! identity is a random id assigned when a monitor first sets up the
+//! database; it travels with backups and synced copies, which therefore
+//! name the database they came from.
+//!
+//! Exports carry the stamp as `lineage.*` metadata; reports end with a
+//! *Lineage* section.
+
+use super::config::MonitorConfig;
+use super::secrets;
+use chrono::prelude::*;
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The program and version writing exports and reports.
+pub const GENERATOR: &str = concat!("rustsys ", env!("CARGO_PKG_VERSION"));
+
+/// Creates the identity table and gives a new database its id.
+pub async fn setup_lineage_table(pool: &SqlitePool) {
+    sqlx::query("CREATE TABLE IF NOT EXISTS database_identity (id TEXT NOT NULL, created_at INTEGER NOT NULL)")
+        .execute(pool)
+        .await
+        .unwrap();
+    if database_id(pool).await.is_none() {
+        let now = Local::now().timestamp();
+        sqlx::query("INSERT INTO database_identity (id, created_at) VALUES (?, ?)")
+            .bind(new_database_id(now))
+            .bind(now)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+}
+
+/// The database's id; `None` for a database no monitor has set up yet.
+pub async fn database_id(pool: &SqlitePool) -> Option<String> {
+    let row: Option<(String,)> =
+        sqlx::query_as("SELECT id FROM database_identity LIMIT 1").fetch_optional(pool).await.ok()?;
+    row.map(|(id,)| id)
+}
+
+fn new_database_id(now: i64) -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let digest = Sha256::new()
+        .chain_update(nanos.to_le_bytes())
+        .chain_update(now.to_le_bytes())
+        .chain_update(std::process::id().to_le_bytes())
+        .finalize();
+    hex(&digest[..16])
+}
+
+/// Lowercase hexadecimal of `bytes`.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        write!(out, "{:02x}", byte).unwrap();
+        out
+    })
+}
+
+/// SHA-256 of the parsed configuration `table`.
+pub fn config_hash(table: &toml::Table) -> String {
+    let canonical = toml::to_string(table).unwrap_or_default();
+    hex(&Sha256::digest(canonical.as_bytes()))
+}
+
+/// Where a monitor's data comes from.
+#[derive(Debug, Clone, Default)]
+pub struct Source {
+    pub config_hash: String,
+    pub database: String,
+    pub database_id: Option<String>,
+}
+
+impl Source {
+    pub async fn load(pool: &SqlitePool, config: &MonitorConfig) -> Self {
+        Source {
+            config_hash: config.config_hash.clone(),
+            database: secrets::redact_url(&config.database_url),
+            database_id: database_id(pool).await,
+        }
+    }
+
+    pub fn database_id(&self) -> &str {
+        self.database_id.as_deref().unwrap_or("unknown")
+    }
+}
+
+/// `ms` as RFC 3339 in UTC.
+pub fn rfc3339(ms: i64) -> String {
+    Utc.timestamp_millis_opt(ms)
+        .single()
+        .map_or_else(|| ms.to_string(), |t| t.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+/// The lineage of one export or report.
+#[derive(Debug, Clone)]
+pub struct Stamp {
+    pub source: Source,
+    /// Covered range in epoch milliseconds; `to` is exclusive.
+    pub from: i64,
+    pub to: i64,
+    pub generated_at: i64,
+}
+
+impl Stamp {
+    pub fn new(source: &Source, from: i64, to: i64) -> Self {
+        Stamp { source: source.clone(), from, to, generated_at: Local::now().timestamp_millis() }
+    }
+
+    /// `lineage.*` metadata entries.
+    pub fn metadata(&self) -> BTreeMap<String, String> {
+        [
+            ("generator", GENERATOR.to_string()),
+            ("config_sha256", self.source.config_hash.clone()),
+            ("database", self.source.database.clone()),
+            ("database_id", self.source.database_id().to_string()),
+            ("from", rfc3339(self.from)),
+            ("to", rfc3339(self.to)),
+            ("generated_at", rfc3339(self.generated_at)),
+        ]
+        .into_iter()
+        .map(|(key, value)| (format!("lineage.{}", key), value))
+        .collect()
+    }
+
+    /// Markdown list of the stamp, for a report's *Lineage* section.
+    pub fn markdown(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "- Generator: {}", GENERATOR).unwrap();
+        writeln!(out, "- Configuration SHA-256: `{}`", self.source.config_hash).unwrap();
+        writeln!(out, "- Database: {} (id `{}`)", self.source.database, self.source.database_id()).unwrap();
+        writeln!(out, "- Range: {} – {} (exclusive)", rfc3339(self.from), rfc3339(self.to)).unwrap();
+        writeln!(out, "- Generated: {}", rfc3339(self.generated_at)).unwrap();
+        out
+    }
+}
@@ -0,0 +1,116 @@
+//! Where a motor runs: time zone, site name and line or area.
+//!
+//! One instance may watch machines in several regions, each monitor's host
+//! clock set to wherever the server happens to be. `[location]` gives the
+//! motor its own IANA `timezone`, e.g. `America/Chicago`, which then decides
+//! the wall-clock time of chart axes and date breaks, when reports fall due
+//! (an `interval_hours = 24` report covers the motor's local day) and their
+//! period, and the shift handovers. Without it the host's zone is used, as
+//! before. `name`, `line` and `area` are labels: they head reports and are
+//! attached to the motor in GraphQL and to exports.
+//!
+//! Timestamps stay epoch-based everywhere; only their display and the
+//! calendar arithmetic change with the zone.
+
+use super::config::MonitorConfig;
+use chrono::prelude::*;
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+
+/// A time zone: a named IANA zone or the host's.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Zone(Option<Tz>);
+
+impl Zone {
+    /// Parses an IANA zone name.
+    pub fn parse(name: &str) -> Result<Zone, String> {
+        name.parse::<Tz>().map(|tz| Zone(Some(tz))).map_err(|_| format!("unknown time zone {:?}", name))
+    }
+
+    /// `location.timezone`, or the host's zone without it; the configuration
+    /// has been validated.
+    pub fn from_config(config: &MonitorConfig) -> Zone {
+        config
+            .location
+            .as_ref()
+            .and_then(|location| location.timezone.as_deref())
+            .and_then(|name| Zone::parse(name).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        self.0.map(|tz| tz.name())
+    }
+
+    /// Wall-clock time of an epoch-millisecond timestamp.
+    pub fn at_millis(&self, ms: i64) -> Option<DateTime<FixedOffset>> {
+        match self.0 {
+            Some(tz) => tz.timestamp_millis_opt(ms).single().map(|t| t.fixed_offset()),
+            None => Local.timestamp_millis_opt(ms).single().map(|t| t.fixed_offset()),
+        }
+    }
+
+    pub fn at_secs(&self, secs: i64) -> Option<DateTime<FixedOffset>> {
+        self.at_millis(secs.checked_mul(1000)?)
+    }
+
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        self.at_millis(Utc::now().timestamp_millis()).unwrap()
+    }
+
+    /// The earliest instant showing `local` on the clock; `None` inside a
+    /// spring-forward gap.
+    pub fn from_local(&self, local: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        match self.0 {
+            Some(tz) => tz.from_local_datetime(local).earliest().map(|t| t.fixed_offset()),
+            None => Local.from_local_datetime(local).earliest().map(|t| t.fixed_offset()),
+        }
+    }
+
+    /// Seconds the zone is ahead of UTC at `secs`.
+    pub fn offset_secs(&self, secs: i64) -> i64 {
+        self.at_secs(secs).map_or(0, |t| t.offset().local_minus_utc() as i64)
+    }
+}
+
+/// The `[location]` of the motor.
+#[derive(Debug, Clone, Default)]
+pub struct Location {
+    pub zone: Zone,
+    pub name: Option<String>,
+    pub line: Option<String>,
+    pub area: Option<String>,
+}
+
+impl Location {
+    pub fn from_config(config: &MonitorConfig) -> Location {
+        let location = config.location.clone().unwrap_or_default();
+        Location { zone: Zone::from_config(config), name: location.name, line: location.line, area: location.area }
+    }
+
+    /// The labels that are set, e.g. `Plant Nord, line 3, area packing`.
+    pub fn label(&self) -> Option<String> {
+        let parts: Vec<String> = [
+            self.name.clone(),
+            self.line.as_ref().map(|line| format!("line {}", line)),
+            self.area.as_ref().map(|area| format!("area {}", area)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+
+    /// Export metadata entries: `location`, `line`, `area` and `timezone`.
+    pub fn metadata(&self) -> BTreeMap<String, String> {
+        [
+            ("location", self.name.clone()),
+            ("line", self.line.clone()),
+            ("area", self.area.clone()),
+            ("timezone", self.zone.name().map(str::to_string)),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value?)))
+        .collect()
+    }
+}
@@ -0,0 +1,189 @@
+//! Periodic SQLite upkeep: integrity checks, WAL checkpoints and VACUUM.
+//!
+//! Edge boxes run on flash storage that can lose power mid-write, so the
+//! database is checked regularly. Every run is recorded in
+//! `maintenance_runs`; a failed integrity check raises a critical
+//! `database_integrity` alarm.
+
+use super::alarms::{self, Severity};
+use super::config::MaintenanceConfig;
+use super::events;
+use chrono::prelude::*;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Task {
+    IntegrityCheck,
+    Checkpoint,
+    Vacuum,
+}
+
+impl Task {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Task::IntegrityCheck => "integrity_check",
+            Task::Checkpoint => "checkpoint",
+            Task::Vacuum => "vacuum",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceRun {
+    pub id: i64,
+    pub task: String,
+    pub started_at: i64,
+    pub duration_ms: i64,
+    pub ok: bool,
+    /// `ok`, or the problems / error reported by SQLite.
+    pub detail: String,
+    /// Database file size after the run, when known.
+    pub size_bytes: Option<i64>,
+}
+
+pub async fn setup_maintenance_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS maintenance_runs (
+            id INTEGER PRIMARY KEY,
+            task TEXT NOT NULL,
+            started_at INTEGER NOT NULL,
+            duration_ms INTEGER NOT NULL,
+            ok INTEGER NOT NULL,
+            detail TEXT NOT NULL,
+            size_bytes INTEGER
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Runs `task` and records the outcome. Unlike the rest of the storage
+/// layer this never panics on SQL errors: a broken database is exactly what
+/// it is meant to report.
+pub async fn run_task(pool: &SqlitePool, task: Task) -> MaintenanceRun {
+    let started_at = Local::now().timestamp();
+    let start = Instant::now();
+    let result: Result<String, sqlx::Error> = match task {
+        Task::IntegrityCheck => sqlx::query_as::<_, (String,)>("PRAGMA integrity_check")
+            .fetch_all(pool)
+            .await
+            .map(|rows| rows.into_iter().map(|(line,)| line).collect::<Vec<_>>().join("; ")),
+        Task::Checkpoint => sqlx::query_as::<_, (i64, i64, i64)>("PRAGMA wal_checkpoint(TRUNCATE)")
+            .fetch_one(pool)
+            .await
+            .map(|(busy, log, checkpointed)| {
+                if busy != 0 {
+                    format!("busy ({} of {} frames checkpointed)", checkpointed, log)
+                } else {
+                    "ok".to_string()
+                }
+            }),
+        Task::Vacuum => sqlx::query("VACUUM").execute(pool).await.map(|_| "ok".to_string()),
+    };
+    let duration_ms = start.elapsed().as_millis() as i64;
+
+    let (ok, detail) = match result {
+        Ok(detail) => (detail == "ok", detail),
+        Err(err) => (false, err.to_string()),
+    };
+    let size_bytes = database_size(pool).await;
+
+    let id = sqlx::query(
+        r#"
+        INSERT INTO maintenance_runs (task, started_at, duration_ms, ok, detail, size_bytes)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(task.name())
+    .bind(started_at)
+    .bind(duration_ms)
+    .bind(ok)
+    .bind(&detail)
+    .bind(size_bytes)
+    .execute(pool)
+    .await
+    .map(|r| r.last_insert_rowid())
+    .unwrap_or(0);
+
+    if ok {
+        log::info!("database {} finished in {} ms", task.name(), duration_ms);
+    } else {
+        log::error!("database {} failed: {}", task.name(), detail);
+    }
+    MaintenanceRun { id, task: task.name().to_string(), started_at, duration_ms, ok, detail, size_bytes }
+}
+
+async fn database_size(pool: &SqlitePool) -> Option<i64> {
+    let (pages,): (i64,) = sqlx::query_as("PRAGMA page_count").fetch_one(pool).await.ok()?;
+    let (page_size,): (i64,) = sqlx::query_as("PRAGMA page_size").fetch_one(pool).await.ok()?;
+    Some(pages * page_size)
+}
+
+/// Most recent runs, newest first.
+pub async fn list_runs(pool: &SqlitePool, limit: Option<i64>) -> Vec<MaintenanceRun> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, task, started_at, duration_ms, ok, detail, size_bytes FROM maintenance_runs
+        ORDER BY started_at DESC, id DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(limit.unwrap_or(-1))
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    rows.iter()
+        .map(|row| MaintenanceRun {
+            id: row.get("id"),
+            task: row.get("task"),
+            started_at: row.get("started_at"),
+            duration_ms: row.get("duration_ms"),
+            ok: row.get("ok"),
+            detail: row.get("detail"),
+            size_bytes: row.get("size_bytes"),
+        })
+        .collect()
+}
+
+pub async fn run_maintenance_scheduler(pool: Arc<SqlitePool>, motor_id: String, config: MaintenanceConfig) {
+    let schedule = [
+        (Task::Checkpoint, config.checkpoint_minutes * 60),
+        (Task::IntegrityCheck, config.integrity_check_hours * 3600),
+        (Task::Vacuum, config.vacuum_hours * 3600),
+    ];
+    let mut last_run = [Instant::now(); 3];
+    let mut integrity_alarm = alarms::SustainedAlarm::new("database_integrity", None, Severity::Critical, 0);
+    let mut interval = time::interval(Duration::from_secs(60));
+
+    loop {
+        interval.tick().await;
+        for (i, &(task, every_secs)) in schedule.iter().enumerate() {
+            if last_run[i].elapsed() < Duration::from_secs(every_secs) {
+                continue;
+            }
+            last_run[i] = Instant::now();
+            let run = run_task(&pool, task).await;
+            if task != Task::IntegrityCheck {
+                continue;
+            }
+            if !run.ok {
+                events::record_event(&pool, &motor_id, run.started_at, "database_corrupt", &run.detail).await;
+            }
+            integrity_alarm
+                .update(&pool, &motor_id, !run.ok, run.started_at, || {
+                    format!("database integrity check failed: {}", run.detail)
+                })
+                .await;
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Motor current signature analysis (MCSA).
+//!
+//! Every `every_minutes`, while the motor runs above `min_load_percent`, a
+//! current waveform captured by the drive or power meter is read as a
+//! register block (`Transport::read_block`) and searched for fault
+//! sidebands around the supply frequency:
+//!
+//! - broken rotor bars: `f · (1 ± 2s)`, with slip `s` from the measured
+//!   speed and the synchronous speed;
+//! - bearing defects: `f ± k · f_r` for each configured bearing order `k`
+//!   (BPFO, BPFI, ... as multiples of the shaft frequency `f_r`).
+//!
+//! Each indicator is the stronger sideband relative to the fundamental, in
+//! dB; around -50 dB is typical for a healthy rotor, above -45 dB suggests
+//! broken bars. Results are stored in `mcsa_results`, trended in reports
+//! (see `trends`) and may raise `mcsa_rotor_bar` / `mcsa_bearing` alarms.
+//! The analysis assumes a line-fed motor running at `line_frequency_hz`.
+
+use super::alarms::{Severity, SustainedAlarm};
+use super::config::McsaConfig;
+use super::transport::Transport;
+use super::{MotorData, MotorSpecs};
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::f64::consts::PI;
+
+/// Slip outside this range means the motor is not loaded normally (or not
+/// line-fed); rotor bar sidebands are not evaluated then.
+const MAX_SLIP: f64 = 0.2;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct McsaResult {
+    /// Epoch seconds.
+    pub timestamp: i64,
+    pub slip: f64,
+    /// Amplitude of the supply-frequency component (A, peak).
+    pub fundamental: f64,
+    /// Stronger rotor bar sideband relative to the fundamental (dB).
+    pub rotor_bar_db: Option<f64>,
+    /// Strongest bearing sideband relative to the fundamental (dB).
+    pub bearing_db: Option<f64>,
+}
+
+pub async fn setup_mcsa_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS mcsa_results (
+            timestamp INTEGER PRIMARY KEY,
+            slip REAL NOT NULL,
+            fundamental REAL NOT NULL,
+            rotor_bar_db REAL,
+            bearing_db REAL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+pub async fn save_result(pool: &SqlitePool, result: &McsaResult) {
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO mcsa_results (timestamp, slip, fundamental, rotor_bar_db, bearing_db)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(result.timestamp)
+    .bind(result.slip)
+    .bind(result.fundamental)
+    .bind(result.rotor_bar_db)
+    .bind(result.bearing_db)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Results with `from <= timestamp < to` (seconds), oldest first.
+pub async fn history(pool: &SqlitePool, from: i64, to: i64) -> Vec<McsaResult> {
+    sqlx::query(
+        r#"
+        SELECT timestamp, slip, fundamental, rotor_bar_db, bearing_db FROM mcsa_results
+        WHERE timestamp >= ? AND timestamp < ? ORDER BY timestamp
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+    .iter()
+    .map(|row| McsaResult {
+        timestamp: row.get("timestamp"),
+        slip: row.get("slip"),
+        fundamental: row.get("fundamental"),
+        rotor_bar_db: row.get("rotor_bar_db"),
+        bearing_db: row.get("bearing_db"),
+    })
+    .collect()
+}
+
+/// Pole count of a line-fed motor: the synchronous speed is the next one
+/// above `rated_speed` (e.g. 1450 rpm at 50 Hz: 1500 rpm, 4 poles).
+pub fn pole_count(line_frequency: f64, rated_speed: f64) -> u32 {
+    let pairs = (60.0 * line_frequency / rated_speed).floor().max(1.0);
+    2 * pairs as u32
+}
+
+/// Amplitude (peak, in sample units) of the `frequency` component, using a
+/// Hann-windowed Goertzel filter. `samples` should have their mean removed.
+pub fn amplitude(samples: &[f64], sample_rate: f64, frequency: f64) -> f64 {
+    let n = samples.len();
+    if n < 2 {
+        return 0.0;
+    }
+    let coeff = 2.0 * (2.0 * PI * frequency / sample_rate).cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for (i, x) in samples.iter().enumerate() {
+        let window = 0.5 - 0.5 * (2.0 * PI * i as f64 / (n - 1) as f64).cos();
+        let s = x * window + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s;
+    }
+    let power = (s1 * s1 + s2 * s2 - coeff * s1 * s2).max(0.0);
+    // The Hann window halves the amplitude of a tone.
+    4.0 * power.sqrt() / n as f64
+}
+
+/// Largest amplitude within one frequency bin of `frequency`, so a peak
+/// between bins is not missed.
+fn peak_amplitude(samples: &[f64], sample_rate: f64, frequency: f64) -> f64 {
+    let bin = sample_rate / samples.len() as f64;
+    (-4..=4)
+        .map(|step| amplitude(samples, sample_rate, frequency + step as f64 * bin / 4.0))
+        .fold(0.0, f64::max)
+}
+
+fn decibels(ratio: f64) -> f64 {
+    20.0 * ratio.max(1e-12).log10()
+}
+
+/// Analyses a current waveform (A) captured at `speed_rpm`.
+pub fn analyze(waveform: &[f64], config: &McsaConfig, specs: &MotorSpecs, speed_rpm: f64, timestamp: i64) -> McsaResult {
+    let mean = waveform.iter().sum::<f64>() / waveform.len().max(1) as f64;
+    let samples: Vec<f64> = waveform.iter().map(|x| x - mean).collect();
+    let rate = config.sample_rate_hz;
+    let line = config.line_frequency_hz;
+    let resolution = rate / samples.len().max(1) as f64;
+
+    let fundamental = peak_amplitude(&samples, rate, line);
+    let poles = config.poles.unwrap_or_else(|| pole_count(line, specs.rated_speed));
+    let synchronous = 120.0 * line / poles as f64;
+    let slip = (synchronous - speed_rpm) / synchronous;
+    let relative = |frequency: f64| decibels(peak_amplitude(&samples, rate, frequency) / fundamental);
+
+    // The sidebands must be clear of the fundamental's own bins.
+    let rotor_bar_db = (fundamental > 0.0 && slip > 0.0 && slip < MAX_SLIP && 2.0 * slip * line > 2.0 * resolution)
+        .then(|| relative(line * (1.0 - 2.0 * slip)).max(relative(line * (1.0 + 2.0 * slip))));
+
+    let shaft = speed_rpm / 60.0;
+    let bearing_db = config
+        .bearing_orders
+        .iter()
+        .map(|order| order * shaft)
+        .filter(|offset| fundamental > 0.0 && *offset > 2.0 * resolution)
+        .flat_map(|offset| [line - offset, line + offset])
+        .filter(|frequency| *frequency > resolution && *frequency < rate / 2.0)
+        .map(relative)
+        .reduce(f64::max);
+
+    McsaResult { timestamp, slip, fundamental, rotor_bar_db, bearing_db }
+}
+
+/// Schedules captures from the polling loop and raises the MCSA alarms.
+#[derive(Debug)]
+pub struct McsaMonitor {
+    config: McsaConfig,
+    specs: MotorSpecs,
+    last_capture: Option<i64>,
+    rotor_bar_alarm: SustainedAlarm,
+    bearing_alarm: SustainedAlarm,
+}
+
+impl McsaMonitor {
+    pub fn new(config: McsaConfig, specs: MotorSpecs) -> Self {
+        McsaMonitor {
+            config,
+            specs,
+            last_capture: None,
+            rotor_bar_alarm: SustainedAlarm::new("mcsa_rotor_bar", Some("current"), Severity::Warning, 0),
+            bearing_alarm: SustainedAlarm::new("mcsa_bearing", Some("current"), Severity::Warning, 0),
+        }
+    }
+
+    fn due(&self, data: &MotorData) -> bool {
+        let load = data.current_power / self.specs.rated_power * 100.0;
+        let interval = self.config.every_minutes as i64 * 60;
+        load >= self.config.min_load_percent && self.last_capture.is_none_or(|last| data.seconds() - last >= interval)
+    }
+
+    /// Captures and analyses a waveform if one is due and the motor is
+    /// loaded enough. `data` is the sample just read, for speed and load.
+    pub async fn maybe_capture(&mut self, pool: &SqlitePool, motor_id: &str, transport: &mut dyn Transport, data: &MotorData) {
+        if !self.due(data) {
+            return;
+        }
+        // Also after a failure, so a transport without block reads is not
+        // asked on every poll.
+        self.last_capture = Some(data.seconds());
+        let words = match transport.read_block(&self.config.waveform, self.config.samples).await {
+            Ok(words) => words,
+            Err(err) => {
+                log::warn!("cannot capture current waveform: {}", err);
+                return;
+            }
+        };
+        let waveform: Vec<f64> = words.iter().map(|w| *w as i16 as f64 * self.config.scale).collect();
+        let result = analyze(&waveform, &self.config, &self.specs, data.current_speed, data.seconds());
+        save_result(pool, &result).await;
+        log::info!(
+            "MCSA: slip {:.2} %, rotor bar {} dB, bearing {} dB",
+            result.slip * 100.0,
+            result.rotor_bar_db.map_or("–".to_string(), |db| format!("{:.1}", db)),
+            result.bearing_db.map_or("–".to_string(), |db| format!("{:.1}", db))
+        );
+
+        let checks = [
+            (&mut self.rotor_bar_alarm, result.rotor_bar_db, self.config.rotor_bar_alarm_db, "rotor bar"),
+            (&mut self.bearing_alarm, result.bearing_db, self.config.bearing_alarm_db, "bearing"),
+        ];
+        for (alarm, value, limit, name) in checks {
+            let (Some(value), Some(limit)) = (value, limit) else {
+                continue;
+            };
+            alarm
+                .update(pool, motor_id, value > limit, result.timestamp, || {
+                    format!("{} sidebands at {:.1} dB above {:.1} dB", name, value, limit)
+                })
+                .await;
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Modbus TCP proxy for a second master on the RTU bus.
+//!
+//! An RTU bus has exactly one master. With `[modbus_proxy]`, rustsys stays
+//! that master and serves the register blocks listed in `blocks` to a PLC
+//! or SCADA system over Modbus TCP: every poll reads the blocks along with
+//! the register map, and read requests (0x03, 0x04) are answered from that
+//! cache without touching the bus. Each block costs one read per poll,
+//! asked for or not.
+//!
+//! Registers outside the blocks answer exception 0x02 (illegal data
+//! address). Values older than `max_age_secs` (bus down, or this node is a
+//! standby) answer 0x0B (gateway target failed to respond), so the client
+//! never mistakes a frozen value for a live one. Writes answer 0x01
+//! (illegal function); commands go through setpoints, where they are
+//! ramped and audited.
+
+use super::config::{ModbusProxyConfig, RegisterDef};
+use super::startup;
+use super::transport::{Transport, READ_HOLDING_REGISTERS, READ_INPUT_REGISTERS};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+const GATEWAY_PATH_UNAVAILABLE: u8 = 0x0A;
+const GATEWAY_TARGET_FAILED: u8 = 0x0B;
+
+/// Most registers a single Modbus read may ask for.
+const MAX_READ_REGISTERS: u16 = 125;
+
+/// Register words last read from the bus, keyed by read function and
+/// address.
+#[derive(Debug, Default)]
+pub struct RegisterCache {
+    words: HashMap<(u8, u16), (u16, Instant)>,
+}
+
+impl RegisterCache {
+    pub fn store(&mut self, function: u8, address: u16, words: &[u16], at: Instant) {
+        for (offset, &word) in words.iter().enumerate() {
+            self.words.insert((function, address.wrapping_add(offset as u16)), (word, at));
+        }
+    }
+
+    /// `count` words from `address`, or the exception code to answer.
+    pub fn read(&self, function: u8, address: u16, count: u16, max_age: Duration, now: Instant) -> Result<Vec<u16>, u8> {
+        let mut words = Vec::with_capacity(count as usize);
+        let mut stale = false;
+        for offset in 0..count {
+            let address = address.checked_add(offset).ok_or(ILLEGAL_DATA_ADDRESS)?;
+            let &(word, at) = self.words.get(&(function, address)).ok_or(ILLEGAL_DATA_ADDRESS)?;
+            stale |= now.duration_since(at) > max_age;
+            words.push(word);
+        }
+        if stale {
+            return Err(GATEWAY_TARGET_FAILED);
+        }
+        Ok(words)
+    }
+}
+
+/// The polling loop's side of the proxy: keeps the cache filled.
+pub struct ModbusProxy {
+    config: ModbusProxyConfig,
+    cache: Arc<Mutex<RegisterCache>>,
+}
+
+impl ModbusProxy {
+    /// Starts the Modbus TCP server on `config.listen`.
+    pub fn start(config: ModbusProxyConfig) -> Self {
+        let cache = Arc::new(Mutex::new(RegisterCache::default()));
+        tokio::spawn(serve(config.clone(), Arc::clone(&cache)));
+        ModbusProxy { config, cache }
+    }
+
+    /// Reads every block once. A failed read leaves the old words in place
+    /// until they age out.
+    pub async fn refresh(&self, transport: &mut dyn Transport) {
+        for block in &self.config.blocks {
+            let def = RegisterDef { address: block.address, count: 1, function: block.function };
+            match transport.read_block(&def, block.count).await {
+                Ok(words) => self.cache.lock().unwrap().store(block.function, block.address, &words, Instant::now()),
+                Err(err) => log::warn!("modbus proxy: cannot read {} register(s) at {}: {}", block.count, block.address, err),
+            }
+        }
+    }
+}
+
+pub async fn serve(config: ModbusProxyConfig, cache: Arc<Mutex<RegisterCache>>) {
+    let component = format!("Modbus TCP proxy on {}", config.listen);
+    let Ok(listener) = startup::retry(&component, || TcpListener::bind(config.listen)).await else {
+        return;
+    };
+    log::info!("Modbus TCP proxy on {}", config.listen);
+    let config = Arc::new(config);
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                log::warn!("modbus proxy: accept failed: {}", err);
+                continue;
+            }
+        };
+        let (config, cache) = (Arc::clone(&config), Arc::clone(&cache));
+        tokio::spawn(async move {
+            if let Err(err) = handle(stream, &config, &cache).await {
+                log::debug!("modbus proxy: connection from {} closed: {}", peer, err);
+            }
+        });
+    }
+}
+
+/// Answers requests on one connection until the client disconnects.
+async fn handle(mut stream: TcpStream, config: &ModbusProxyConfig, cache: &Mutex<RegisterCache>) -> io::Result<()> {
+    loop {
+        // MBAP header: transaction id, protocol id (0), length, unit id.
+        let mut header = [0u8; 7];
+        match stream.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if !(2..=254).contains(&length) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid MBAP length {}", length)));
+        }
+        let mut pdu = vec![0u8; length - 1];
+        stream.read_exact(&mut pdu).await?;
+        if header[2..4] != [0, 0] {
+            continue;
+        }
+
+        let response = respond(config, cache, header[6], &pdu, Instant::now());
+        let mut frame = header[..4].to_vec();
+        frame.extend_from_slice(&(response.len() as u16 + 1).to_be_bytes());
+        frame.push(header[6]);
+        frame.extend_from_slice(&response);
+        stream.write_all(&frame).await?;
+    }
+}
+
+/// Response PDU to the request `pdu` for `unit`.
+fn respond(config: &ModbusProxyConfig, cache: &Mutex<RegisterCache>, unit: u8, pdu: &[u8], now: Instant) -> Vec<u8> {
+    let function = pdu[0];
+    let exception = |code: u8| vec![function | 0x80, code];
+    if config.unit_id.is_some_and(|id| id != unit) {
+        return exception(GATEWAY_PATH_UNAVAILABLE);
+    }
+    if function != READ_HOLDING_REGISTERS && function != READ_INPUT_REGISTERS {
+        return exception(ILLEGAL_FUNCTION);
+    }
+    let [_, address_hi, address_lo, count_hi, count_lo] = *pdu else {
+        return exception(ILLEGAL_DATA_VALUE);
+    };
+    let (address, count) = (u16::from_be_bytes([address_hi, address_lo]), u16::from_be_bytes([count_hi, count_lo]));
+    if !(1..=MAX_READ_REGISTERS).contains(&count) {
+        return exception(ILLEGAL_DATA_VALUE);
+    }
+    let max_age = Duration::from_secs(config.max_age_secs);
+    match cache.lock().unwrap().read(function, address, count, max_age, now) {
+        Ok(words) => {
+            let mut response = vec![function, (count * 2) as u8];
+            response.extend(words.iter().flat_map(|word| word.to_be_bytes()));
+            response
+        }
+        Err(code) => exception(code),
+    }
+}
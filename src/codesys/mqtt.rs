@@ -0,0 +1,299 @@
+//! MQTT sink: publishes every sample to a broker.
+//!
+//! In `json` mode each sample is published as JSON to `topic`. In
+//! `sparkplug_b` mode the monitor acts as a Sparkplug B edge node with one
+//! device (the motor): on every connect it publishes NBIRTH and DBIRTH
+//! certificates naming each metric and its alias, then DDATA messages
+//! carrying values by alias with a rolling sequence number. The broker
+//! publishes NDEATH (the MQTT will) when the connection drops, and an NCMD
+//! `Node Control/Rebirth` request triggers fresh births. This lets Ignition
+//! and other Sparkplug-aware SCADA systems discover the motor on their own.
+//!
+//! The sink runs under the `sink` supervisor. Births are sent ahead of the
+//! first sample after a connect or rebirth request. A failed publish, or a
+//! connection the event loop lost since the last sample, fails the write:
+//! the supervisor records it in the sink's health, backs off and reopens
+//! the client.
+
+use super::config::{MqttConfig, MqttMode, TimestampStyle};
+use super::sink::Sink;
+use super::sparkplug::{self, Metric, Payload, Value};
+use super::tls;
+use super::{MotorData, Signal};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Sparkplug B namespace prefix of every topic.
+const NAMESPACE: &str = "spBv1.0";
+
+enum Control {
+    Connected,
+    Rebirth,
+    /// The event loop lost the connection and stopped.
+    Lost(String),
+}
+
+/// Sparkplug metric names in alias order (alias = index + 1).
+fn metric_names(channels: &[String]) -> Vec<String> {
+    Signal::ALL
+        .iter()
+        .map(|s| s.name().to_string())
+        .chain(std::iter::once("speed_reference".to_string()))
+        .chain(channels.iter().map(|c| format!("temperatures/{}", c)))
+        .collect()
+}
+
+fn metric_values(data: &MotorData, channels: &[String]) -> Vec<Option<f64>> {
+    Signal::ALL
+        .iter()
+        .map(|&s| Some(data.value(s)))
+        .chain(std::iter::once(data.speed_reference))
+        .chain(channels.iter().map(|c| data.temperatures.get(c).copied()))
+        .collect()
+}
+
+fn double(value: Option<f64>) -> Value {
+    value.map_or(Value::Null, Value::Double)
+}
+
+struct EdgeNode {
+    group_id: String,
+    edge_node_id: String,
+    device_id: String,
+    bd_seq: u64,
+    seq: u64,
+    names: Vec<String>,
+    channels: Vec<String>,
+    last: Option<MotorData>,
+}
+
+impl EdgeNode {
+    fn topic(&self, kind: &str) -> String {
+        match kind {
+            "NBIRTH" | "NDEATH" | "NCMD" => format!("{}/{}/{}/{}", NAMESPACE, self.group_id, kind, self.edge_node_id),
+            _ => format!("{}/{}/{}/{}/{}", NAMESPACE, self.group_id, kind, self.edge_node_id, self.device_id),
+        }
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        let seq = self.seq;
+        self.seq = (self.seq + 1) % 256;
+        seq
+    }
+
+    /// The sample's sequence number, aliased after the value metrics.
+    fn sequence_metric(&self, data: Option<&MotorData>, timestamp: u64, named: bool) -> Metric {
+        Metric {
+            name: named.then(|| "sequence".into()),
+            alias: Some(self.names.len() as u64 + 1),
+            timestamp: Some(timestamp),
+            datatype: sparkplug::UINT64,
+            value: data.and_then(|d| d.sequence).map_or(Value::Null, |s| Value::UInt64(s as u64)),
+        }
+    }
+
+    fn death(&self) -> Vec<u8> {
+        let bd_seq = Metric { name: Some("bdSeq".into()), alias: None, timestamp: None, datatype: sparkplug::UINT64, value: Value::UInt64(self.bd_seq) };
+        sparkplug::encode(&Payload { timestamp: Local::now().timestamp_millis() as u64, metrics: vec![bd_seq], seq: None })
+    }
+
+    /// NBIRTH and DBIRTH, restarting the sequence at 0.
+    fn births(&mut self) -> [(String, Vec<u8>); 2] {
+        let now = Local::now().timestamp_millis() as u64;
+        self.seq = 0;
+        let node = Payload {
+            timestamp: now,
+            metrics: vec![
+                Metric { name: Some("bdSeq".into()), alias: None, timestamp: Some(now), datatype: sparkplug::UINT64, value: Value::UInt64(self.bd_seq) },
+                Metric { name: Some(sparkplug::REBIRTH.into()), alias: None, timestamp: Some(now), datatype: sparkplug::BOOLEAN, value: Value::Boolean(false) },
+            ],
+            seq: Some(self.next_seq()),
+        };
+        let values = self.last.as_ref().map(|d| metric_values(d, &self.channels)).unwrap_or_else(|| vec![None; self.names.len()]);
+        let mut metrics: Vec<Metric> = self
+            .names
+            .iter()
+            .zip(values)
+            .enumerate()
+            .map(|(i, (name, value))| Metric {
+                name: Some(name.clone()),
+                alias: Some(i as u64 + 1),
+                timestamp: Some(now),
+                datatype: sparkplug::DOUBLE,
+                value: double(value),
+            })
+            .collect();
+        metrics.push(self.sequence_metric(self.last.as_ref(), now, true));
+        let device = Payload { timestamp: now, metrics, seq: Some(self.next_seq()) };
+        [(self.topic("NBIRTH"), sparkplug::encode(&node)), (self.topic("DBIRTH"), sparkplug::encode(&device))]
+    }
+
+    fn data(&mut self, data: &MotorData) -> (String, Vec<u8>) {
+        let timestamp = data.timestamp as u64;
+        let mut metrics: Vec<Metric> = metric_values(data, &self.channels)
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| Metric { name: None, alias: Some(i as u64 + 1), timestamp: Some(timestamp), datatype: sparkplug::DOUBLE, value: double(value) })
+            .collect();
+        metrics.push(self.sequence_metric(Some(data), timestamp, false));
+        let payload = Payload { timestamp, metrics, seq: Some(self.next_seq()) };
+        self.last = Some(data.clone());
+        (self.topic("DDATA"), sparkplug::encode(&payload))
+    }
+}
+
+/// Publishes every sample it is given. `channels` are the configured
+/// temperature channels, fixed for the Sparkplug births.
+pub struct MqttSink {
+    config: MqttConfig,
+    topic: String,
+    node: Option<EdgeNode>,
+    client: Option<AsyncClient>,
+    eventloop: Option<JoinHandle<()>>,
+    control: Option<mpsc::UnboundedReceiver<Control>>,
+    /// Style of the `timestamp` of JSON payloads.
+    timestamps: TimestampStyle,
+}
+
+impl MqttSink {
+    pub fn new(
+        config: MqttConfig,
+        site: Option<String>,
+        motor_id: String,
+        channels: Vec<String>,
+        timestamps: TimestampStyle,
+    ) -> Self {
+        let node = match config.mode {
+            MqttMode::Json => None,
+            MqttMode::SparkplugB => Some(EdgeNode {
+                group_id: config.group_id.clone().or_else(|| site.clone()).unwrap_or_default(),
+                edge_node_id: config.edge_node_id.clone().unwrap_or_else(|| "rustsys".to_string()),
+                device_id: config.device_id.clone().unwrap_or_else(|| motor_id.clone()),
+                // bdSeq must match between NBIRTH and the broker-held NDEATH;
+                // it changes with every restart.
+                bd_seq: Local::now().timestamp().rem_euclid(256) as u64,
+                seq: 0,
+                names: metric_names(&channels),
+                channels,
+                last: None,
+            }),
+        };
+        let topic = match (&config.topic, &site) {
+            (Some(topic), _) => topic.clone(),
+            (None, Some(_)) => "rustsys/{site}/{motor}/samples".to_string(),
+            (None, None) => "rustsys/{motor}/samples".to_string(),
+        };
+        let topic = topic.replace("{site}", site.as_deref().unwrap_or_default()).replace("{motor}", &motor_id);
+        let config = MqttConfig {
+            client_id: Some(config.client_id.clone().unwrap_or_else(|| format!("rustsys-{}", motor_id))),
+            ..config
+        };
+        MqttSink { config, topic, node, client: None, eventloop: None, control: None, timestamps }
+    }
+
+    /// Handles connects, rebirth requests and connection losses reported by
+    /// the event loop; fails once the connection is lost.
+    async fn handle_control(&mut self) -> Result<(), String> {
+        let (Some(control), Some(client)) = (self.control.as_mut(), &self.client) else {
+            return Ok(());
+        };
+        while let Ok(message) = control.try_recv() {
+            let node = match message {
+                Control::Lost(err) => return Err(format!("MQTT connection lost: {}", err)),
+                _ => match self.node.as_mut() {
+                    Some(node) => node,
+                    None => continue,
+                },
+            };
+            if let Control::Connected = message {
+                if let Err(err) = client.subscribe(node.topic("NCMD"), QoS::AtLeastOnce).await {
+                    log::warn!("cannot subscribe to {}: {}", node.topic("NCMD"), err);
+                }
+            }
+            for (topic, payload) in node.births() {
+                if let Err(err) = client.publish(topic, QoS::AtMostOnce, false, payload).await {
+                    log::warn!("cannot publish Sparkplug birth: {}", err);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        if let Some(eventloop) = self.eventloop.take() {
+            eventloop.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl Sink<MotorData> for MqttSink {
+    async fn open(&mut self) -> Result<(), String> {
+        if let Some(eventloop) = self.eventloop.take() {
+            eventloop.abort();
+        }
+        let client_id = self.config.client_id.clone().unwrap_or_default();
+        let mut options = MqttOptions::new(client_id, self.config.host.clone(), self.config.port);
+        options.set_keep_alive(Duration::from_secs(self.config.keep_alive_secs));
+        if let Some(username) = &self.config.username {
+            options.set_credentials(username.clone(), self.config.password.clone().unwrap_or_default());
+        }
+        if let Some(tls) = &self.config.tls {
+            options.set_transport(tls::mqtt_transport(tls).map_err(|err| format!("mqtt.tls: {}", err))?);
+        }
+        if let Some(node) = &self.node {
+            options.set_last_will(LastWill::new(node.topic("NDEATH"), node.death(), QoS::AtLeastOnce, false));
+        }
+
+        let (client, mut eventloop) = AsyncClient::new(options, 64);
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        let ncmd = self.node.as_ref().map(|n| n.topic("NCMD"));
+        self.eventloop = Some(tokio::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        log::info!("MQTT connected");
+                        let _ = control_tx.send(Control::Connected);
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        if Some(&publish.topic) == ncmd.as_ref() && sparkplug::is_rebirth_request(&publish.payload) {
+                            let _ = control_tx.send(Control::Rebirth);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        log::warn!("MQTT connection error: {}", err);
+                        let _ = control_tx.send(Control::Lost(err.to_string()));
+                        break;
+                    }
+                }
+            }
+        }));
+        self.client = Some(client);
+        self.control = Some(control_rx);
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &MotorData) -> Result<(), String> {
+        self.handle_control().await?;
+        let (topic, payload) = match self.node.as_mut() {
+            Some(node) => node.data(data),
+            None => {
+                let mut json = serde_json::to_value(data).map_err(|err| err.to_string())?;
+                self.timestamps.restyle(&mut json, &["timestamp"]);
+                (self.topic.clone(), serde_json::to_vec(&json).map_err(|err| err.to_string())?)
+            }
+        };
+        let client = self.client.as_ref().ok_or("not open")?;
+        client
+            .publish(topic, QoS::AtLeastOnce, false, payload)
+            .await
+            .map_err(|err| format!("cannot publish sample: {}", err))
+    }
+}
@@ -0,0 +1,218 @@
+//! NATS JetStream sink: publishes samples and events with at-least-once
+//! delivery.
+//!
+//! Unlike the MQTT sink, which publishes the samples it is handed, this
+//! sink follows the database: each sample from the polling loop only wakes
+//! it up, and it reads stored samples and events past its cursor, publishes
+//! them to JetStream and waits for the server's acknowledgement before
+//! moving the cursor on. The cursors live in the `nats_cursor` table, so a
+//! broker outage or a restart resumes where publishing stopped instead of
+//! losing what was stored meanwhile. A new cursor starts at the current
+//! time; history is not replayed.
+//!
+//! The sink runs under the `sink` supervisor: a failed connect or publish
+//! fails the write, which is recorded in the sink's health, and the client
+//! is reconnected after a backoff.
+//!
+//! A message can be published twice (acknowledged, but the cursor not yet
+//! saved). Each carries a `Nats-Msg-Id` header (`<motor>-<timestamp>` for
+//! samples, `<motor>-event-<id>` for events), so the stream's duplicate
+//! window drops the second copy. The subjects must be bound to a stream on
+//! the server; rustsys does not create one.
+
+use super::config::NatsConfig;
+use super::events::{self, Event};
+use super::secrets;
+use super::sink::Sink;
+use super::tls;
+use super::{motor_data_range, MotorData};
+use async_nats::jetstream::{self, Context};
+use async_nats::HeaderMap;
+use async_trait::async_trait;
+use chrono::prelude::*;
+use sqlx::sqlite::SqlitePool;
+use std::error::Error;
+use std::sync::Arc;
+
+const SAMPLES_CURSOR: &str = "samples";
+const EVENTS_CURSOR: &str = "events";
+
+/// A failed connect or publish.
+type Failure = Box<dyn Error + Send + Sync>;
+
+pub async fn setup_nats_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS nats_cursor (
+            name TEXT PRIMARY KEY,
+            position INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn load_cursor(pool: &SqlitePool, name: &str) -> Option<i64> {
+    sqlx::query_as::<_, (i64,)>("SELECT position FROM nats_cursor WHERE name = ?")
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+        .unwrap()
+        .map(|(position,)| position)
+}
+
+async fn save_cursor(pool: &SqlitePool, name: &str, position: i64) {
+    sqlx::query("INSERT OR REPLACE INTO nats_cursor (name, position) VALUES (?, ?)")
+        .bind(name)
+        .bind(position)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// Subject templates with `{site}` and `{motor}` filled in; events keep
+/// `{kind}` for `event_subject`.
+#[derive(Debug, Clone)]
+pub struct Subjects {
+    pub samples: String,
+    pub events: String,
+}
+
+impl Subjects {
+    pub fn new(config: &NatsConfig, site: Option<&str>, motor_id: &str) -> Self {
+        let prefix = match site {
+            Some(_) => "rustsys.{site}.{motor}",
+            None => "rustsys.{motor}",
+        };
+        let fill = |template: &str| template.replace("{site}", site.unwrap_or_default()).replace("{motor}", motor_id);
+        Subjects {
+            samples: fill(config.samples_subject.as_deref().unwrap_or(&format!("{}.samples", prefix))),
+            events: fill(config.events_subject.as_deref().unwrap_or(&format!("{}.events.{{kind}}", prefix))),
+        }
+    }
+
+    /// Subject of `event`; a kind is a single subject token.
+    pub fn event_subject(&self, event: &Event) -> String {
+        self.events.replace("{kind}", &event.kind.replace(['.', ' ', '*', '>'], "_"))
+    }
+}
+
+async fn connect(config: &NatsConfig) -> Result<Context, Failure> {
+    let options = match &config.credentials_file {
+        Some(path) => async_nats::ConnectOptions::with_credentials_file(path.clone()).await?,
+        None => async_nats::ConnectOptions::new(),
+    };
+    let options = match &config.tls {
+        Some(tls) => tls::nats_options(options, tls),
+        None => options,
+    };
+    let client = options.name("rustsys").connect(config.url.as_str()).await?;
+    Ok(jetstream::new(client))
+}
+
+/// Publishes `payload` and waits for JetStream to store it.
+async fn publish(js: &Context, subject: String, id: String, payload: Vec<u8>) -> Result<(), Failure> {
+    let mut headers = HeaderMap::new();
+    headers.insert("Nats-Msg-Id", id.as_str());
+    js.publish_with_headers(subject, headers, payload.into()).await?.await?;
+    Ok(())
+}
+
+/// Publishes the next batch of samples; returns how many were acknowledged.
+async fn publish_samples(
+    pool: &SqlitePool,
+    js: &Context,
+    subjects: &Subjects,
+    motor_id: &str,
+    batch: i64,
+) -> Result<usize, Failure> {
+    let now = Local::now().timestamp_millis();
+    let cursor = match load_cursor(pool, SAMPLES_CURSOR).await {
+        Some(cursor) => cursor,
+        None => {
+            save_cursor(pool, SAMPLES_CURSOR, now).await;
+            now
+        }
+    };
+    let samples: Vec<MotorData> = motor_data_range(pool, cursor + 1, i64::MAX, Some(batch)).await;
+    for data in &samples {
+        let id = format!("{}-{}", motor_id, data.timestamp);
+        publish(js, subjects.samples.clone(), id, serde_json::to_vec(data)?).await?;
+        save_cursor(pool, SAMPLES_CURSOR, data.timestamp).await;
+    }
+    Ok(samples.len())
+}
+
+/// Publishes the next batch of events; returns how many were acknowledged.
+async fn publish_events(
+    pool: &SqlitePool,
+    js: &Context,
+    subjects: &Subjects,
+    motor_id: &str,
+    batch: i64,
+) -> Result<usize, Failure> {
+    let cursor = match load_cursor(pool, EVENTS_CURSOR).await {
+        Some(cursor) => cursor,
+        None => {
+            let (latest,): (Option<i64>,) = sqlx::query_as("SELECT MAX(id) FROM events").fetch_one(pool).await.unwrap();
+            let latest = latest.unwrap_or(0);
+            save_cursor(pool, EVENTS_CURSOR, latest).await;
+            latest
+        }
+    };
+    let pending = events::events_after(pool, cursor, batch).await;
+    for event in &pending {
+        let id = format!("{}-event-{}", motor_id, event.id);
+        publish(js, subjects.event_subject(event), id, serde_json::to_vec(event)?).await?;
+        save_cursor(pool, EVENTS_CURSOR, event.id).await;
+    }
+    Ok(pending.len())
+}
+
+/// Publishes stored samples and events past its cursors whenever it is
+/// handed a sample.
+pub struct NatsSink {
+    pool: Arc<SqlitePool>,
+    config: NatsConfig,
+    subjects: Subjects,
+    motor_id: String,
+    js: Option<Context>,
+}
+
+impl NatsSink {
+    pub fn new(pool: Arc<SqlitePool>, config: NatsConfig, site: Option<String>, motor_id: String) -> Self {
+        let subjects = Subjects::new(&config, site.as_deref(), &motor_id);
+        NatsSink { pool, config, subjects, motor_id, js: None }
+    }
+
+    /// Publishes everything past the cursors, batch by batch.
+    async fn publish_pending(&self, js: &Context) -> Result<(), Failure> {
+        let batch = self.config.batch_size;
+        loop {
+            let samples = publish_samples(&self.pool, js, &self.subjects, &self.motor_id, batch).await?;
+            let events = publish_events(&self.pool, js, &self.subjects, &self.motor_id, batch).await?;
+            // A full batch means more is waiting.
+            if (samples as i64) < batch && (events as i64) < batch {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Sink<MotorData> for NatsSink {
+    async fn open(&mut self) -> Result<(), String> {
+        let url = secrets::redact_url(&self.config.url);
+        let js = connect(&self.config).await.map_err(|err| format!("cannot connect to NATS at {}: {}", url, err))?;
+        log::info!("NATS connected to {}", url);
+        self.js = Some(js);
+        Ok(())
+    }
+
+    async fn write(&mut self, _wake: &MotorData) -> Result<(), String> {
+        let js = self.js.as_ref().ok_or("not open")?;
+        self.publish_pending(js).await.map_err(|err| format!("NATS publish failed: {}", err))
+    }
+}
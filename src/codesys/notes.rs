@@ -0,0 +1,93 @@
+//! Operator notes: timestamped remarks attached to a motor ("replaced
+//! belt", "greased bearings") that explain changes in the data. They are
+//! drawn as markers on charts and listed in reports.
+
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Note {
+    pub id: i64,
+    pub motor_id: String,
+    /// Epoch seconds the note refers to (not necessarily when it was written).
+    pub timestamp: i64,
+    pub author: String,
+    pub text: String,
+    pub created_at: i64,
+}
+
+pub async fn setup_notes_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY,
+            motor_id TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            author TEXT NOT NULL,
+            text TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+pub async fn add_note(pool: &SqlitePool, motor_id: &str, timestamp: i64, author: &str, text: &str, created_at: i64) -> i64 {
+    sqlx::query("INSERT INTO notes (motor_id, timestamp, author, text, created_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(motor_id)
+        .bind(timestamp)
+        .bind(author)
+        .bind(text)
+        .bind(created_at)
+        .execute(pool)
+        .await
+        .unwrap()
+        .last_insert_rowid()
+}
+
+/// Returns false if no such note exists.
+pub async fn delete_note(pool: &SqlitePool, id: i64) -> bool {
+    sqlx::query("DELETE FROM notes WHERE id = ?").bind(id).execute(pool).await.unwrap().rows_affected() > 0
+}
+
+/// Notes for `motor_id` with `from <= timestamp < to`, oldest first.
+pub async fn list_notes(pool: &SqlitePool, motor_id: &str, from: i64, to: i64) -> Vec<Note> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, motor_id, timestamp, author, text, created_at FROM notes
+        WHERE motor_id = ? AND timestamp >= ? AND timestamp < ?
+        ORDER BY timestamp
+        "#,
+    )
+    .bind(motor_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    rows.iter()
+        .map(|row| Note {
+            id: row.get("id"),
+            motor_id: row.get("motor_id"),
+            timestamp: row.get("timestamp"),
+            author: row.get("author"),
+            text: row.get("text"),
+            created_at: row.get("created_at"),
+        })
+        .collect()
+}
+
+/// Chart markers (millisecond timestamp, label) for notes in `[from, to)`,
+/// both given in milliseconds.
+pub async fn markers(pool: &SqlitePool, motor_id: &str, from: i64, to: i64) -> Vec<(i64, String)> {
+    list_notes(pool, motor_id, from.div_euclid(1000), to.div_euclid(1000) + 1)
+        .await
+        .into_iter()
+        .map(|note| (note.timestamp * 1000, note.text))
+        .filter(|(t, _)| *t >= from && *t < to)
+        .collect()
+}
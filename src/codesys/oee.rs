@@ -0,0 +1,194 @@
+//! OEE-style KPIs: availability, a performance proxy and quality.
+//!
+//! - Availability: time the motor ran within its scheduled time over the
+//!   scheduled time. The scheduled time is the `[schedule]` windows, or the
+//!   whole period without a schedule. The motor runs above
+//!   `schedule.running_speed_rpm`, else above zero.
+//! - Performance: mean speed while running, against `motor.rated_speed`. It
+//!   is a crude proxy: a motor that is meant to run slow scores low.
+//! - Quality: share of the run time without an active alarm. Alarms raised
+//!   in the period are counted as quality events.
+//!
+//! OEE is the product of the three, with performance capped at 100 %. The
+//! KPIs come from the stored samples and alarms, like `fleet::usage`, and
+//! are summed over several motors before the ratios are taken, so a group's
+//! KPIs weigh each motor by its time. Motors are grouped by `group` in the
+//! fleet report; `kpis` on GraphQL serves one motor's KPIs, and reports get
+//! a section with them.
+
+use super::config::{MonitorConfig, ScheduleConfig};
+use super::location::Zone;
+use super::schedule;
+use super::{motor_data_range, MotorData};
+use sqlx::sqlite::SqlitePool;
+
+/// Samples read per query while scanning the period.
+const PAGE_SIZE: i64 = 5000;
+/// Gaps longer than this (ms) are outages and don't count as run time.
+const MAX_GAP_MS: i64 = 60_000;
+/// Step (seconds) at which the schedule is sampled for the scheduled time.
+const SCHEDULE_STEP_SECS: i64 = 60;
+/// Longest period (ms) the KPIs are asked for at once: a year and a day.
+pub const MAX_RANGE_MS: i64 = 366 * 86_400_000;
+
+/// What the KPIs need from the monitor configuration.
+#[derive(Debug, Clone)]
+pub struct KpiSettings {
+    pub motor_id: String,
+    pub rated_speed: f64,
+    pub schedule: Option<ScheduleConfig>,
+    /// Clock of the schedule windows.
+    pub zone: Zone,
+}
+
+impl KpiSettings {
+    pub fn from_config(config: &MonitorConfig) -> KpiSettings {
+        KpiSettings {
+            motor_id: config.motor_id.clone(),
+            rated_speed: config.motor.rated_speed,
+            schedule: config.schedule.clone(),
+            zone: Zone::from_config(config),
+        }
+    }
+
+    fn scheduled(&self, at_ms: i64) -> bool {
+        match (&self.schedule, self.zone.at_millis(at_ms)) {
+            (Some(schedule), Some(at)) => schedule::scheduled(schedule, &at),
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+
+    fn running(&self, data: &MotorData) -> bool {
+        data.current_speed > self.schedule.as_ref().map_or(0.0, |s| s.running_speed_rpm)
+    }
+}
+
+/// Times behind the KPIs, in seconds; add up over motors.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Kpis {
+    pub scheduled_secs: f64,
+    /// Run time within the scheduled time.
+    pub run_secs: f64,
+    /// Run time weighted by speed over rated speed.
+    pub rated_speed_secs: f64,
+    /// Run time without an active alarm.
+    pub clean_secs: f64,
+    /// Alarms raised in the period.
+    pub quality_events: i64,
+}
+
+impl Kpis {
+    pub fn add(&mut self, other: &Kpis) {
+        self.scheduled_secs += other.scheduled_secs;
+        self.run_secs += other.run_secs;
+        self.rated_speed_secs += other.rated_speed_secs;
+        self.clean_secs += other.clean_secs;
+        self.quality_events += other.quality_events;
+    }
+
+    /// `None` without scheduled time.
+    pub fn availability(&self) -> Option<f64> {
+        (self.scheduled_secs > 0.0).then(|| (self.run_secs / self.scheduled_secs).min(1.0))
+    }
+
+    /// `None` when the motor did not run.
+    pub fn performance(&self) -> Option<f64> {
+        (self.run_secs > 0.0).then(|| self.rated_speed_secs / self.run_secs)
+    }
+
+    /// `None` when the motor did not run.
+    pub fn quality(&self) -> Option<f64> {
+        (self.run_secs > 0.0).then(|| self.clean_secs / self.run_secs)
+    }
+
+    /// Availability, quality and performance capped at 1, multiplied; a
+    /// motor scheduled but never run has an OEE of zero.
+    pub fn oee(&self) -> Option<f64> {
+        let availability = self.availability()?;
+        match (self.performance(), self.quality()) {
+            (Some(performance), Some(quality)) => Some(availability * performance.min(1.0) * quality),
+            _ => Some(0.0),
+        }
+    }
+}
+
+/// Seconds of `[from, to)` (milliseconds) inside the schedule.
+fn scheduled_secs(settings: &KpiSettings, from: i64, to: i64) -> f64 {
+    if settings.schedule.is_none() {
+        return to.checked_sub(from).map_or(0.0, |ms| ms as f64 / 1000.0).max(0.0);
+    }
+    let step = SCHEDULE_STEP_SECS * 1000;
+    let mut secs = 0.0;
+    let mut at = from;
+    while at < to {
+        let next = at.saturating_add(step).min(to);
+        if settings.scheduled(at) {
+            secs += (next - at) as f64 / 1000.0;
+        }
+        at = next;
+    }
+    secs
+}
+
+/// Alarms of the motor overlapping `[from, to)`, as `[raised, cleared)`
+/// intervals in milliseconds; one still active runs to `to`.
+async fn alarm_intervals(pool: &SqlitePool, motor_id: &str, from: i64, to: i64) -> Vec<(i64, i64)> {
+    let rows: Vec<(i64, Option<i64>)> = sqlx::query_as(
+        "SELECT raised_at, cleared_at FROM alarms
+         WHERE motor_id = ? AND raised_at < ? AND (cleared_at IS NULL OR cleared_at >= ?)",
+    )
+    .bind(motor_id)
+    .bind(to.div_euclid(1000))
+    .bind(from.div_euclid(1000))
+    .fetch_all(pool)
+    .await
+    .unwrap();
+    rows.into_iter().map(|(raised, cleared)| (raised * 1000, cleared.map_or(to, |c| c * 1000))).collect()
+}
+
+/// KPIs of one motor over `[from, to)` (milliseconds).
+pub async fn kpis(pool: &SqlitePool, settings: &KpiSettings, from: i64, to: i64) -> Kpis {
+    let alarms = alarm_intervals(pool, &settings.motor_id, from, to).await;
+    let mut kpis = Kpis { scheduled_secs: scheduled_secs(settings, from, to), ..Kpis::default() };
+    let mut previous: Option<MotorData> = None;
+    let mut cursor = from;
+    loop {
+        let page = motor_data_range(pool, cursor, to, Some(PAGE_SIZE)).await;
+        for data in &page {
+            if let Some(prev) = &previous {
+                let dt_ms = data.timestamp - prev.timestamp;
+                if settings.running(prev) && dt_ms <= MAX_GAP_MS && settings.scheduled(prev.timestamp) {
+                    let secs = dt_ms as f64 / 1000.0;
+                    kpis.run_secs += secs;
+                    if settings.rated_speed > 0.0 {
+                        kpis.rated_speed_secs += prev.current_speed / settings.rated_speed * secs;
+                    }
+                    if !alarms.iter().any(|&(raised, cleared)| (raised..cleared).contains(&prev.timestamp)) {
+                        kpis.clean_secs += secs;
+                    }
+                }
+            }
+            previous = Some(data.clone());
+        }
+        match page.last() {
+            Some(last) if page.len() as i64 == PAGE_SIZE => cursor = last.timestamp + 1,
+            _ => break,
+        }
+    }
+    let (events,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM alarms WHERE motor_id = ? AND raised_at >= ? AND raised_at < ?")
+            .bind(&settings.motor_id)
+            .bind(from.div_euclid(1000))
+            .bind(to.div_euclid(1000))
+            .fetch_one(pool)
+            .await
+            .unwrap();
+    kpis.quality_events = events;
+    kpis
+}
+
+/// A ratio as a percentage for tables, `–` when undefined.
+pub fn percent(value: Option<f64>) -> String {
+    value.map_or("–".to_string(), |v| format!("{:.1} %", v * 100.0))
+}
@@ -0,0 +1,155 @@
+//! Output path templates for generated files (charts, reports).
+//!
+//! Templates may use `{motor}`, `{signal}`, `{date}` (`YYYY-MM-DD`) and
+//! `{time}` (`HHMM`), e.g. `charts/{motor}/{signal}_{date}.png`. Dates are in
+//! local time. Parent directories are created on demand.
+//!
+//! Files are written with `write_atomically`: into a hidden temporary file
+//! next to the target, then renamed over it, so a reader (e.g. an HMI
+//! polling a chart) sees either the old or the new file, never half of one.
+//!
+//! Rendered PNGs carry `tEXt` chunks (`chart_text`) naming the motor, the
+//! window shown and when the image was generated, so a chart copied out of
+//! its folder (into a ticket or an email) still says what it shows.
+
+use super::disk;
+use chrono::prelude::*;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const PLACEHOLDERS: [&str; 4] = ["motor", "signal", "date", "time"];
+
+/// Values substituted into a template. `signal` is empty for per-motor files.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputContext<'a> {
+    pub motor: &'a str,
+    pub signal: &'a str,
+    pub timestamp: i64,
+}
+
+pub fn render_path(template: &str, ctx: &OutputContext<'_>) -> PathBuf {
+    let local = Local.timestamp_opt(ctx.timestamp, 0).single().unwrap_or_else(Local::now);
+    PathBuf::from(
+        template
+            .replace("{motor}", ctx.motor)
+            .replace("{signal}", ctx.signal)
+            .replace("{date}", &local.format("%Y-%m-%d").to_string())
+            .replace("{time}", &local.format("%H%M").to_string()),
+    )
+}
+
+/// Creates the parent directory of `path` if it does not exist yet. Fails
+/// while the disk guard reports low `space`, so the file is not written.
+pub fn ensure_parent(path: &Path, space: &disk::Space) -> std::io::Result<()> {
+    space.check()?;
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Hidden sibling of `path` used while writing it: `charts/power.png` is
+/// written as `charts/.power.tmp.png`. The extension is kept because some
+/// encoders pick the image format from it.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!(".{}.tmp.{}", stem, ext.to_string_lossy()),
+        None => format!(".{}.tmp", stem),
+    };
+    path.with_file_name(name)
+}
+
+/// Calls `write` with a temporary path, then renames the result to `path`
+/// (atomic on the same file system). The temporary file is removed if
+/// `write` fails.
+pub fn write_atomically<F>(path: &Path, write: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnOnce(&Path) -> Result<(), Box<dyn Error>>,
+{
+    let temp = temp_path(path);
+    if let Err(err) = write(&temp) {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// `tEXt` entries for a chart of `motor` showing `[from, to]` (epoch
+/// milliseconds). Times are RFC 3339 in UTC.
+pub fn chart_text(motor: &str, from: i64, to: i64) -> Vec<(&'static str, String)> {
+    let time = |ms: i64| {
+        Utc.timestamp_millis_opt(ms).single().map_or_else(String::new, |t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+    };
+    vec![
+        ("motor_id", motor.to_string()),
+        ("window_from", time(from)),
+        ("window_to", time(to)),
+        ("generated_at", time(Utc::now().timestamp_millis())),
+    ]
+}
+
+/// Inserts `tEXt` chunks for `text` (keyword, value) into the PNG at
+/// `path`, right after its header. Non-Latin-1 characters are replaced by
+/// `?`, which is all a `tEXt` chunk can hold.
+pub fn add_png_text(path: &Path, text: &[(&str, String)]) -> Result<(), Box<dyn Error>> {
+    if text.is_empty() {
+        return Ok(());
+    }
+    let png = fs::read(path)?;
+    // Signature (8 bytes), then IHDR: length, type, 13 data bytes, CRC.
+    if png.len() < 33 || &png[12..16] != b"IHDR" {
+        return Err(format!("{}: not a PNG file", path.display()).into());
+    }
+    let mut out = Vec::with_capacity(png.len() + 64 * text.len());
+    out.extend_from_slice(&png[..33]);
+    for (keyword, value) in text {
+        let mut chunk = b"tEXt".to_vec();
+        chunk.extend(latin1(keyword));
+        chunk.push(0);
+        chunk.extend(latin1(value));
+        out.extend_from_slice(&(chunk.len() as u32 - 4).to_be_bytes());
+        out.extend_from_slice(&chunk);
+        out.extend_from_slice(&crc32(&chunk).to_be_bytes());
+    }
+    out.extend_from_slice(&png[33..]);
+    fs::write(path, out)?;
+    Ok(())
+}
+
+fn latin1(text: &str) -> impl Iterator<Item = u8> + '_ {
+    text.chars().map(|c| u8::try_from(u32::from(c)).ok().filter(|&b| b != 0).unwrap_or(b'?'))
+}
+
+/// CRC-32 as used by PNG chunks (ISO 3309, reflected, polynomial 0xEDB88320).
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Names of placeholders in `template` that are not recognised.
+pub fn unknown_placeholders(template: &str) -> Vec<String> {
+    let mut unknown = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let after = &rest[open + 1..];
+        let Some(close) = after.find('}') else {
+            unknown.push(after.to_string());
+            break;
+        };
+        let name = &after[..close];
+        if !PLACEHOLDERS.contains(&name) {
+            unknown.push(name.to_string());
+        }
+        rest = &after[close + 1..];
+    }
+    unknown
+}
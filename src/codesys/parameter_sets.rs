@@ -0,0 +1,115 @@
+//! Parameter sets: related registers written together or not at all.
+//!
+//! Some drives only take a new setpoint together with its enable bit, or a
+//! ramp time with the speed it belongs to; half a group written leaves the
+//! drive in a state nobody asked for. `[control.parameter_sets.<name>]`
+//! lists the holding registers of a group and their values, and
+//! `POST /control/parameter_set?name=<name>` writes them like any other
+//! control command.
+//!
+//! The current values are read first. Writes at consecutive addresses go
+//! out as one Modbus 0x10 (write multiple registers) request, which the
+//! device applies as a whole. Otherwise, and on transports without 0x10,
+//! they are sent one by one in the order listed. When a write fails, every
+//! register that may have taken a new value gets its old one back, last
+//! first. Rolling back can fail too, e.g. when the connection is lost; the
+//! error says so, since the drive is then left part-way.
+
+use super::config::{ParameterSet, ParameterWrite, RegisterDef};
+use super::transport::{Transport, TransportError, READ_HOLDING_REGISTERS};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SetError {
+    /// The current values could not be read; nothing was written.
+    NotWritten(TransportError),
+    /// A write failed and the old values were restored.
+    RolledBack(TransportError),
+    /// A write failed and so did restoring the old values.
+    RollbackFailed { error: TransportError, rollback: TransportError },
+}
+
+impl fmt::Display for SetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetError::NotWritten(err) => write!(f, "nothing written, cannot read the current values: {}", err),
+            SetError::RolledBack(err) => write!(f, "{}; old values restored", err),
+            SetError::RollbackFailed { error, rollback } => {
+                write!(f, "{}; restoring the old values failed too, the set is partly written: {}", error, rollback)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SetError {}
+
+fn holding(address: u16) -> RegisterDef {
+    RegisterDef { address, count: 1, function: READ_HOLDING_REGISTERS }
+}
+
+/// The first address, when the writes cover consecutive addresses in order.
+fn consecutive(writes: &[ParameterWrite]) -> Option<u16> {
+    let first = writes.first()?.address;
+    writes
+        .iter()
+        .enumerate()
+        .all(|(i, write)| first.checked_add(i as u16) == Some(write.address))
+        .then_some(first)
+}
+
+/// `1200 to register 100, 1 to register 101`.
+pub fn describe(set: &ParameterSet) -> String {
+    let writes: Vec<String> =
+        set.writes.iter().map(|write| format!("{} to register {}", write.value, write.address)).collect();
+    writes.join(", ")
+}
+
+/// Writes `set`, restoring the old values if that fails part-way.
+pub async fn write(transport: &mut dyn Transport, set: &ParameterSet) -> Result<(), SetError> {
+    let mut old = Vec::with_capacity(set.writes.len());
+    for write in &set.writes {
+        let value = transport.read(&holding(write.address)).await.map_err(SetError::NotWritten)?;
+        old.push(ParameterWrite { address: write.address, value: value as u16 });
+    }
+    match consecutive(&set.writes).filter(|_| transport.writes_multiple()) {
+        Some(address) => {
+            let values: Vec<u16> = set.writes.iter().map(|write| write.value).collect();
+            let Err(error) = transport.write_multiple(address, &values).await else {
+                return Ok(());
+            };
+            let values: Vec<u16> = old.iter().map(|write| write.value).collect();
+            match transport.write_multiple(address, &values).await {
+                Ok(()) => Err(SetError::RolledBack(error)),
+                Err(rollback) => Err(SetError::RollbackFailed { error, rollback }),
+            }
+        }
+        None => {
+            for (i, write) in set.writes.iter().enumerate() {
+                let Err(error) = transport.write(&holding(write.address), write.value).await else {
+                    continue;
+                };
+                // The failed write may have taken too, e.g. when verification rejected it.
+                return match restore(transport, &old[..=i]).await {
+                    Ok(()) => Err(SetError::RolledBack(error)),
+                    Err(rollback) => Err(SetError::RollbackFailed { error, rollback }),
+                };
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Writes `old` back, last first, carrying on past failures; returns the
+/// first.
+async fn restore(transport: &mut dyn Transport, old: &[ParameterWrite]) -> Result<(), TransportError> {
+    let mut result = Ok(());
+    for write in old.iter().rev() {
+        if let Err(err) = transport.write(&holding(write.address), write.value).await {
+            log::error!("cannot restore register {} to {}: {}", write.address, write.value, err);
+            if result.is_ok() {
+                result = Err(err);
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,389 @@
+//! Bounded channels between the polling loop and its sinks.
+//!
+//! Every sink (sample storage, window statistics, ...) is fed through its
+//! own bounded channel and drained by its own task, so a slow sink can never
+//! stall polling. What happens when a sink's channel is full is chosen per
+//! sink with `overflow`:
+//!
+//! - `block`: the poller waits for room. No data is lost, but polling stalls.
+//! - `drop_oldest`: the oldest queued item is discarded and counted.
+//! - `spill_to_disk`: new items are appended to `<spill_dir>/<sink>.spool`
+//!   and replayed, in order, once the sink catches up. A spill file left by
+//!   a previous run is replayed on startup.
+//!
+//! The spool holds frames of up to `SPILL_BATCH` items: the marker `RSP1`,
+//! the length and CRC-32 of the payload (both little-endian `u32`), and the
+//! payload, the items as zstd-compressed JSON lines. Spilled items are
+//! collected in memory until a frame is full, so the last, partial frame is
+//! lost if the process dies. Replay reads one frame at a time, so a long
+//! outage costs disk, not memory, and records how far it got in
+//! `<sink>.spool.offset`; after a restart it continues there instead of
+//! handing the sink the same items again. A frame that fails its CRC or does
+//! not decode, e.g. after a power cut mid-write, is skipped up to the next
+//! marker; the frames after it are replayed as usual. A `<sink>.jsonl`
+//! spill of an older version is converted on startup.
+
+use super::config::{OverflowPolicy, SinkConfig};
+use super::output::crc32;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time;
+
+/// Marks the start of every spool frame.
+const SPILL_MAGIC: &[u8; 4] = b"RSP1";
+/// Marker, payload length and CRC.
+const HEADER_LEN: u64 = 12;
+/// Larger frames are taken for corruption.
+const MAX_FRAME: u32 = 64 << 20;
+const SPILL_LEVEL: i32 = 3;
+/// Items per spool frame.
+const SPILL_BATCH: usize = 64;
+/// Wait before replaying again after the spool could not be read.
+const REPLAY_RETRY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct SpillState {
+    /// True while the file or `batch` holds items not yet handed to the
+    /// sink. New items are spilled too, so they stay behind those.
+    active: bool,
+    /// Where the next frame to replay starts.
+    offset: u64,
+    /// JSON lines of spilled items not yet written as a frame.
+    batch: Vec<u8>,
+    batch_items: usize,
+}
+
+/// Items that did not fit the channel, kept on disk in arrival order.
+#[derive(Debug)]
+struct Spill {
+    path: PathBuf,
+    offset_path: PathBuf,
+    state: Mutex<SpillState>,
+}
+
+impl Spill {
+    fn open<T: Serialize + DeserializeOwned>(dir: &str, name: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = PathBuf::from(dir).join(format!("{}.spool", name));
+        let offset_path = PathBuf::from(dir).join(format!("{}.spool.offset", name));
+        let spill = Spill { path, offset_path, state: Mutex::new(SpillState::default()) };
+        let mut state = SpillState::default();
+        spill.convert_legacy::<T>(&PathBuf::from(dir).join(format!("{}.jsonl", name)), &mut state)?;
+        let len = fs::metadata(&spill.path).map_or(0, |m| m.len());
+        state.offset = fs::read_to_string(&spill.offset_path)
+            .ok()
+            .and_then(|offset| offset.trim().parse().ok())
+            .filter(|&offset| offset <= len)
+            .unwrap_or(0);
+        state.active = state.offset < len;
+        if state.active {
+            log::info!("sink {}: replaying spilled items from {}", name, spill.path.display());
+        }
+        *spill.state.lock().unwrap() = state;
+        Ok(spill)
+    }
+
+    /// Appends the items of a JSON-lines spill file to the spool and removes it.
+    fn convert_legacy<T: Serialize + DeserializeOwned>(&self, legacy: &Path, state: &mut SpillState) -> io::Result<()> {
+        let file = match File::open(legacy) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        for line in BufReader::new(file).lines() {
+            match serde_json::from_str::<T>(&line?) {
+                Ok(item) => self.append(state, &item)?,
+                Err(err) => log::warn!("skipping unreadable line in {}: {}", legacy.display(), err),
+            }
+        }
+        self.flush(state)?;
+        log::info!("converted {} to {}", legacy.display(), self.path.display());
+        fs::remove_file(legacy)
+    }
+
+    /// Adds `item` to the batch and writes the batch once it is full. A
+    /// batch that cannot be written is kept and written with the next item.
+    fn append<T: Serialize>(&self, state: &mut SpillState, item: &T) -> io::Result<()> {
+        serde_json::to_writer(&mut state.batch, item).map_err(io::Error::from)?;
+        state.batch.push(b'\n');
+        state.batch_items += 1;
+        if state.batch_items >= SPILL_BATCH {
+            self.flush(state)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the items of the batch as one frame.
+    fn flush(&self, state: &mut SpillState) -> io::Result<()> {
+        if state.batch.is_empty() {
+            return Ok(());
+        }
+        let payload = zstd::encode_all(state.batch.as_slice(), SPILL_LEVEL)?;
+        let mut frame = Vec::with_capacity(HEADER_LEN as usize + payload.len());
+        frame.extend_from_slice(SPILL_MAGIC);
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&crc32(&payload).to_le_bytes());
+        frame.extend_from_slice(&payload);
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(&frame)?;
+        state.batch.clear();
+        state.batch_items = 0;
+        Ok(())
+    }
+
+    /// Records that the frames before `offset` have been replayed.
+    fn save_offset(&self, offset: u64) -> io::Result<()> {
+        fs::write(&self.offset_path, offset.to_string())
+    }
+
+    /// Removes the replayed spool and its offset.
+    fn remove(&self) -> io::Result<()> {
+        for path in [&self.path, &self.offset_path] {
+            match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Items of the first intact frame at or after `offset`, which is moved
+    /// past it; `None` at the end of the file.
+    fn next_frame<T: DeserializeOwned>(&self, offset: &mut u64) -> io::Result<Option<Vec<T>>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let len = file.metadata()?.len();
+        while *offset + HEADER_LEN <= len {
+            file.seek(SeekFrom::Start(*offset))?;
+            let mut header = [0u8; HEADER_LEN as usize];
+            file.read_exact(&mut header)?;
+            let size = u32::from_le_bytes(header[4..8].try_into().unwrap());
+            let crc = u32::from_le_bytes(header[8..12].try_into().unwrap());
+            let end = *offset + HEADER_LEN + size as u64;
+            if &header[..4] == SPILL_MAGIC && size <= MAX_FRAME && end <= len {
+                let mut payload = vec![0; size as usize];
+                file.read_exact(&mut payload)?;
+                if crc32(&payload) == crc {
+                    if let Ok(lines) = zstd::decode_all(payload.as_slice()) {
+                        *offset = end;
+                        return Ok(Some(self.parse(&lines)));
+                    }
+                }
+            }
+            log::warn!("{}: damaged frame at byte {}, skipping to the next one", self.path.display(), offset);
+            match find_marker(&mut file, *offset + 1)? {
+                Some(next) => *offset = next,
+                None => break,
+            }
+        }
+        Ok(None)
+    }
+
+    fn parse<T: DeserializeOwned>(&self, lines: &[u8]) -> Vec<T> {
+        let mut items = Vec::new();
+        for line in lines.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+            match serde_json::from_slice(line) {
+                Ok(item) => items.push(item),
+                Err(err) => log::warn!("skipping unreadable item in {}: {}", self.path.display(), err),
+            }
+        }
+        items
+    }
+}
+
+/// Offset of the first frame marker at or after `from`.
+fn find_marker(file: &mut File, from: u64) -> io::Result<Option<u64>> {
+    file.seek(SeekFrom::Start(from))?;
+    let mut window = [0u8; 4];
+    for (i, byte) in BufReader::new(file).bytes().enumerate() {
+        window.rotate_left(1);
+        window[3] = byte?;
+        if i >= 3 && &window == SPILL_MAGIC {
+            return Ok(Some(from + i as u64 - 3));
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Debug)]
+enum Tx<T> {
+    Block(mpsc::Sender<T>),
+    DropOldest(broadcast::Sender<T>),
+    Spill(mpsc::Sender<T>, Arc<Spill>),
+}
+
+#[derive(Debug)]
+enum Rx<T> {
+    Block(mpsc::Receiver<T>),
+    DropOldest(broadcast::Receiver<T>),
+    Spill(mpsc::Receiver<T>, Arc<Spill>, VecDeque<T>),
+}
+
+/// Producer side, held by the polling loop.
+#[derive(Debug)]
+pub struct SinkSender<T> {
+    name: String,
+    tx: Tx<T>,
+}
+
+/// Consumer side, drained by the sink's task.
+#[derive(Debug)]
+pub struct SinkReceiver<T> {
+    name: String,
+    rx: Rx<T>,
+}
+
+/// Creates the channel for sink `name` according to `config`.
+pub fn channel<T>(name: &str, config: &SinkConfig) -> io::Result<(SinkSender<T>, SinkReceiver<T>)>
+where
+    T: Clone + Serialize + DeserializeOwned + Send + 'static,
+{
+    let (tx, rx) = match config.overflow {
+        OverflowPolicy::Block => {
+            let (tx, rx) = mpsc::channel(config.capacity);
+            (Tx::Block(tx), Rx::Block(rx))
+        }
+        OverflowPolicy::DropOldest => {
+            let (tx, rx) = broadcast::channel(config.capacity);
+            (Tx::DropOldest(tx), Rx::DropOldest(rx))
+        }
+        OverflowPolicy::SpillToDisk => {
+            let spill = Arc::new(Spill::open::<T>(&config.spill_dir, name)?);
+            let (tx, rx) = mpsc::channel(config.capacity);
+            (Tx::Spill(tx, Arc::clone(&spill)), Rx::Spill(rx, spill, VecDeque::new()))
+        }
+    };
+    Ok((SinkSender { name: name.to_string(), tx }, SinkReceiver { name: name.to_string(), rx }))
+}
+
+impl<T: Serialize> SinkSender<T> {
+    /// Queues `item` for the sink. Only waits with the `block` policy.
+    pub async fn send(&self, item: T) {
+        match &self.tx {
+            Tx::Block(tx) => {
+                if tx.send(item).await.is_err() {
+                    log::error!("sink {} has stopped", self.name);
+                }
+            }
+            Tx::DropOldest(tx) => {
+                if tx.send(item).is_err() {
+                    log::error!("sink {} has stopped", self.name);
+                }
+            }
+            Tx::Spill(tx, spill) => {
+                let mut state = spill.state.lock().unwrap();
+                let item = if state.active {
+                    item
+                } else {
+                    match tx.try_send(item) {
+                        Ok(()) => return,
+                        Err(mpsc::error::TrySendError::Full(item)) => {
+                            log::warn!("sink {} is falling behind, spilling to {}", self.name, spill.path.display());
+                            item
+                        }
+                        Err(mpsc::error::TrySendError::Closed(_)) => {
+                            log::error!("sink {} has stopped", self.name);
+                            return;
+                        }
+                    }
+                };
+                match spill.append(&mut state, &item) {
+                    Ok(()) => state.active = true,
+                    Err(err) => log::error!("sink {}: cannot spill to {}: {}", self.name, spill.path.display(), err),
+                }
+            }
+        }
+    }
+}
+
+impl<T: Clone + DeserializeOwned> SinkReceiver<T> {
+    /// Next item in arrival order; `None` once the sender is gone and
+    /// everything queued has been delivered.
+    pub async fn recv(&mut self) -> Option<T> {
+        match &mut self.rx {
+            Rx::Block(rx) => rx.recv().await,
+            Rx::DropOldest(rx) => loop {
+                match rx.recv().await {
+                    Ok(item) => return Some(item),
+                    Err(broadcast::error::RecvError::Lagged(dropped)) => {
+                        log::warn!("sink {} is falling behind, dropped {} oldest items", self.name, dropped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            },
+            Rx::Spill(rx, spill, replay) => loop {
+                if let Some(item) = replay.pop_front() {
+                    return Some(item);
+                }
+                // Checked under the lock so the sender cannot start spilling
+                // between draining the channel and looking at the file.
+                let closed = {
+                    let mut state = spill.state.lock().unwrap();
+                    let closed = match rx.try_recv() {
+                        Ok(item) => return Some(item),
+                        Err(mpsc::error::TryRecvError::Empty) => false,
+                        Err(mpsc::error::TryRecvError::Disconnected) => true,
+                    };
+                    // The channel is drained, so everything spilled comes next,
+                    // one frame at a time, then the batch not yet written.
+                    if state.active {
+                        // The frames before the offset have all been handed out.
+                        if let Err(err) = spill.save_offset(state.offset) {
+                            log::warn!("sink {}: cannot record the replay offset: {}", self.name, err);
+                        }
+                        let mut offset = state.offset;
+                        match spill.next_frame(&mut offset) {
+                            Ok(Some(items)) => {
+                                state.offset = offset;
+                                replay.extend(items);
+                                continue;
+                            }
+                            Ok(None) if !state.batch.is_empty() => {
+                                replay.extend(spill.parse(&state.batch));
+                                state.batch.clear();
+                                state.batch_items = 0;
+                                continue;
+                            }
+                            Ok(None) => {
+                                if let Err(err) = spill.remove() {
+                                    log::error!("sink {}: cannot remove {}: {}", self.name, spill.path.display(), err);
+                                }
+                                *state = SpillState::default();
+                                continue;
+                            }
+                            // Stays active, so new items keep queueing behind
+                            // the unread ones; retried after a pause.
+                            Err(err) => {
+                                log::error!("sink {}: cannot replay {}: {}", self.name, spill.path.display(), err);
+                                None
+                            }
+                        }
+                    } else {
+                        Some(closed)
+                    }
+                };
+                let Some(closed) = closed else {
+                    time::sleep(REPLAY_RETRY).await;
+                    continue;
+                };
+                if closed {
+                    return None;
+                }
+                match rx.recv().await {
+                    Some(item) => return Some(item),
+                    None => continue,
+                }
+            },
+        }
+    }
+}
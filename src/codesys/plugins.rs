@@ -0,0 +1,238 @@
+//! Analytics plugins loaded from dynamic libraries.
+//!
+//! A plugin sees every sample and may emit derived values (stored in the
+//! `plugin_data` table) and alarm conditions (raised like built-in alarms,
+//! with the rule prefixed by the plugin name). This lets customers add
+//! proprietary analytics such as pump cavitation detection without
+//! modifying rustsys.
+//!
+//! Plugins are `cdylib` crates that depend on the `codesys` library and export a
+//! constructor with `declare_plugin!`. The Rust ABI is not stable, so a
+//! plugin must be built with the same compiler and rustsys version as the
+//! monitor loading it.
+//!
+//! ```ignore
+//! struct Cavitation { limit: f64 }
+//!
+//! impl Plugin for Cavitation {
+//!     fn name(&self) -> &str {
+//!         "cavitation"
+//!     }
+//!
+//!     fn process(&mut self, data: &MotorData) -> Vec<Output> {
+//!         let index = data.current_power / data.current_speed.max(1.0);
+//!         vec![
+//!             Output::Value { name: "index".into(), value: index },
+//!             Output::Alarm {
+//!                 rule: "detected".into(),
+//!                 severity: Severity::Warning,
+//!                 active: index > self.limit,
+//!                 message: format!("cavitation index {:.2}", index),
+//!             },
+//!         ]
+//!     }
+//! }
+//!
+//! codesys::declare_plugin!(|settings: &Settings| {
+//!     let limit = settings.get("limit").and_then(|v| v.as_float()).unwrap_or(0.5);
+//!     Ok(Box::new(Cavitation { limit }) as Box<dyn Plugin>)
+//! });
+//! ```
+
+use super::alarms::{Severity, SustainedAlarm};
+use super::config::PluginConfig;
+use super::MotorData;
+use libloading::Library;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+
+/// The `settings` table of the plugin's `[[plugins]]` entry.
+pub type Settings = toml::Table;
+
+/// Signature of the `rustsys_plugin_create` symbol exported by a plugin.
+pub type CreateFn = fn(&Settings) -> Result<Box<dyn Plugin>, String>;
+
+const CREATE_SYMBOL: &[u8] = b"rustsys_plugin_create";
+
+pub trait Plugin: Send {
+    /// Short snake_case name; prefixes the plugin's alarm rules.
+    fn name(&self) -> &str;
+
+    /// Called for every sample, oldest first.
+    fn process(&mut self, data: &MotorData) -> Vec<Output>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// A derived value stored at the sample's timestamp.
+    Value { name: String, value: f64 },
+    /// The current state of an alarm condition. The alarm is raised when
+    /// `active` becomes true and cleared when it turns false again.
+    Alarm { rule: String, severity: Severity, active: bool, message: String },
+}
+
+/// Exports `$create` (a `CreateFn`) as the plugin constructor.
+#[macro_export]
+macro_rules! declare_plugin {
+    ($create:expr) => {
+        #[no_mangle]
+        pub fn rustsys_plugin_create(
+            settings: &$crate::codesys::plugins::Settings,
+        ) -> Result<Box<dyn $crate::codesys::plugins::Plugin>, String> {
+            let create: $crate::codesys::plugins::CreateFn = $create;
+            create(settings)
+        }
+    };
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginValue {
+    /// Epoch milliseconds of the sample the value was derived from.
+    pub timestamp: i64,
+    pub value: f64,
+}
+
+pub async fn setup_plugin_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS plugin_data (
+            timestamp INTEGER NOT NULL,
+            plugin TEXT NOT NULL,
+            name TEXT NOT NULL,
+            value REAL NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query("CREATE INDEX IF NOT EXISTS plugin_data_name_timestamp ON plugin_data (plugin, name, timestamp)")
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// Values of `plugin`'s `name` with `from <= timestamp < to` (milliseconds), oldest first.
+pub async fn list_values(pool: &SqlitePool, plugin: &str, name: &str, from: i64, to: i64) -> Vec<PluginValue> {
+    let rows = sqlx::query(
+        r#"
+        SELECT timestamp, value FROM plugin_data
+        WHERE plugin = ? AND name = ? AND timestamp >= ? AND timestamp < ?
+        ORDER BY timestamp
+        "#,
+    )
+    .bind(plugin)
+    .bind(name)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    rows.iter().map(|row| PluginValue { timestamp: row.get("timestamp"), value: row.get("value") }).collect()
+}
+
+struct Loaded {
+    // Dropped before `_library`, which holds the plugin's code.
+    plugin: Box<dyn Plugin>,
+    alarms: HashMap<String, SustainedAlarm>,
+    _library: Library,
+}
+
+/// The loaded plugins, fed from the polling loop.
+#[derive(Default)]
+pub struct PluginHost {
+    plugins: Vec<Loaded>,
+}
+
+fn load(config: &PluginConfig) -> Result<Loaded, String> {
+    // SAFETY: loading runs the library's initialisers and trusts the exported
+    // constructor to have the `CreateFn` signature; plugins are configured by
+    // the operator and must be built against this rustsys version.
+    unsafe {
+        let library = Library::new(&config.path).map_err(|err| err.to_string())?;
+        let create = *library.get::<CreateFn>(CREATE_SYMBOL).map_err(|err| err.to_string())?;
+        let plugin = create(&config.settings)?;
+        Ok(Loaded { plugin, alarms: HashMap::new(), _library: library })
+    }
+}
+
+impl PluginHost {
+    /// Loads every configured plugin. A plugin that fails to load is logged
+    /// and skipped so the monitor keeps running without it.
+    pub fn load(configs: &[PluginConfig]) -> Self {
+        let mut plugins = Vec::new();
+        for config in configs {
+            match load(config) {
+                Ok(loaded) => {
+                    log::info!("loaded plugin {} from {}", loaded.plugin.name(), config.path.display());
+                    plugins.push(loaded);
+                }
+                Err(err) => log::error!("cannot load plugin {}: {}", config.path.display(), err),
+            }
+        }
+        PluginHost { plugins }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    /// Runs every plugin on `data`, storing derived values and updating
+    /// plugin alarms. Returns `(alarm id, rule)` for each alarm raised. A
+    /// plugin that panics is unloaded.
+    pub async fn process(&mut self, pool: &SqlitePool, motor_id: &str, data: &MotorData) -> Vec<(i64, String)> {
+        let mut raised = Vec::new();
+        let mut failed = Vec::new();
+        for (index, loaded) in self.plugins.iter_mut().enumerate() {
+            let plugin = &mut loaded.plugin;
+            let outputs = match panic::catch_unwind(AssertUnwindSafe(|| plugin.process(data))) {
+                Ok(outputs) => outputs,
+                Err(_) => {
+                    log::error!("plugin {} panicked and was unloaded", plugin.name());
+                    failed.push(index);
+                    continue;
+                }
+            };
+            let name = plugin.name().to_string();
+
+            if outputs.iter().any(|output| matches!(output, Output::Value { .. })) {
+                let mut tx = pool.begin().await.unwrap();
+                for output in &outputs {
+                    if let Output::Value { name: value_name, value } = output {
+                        sqlx::query("INSERT INTO plugin_data (timestamp, plugin, name, value) VALUES (?, ?, ?, ?)")
+                            .bind(data.timestamp)
+                            .bind(&name)
+                            .bind(value_name)
+                            .bind(value)
+                            .execute(&mut tx)
+                            .await
+                            .unwrap();
+                    }
+                }
+                tx.commit().await.unwrap();
+            }
+
+            for output in outputs {
+                let Output::Alarm { rule, severity, active, message } = output else {
+                    continue;
+                };
+                let rule = format!("{}_{}", name, rule);
+                let alarm = loaded
+                    .alarms
+                    .entry(rule.clone())
+                    .or_insert_with(|| SustainedAlarm::new(&rule, None, severity, 0));
+                if let Some(id) = alarm.update(pool, motor_id, active, data.seconds(), || message).await {
+                    raised.push((id, rule));
+                }
+            }
+        }
+        for index in failed.into_iter().rev() {
+            self.plugins.remove(index);
+        }
+        raised
+    }
+}
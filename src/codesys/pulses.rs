@@ -0,0 +1,96 @@
+//! Pulse and encoder inputs of auxiliary Modbus IO modules.
+//!
+//! `[registers.pulses.<name>]` lists pulse counters, e.g. of a conveyor
+//! encoder or a flow meter wired to an IO module on the RTU bus at its own
+//! `unit_id`. They are read with every motor poll. The increase since the
+//! previous read, taking a wrap into account as for `counters`, over the
+//! time between the two reads is the pulse rate in Hz; times `scale` it is
+//! the value in engineering units, e.g. m/min of line speed.
+//!
+//! Rates ride along with the sample (`MotorData::pulses`), are stored in
+//! `pulse_data` next to the temperature channels and archived with the
+//! samples, and can be named in alarm rules, `precision` and
+//! `registers.units` like any other value. The first read of a counter, a
+//! counter that cannot be read and one that was reset give no rate for that
+//! sample.
+
+use super::config::PulseInput;
+use super::counters::{counter_step, Step};
+use super::transport::Transport;
+use super::MotorData;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+
+pub async fn setup_pulse_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS pulse_data (
+            timestamp INTEGER NOT NULL,
+            channel TEXT NOT NULL,
+            value REAL NOT NULL,
+            site TEXT NOT NULL DEFAULT ''
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query("CREATE INDEX IF NOT EXISTS pulse_data_timestamp ON pulse_data (timestamp)")
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// Rate in Hz of `pulses` counted over `dt_ms`; `None` without elapsed time.
+pub fn pulse_rate(pulses: u64, dt_ms: i64) -> Option<f64> {
+    (dt_ms > 0).then(|| pulses as f64 * 1000.0 / dt_ms as f64)
+}
+
+/// The polling loop's side: reads the counters and derives their rates.
+pub struct PulseReader {
+    inputs: BTreeMap<String, PulseInput>,
+    /// Raw value and time (ms) of each counter's previous read.
+    previous: HashMap<String, (u64, i64)>,
+}
+
+impl PulseReader {
+    pub fn new(inputs: BTreeMap<String, PulseInput>) -> Self {
+        PulseReader { inputs, previous: HashMap::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Reads every counter and adds its rate to `data.pulses`.
+    pub async fn read(&mut self, transport: &mut dyn Transport, data: &mut MotorData) {
+        for (name, input) in &self.inputs {
+            let register = input.register();
+            let read = match input.unit_id {
+                Some(unit_id) => transport.read_unit(unit_id, &register).await,
+                None => transport.read(&register).await,
+            };
+            let raw = match read {
+                Ok(raw) => raw as u64,
+                Err(err) => {
+                    log::warn!("cannot read pulse input {}: {}", name, err);
+                    self.previous.remove(name);
+                    continue;
+                }
+            };
+            let Some((previous, at)) = self.previous.insert(name.clone(), (raw, data.timestamp)) else {
+                continue;
+            };
+            let pulses = match counter_step(previous, raw, input.bits(), input.max_step()) {
+                Step::Up(n) | Step::Wrapped(n) => n,
+                Step::Reset(_) => {
+                    log::warn!("pulse input {} dropped from {} to {}, counting on from there", name, previous, raw);
+                    continue;
+                }
+            };
+            if let Some(hz) = pulse_rate(pulses, data.timestamp - at) {
+                data.pulses.insert(name.clone(), hz * input.scale);
+            }
+        }
+    }
+}
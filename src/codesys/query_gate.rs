@@ -0,0 +1,139 @@
+//! Gate between API reads and the database.
+//!
+//! SQLite has one writer, and the poll loop inserts a sample every
+//! `poll_interval_ms`. Dashboards that refresh many panels at once, or a
+//! client stuck in a retry loop, would otherwise take every connection of
+//! the pool the inserts go through. So the HTTP API, GraphQL and Arrow Flight
+//! read through a pool of their own with `api.queries.connections`
+//! read-only connections; the database is in WAL mode, so these readers never
+//! block the writer. An in-memory database cannot be opened twice and
+//! shares the monitor's pool.
+//!
+//! Each client (by IP address) may make `requests_per_minute` requests,
+//! plus a `burst` at once; beyond that the request is answered with
+//! `429 Too Many Requests` and `Retry-After`. The latest sample, which every
+//! dashboard asks for on every refresh, is read at most once per
+//! `latest_cache_ms` and answered from memory in between; concurrent
+//! requests for it wait for the same read.
+
+use super::config::QueryGateConfig;
+use super::site;
+use super::{raw_motor_data_range, MotorData};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Clients remembered before those with a full allowance are forgotten.
+const MAX_CLIENTS: usize = 1024;
+
+/// A client's allowance.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+pub struct QueryGate {
+    pool: Arc<SqlitePool>,
+    /// Requests per second and burst; no limit when absent.
+    rate: Option<(f64, f64)>,
+    clients: Mutex<HashMap<IpAddr, Bucket>>,
+    latest_ttl: Duration,
+    latest: tokio::sync::Mutex<Option<(Instant, Option<MotorData>)>>,
+}
+
+impl QueryGate {
+    /// Opens the read pool of `database_url`; falls back to `shared`, the
+    /// monitor's pool, for an in-memory database or when it cannot be opened.
+    pub async fn open(config: &QueryGateConfig, database_url: &str, shared: &Arc<SqlitePool>) -> Self {
+        let pool = if is_in_memory(database_url) {
+            Arc::clone(shared)
+        } else {
+            match read_pool(database_url, config.connections).await {
+                Ok(pool) => Arc::new(pool),
+                Err(err) => {
+                    log::warn!("API reads share the monitor's connections: cannot open a read pool: {}", err);
+                    Arc::clone(shared)
+                }
+            }
+        };
+        QueryGate::new(config, pool)
+    }
+
+    pub fn new(config: &QueryGateConfig, pool: Arc<SqlitePool>) -> Self {
+        QueryGate {
+            pool,
+            rate: (config.requests_per_minute > 0)
+                .then(|| (config.requests_per_minute as f64 / 60.0, config.burst as f64)),
+            clients: Mutex::new(HashMap::new()),
+            latest_ttl: Duration::from_millis(config.latest_cache_ms),
+            latest: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// The pool API reads go through.
+    pub fn pool(&self) -> &Arc<SqlitePool> {
+        &self.pool
+    }
+
+    /// Takes one request from `client`'s allowance; when it is used up,
+    /// returns how long until the next request is allowed.
+    pub fn admit(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let Some((per_sec, burst)) = self.rate else {
+            return Ok(());
+        };
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_CLIENTS {
+            clients.retain(|_, bucket| refill(bucket, now, per_sec, burst) < burst);
+        }
+        let bucket = clients.entry(client).or_insert(Bucket { tokens: burst, updated: now });
+        if refill(bucket, now, per_sec, burst) >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+
+    /// The latest stored sample, read at most once per `latest_cache_ms`.
+    pub async fn latest(&self) -> Option<MotorData> {
+        let mut cached = self.latest.lock().await;
+        if let Some((read_at, sample)) = &*cached {
+            if read_at.elapsed() < self.latest_ttl {
+                return sample.clone();
+            }
+        }
+        let sample = latest_sample(&self.pool).await;
+        *cached = Some((Instant::now(), sample.clone()));
+        sample
+    }
+}
+
+/// Tops `bucket` up for the time passed since it was last updated; returns
+/// its tokens.
+fn refill(bucket: &mut Bucket, now: Instant, per_sec: f64, burst: f64) -> f64 {
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * per_sec).min(burst);
+    bucket.updated = now;
+    bucket.tokens
+}
+
+fn is_in_memory(database_url: &str) -> bool {
+    database_url.contains(":memory:") || database_url.contains("mode=memory")
+}
+
+async fn read_pool(database_url: &str, connections: u32) -> Result<SqlitePool, sqlx::Error> {
+    let options = database_url.parse::<SqliteConnectOptions>()?.read_only(true);
+    SqlitePoolOptions::new().max_connections(connections).connect_with(options).await
+}
+
+/// Samples still in `motor_data` only; the newest are never archived.
+async fn latest_sample(pool: &SqlitePool) -> Option<MotorData> {
+    let query = format!("SELECT MAX(timestamp) FROM motor_data WHERE {}", site::SAMPLES);
+    let (timestamp,): (Option<i64>,) = sqlx::query_as(&query).fetch_one(pool).await.ok()?;
+    let timestamp = timestamp?;
+    raw_motor_data_range(pool, timestamp, timestamp + 1, Some(1)).await.pop()
+}
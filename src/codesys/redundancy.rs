@@ -0,0 +1,167 @@
+//! Active/standby redundancy between two monitors, without a broker.
+//!
+//! Both nodes exchange UDP heartbeats. Only the active node opens the bus and
+//! polls; it forwards every sample to its peer, which stores the mirrored
+//! samples in its own database so it can take over seamlessly.
+//!
+//! Failover rules:
+//! - the `primary` becomes active once it has waited one failover period and
+//!   the peer is not active;
+//! - the `standby` becomes active only when heartbeats stop arriving;
+//! - if both ever end up active (e.g. after a network partition heals), the
+//!   standby steps down.
+//!
+//! A node that took over keeps the role when the other comes back, to avoid
+//! flapping. Samples at or before the last timestamp seen from the peer are
+//! dropped, so a handover never writes the same second twice.
+
+use super::config::{RedundancyConfig, RedundancyRole};
+use super::MotorData;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::time;
+
+#[derive(Debug, Serialize, Deserialize)]
+enum PeerMessage {
+    Heartbeat { node: String, active: bool, last_sample: Option<i64> },
+    Sample(MotorData),
+}
+
+#[derive(Debug, Default)]
+struct PeerState {
+    last_seen: Option<Instant>,
+    active: bool,
+    /// Newest sample timestamp written by either node.
+    last_sample: Option<i64>,
+    mirrored: Vec<MotorData>,
+}
+
+pub struct Redundancy {
+    config: RedundancyConfig,
+    socket: UdpSocket,
+    active: AtomicBool,
+    peer: Mutex<PeerState>,
+    started: Instant,
+}
+
+impl Redundancy {
+    /// Binds the heartbeat socket and starts the receive and heartbeat tasks.
+    pub async fn start(config: RedundancyConfig) -> std::io::Result<Arc<Redundancy>> {
+        let socket = UdpSocket::bind(config.listen).await?;
+        let node = Arc::new(Redundancy {
+            config,
+            socket,
+            active: AtomicBool::new(false),
+            peer: Mutex::new(PeerState::default()),
+            started: Instant::now(),
+        });
+        tokio::spawn(Arc::clone(&node).receive_loop());
+        tokio::spawn(Arc::clone(&node).heartbeat_loop());
+        Ok(node)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// Samples received from the active peer since the last call.
+    pub fn take_mirrored(&self) -> Vec<MotorData> {
+        std::mem::take(&mut self.peer.lock().unwrap().mirrored)
+    }
+
+    /// Records a locally polled sample and forwards it to the peer. Returns
+    /// false if the sample overlaps data the peer already produced.
+    pub async fn publish(&self, data: &MotorData) -> bool {
+        {
+            let mut peer = self.peer.lock().unwrap();
+            if peer.last_sample.is_some_and(|last| data.timestamp <= last) {
+                return false;
+            }
+            peer.last_sample = Some(data.timestamp);
+        }
+        self.send(&PeerMessage::Sample(data.clone())).await;
+        true
+    }
+
+    async fn send(&self, message: &PeerMessage) {
+        let payload = serde_json::to_vec(message).unwrap();
+        if let Err(err) = self.socket.send_to(&payload, self.config.peer).await {
+            log::debug!("redundancy: cannot reach peer {}: {}", self.config.peer, err);
+        }
+    }
+
+    async fn receive_loop(self: Arc<Self>) {
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(err) => {
+                    log::warn!("redundancy: receive failed: {}", err);
+                    continue;
+                }
+            };
+            if from.ip() != self.config.peer.ip() {
+                continue;
+            }
+            let Ok(message) = serde_json::from_slice::<PeerMessage>(&buf[..len]) else {
+                log::warn!("redundancy: ignoring malformed message from {}", from);
+                continue;
+            };
+
+            let mut peer = self.peer.lock().unwrap();
+            peer.last_seen = Some(Instant::now());
+            match message {
+                PeerMessage::Heartbeat { active, last_sample, .. } => {
+                    peer.active = active;
+                    peer.last_sample = peer.last_sample.max(last_sample);
+                }
+                PeerMessage::Sample(data) => {
+                    peer.active = true;
+                    let fresh = peer.last_sample.is_none_or(|last| data.timestamp > last);
+                    if fresh && !self.is_active() {
+                        peer.last_sample = Some(data.timestamp);
+                        peer.mirrored.push(data);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn heartbeat_loop(self: Arc<Self>) {
+        let failover = Duration::from_millis(self.config.failover_ms);
+        let mut interval = time::interval(Duration::from_millis(self.config.heartbeat_ms));
+        loop {
+            interval.tick().await;
+
+            let (peer_alive, peer_active, last_sample) = {
+                let peer = self.peer.lock().unwrap();
+                let alive = peer.last_seen.is_some_and(|seen| seen.elapsed() < failover);
+                (alive, alive && peer.active, peer.last_sample)
+            };
+            let active = self.is_active();
+            let warmed_up = self.started.elapsed() >= failover;
+
+            let promote = match self.config.role {
+                RedundancyRole::Primary => warmed_up && !peer_active,
+                RedundancyRole::Standby => warmed_up && !peer_alive,
+            };
+            if !active && promote {
+                log::warn!("redundancy: peer {} not active, taking over polling", self.config.peer);
+                self.active.store(true, Ordering::SeqCst);
+            } else if active && peer_active && self.config.role == RedundancyRole::Standby {
+                log::warn!("redundancy: both nodes active, standby stepping down");
+                self.active.store(false, Ordering::SeqCst);
+            }
+
+            let message = PeerMessage::Heartbeat {
+                node: self.config.node_name.clone(),
+                active: self.is_active(),
+                last_sample,
+            };
+            self.send(&message).await;
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! Periodic Markdown reports.
+//!
+//! Every `report.interval_hours` the scheduler writes a report covering the
+//! period that just ended to `report.path_template`. Each analysis
+//! contributes one `Section`. With `[trends]` configured, a section on
+//! long-term drift and the remaining useful life is added; with
+//! `[correlation]`, one on cross-signal correlation, whose lag plots are
+//! written next to the report; with `[environment]`, one on ambient
+//! conditions and the motor's temperature rise above ambient. Availability
+//! and the other OEE-style KPIs (see `oee`) are always included. Every report
+//! ends with its lineage (see `lineage`).
+//!
+//! Periods are counted from midnight in the motor's time zone (see
+//! `location`), so a daily report covers the motor's own day.
+
+use super::analytics::{self, format_duration};
+use super::config::{EnvironmentConfig, ReportConfig};
+use super::disk;
+use super::correlation::{self, CorrelationSettings, LagCurve};
+use super::environment;
+use super::lineage::{self, Stamp};
+use super::location::{Location, Zone};
+use super::notes;
+use super::oee::{self, percent, KpiSettings};
+use super::output::{chart_text, ensure_parent, render_path, OutputContext};
+use super::trends::{self, TrendSettings};
+use chrono::prelude::*;
+use sqlx::sqlite::SqlitePool;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub heading: String,
+    /// Markdown body.
+    pub body: String,
+    /// Lag plots, linked below the body.
+    pub charts: Vec<LagCurve>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub motor_id: String,
+    pub from: i64,
+    pub to: i64,
+    pub sections: Vec<Section>,
+    pub lineage: Stamp,
+    pub location: Location,
+}
+
+/// Builds the report for `[from, to)`; trends are fitted over the window
+/// ending at `to`.
+#[allow(clippy::too_many_arguments)]
+pub async fn generate_report(
+    pool: &SqlitePool,
+    motor_id: &str,
+    from: i64,
+    to: i64,
+    trends: Option<&TrendSettings>,
+    correlation: Option<&CorrelationSettings>,
+    environment: Option<&EnvironmentConfig>,
+    kpis: &KpiSettings,
+    lineage: &lineage::Source,
+    location: &Location,
+) -> Report {
+    let mut sections = vec![
+        alarm_reliability_section(pool, from, to).await,
+        kpi_section(pool, kpis, from, to).await,
+        notes_section(pool, motor_id, location.zone, from, to).await,
+    ];
+    if let Some(settings) = trends {
+        sections.push(trends_section(pool, settings, to).await);
+    }
+    if let Some(settings) = correlation {
+        sections.push(correlation_section(pool, settings, from, to).await);
+    }
+    if let Some(config) = environment {
+        sections.push(environment_section(pool, config, location.zone, from, to).await);
+    }
+    let lineage = Stamp::new(lineage, from * 1000, to * 1000);
+    Report { motor_id: motor_id.to_string(), from, to, sections, lineage, location: location.clone() }
+}
+
+async fn alarm_reliability_section(pool: &SqlitePool, from: i64, to: i64) -> Section {
+    let stats = analytics::alarm_frequency(pool, from, to).await;
+    let mut body = String::new();
+    if stats.is_empty() {
+        body.push_str("No alarms were raised in this period.\n");
+    } else {
+        body.push_str("| Rank | Rule | Alarms | Mean time between | Avg. clear time | Still active |\n");
+        body.push_str("|---:|---|---:|---:|---:|---:|\n");
+        for (rank, rule) in stats.iter().enumerate() {
+            writeln!(
+                body,
+                "| {} | {} | {} | {} | {} | {} |",
+                rank + 1,
+                rule.rule,
+                rule.count,
+                rule.mean_time_between_secs.map_or("–".to_string(), format_duration),
+                rule.avg_clear_secs.map_or("–".to_string(), format_duration),
+                rule.active
+            )
+            .unwrap();
+        }
+    }
+    Section { heading: "Alarm reliability".to_string(), body, charts: Vec::new() }
+}
+
+async fn kpi_section(pool: &SqlitePool, settings: &KpiSettings, from: i64, to: i64) -> Section {
+    let kpis = oee::kpis(pool, settings, from * 1000, to * 1000).await;
+    let mut body = String::new();
+    body.push_str("| KPI | Value |\n");
+    body.push_str("|---|---:|\n");
+    writeln!(body, "| Scheduled time | {} |", format_duration(kpis.scheduled_secs)).unwrap();
+    writeln!(body, "| Run time (scheduled) | {} |", format_duration(kpis.run_secs)).unwrap();
+    writeln!(body, "| Availability | {} |", percent(kpis.availability())).unwrap();
+    writeln!(body, "| Performance (speed vs. rated) | {} |", percent(kpis.performance())).unwrap();
+    writeln!(body, "| Quality (run time without alarms) | {} |", percent(kpis.quality())).unwrap();
+    writeln!(body, "| OEE | {} |", percent(kpis.oee())).unwrap();
+    writeln!(body, "| Quality events (alarms) | {} |", kpis.quality_events).unwrap();
+    Section { heading: "Availability".to_string(), body, charts: Vec::new() }
+}
+
+async fn notes_section(pool: &SqlitePool, motor_id: &str, zone: Zone, from: i64, to: i64) -> Section {
+    let notes = notes::list_notes(pool, motor_id, from, to).await;
+    let mut body = String::new();
+    if notes.is_empty() {
+        body.push_str("No operator notes in this period.\n");
+    } else {
+        body.push_str("| Time | Author | Note |\n");
+        body.push_str("|---|---|---|\n");
+        for note in &notes {
+            let text = note.text.replace('|', "\\|").replace('\n', " ");
+            writeln!(body, "| {} | {} | {} |", format_timestamp(zone, note.timestamp), note.author, text).unwrap();
+        }
+    }
+    Section { heading: "Operator notes".to_string(), body, charts: Vec::new() }
+}
+
+async fn trends_section(pool: &SqlitePool, settings: &TrendSettings, to: i64) -> Section {
+    let trends = trends::analyze(pool, settings, to).await;
+    let number = |value: Option<f64>, decimals: usize| value.map_or("–".to_string(), |v| format!("{:.*}", decimals, v));
+    let mut body = String::new();
+    writeln!(body, "Fitted over the last {} days.\n", settings.config.window_days).unwrap();
+    body.push_str("| Trend | Days | Current | Drift per day | Threshold | Days to threshold |\n");
+    body.push_str("|---|---:|---:|---:|---:|---:|\n");
+    for trend in &trends {
+        let name = match trend.unit {
+            "" => trend.name.to_string(),
+            unit => format!("{} ({})", trend.name, unit),
+        };
+        writeln!(
+            body,
+            "| {} | {} | {} | {} | {} | {} |",
+            name,
+            trend.points,
+            number(trend.current(), 3),
+            number(trend.slope_per_day(), 4),
+            number(trend.threshold, 3),
+            number(trend.days_to_threshold(), 0)
+        )
+        .unwrap();
+    }
+    body.push('\n');
+    match trends::remaining_useful_life(&trends) {
+        Some((days, trend)) => writeln!(
+            body,
+            "Estimated remaining useful life: **{:.0} days**, limited by {}.",
+            days,
+            trend.name.to_lowercase()
+        )
+        .unwrap(),
+        None => body.push_str("No trend is heading towards its threshold; no remaining useful life estimate.\n"),
+    }
+    Section { heading: "Trends and remaining useful life".to_string(), body, charts: Vec::new() }
+}
+
+async fn correlation_section(pool: &SqlitePool, settings: &CorrelationSettings, from: i64, to: i64) -> Section {
+    let correlation = correlation::analyze(pool, settings, from, to).await;
+    let number = |value: Option<f64>| value.map_or("–".to_string(), |v| format!("{:.2}", v));
+    let mut body = String::new();
+    writeln!(
+        body,
+        "Pearson correlation of {} s means; – where a signal did not vary.\n",
+        settings.config.bucket_secs
+    )
+    .unwrap();
+    writeln!(body, "| | {} |", correlation.names.join(" | ")).unwrap();
+    writeln!(body, "|---|{}", "---:|".repeat(correlation.names.len())).unwrap();
+    for (name, row) in correlation.names.iter().zip(&correlation.matrix) {
+        let cells: Vec<String> = row.iter().map(|&r| number(r)).collect();
+        writeln!(body, "| {} | {} |", name, cells.join(" | ")).unwrap();
+    }
+    if !correlation.lags.is_empty() {
+        writeln!(
+            body,
+            "\nStrongest correlation within ±{} min; at a positive lag the follower trails the leader.\n",
+            settings.config.max_lag_minutes
+        )
+        .unwrap();
+        body.push_str("| Leader | Follower | Correlation | Lag (min) |\n");
+        body.push_str("|---|---|---:|---:|\n");
+        for curve in &correlation.lags {
+            let peak = curve.peak();
+            writeln!(
+                body,
+                "| {} | {} | {} | {} |",
+                curve.leader,
+                curve.follower,
+                number(peak.map(|p| p.1)),
+                peak.map_or("–".to_string(), |p| format!("{:.0}", p.0))
+            )
+            .unwrap();
+        }
+    }
+    Section { heading: "Signal correlation".to_string(), body, charts: correlation.lags }
+}
+
+async fn environment_section(pool: &SqlitePool, config: &EnvironmentConfig, zone: Zone, from: i64, to: i64) -> Section {
+    let summaries = environment::sensor_summaries(pool, from, to).await;
+    let mut body = String::new();
+    if summaries.is_empty() {
+        body.push_str("No environment readings in this period.\n");
+    } else {
+        body.push_str("| Sensor | Quantity | Readings | Min | Mean | Max |\n");
+        body.push_str("|---|---|---:|---:|---:|---:|\n");
+        for s in &summaries {
+            let unit = config.sensors.get(&s.sensor).map_or("", |sensor| sensor.quantity.unit());
+            writeln!(
+                body,
+                "| {} | {} | {} | {:.1} {unit} | {:.1} {unit} | {:.1} {unit} |",
+                s.sensor,
+                s.quantity,
+                s.readings,
+                s.min,
+                s.mean,
+                s.max,
+                unit = unit
+            )
+            .unwrap();
+        }
+    }
+    if let Some(ambient) = config.ambient_sensor() {
+        let rise = environment::hourly_rise(pool, ambient, from, to).await;
+        body.push('\n');
+        if rise.is_empty() {
+            writeln!(body, "No hour has both heat statistics and {} readings; no temperature rise.", ambient).unwrap();
+        } else {
+            let mean = rise.iter().map(|(_, r)| r).sum::<f64>() / rise.len() as f64;
+            let (hour, max) = rise.iter().copied().fold((0, f64::NEG_INFINITY), |a, b| if b.1 > a.1 { b } else { a });
+            writeln!(
+                body,
+                "Temperature rise above {}: mean **{:.1} K** over {} hours, highest {:.1} K in the hour from {}.",
+                ambient,
+                mean,
+                rise.len(),
+                max,
+                format_timestamp(zone, hour)
+            )
+            .unwrap();
+        }
+    }
+    Section { heading: "Environment".to_string(), body, charts: Vec::new() }
+}
+
+/// File name of a lag plot of the report whose file stem is `stem`.
+fn chart_file_name(stem: &str, curve: &LagCurve) -> String {
+    format!("{}_{}.png", stem, curve.name())
+}
+
+/// Markdown of `report`; charts are linked as written by `write_report`
+/// for a report file with stem `stem`.
+pub fn render_markdown(report: &Report, stem: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "# Motor report: {}\n", report.motor_id).unwrap();
+    if let Some(label) = report.location.label() {
+        writeln!(out, "Location: {}\n", label).unwrap();
+    }
+    let zone = report.location.zone;
+    writeln!(out, "Period: {} – {}\n", format_timestamp(zone, report.from), format_timestamp(zone, report.to)).unwrap();
+    for section in &report.sections {
+        writeln!(out, "## {}\n", section.heading).unwrap();
+        out.push_str(&section.body);
+        out.push('\n');
+        for curve in &section.charts {
+            let file = chart_file_name(stem, curve);
+            writeln!(out, "![{} following {}]({})\n", curve.follower, curve.leader, file).unwrap();
+        }
+    }
+    out.push_str("## Lineage\n\n");
+    out.push_str(&report.lineage.markdown());
+    out
+}
+
+fn format_timestamp(zone: Zone, timestamp: i64) -> String {
+    zone.at_secs(timestamp).map_or_else(|| timestamp.to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string())
+}
+
+/// Writes `report` to the path rendered from `path_template` and returns it.
+/// Charts go next to it; one that cannot be drawn is logged and skipped.
+pub fn write_report(report: &Report, path_template: &str, space: &disk::Space) -> std::io::Result<PathBuf> {
+    let ctx = OutputContext { motor: &report.motor_id, signal: "", timestamp: report.to };
+    let path = render_path(path_template, &ctx);
+    ensure_parent(&path, space)?;
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let text = chart_text(&report.motor_id, report.from * 1000, report.to * 1000);
+    for curve in report.sections.iter().flat_map(|s| &s.charts) {
+        let chart = dir.join(chart_file_name(&stem, curve));
+        if let Err(err) = correlation::draw_lag_plot(&chart, curve, &text) {
+            log::warn!("cannot draw {}: {}", chart.display(), err);
+        }
+    }
+    fs::write(&path, render_markdown(report, &stem))?;
+    Ok(path)
+}
+
+/// Generates a report at the end of every reporting period, forever.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_report_scheduler(
+    pool: Arc<SqlitePool>,
+    motor_id: String,
+    config: ReportConfig,
+    trends: Option<TrendSettings>,
+    correlation: Option<CorrelationSettings>,
+    environment: Option<EnvironmentConfig>,
+    kpis: KpiSettings,
+    lineage: lineage::Source,
+    location: Location,
+    space: disk::Space,
+) {
+    let period = config.interval_hours as i64 * 3600;
+    loop {
+        let now = Utc::now().timestamp();
+        let local = now + location.zone.offset_secs(now);
+        let next = now - local.rem_euclid(period) + period;
+        time::sleep(Duration::from_secs((next - now) as u64)).await;
+
+        let report = generate_report(
+            &pool,
+            &motor_id,
+            next - period,
+            next,
+            trends.as_ref(),
+            correlation.as_ref(),
+            environment.as_ref(),
+            &kpis,
+            &lineage,
+            &location,
+        )
+        .await;
+        match write_report(&report, &config.path_template, &space) {
+            Ok(path) => log::info!("wrote report {}", path.display()),
+            Err(err) => log::error!("cannot write report: {}", err),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Rules engine: every threshold alarm evaluated against each sample.
+//!
+//! A rule is a condition over the sample's values (comparisons of a signal,
+//! `speed_reference` or temperature channel with a value, combined with
+//! `all` / `any`), how long it must hold, a severity, a message template
+//! and the actions taken when it raises its alarm. Rules come from three
+//! places, all evaluated the same way:
+//!
+//! - the signal limits (`limits`), named `<signal>_high_<severity>`;
+//! - the temperature channel thresholds, named `temperature_<name>_<severity>`;
+//! - `[[alarm_rules]]` in the configuration, e.g.
+//!
+//! ```toml
+//! [[alarm_rules]]
+//! name = "hot_at_low_load"
+//! condition = { all = [
+//!     { signal = "heat", op = ">", value = 85.0 },
+//!     { signal = "power", op = "<", value = 1.0 },
+//! ] }
+//! duration_secs = 60
+//! message = "heat {heat} °C at only {power} kW"
+//! ```
+//!
+//! A comparison with a value missing from the sample (a temperature channel
+//! that was not read) is unknown. `all` is false if any part is false and
+//! `any` true if any part is true; otherwise an unknown part makes the whole
+//! condition unknown, and the rule keeps its state until the next sample.
+
+use super::alarms::{Severity, SustainedAlarm};
+use super::config::{AlarmRuleConfig, Comparator, Condition, MonitorConfig, RegisterMap, RuleAction};
+use super::events;
+use super::limits;
+use super::{MotorData, Signal};
+use sqlx::sqlite::SqlitePool;
+
+/// Names of the `{...}` placeholders in `template`.
+pub fn placeholders(template: &str) -> Vec<&str> {
+    template.split('{').skip(1).filter_map(|part| part.split_once('}').map(|(name, _)| name)).collect()
+}
+
+/// Whether `condition` holds for `data`; `None` if that depends on a
+/// missing value.
+pub fn holds(condition: &Condition, data: &MotorData) -> Option<bool> {
+    let combine = |conditions: &[Condition], decisive: bool| {
+        let results: Vec<Option<bool>> = conditions.iter().map(|c| holds(c, data)).collect();
+        if results.contains(&Some(decisive)) {
+            Some(decisive)
+        } else if results.contains(&None) {
+            None
+        } else {
+            Some(!decisive)
+        }
+    };
+    match condition {
+        Condition::All { all } => combine(all, false),
+        Condition::Any { any } => combine(any, true),
+        Condition::Compare { signal, op, value } => data.named_value(signal).map(|v| op.holds(v, *value)),
+    }
+}
+
+/// `condition` with the current values, e.g. `heat 91.2 > 85 and power 0.4 < 1`.
+pub fn describe(condition: &Condition, data: &MotorData) -> String {
+    let join = |conditions: &[Condition], word: &str| {
+        let parts: Vec<String> = conditions
+            .iter()
+            .map(|c| match c {
+                Condition::Compare { .. } => describe(c, data),
+                _ => format!("({})", describe(c, data)),
+            })
+            .collect();
+        parts.join(word)
+    };
+    match condition {
+        Condition::All { all } => join(all, " and "),
+        Condition::Any { any } => join(any, " or "),
+        Condition::Compare { signal, op, value } => {
+            let current = data.named_value(signal).map_or("–".to_string(), |v| format!("{:.1}", v));
+            format!("{} {} {} {}", signal, current, op.symbol(), value)
+        }
+    }
+}
+
+/// `template` with `{rule}` and each `{<name>}` of a known value filled in.
+pub fn render_message(template: &str, rule: &str, data: &MotorData) -> String {
+    let mut message = template.to_string();
+    for name in placeholders(template) {
+        let value = match name {
+            "rule" => rule.to_string(),
+            _ => match data.named_value(name) {
+                Some(value) => format!("{:.1}", value),
+                None => continue,
+            },
+        };
+        message = message.replace(&format!("{{{}}}", name), &value);
+    }
+    message
+}
+
+/// The rules of the temperature channel thresholds.
+pub fn temperature_rules(registers: &RegisterMap) -> Vec<AlarmRuleConfig> {
+    let mut rules = Vec::new();
+    for (name, channel) in &registers.temperatures {
+        let levels = [(channel.warn, Severity::Warning), (channel.alarm, Severity::Critical)];
+        for (threshold, severity) in levels {
+            if let Some(threshold) = threshold {
+                rules.push(AlarmRuleConfig {
+                    name: format!("temperature_{}_{}", name, severity.name()),
+                    condition: Condition::Compare { signal: name.clone(), op: Comparator::Above, value: threshold },
+                    duration_secs: channel.duration_secs,
+                    severity,
+                    message: Some(format!("{} temperature {{{}}} °C above {:.1} °C", name, name, threshold)),
+                    actions: None,
+                    startup_grace_secs: channel.startup_grace_secs,
+                });
+            }
+        }
+    }
+    rules
+}
+
+#[derive(Debug)]
+struct Rule {
+    config: AlarmRuleConfig,
+    actions: Vec<RuleAction>,
+    alarm: SustainedAlarm,
+    /// Signals charted in the incident snapshot.
+    signals: Vec<Signal>,
+}
+
+/// An alarm raised by a rule.
+#[derive(Debug, Clone)]
+pub struct Raised {
+    pub id: i64,
+    pub rule: String,
+    pub signals: Vec<Signal>,
+    pub actions: Vec<RuleAction>,
+}
+
+#[derive(Debug)]
+pub struct RuleEngine {
+    rules: Vec<Rule>,
+}
+
+impl RuleEngine {
+    /// The limit, temperature and configured rules of `config`.
+    pub fn new(config: &MonitorConfig) -> Self {
+        let snapshots = config.snapshots.is_some();
+        let rules = limits::limit_rules(&config.limits, &config.motor)
+            .into_iter()
+            .chain(temperature_rules(&config.registers))
+            .chain(config.alarm_rules.iter().cloned())
+            .map(|config| {
+                let signal = match config.condition.signals().as_slice() {
+                    [signal] => Some(signal.to_string()),
+                    _ => None,
+                };
+                let alarm = SustainedAlarm::new(&config.name, signal.as_deref(), config.severity, config.duration_secs)
+                    .with_startup_grace(config.startup_grace_secs);
+                let mut signals: Vec<Signal> = Vec::new();
+                for signal in config.condition.signals().into_iter().filter_map(Signal::from_name) {
+                    if !signals.contains(&signal) {
+                        signals.push(signal);
+                    }
+                }
+                // Temperature channels are not charted; heat and load are.
+                if signals.is_empty() {
+                    signals = vec![Signal::Heat, Signal::Power];
+                }
+                Rule { actions: config.actions(snapshots), config, alarm, signals }
+            })
+            .collect();
+        RuleEngine { rules }
+    }
+
+    /// Starts the startup grace period of every rule.
+    pub fn motor_started(&mut self, at: i64) {
+        for rule in &mut self.rules {
+            rule.alarm.motor_started(at);
+        }
+    }
+
+    /// Evaluates every rule against `data`; returns the alarms raised.
+    pub async fn update(&mut self, pool: &SqlitePool, motor_id: &str, data: &MotorData) -> Vec<Raised> {
+        let mut raised = Vec::new();
+        for rule in &mut self.rules {
+            let config = &rule.config;
+            let message = || match &config.message {
+                Some(template) => render_message(template, &config.name, data),
+                None => describe(&config.condition, data),
+            };
+            let Some(condition) = holds(&config.condition, data) else {
+                continue;
+            };
+            let Some(id) = rule.alarm.update(pool, motor_id, condition, data.seconds(), message).await else {
+                continue;
+            };
+            if rule.actions.contains(&RuleAction::Event) {
+                let message = format!("{}: {}", config.name, message());
+                events::record_event(pool, motor_id, data.seconds(), "alarm_rule", &message).await;
+            }
+            raised.push(Raised {
+                id,
+                rule: config.name.clone(),
+                signals: rule.signals.clone(),
+                actions: rule.actions.clone(),
+            });
+        }
+        raised
+    }
+}
@@ -0,0 +1,99 @@
+//! Expected operating schedule.
+//!
+//! `[schedule]` lists the windows in which the motor is supposed to run,
+//! e.g. 06:00–22:00 on weekdays, in the motor's time zone (see `location`).
+//! A window whose end is not after its start runs past midnight into the
+//! next day. The motor counts as running above `running_speed_rpm`. Two
+//! alarms compare the actual with the expected state, each after
+//! `delay_secs` so start-up and shift changes don't trip them:
+//!
+//! - `schedule_not_running`: stopped during a scheduled window (a tripped
+//!   drive, a missing start command);
+//! - `schedule_unexpected_run`: running outside every window (a forgotten
+//!   stop, a manual override left on).
+
+use super::alarms::SustainedAlarm;
+use super::config::ScheduleConfig;
+use super::location::Zone;
+use super::MotorData;
+use chrono::prelude::*;
+use sqlx::sqlite::SqlitePool;
+
+/// `HH:MM` as minutes since midnight; `24:00` is accepted as an end time.
+pub fn parse_time(text: &str) -> Option<u32> {
+    let (hours, minutes) = text.split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    match (hours, minutes) {
+        (24, 0) => Some(24 * 60),
+        (0..=23, 0..=59) => Some(hours * 60 + minutes),
+        _ => None,
+    }
+}
+
+/// Day names: `mon` ... `sun` (or spelled out), case-insensitive.
+pub fn parse_day(text: &str) -> Option<Weekday> {
+    text.parse().ok()
+}
+
+/// Whether `at`, on the motor's clock, falls inside any window of `config`.
+pub fn scheduled(config: &ScheduleConfig, at: &DateTime<FixedOffset>) -> bool {
+    let minute = at.hour() * 60 + at.minute();
+    let today = at.weekday();
+    config.windows.iter().any(|window| {
+        let (Some(start), Some(end)) = (parse_time(&window.start), parse_time(&window.end)) else {
+            return false;
+        };
+        window.days.iter().filter_map(|d| parse_day(d)).any(|day| {
+            if start < end {
+                day == today && (start..end).contains(&minute)
+            } else {
+                (day == today && minute >= start) || (day.succ() == today && minute < end)
+            }
+        })
+    })
+}
+
+#[derive(Debug)]
+pub struct ScheduleMonitor {
+    config: ScheduleConfig,
+    zone: Zone,
+    not_running: SustainedAlarm,
+    unexpected_run: SustainedAlarm,
+}
+
+impl ScheduleMonitor {
+    pub fn new(config: ScheduleConfig, zone: Zone) -> Self {
+        let alarm = |rule| SustainedAlarm::new(rule, Some("speed"), config.severity, config.delay_secs);
+        ScheduleMonitor {
+            not_running: alarm("schedule_not_running"),
+            unexpected_run: alarm("schedule_unexpected_run"),
+            config,
+            zone,
+        }
+    }
+
+    /// Returns `(alarm id, rule)` for each alarm raised by `data`.
+    pub async fn update(&mut self, pool: &SqlitePool, motor_id: &str, data: &MotorData) -> Vec<(i64, String)> {
+        let Some(at) = self.zone.at_millis(data.timestamp) else {
+            return Vec::new();
+        };
+        let expected = scheduled(&self.config, &at);
+        let running = data.current_speed.abs() > self.config.running_speed_rpm;
+        let now = data.seconds();
+        let speed = data.current_speed;
+        let mut raised = Vec::new();
+        let checks = [
+            (&mut self.not_running, expected && !running, "stopped during scheduled operation"),
+            (&mut self.unexpected_run, !expected && running, "running outside scheduled operation"),
+        ];
+        for (alarm, condition, text) in checks {
+            let id = alarm
+                .update(pool, motor_id, condition, now, || format!("{} (speed {:.0} rpm)", text, speed))
+                .await;
+            if let Some(id) = id {
+                raised.push((id, alarm.rule.clone()));
+            }
+        }
+        raised
+    }
+}
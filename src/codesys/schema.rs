@@ -0,0 +1,30 @@
+//! JSON Schemas for the configuration file and the API's payloads, so HMI
+//! and integration vendors can validate what they exchange with rustsys.
+//! Served at `/schemas/<name>` and printed by `rustsys --schema <name>`.
+//!
+//! The `config` schema describes a configuration after `extends` and
+//! `[templates]` have been resolved. `alarm_rule` describes one
+//! `[[alarm_rules]]` entry, for tools that generate alarm definitions; `alarm`
+//! is an alarm as raised.
+
+use super::alarms::Alarm;
+use super::api::ExportParams;
+use super::config::{AlarmRuleConfig, MonitorConfig};
+use super::{MotorData, MotorSpecs};
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+
+pub const NAMES: [&str; 6] = ["config", "motor_data", "motor_specs", "alarm_rule", "alarm", "export_params"];
+
+pub fn schema(name: &str) -> Option<RootSchema> {
+    let schema = match name {
+        "config" => schema_for!(MonitorConfig),
+        "motor_data" => schema_for!(MotorData),
+        "motor_specs" => schema_for!(MotorSpecs),
+        "alarm_rule" => schema_for!(AlarmRuleConfig),
+        "alarm" => schema_for!(Alarm),
+        "export_params" => schema_for!(ExportParams),
+        _ => return None,
+    };
+    Some(schema)
+}
@@ -0,0 +1,136 @@
+//! Commissioning self-test (`rustsys selftest`).
+//!
+//! Opens the configured transport, reads every register of the register map
+//! once, and checks each value against a plausible range. Wrong addresses,
+//! swapped words and scaling mistakes typically read far outside these
+//! ranges (e.g. 65535 for an unmapped register). Each read is timed, so slow slaves and
+//! marginal serial links show up before the monitor goes live.
+
+use super::config::{MonitorConfig, RegisterDef};
+use super::devices::DeviceInfo;
+use super::transport::{self, Transport};
+use std::fmt::Write as _;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+/// Reads slower than this pass with a warning.
+const SLOW_READ: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+pub struct Check {
+    /// Dotted config key, e.g. `registers.speed`, or `connect`.
+    pub name: String,
+    pub address: Option<u16>,
+    pub value: Option<f64>,
+    pub range: Option<RangeInclusive<f64>>,
+    pub latency: Duration,
+    /// `None` on success.
+    pub problem: Option<String>,
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.problem.is_none()
+    }
+}
+
+/// Plausible raw values for a register key.
+fn valid_range(config: &MonitorConfig, key: &str) -> RangeInclusive<f64> {
+    let max_speed = config.motor.max_speed * 1.1;
+    match key {
+        "registers.voltage" => 0.0..=1000.0,
+        "registers.current" => 0.0..=1000.0,
+        "registers.heat" => 0.0..=200.0,
+        "registers.speed" | "registers.speed_reference" => 0.0..=max_speed,
+        "registers.speed_estimate.frequency" => 0.0..=400.0, // Hz
+        _ => 0.0..=250.0, // temperature channels, °C
+    }
+}
+
+/// Runs the self-test and returns the device identification, where the
+/// transport supports it, with the checks. Stops after the connection check
+/// if the transport cannot be opened.
+pub async fn run(config: &MonitorConfig) -> (Option<DeviceInfo>, Vec<Check>) {
+    let started = Instant::now();
+    let connected = transport::connect(config).await;
+    let mut checks = vec![Check {
+        name: "connect".to_string(),
+        address: None,
+        value: None,
+        range: None,
+        latency: started.elapsed(),
+        problem: connected.as_ref().err().map(|err| err.to_string()),
+    }];
+    let Ok(mut transport) = connected else {
+        return (None, checks);
+    };
+
+    let started = Instant::now();
+    let device = match transport.identify().await {
+        Ok(device) => device,
+        Err(err) => {
+            checks.push(Check {
+                name: "identify".to_string(),
+                address: None,
+                value: None,
+                range: None,
+                latency: started.elapsed(),
+                problem: Some(err.to_string()),
+            });
+            None
+        }
+    };
+
+    for (name, def) in config.registers.entries() {
+        let range = valid_range(config, &name);
+        checks.push(check_register(transport.as_mut(), name, def, range).await);
+    }
+    (device, checks)
+}
+
+async fn check_register(transport: &mut dyn Transport, name: String, def: RegisterDef, range: RangeInclusive<f64>) -> Check {
+    let started = Instant::now();
+    let result = transport.read(&def).await;
+    let latency = started.elapsed();
+    let (value, problem) = match result {
+        Ok(value) if !range.contains(&value) => {
+            (Some(value), Some(format!("{} outside {} – {}", value, range.start(), range.end())))
+        }
+        Ok(value) => (Some(value), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+    Check { name, address: Some(def.address), value, range: Some(range), latency, problem }
+}
+
+/// The pass/fail table printed by `rustsys selftest`.
+pub fn render_table(checks: &[Check]) -> String {
+    let mut out = String::new();
+    writeln!(out, "{:<34} {:>7} {:>10} {:>16} {:>9}  RESULT", "CHECK", "ADDRESS", "VALUE", "VALID RANGE", "LATENCY").unwrap();
+    for check in checks {
+        let address = check.address.map_or("-".to_string(), |a| a.to_string());
+        let value = check.value.map_or("-".to_string(), |v| format!("{:.1}", v));
+        let range = check.range.as_ref().map_or("-".to_string(), |r| format!("{:.0} – {:.0}", r.start(), r.end()));
+        let result = match &check.problem {
+            Some(problem) => format!("FAIL  {}", problem),
+            None if check.latency > SLOW_READ => "PASS  (slow)".to_string(),
+            None => "PASS".to_string(),
+        };
+        writeln!(
+            out,
+            "{:<34} {:>7} {:>10} {:>16} {:>6} ms  {}",
+            check.name,
+            address,
+            value,
+            range,
+            check.latency.as_millis(),
+            result
+        )
+        .unwrap();
+    }
+    let failed = checks.iter().filter(|c| !c.passed()).count();
+    match failed {
+        0 => writeln!(out, "\nall {} check(s) passed", checks.len()).unwrap(),
+        n => writeln!(out, "\n{} of {} check(s) failed", n, checks.len()).unwrap(),
+    }
+    out
+}
@@ -0,0 +1,89 @@
+//! Fault sentinels: raw register values that stand for a sensor fault.
+//!
+//! Many transmitters and drives report a broken wire or an input out of
+//! range not as a Modbus exception but as a fixed value, typically `0xFFFF`
+//! or `0x8000` (-32768 read as unsigned). Stored as read, a disconnected
+//! PT100 is a 65535 °C sample that alarms and flattens every chart of the
+//! channel. `[registers.sentinels.<name>]` lists the fault `values` of a
+//! register and what to do when it reads one:
+//!
+//! - `last_good` (default): repeat the last value that was not a sentinel.
+//! - `default`: use `default`.
+//! - `comm_fail`: treat the value as unreadable. A temperature channel or
+//!   `speed_reference` is left out of the sample; the rest of it is stored.
+//!   Voltage, current, heat and speed are part of every sample, so without
+//!   them the poll fails and the sample shows as a gap.
+//!
+//! A substituted value is listed in `MotorData::estimated`, like a soft
+//! sensor's, so it is not taken for a measurement. `last_good` without a
+//! good value yet is handled like `comm_fail`. Names are the `registers`
+//! entries (`REGISTERS`: `voltage`, `current`, `heat`, `speed`,
+//! `speed_reference`, and `frequency` for the register of `speed_estimate`)
+//! and temperature channels, which may not take one of those names. Pulse
+//! counters pass through every value on their way round and are not
+//! checked.
+
+use super::config::{SentinelAction, SentinelConfig};
+use super::transport::TransportError;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Name of the `speed_estimate` frequency register.
+pub const FREQUENCY: &str = "frequency";
+
+/// Sentinel names of the registers other than temperature channels.
+pub const REGISTERS: [&str; 6] = ["voltage", "current", "heat", "speed", "speed_reference", FREQUENCY];
+
+/// The polling loop's side: checks readings and keeps the last good ones.
+#[derive(Debug, Default)]
+pub struct SentinelFilter {
+    config: BTreeMap<String, SentinelConfig>,
+    last_good: HashMap<String, f64>,
+    /// Registers whose latest reading was a sentinel, to log only changes.
+    faulted: HashSet<String>,
+}
+
+impl SentinelFilter {
+    pub fn new(config: BTreeMap<String, SentinelConfig>) -> Self {
+        SentinelFilter { config, ..Default::default() }
+    }
+
+    /// `raw` as read from register `name`, or its substitute if it is a
+    /// fault sentinel; substituted names are added to `estimated`. Fails
+    /// the poll when there is no substitute.
+    pub fn check(&mut self, name: &str, raw: f64, estimated: &mut Vec<String>) -> Result<f64, TransportError> {
+        self.substitute(name, raw, estimated).ok_or_else(|| {
+            TransportError::Protocol(format!("{} reads the sensor fault value {:#06x}", name, raw as u32))
+        })
+    }
+
+    /// Like `check`, for a signal a sample can do without: `None` leaves it
+    /// out of the sample instead of failing the poll.
+    pub fn check_optional(&mut self, name: &str, raw: f64, estimated: &mut Vec<String>) -> Option<f64> {
+        self.substitute(name, raw, estimated)
+    }
+
+    fn substitute(&mut self, name: &str, raw: f64, estimated: &mut Vec<String>) -> Option<f64> {
+        let Some(config) = self.config.get(name) else {
+            return Some(raw);
+        };
+        if !config.values.iter().any(|&value| value as f64 == raw) {
+            if self.faulted.remove(name) {
+                log::info!("{} reads {} again after a sensor fault", name, raw);
+            }
+            self.last_good.insert(name.to_string(), raw);
+            return Some(raw);
+        }
+        if self.faulted.insert(name.to_string()) {
+            log::warn!("{} reads the sensor fault value {:#06x}; {:?}", name, raw as u32, config.action);
+        }
+        let substitute = match config.action {
+            SentinelAction::LastGood => self.last_good.get(name).copied(),
+            SentinelAction::Default => config.default,
+            SentinelAction::CommFail => None,
+        };
+        if substitute.is_some() {
+            estimated.push(name.to_string());
+        }
+        substitute
+    }
+}
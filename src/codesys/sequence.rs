@@ -0,0 +1,42 @@
+//! Per-motor sample sequence numbers.
+//!
+//! Every sample read from the field bus gets the next number of a
+//! monotonically increasing sequence, which is stored with it and carried by
+//! every stream (MQTT, exports, Arrow Flight, GraphQL). A consumer that sees
+//! a gap has lost samples; one that sees a number twice got a duplicate.
+//! After a restart the sequence continues from the highest stored number,
+//! and a standby that takes over continues after the last sample mirrored
+//! from the primary.
+
+use super::{archive, MotorData};
+use sqlx::sqlite::SqlitePool;
+
+#[derive(Debug)]
+pub struct Sequencer {
+    last: i64,
+}
+
+impl Sequencer {
+    /// Continues after the highest sequence number in the database, raw or
+    /// archived.
+    pub async fn load(pool: &SqlitePool) -> Self {
+        let (raw,): (Option<i64>,) =
+            sqlx::query_as("SELECT MAX(sequence) FROM motor_data").fetch_one(pool).await.unwrap();
+        let archived = archive::latest_sequence(pool).await;
+        Sequencer { last: raw.max(archived).unwrap_or(0) }
+    }
+
+    /// Numbers a freshly read sample.
+    pub fn assign(&mut self, data: &mut MotorData) {
+        self.last += 1;
+        data.sequence = Some(self.last);
+    }
+
+    /// Notes samples numbered by the primary, so numbering continues after
+    /// them once this node takes over.
+    pub fn observe(&mut self, samples: &[MotorData]) {
+        for sequence in samples.iter().filter_map(|d| d.sequence) {
+            self.last = self.last.max(sequence);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Serial adapters picked by USB ID instead of device path.
+//!
+//! With several USB-serial adapters plugged in, `/dev/ttyUSB0` may be a
+//! different adapter after every reboot. `[serial.usb]` names the adapter by
+//! its USB vendor and product id and, to tell identical adapters apart, its
+//! serial number; the device path is looked up every time the bus is
+//! opened, so a reconnect finds the adapter again after it was re-plugged
+//! or renumbered. `rustsys ports` lists the adapters present with their IDs.
+
+use super::config::UsbSerialId;
+use tokio_serial::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+fn is_match(id: &UsbSerialId, usb: &UsbPortInfo) -> bool {
+    usb.vid == id.vid
+        && usb.pid == id.pid
+        && id.serial_number.as_ref().is_none_or(|serial| usb.serial_number.as_ref() == Some(serial))
+}
+
+/// Describes `id` the way `rustsys ports` lists adapters.
+fn describe(id: &UsbSerialId) -> String {
+    match &id.serial_number {
+        Some(serial) => format!("{:04x}:{:04x} serial {:?}", id.vid, id.pid, serial),
+        None => format!("{:04x}:{:04x}", id.vid, id.pid),
+    }
+}
+
+/// Device path of the one adapter matching `id` among `ports`.
+pub fn find(id: &UsbSerialId, ports: &[SerialPortInfo]) -> Result<String, String> {
+    let found: Vec<&str> = ports
+        .iter()
+        .filter(|port| matches!(&port.port_type, SerialPortType::UsbPort(usb) if is_match(id, usb)))
+        .map(|port| port.port_name.as_str())
+        .collect();
+    match found.as_slice() {
+        [path] => Ok(path.to_string()),
+        [] => Err(format!("no USB serial adapter {} is plugged in", describe(id))),
+        paths => Err(format!(
+            "{} USB serial adapters match {} ({}); set serial_number to pick one",
+            paths.len(),
+            describe(id),
+            paths.join(", ")
+        )),
+    }
+}
+
+/// Looks up the device path of the adapter `id` among the ports present.
+pub fn resolve(id: &UsbSerialId) -> Result<String, String> {
+    let ports = tokio_serial::available_ports().map_err(|err| format!("cannot list serial ports: {}", err))?;
+    find(id, &ports)
+}
+
+/// One line per serial port present: path, and USB IDs where known.
+pub fn list() -> Result<Vec<String>, String> {
+    let ports = tokio_serial::available_ports().map_err(|err| format!("cannot list serial ports: {}", err))?;
+    Ok(ports
+        .iter()
+        .map(|port| match &port.port_type {
+            SerialPortType::UsbPort(usb) => {
+                let mut line = format!("{}  vid = 0x{:04x}, pid = 0x{:04x}", port.port_name, usb.vid, usb.pid);
+                if let Some(serial) = &usb.serial_number {
+                    line.push_str(&format!(", serial_number = {:?}", serial));
+                }
+                let product: Vec<&str> =
+                    [&usb.manufacturer, &usb.product].into_iter().flatten().map(String::as_str).collect();
+                if !product.is_empty() {
+                    line.push_str(&format!("  ({})", product.join(" ")));
+                }
+                line
+            }
+            _ => port.port_name.clone(),
+        })
+        .collect())
+}
@@ -0,0 +1,328 @@
+//! Speed and torque setpoints with a software ramp.
+//!
+//! Operators request a setpoint through the API; the polling loop then
+//! steps the written value towards it at `accel_per_sec` (moving away from
+//! zero) or `decel_per_sec` (moving towards zero) instead of commanding an
+//! instantaneous jump, which protects couplings, belts and the driven load.
+//! A channel without rates jumps straight to the target. Every request is
+//! recorded in the audit log. An interlock trip cancels any ramp in
+//! progress; until the interlock resets, requests are refused and queued
+//! ones are dropped rather than written.
+//!
+//! Writes are skipped (dry run) when the global `dry_run` is set or the
+//! section's `writes_enabled` is not; each step is then logged with the
+//! register value it would have written. A single request can also be a
+//! dry run: it leaves the running ramp alone and only audits the ramp it
+//! would have started.
+
+use super::audit;
+use super::config::{SetpointChannel, SetpointConfig};
+use super::events;
+use super::transport::{Transport, TransportError};
+use super::{MotorData, MotorSpecs};
+use sqlx::sqlite::SqlitePool;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetpointKind {
+    Speed,
+    Torque,
+}
+
+impl SetpointKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SetpointKind::Speed => "speed",
+            SetpointKind::Torque => "torque",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SetpointCommand {
+    pub kind: SetpointKind,
+    pub value: f64,
+    pub by: String,
+    /// Audit the ramp this request would start without starting it.
+    pub dry_run: bool,
+}
+
+/// Rate-limited approach from the current value to a target.
+#[derive(Debug, Clone)]
+pub struct Ramp {
+    accel_per_sec: Option<f64>,
+    decel_per_sec: Option<f64>,
+    current: Option<f64>,
+    target: Option<f64>,
+}
+
+impl Ramp {
+    pub fn new(accel_per_sec: Option<f64>, decel_per_sec: Option<f64>) -> Self {
+        Ramp { accel_per_sec, decel_per_sec, current: None, target: None }
+    }
+
+    /// Starts ramping from `from` (the drive's present value) to `target`.
+    pub fn start(&mut self, from: f64, target: f64) {
+        if self.target.is_none() {
+            self.current = Some(from);
+        }
+        self.target = Some(target);
+    }
+
+    pub fn cancel(&mut self) {
+        self.target = None;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Seconds a fresh ramp with these rates takes from `from` to `target`.
+    pub fn duration_secs(&self, from: f64, target: f64) -> f64 {
+        const DT: f64 = 0.1;
+        let mut ramp = Ramp::new(self.accel_per_sec, self.decel_per_sec);
+        ramp.start(from, target);
+        let mut secs = 0.0;
+        while ramp.step(DT).is_some_and(|value| value != target) {
+            secs += DT;
+        }
+        secs
+    }
+
+    /// Advances by `dt_secs` and returns the value to write, if any. The
+    /// ramp finishes once the target has been returned.
+    pub fn step(&mut self, dt_secs: f64) -> Option<f64> {
+        let target = self.target?;
+        let current = self.current.unwrap_or(target);
+        let away_from_zero = target.abs() > current.abs() && target.signum() * current.signum() >= 0.0;
+        let rate = if away_from_zero { self.accel_per_sec } else { self.decel_per_sec };
+        let next = match rate {
+            Some(rate) => {
+                let max_step = rate * dt_secs;
+                current + (target - current).clamp(-max_step, max_step)
+            }
+            None => target,
+        };
+        self.current = Some(next);
+        if next == target {
+            self.target = None;
+        }
+        Some(next)
+    }
+}
+
+/// Sending side handed to the API; validates requests before queueing them.
+#[derive(Debug, Clone)]
+pub struct SetpointHandle {
+    tx: mpsc::Sender<SetpointCommand>,
+    speed: Option<(f64, f64)>,
+    torque: Option<(f64, f64)>,
+    /// Set while the interlock is tripped.
+    tripped: Arc<AtomicBool>,
+}
+
+impl SetpointHandle {
+    /// Whether a `kind` setpoint of `value` would be accepted.
+    pub fn check(&self, kind: SetpointKind, value: f64) -> Result<(), String> {
+        let range = match kind {
+            SetpointKind::Speed => self.speed,
+            SetpointKind::Torque => self.torque,
+        };
+        let Some((min, max)) = range else {
+            return Err(format!("no {} setpoint register configured", kind.name()));
+        };
+        if !(value >= min && value <= max) {
+            return Err(format!("{} setpoint must be between {} and {}", kind.name(), min, max));
+        }
+        Ok(())
+    }
+
+    pub fn submit(&self, command: SetpointCommand) -> Result<(), String> {
+        if self.tripped.load(Ordering::Relaxed) {
+            return Err("interlock tripped; setpoints are refused until it resets".to_string());
+        }
+        self.check(command.kind, command.value)?;
+        self.tx.try_send(command).map_err(|_| "setpoint queue is full, try again".to_string())
+    }
+}
+
+/// Allowed range for a channel; the upper bound defaults to the motor's
+/// maximum speed or peak torque, and is capped at what the register holds
+/// (65535 counts), so larger values are refused rather than cut off.
+fn range(kind: SetpointKind, channel: &SetpointChannel, specs: &MotorSpecs) -> (f64, f64) {
+    let max = channel.max.unwrap_or(match kind {
+        SetpointKind::Speed => specs.max_speed,
+        SetpointKind::Torque => specs.peak_torque,
+    });
+    (channel.min, max.min(u16::MAX as f64 * channel.scale))
+}
+
+/// Receiving side, driven from the polling loop.
+#[derive(Debug)]
+pub struct SetpointController {
+    config: SetpointConfig,
+    rx: mpsc::Receiver<SetpointCommand>,
+    speed: Ramp,
+    torque: Ramp,
+    last_step: Option<Instant>,
+    /// Latest (speed reference or speed, torque) reading, where ramps start.
+    observed: Option<(f64, f64)>,
+    /// Global `dry_run`.
+    dry_run: bool,
+    /// Shared with the handles; set while the interlock is tripped.
+    tripped: Arc<AtomicBool>,
+}
+
+pub fn controller(config: SetpointConfig, specs: &MotorSpecs, dry_run: bool) -> (SetpointHandle, SetpointController) {
+    let (tx, rx) = mpsc::channel(16);
+    let tripped = Arc::new(AtomicBool::new(false));
+    let handle = SetpointHandle {
+        tx,
+        tripped: Arc::clone(&tripped),
+        speed: config.speed.as_ref().map(|c| range(SetpointKind::Speed, c, specs)),
+        torque: config.torque.as_ref().map(|c| range(SetpointKind::Torque, c, specs)),
+    };
+    let ramp = |channel: &Option<SetpointChannel>| {
+        Ramp::new(channel.as_ref().and_then(|c| c.accel_per_sec), channel.as_ref().and_then(|c| c.decel_per_sec))
+    };
+    let controller = SetpointController {
+        speed: ramp(&config.speed),
+        torque: ramp(&config.torque),
+        config,
+        rx,
+        last_step: None,
+        observed: None,
+        dry_run,
+        tripped,
+    };
+    (handle, controller)
+}
+
+impl SetpointController {
+    pub fn observe(&mut self, data: &MotorData) {
+        self.observed = Some((data.speed_reference.unwrap_or(data.current_speed), data.current_torque));
+    }
+
+    /// Cancels ramps in progress, e.g. after an interlock trip.
+    pub fn halt(&mut self) {
+        if self.speed.is_active() || self.torque.is_active() {
+            log::warn!("setpoint ramps cancelled");
+        }
+        self.speed.cancel();
+        self.torque.cancel();
+    }
+
+    /// Mirrors the interlock's latch: while tripped, ramps are cancelled,
+    /// requests refused and queued ones dropped.
+    pub fn set_interlock_tripped(&mut self, tripped: bool) {
+        if tripped && !self.tripped.swap(true, Ordering::Relaxed) {
+            self.halt();
+        } else if !tripped {
+            self.tripped.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Why writes are skipped, if they are.
+    fn dry_run_reason(&self) -> Option<&'static str> {
+        if self.dry_run {
+            Some("dry run, dry_run = true")
+        } else if !self.config.writes_enabled {
+            Some("dry run, writes_enabled = false")
+        } else {
+            None
+        }
+    }
+
+    /// Takes queued requests and writes the next ramp step of each channel.
+    pub async fn step(
+        &mut self,
+        pool: &SqlitePool,
+        mut transport: Option<&mut (dyn Transport + '_)>,
+        motor_id: &str,
+        now: i64,
+    ) {
+        while let Ok(command) = self.rx.try_recv() {
+            if self.tripped.load(Ordering::Relaxed) {
+                let detail = format!("{} setpoint {} refused: interlock tripped", command.kind.name(), command.value);
+                audit::record(pool, motor_id, now, &command.by, "setpoint", &detail).await;
+                continue;
+            }
+            let (speed, torque) = self.observed.unwrap_or((command.value, command.value));
+            let (ramp, from, channel) = match command.kind {
+                SetpointKind::Speed => (&mut self.speed, speed, &self.config.speed),
+                SetpointKind::Torque => (&mut self.torque, torque, &self.config.torque),
+            };
+            let mut detail = format!("{} setpoint {} -> {}", command.kind.name(), from, command.value);
+            if command.dry_run {
+                if let Some(channel) = channel {
+                    let raw = (command.value / channel.scale).round().clamp(0.0, u16::MAX as f64) as u16;
+                    detail.push_str(&format!(
+                        " (dry run, requested): would ramp for {:.1} s and end with {} in register {}",
+                        ramp.duration_secs(from, command.value),
+                        raw,
+                        channel.register.address
+                    ));
+                }
+            } else {
+                ramp.start(from, command.value);
+                if let Some(reason) = self.dry_run_reason() {
+                    detail.push_str(&format!(" ({})", reason));
+                }
+            }
+            audit::record(pool, motor_id, now, &command.by, "setpoint", &detail).await;
+        }
+
+        let instant = Instant::now();
+        let dt = self.last_step.map_or(0.0, |last| (instant - last).as_secs_f64());
+        self.last_step = Some(instant);
+        if self.tripped.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let dry_run = self.dry_run_reason().is_some();
+        for kind in [SetpointKind::Speed, SetpointKind::Torque] {
+            let (ramp, channel) = match kind {
+                SetpointKind::Speed => (&mut self.speed, &self.config.speed),
+                SetpointKind::Torque => (&mut self.torque, &self.config.torque),
+            };
+            let (Some(channel), Some(value)) = (channel, ramp.step(dt)) else {
+                continue;
+            };
+            // Targets fit the register (`range`); only a ramp starting from
+            // an observed value below zero passes through values cut to 0.
+            let raw = (value / channel.scale).round().clamp(0.0, u16::MAX as f64) as u16;
+            if dry_run {
+                log::info!(
+                    "dry run: {} setpoint step {:.1}, would write {} to register {}",
+                    kind.name(),
+                    value,
+                    raw,
+                    channel.register.address
+                );
+                continue;
+            }
+            log::debug!("{} setpoint step {:.1} (register value {})", kind.name(), value, raw);
+            if let Some(transport) = transport.as_deref_mut() {
+                match transport.write(&channel.register, raw).await {
+                    Ok(()) => {}
+                    Err(err @ TransportError::Rejected { .. }) => {
+                        // Further steps would be refused alike.
+                        log::error!("{} setpoint not taken, ramp cancelled: {}", kind.name(), err);
+                        ramp.cancel();
+                        let message = format!("{} setpoint {:.1}: {}", kind.name(), value, err);
+                        events::record_event(pool, motor_id, now, "write_failed", &message).await;
+                    }
+                    Err(TransportError::Unverified(err)) => {
+                        log::warn!("{} setpoint {:.1} written, read-back failed: {}", kind.name(), value, err);
+                        let message = format!("{} setpoint {:.1}: read-back failed: {}", kind.name(), value, err);
+                        events::record_event(pool, motor_id, now, "write_unverified", &message).await;
+                    }
+                    Err(err) => log::error!("cannot write {} setpoint: {}", kind.name(), err),
+                }
+            }
+        }
+    }
+}
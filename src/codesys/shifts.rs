@@ -0,0 +1,234 @@
+//! Per-shift summaries written at every shift handover.
+//!
+//! `[[shifts.shift]]` defines the shifts by name and `HH:MM` start and end
+//! in the motor's time zone (see `location`), e.g. 06:00–14:00,
+//! 14:00–22:00 and 22:00–06:00; a shift whose end is not after its start
+//! runs past midnight. When a shift ends, a summary of it is written to
+//! `shifts.path_template`: energy, run time and average load (see
+//! `fleet::usage`), the hottest the motor and each configured temperature
+//! channel got, and the alarms raised, per rule. Shifts that end at the
+//! same instant are each summarised. The same figures are recorded in one line as a `shift_summary`
+//! event, so they reach whoever follows the events: the API, GraphQL and
+//! the NATS event stream.
+//!
+//! Summaries are written from the database when the shift has ended; a
+//! monitor that is down at handover writes none for that shift.
+
+use super::analytics::{self, format_duration};
+use super::config::{MonitorConfig, ShiftDef, ShiftsConfig};
+use super::disk;
+use super::events;
+use super::fleet::{self, Usage};
+use super::location::Zone;
+use super::output::{ensure_parent, render_path, OutputContext};
+use super::schedule::parse_time;
+use chrono::prelude::*;
+use chrono::Duration as ChronoDuration;
+use sqlx::sqlite::SqlitePool;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+
+/// What the shift summaries need from the monitor configuration.
+#[derive(Debug, Clone)]
+pub struct ShiftSettings {
+    pub config: ShiftsConfig,
+    pub motor_id: String,
+    pub rated_power: f64,
+    pub heat_limit: Option<f64>,
+    pub zone: Zone,
+    /// Configured temperature channels, whose maxima are reported.
+    pub channels: Vec<String>,
+}
+
+impl ShiftSettings {
+    /// `None` without a `[shifts]` section.
+    pub fn from_config(config: &MonitorConfig) -> Option<ShiftSettings> {
+        config.shifts.as_ref().map(|shifts| ShiftSettings {
+            config: shifts.clone(),
+            motor_id: config.motor_id.clone(),
+            rated_power: config.motor.rated_power,
+            heat_limit: fleet::heat_limit(config),
+            zone: Zone::from_config(config),
+            channels: config.registers.temperatures.keys().cloned().collect(),
+        })
+    }
+}
+
+fn at(zone: Zone, day: NaiveDate, minutes: u32) -> Option<DateTime<FixedOffset>> {
+    let midnight = day.and_hms_opt(0, 0, 0)?;
+    zone.from_local(&(midnight + ChronoDuration::minutes(minutes as i64)))
+}
+
+/// Start and end of the occurrence of `shift` that ends on `day` in `zone`.
+pub fn occurrence(
+    shift: &ShiftDef,
+    zone: Zone,
+    day: NaiveDate,
+) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let (start, end) = (parse_time(&shift.start)?, parse_time(&shift.end)?);
+    let start_day = if start < end { day } else { day.pred_opt()? };
+    Some((at(zone, start_day, start)?, at(zone, day, end)?))
+}
+
+/// The shifts that end next after `now`, all at the same instant, with
+/// their start and end; empty when none ends in the next two days.
+pub fn next_handovers(
+    config: &ShiftsConfig,
+    zone: Zone,
+    now: DateTime<FixedOffset>,
+) -> Vec<(&ShiftDef, DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let today = now.date_naive();
+    let upcoming: Vec<_> = [today.pred_opt(), Some(today), today.succ_opt()]
+        .into_iter()
+        .flatten()
+        .flat_map(|day| config.shift.iter().filter_map(move |shift| Some((shift, occurrence(shift, zone, day)?))))
+        .filter(|(_, (_, end))| *end > now)
+        .map(|(shift, (start, end))| (shift, start, end))
+        .collect();
+    let Some(next) = upcoming.iter().map(|(_, _, end)| *end).min() else {
+        return Vec::new();
+    };
+    upcoming.into_iter().filter(|(_, _, end)| *end == next).collect()
+}
+
+/// Figures of one shift.
+#[derive(Debug, Clone)]
+pub struct ShiftSummary {
+    pub motor_id: String,
+    pub shift: String,
+    /// Epoch seconds.
+    pub from: i64,
+    pub to: i64,
+    pub usage: Usage,
+    pub rated_power: f64,
+    pub heat_limit: Option<f64>,
+    /// Configured temperature channels.
+    pub channels: Vec<String>,
+    pub alarms: Vec<analytics::RuleAlarmStats>,
+}
+
+impl ShiftSummary {
+    pub fn alarm_count(&self) -> i64 {
+        self.alarms.iter().map(|rule| rule.count).sum()
+    }
+
+    /// Mean load while running, as % of rated power.
+    pub fn avg_load_percent(&self) -> Option<f64> {
+        (self.usage.run_secs > 0.0 && self.rated_power > 0.0).then(|| self.usage.load_secs / self.usage.run_secs)
+    }
+
+    /// One line for the `shift_summary` event.
+    pub fn message(&self) -> String {
+        let mut message = format!(
+            "shift {} ended: {:.1} kWh, ran {}, {} alarm(s)",
+            self.shift,
+            self.usage.energy_kwh,
+            format_duration(self.usage.run_secs),
+            self.alarm_count()
+        );
+        if let Some(heat) = self.usage.max_heat {
+            write!(message, ", max heat {:.1}", heat).unwrap();
+        }
+        for channel in &self.channels {
+            if let Some(max) = self.usage.max_temperatures.get(channel) {
+                write!(message, ", max {} {:.1}", channel, max).unwrap();
+            }
+        }
+        message
+    }
+}
+
+/// Summarises `[from, to)` (epoch seconds) as shift `shift`.
+pub async fn summarize(pool: &SqlitePool, settings: &ShiftSettings, shift: &str, from: i64, to: i64) -> ShiftSummary {
+    ShiftSummary {
+        motor_id: settings.motor_id.clone(),
+        shift: shift.to_string(),
+        from,
+        to,
+        usage: fleet::usage(pool, settings.rated_power, from * 1000, to * 1000).await,
+        rated_power: settings.rated_power,
+        heat_limit: settings.heat_limit,
+        channels: settings.channels.clone(),
+        alarms: analytics::alarm_frequency(pool, from, to).await,
+    }
+}
+
+fn format_timestamp(zone: Zone, timestamp: i64) -> String {
+    zone.at_secs(timestamp).map_or_else(|| timestamp.to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string())
+}
+
+/// Markdown of `summary`, for the incoming shift; times are shown in `zone`.
+pub fn render_markdown(summary: &ShiftSummary, zone: Zone) -> String {
+    let number = |value: Option<f64>, unit: &str| value.map_or("–".to_string(), |v| format!("{:.1}{}", v, unit));
+    let mut out = String::new();
+    writeln!(out, "# Shift handover: {} – {}\n", summary.motor_id, summary.shift).unwrap();
+    let (from, to) = (format_timestamp(zone, summary.from), format_timestamp(zone, summary.to));
+    writeln!(out, "Shift: {} – {}\n", from, to).unwrap();
+    out.push_str("| | |\n|---|---:|\n");
+    writeln!(out, "| Energy | {:.1} kWh |", summary.usage.energy_kwh).unwrap();
+    writeln!(out, "| Run time | {} |", format_duration(summary.usage.run_secs)).unwrap();
+    writeln!(out, "| Average load | {} |", number(summary.avg_load_percent(), " %")).unwrap();
+    writeln!(out, "| Max heat | {} |", number(summary.usage.max_heat, "")).unwrap();
+    if let Some(limit) = summary.heat_limit {
+        writeln!(out, "| Heat limit | {:.1} |", limit).unwrap();
+    }
+    for channel in &summary.channels {
+        let max = summary.usage.max_temperatures.get(channel).copied();
+        writeln!(out, "| Max {} | {} |", channel, number(max, " °C")).unwrap();
+    }
+    writeln!(out, "| Alarms | {} |", summary.alarm_count()).unwrap();
+    out.push_str("\n## Alarms\n\n");
+    if summary.alarms.is_empty() {
+        out.push_str("No alarms were raised during the shift.\n");
+    } else {
+        out.push_str("| Rule | Alarms | Still active |\n");
+        out.push_str("|---|---:|---:|\n");
+        for rule in &summary.alarms {
+            writeln!(out, "| {} | {} | {} |", rule.rule, rule.count, rule.active).unwrap();
+        }
+    }
+    out
+}
+
+/// Writes `summary` to the path rendered from `path_template` and returns it.
+pub fn write_summary(
+    summary: &ShiftSummary,
+    path_template: &str,
+    zone: Zone,
+    space: &disk::Space,
+) -> std::io::Result<PathBuf> {
+    let ctx = OutputContext { motor: &summary.motor_id, signal: "", timestamp: summary.to };
+    let path = render_path(path_template, &ctx);
+    ensure_parent(&path, space)?;
+    fs::write(&path, render_markdown(summary, zone))?;
+    Ok(path)
+}
+
+/// Summarises every shift when it ends, forever.
+pub async fn run_shift_scheduler(pool: Arc<SqlitePool>, settings: ShiftSettings, space: disk::Space) {
+    loop {
+        let ending: Vec<(String, i64, i64)> = next_handovers(&settings.config, settings.zone, settings.zone.now())
+            .into_iter()
+            .map(|(shift, start, end)| (shift.name.clone(), start.timestamp(), end.timestamp()))
+            .collect();
+        let Some(&(_, _, end)) = ending.first() else {
+            log::error!("no shift ends in the next two days; shift summaries stop");
+            return;
+        };
+        let wait = (end - settings.zone.now().timestamp()).max(0) as u64;
+        time::sleep(Duration::from_secs(wait + 1)).await;
+
+        for (name, start, end) in ending {
+            let summary = summarize(&pool, &settings, &name, start, end).await;
+            match write_summary(&summary, &settings.config.path_template, settings.zone, &space) {
+                Ok(path) => log::info!("wrote shift summary {}", path.display()),
+                Err(err) => log::error!("cannot write shift summary: {}", err),
+            }
+            events::record_event(&pool, &settings.motor_id, end, "shift_summary", &summary.message()).await;
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! The `Sink` trait and the supervisor that runs every sink.
+//!
+//! Everything the polling loop hands off (sample batches for the database,
+//! closed statistics windows, samples for MQTT, NATS, the charts and
+//! stdout) goes to a `Sink`. `Supervisor::spawn` gives each sink its own bounded queue (see
+//! `pipeline`, with the sink's own overflow policy) and its own task, so
+//! one slow or broken sink neither stalls polling nor the others. `FanOut`
+//! hands one item to several sinks.
+//!
+//! The task opens the sink before the first write. A write that fails, or
+//! panics, counts as a failure, and the sink is reopened before the next
+//! write. `on_error` decides what happens to the item: `retry` (default)
+//! writes it again after a backoff that doubles up to `max_backoff_secs`,
+//! so nothing is lost while the queue's overflow policy absorbs the
+//! backlog; `skip` drops it and goes on with the next. State and counters
+//! per sink are kept in `SinkHealthMap` and served by GraphQL `sinks`.
+
+use super::config::{RestartPolicy, SinkConfig};
+use super::pipeline::{self, SinkReceiver, SinkSender};
+use super::stats::{self, WindowSummary};
+use super::{insert_motor_data_batch, MotorData};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io::{self, Write};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time;
+
+/// First wait before retrying a failed sink.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+#[async_trait]
+pub trait Sink<T: Sync>: Send {
+    /// Connects or otherwise prepares the sink. Called before the first
+    /// write and again after every failure.
+    async fn open(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn write(&mut self, item: &T) -> Result<(), String>;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkState {
+    /// Waiting for its first item.
+    #[default]
+    Idle,
+    Running,
+    /// Failed; waiting to reopen and retry.
+    Backoff,
+    /// Its queue was closed; the monitor is shutting down.
+    Stopped,
+}
+
+impl SinkState {
+    pub fn name(self) -> &'static str {
+        match self {
+            SinkState::Idle => "idle",
+            SinkState::Running => "running",
+            SinkState::Backoff => "backoff",
+            SinkState::Stopped => "stopped",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SinkHealth {
+    pub state: SinkState,
+    /// Items written.
+    pub written: u64,
+    pub failures: u64,
+    /// Items dropped by `on_error = "skip"`.
+    pub skipped: u64,
+    pub last_error: Option<String>,
+    /// Epoch seconds.
+    pub last_error_at: Option<i64>,
+}
+
+/// Health of every sink by name.
+pub type SinkHealthMap = Arc<Mutex<BTreeMap<String, SinkHealth>>>;
+
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    health: SinkHealthMap,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor::default()
+    }
+
+    pub fn health(&self) -> SinkHealthMap {
+        Arc::clone(&self.health)
+    }
+
+    /// Starts `sink` behind its own queue and returns the sender feeding it.
+    pub fn spawn<T, S>(&self, name: &str, sink: S, config: &SinkConfig) -> io::Result<SinkSender<T>>
+    where
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+        S: Sink<T> + 'static,
+    {
+        let (tx, rx) = pipeline::channel(name, config)?;
+        self.health.lock().unwrap().insert(name.to_string(), SinkHealth::default());
+        tokio::spawn(supervise(name.to_string(), sink, rx, config.clone(), self.health()));
+        Ok(tx)
+    }
+}
+
+/// Runs `future`, turning a panic into an error.
+async fn guarded(future: impl Future<Output = Result<(), String>>) -> Result<(), String> {
+    match AssertUnwindSafe(future).catch_unwind().await {
+        Ok(result) => result,
+        Err(panic) => Err(match panic.downcast_ref::<&str>() {
+            Some(message) => format!("panicked: {}", message),
+            None => panic.downcast_ref::<String>().map_or("panicked".to_string(), |m| format!("panicked: {}", m)),
+        }),
+    }
+}
+
+async fn supervise<T, S>(name: String, mut sink: S, mut rx: SinkReceiver<T>, config: SinkConfig, health: SinkHealthMap)
+where
+    T: Clone + DeserializeOwned + Send + Sync,
+    S: Sink<T>,
+{
+    let update = |change: &dyn Fn(&mut SinkHealth)| change(health.lock().unwrap().entry(name.clone()).or_default());
+    let max_backoff = Duration::from_secs(config.max_backoff_secs);
+    let mut backoff = INITIAL_BACKOFF;
+    let mut open = false;
+    while let Some(item) = rx.recv().await {
+        loop {
+            let result = if open { Ok(()) } else { guarded(sink.open()).await };
+            let result = match result {
+                Ok(()) => {
+                    open = true;
+                    guarded(sink.write(&item)).await
+                }
+                Err(err) => Err(format!("cannot open: {}", err)),
+            };
+            let Err(err) = result else {
+                backoff = INITIAL_BACKOFF;
+                update(&|h| {
+                    h.state = SinkState::Running;
+                    h.written += 1;
+                });
+                break;
+            };
+            open = false;
+            log::warn!("sink {}: {}", name, err);
+            let now = Local::now().timestamp();
+            update(&|h| {
+                h.failures += 1;
+                h.last_error = Some(err.clone());
+                h.last_error_at = Some(now);
+            });
+            if config.on_error == RestartPolicy::Skip {
+                update(&|h| h.skipped += 1);
+                break;
+            }
+            update(&|h| h.state = SinkState::Backoff);
+            time::sleep(backoff).await;
+            backoff = (backoff * 2).min(max_backoff);
+        }
+    }
+    update(&|h| h.state = SinkState::Stopped);
+}
+
+/// Senders of several sinks taking the same items.
+#[derive(Debug)]
+pub struct FanOut<T> {
+    senders: Vec<SinkSender<T>>,
+}
+
+impl<T> Default for FanOut<T> {
+    fn default() -> Self {
+        FanOut { senders: Vec::new() }
+    }
+}
+
+impl<T: Clone + Serialize> FanOut<T> {
+    pub fn push(&mut self, sender: SinkSender<T>) {
+        self.senders.push(sender);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+    }
+
+    pub async fn send(&self, item: T) {
+        for sender in &self.senders {
+            sender.send(item.clone()).await;
+        }
+    }
+}
+
+/// Sample batches into the database, rounded to `[precision]`.
+pub struct StorageSink {
+    pool: Arc<SqlitePool>,
+    precision: BTreeMap<String, f64>,
+}
+
+impl StorageSink {
+    pub fn new(pool: Arc<SqlitePool>, precision: BTreeMap<String, f64>) -> Self {
+        StorageSink { pool, precision }
+    }
+}
+
+#[async_trait]
+impl Sink<Vec<MotorData>> for StorageSink {
+    async fn write(&mut self, batch: &Vec<MotorData>) -> Result<(), String> {
+        if self.precision.is_empty() {
+            insert_motor_data_batch(&self.pool, batch).await;
+        } else {
+            let quantized: Vec<MotorData> = batch.iter().map(|d| d.quantized(&self.precision)).collect();
+            insert_motor_data_batch(&self.pool, &quantized).await;
+        }
+        Ok(())
+    }
+}
+
+/// Closed statistics windows into the database.
+pub struct StatsSink {
+    pool: Arc<SqlitePool>,
+}
+
+impl StatsSink {
+    pub fn new(pool: Arc<SqlitePool>) -> Self {
+        StatsSink { pool }
+    }
+}
+
+#[async_trait]
+impl Sink<Vec<WindowSummary>> for StatsSink {
+    async fn write(&mut self, summaries: &Vec<WindowSummary>) -> Result<(), String> {
+        stats::insert_window_summaries(&self.pool, summaries).await;
+        Ok(())
+    }
+}
+
+/// Samples as JSON lines on standard output, for piping into other tools.
+pub struct StdoutSink;
+
+#[async_trait]
+impl Sink<MotorData> for StdoutSink {
+    async fn write(&mut self, data: &MotorData) -> Result<(), String> {
+        let line = serde_json::to_string(data).map_err(|err| err.to_string())?;
+        let mut out = io::stdout().lock();
+        writeln!(out, "{}", line).and_then(|_| out.flush()).map_err(|err| err.to_string())
+    }
+}
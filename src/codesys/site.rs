@@ -0,0 +1,68 @@
+//! Site (tenant) namespace.
+//!
+//! With `site` set, a monitor claims its database for that site on first
+//! start and refuses to write into a database claimed by another site, so
+//! data of different customers never ends up in the same tables. The API is
+//! served under `/sites/<site>/`, MQTT topics carry the site, and exports
+//! name it in their metadata. Without `site` the database is claimed for
+//! the unnamed default site.
+//!
+//! Each row of samples (`motor_data`, `temperature_data`, `pulse_data`) and
+//! of window statistics (`signal_stats`, `signal_rollups`) records the site
+//! it was taken for, and reads only return rows of the site the database is
+//! claimed for (`SAMPLES`), so rows copied in from another site's database
+//! never show up as this site's data. Other tables (alarms, events, audit
+//! log, ...) are not tagged; the claim alone keeps them to one site.
+
+use sqlx::sqlite::SqlitePool;
+
+/// SQL for the site the database is claimed for; `''` for the default site
+/// and for an unclaimed database.
+pub const CLAIMED: &str = "(SELECT COALESCE(MAX(name), '') FROM site)";
+
+/// SQL condition selecting the sample and statistics rows of the claimed
+/// site.
+pub const SAMPLES: &str = "site = (SELECT COALESCE(MAX(name), '') FROM site)";
+
+pub async fn setup_site_table(pool: &SqlitePool) {
+    sqlx::query("CREATE TABLE IF NOT EXISTS site (name TEXT NOT NULL)").execute(pool).await.unwrap();
+}
+
+/// The site the database belongs to; `None` if it has not been claimed yet.
+pub async fn stored_site(pool: &SqlitePool) -> Option<String> {
+    let row: Option<(String,)> = sqlx::query_as("SELECT name FROM site LIMIT 1").fetch_optional(pool).await.ok()?;
+    row.map(|(name,)| name)
+}
+
+fn label(site: &str) -> String {
+    match site {
+        "" => "the default site".to_string(),
+        name => format!("site {:?}", name),
+    }
+}
+
+/// Fails if the database belongs to a site other than `site` (`""` for the
+/// default site).
+pub async fn check(pool: &SqlitePool, site: &str) -> Result<(), String> {
+    match stored_site(pool).await {
+        Some(stored) if stored != site => Err(format!(
+            "database belongs to {}, not {}; use a separate database per site",
+            label(&stored),
+            label(site)
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Like `check`, and records `site` as the owner of an unclaimed database.
+pub async fn claim(pool: &SqlitePool, site: &str) -> Result<(), String> {
+    check(pool, site).await?;
+    if stored_site(pool).await.is_none() {
+        sqlx::query("INSERT INTO site (name) VALUES (?)")
+            .bind(site)
+            .execute(pool)
+            .await
+            .map_err(|err| format!("cannot claim the database for {}: {}", label(site), err))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Incident snapshots: when an alarm trips, chart the minutes leading up to
+//! it for the signals involved, so the alarm record points at the evidence.
+//!
+//! A snapshot is captured in a task of its own and its charts are drawn on
+//! the blocking pool, so an alarm never delays the next poll.
+
+use super::charts::chart_spec;
+use super::config::{FormatConfig, SnapshotConfig};
+use super::disk;
+use super::output::{chart_text, ensure_parent, render_path, OutputContext};
+use super::notes;
+use super::units::Units;
+use super::{draw_chart_with_markers, motor_data_range, Signal};
+use sqlx::sqlite::SqlitePool;
+use std::path::PathBuf;
+use tokio::task;
+
+/// Renders one chart per signal covering `config.minutes` before `now`
+/// (milliseconds) into a folder for this alarm and returns the folder path.
+///
+/// Older samples come from the database; the newest ones (possibly not yet
+/// flushed) from `recent`, each signal's in-memory series.
+#[allow(clippy::too_many_arguments)]
+pub async fn capture(
+    pool: &SqlitePool,
+    config: &SnapshotConfig,
+    format: FormatConfig,
+    units: &Units,
+    motor_id: &str,
+    alarm_id: i64,
+    rule: &str,
+    recent: Vec<(Signal, Vec<(i64, f64)>)>,
+    now: i64,
+    space: disk::Space,
+) -> Option<PathBuf> {
+    let from = now - config.minutes * 60_000;
+    let stored = motor_data_range(pool, from, now + 1, None).await;
+    let stored_until = stored.last().map_or(i64::MIN, |d| d.timestamp);
+
+    let ctx = OutputContext { motor: motor_id, signal: "", timestamp: now.div_euclid(1000) };
+    let folder = render_path(&config.dir_template, &ctx).join(format!("{}_{}", alarm_id, rule));
+
+    let markers = notes::markers(pool, motor_id, from, now + 1).await;
+    let mut charts = Vec::new();
+    for (signal, series) in recent {
+        let mut points: Vec<(i64, f64)> = stored.iter().map(|d| (d.timestamp, d.value(signal))).collect();
+        points.extend(series.into_iter().filter(|(t, _)| *t > stored_until && *t >= from));
+        if points.is_empty() {
+            continue;
+        }
+
+        let path = folder.join(format!("{}.png", signal.name()));
+        let points: Vec<(i64, f64)> = points.into_iter().map(|(t, v)| (t, units.display(signal.name(), v))).collect();
+        let (title, y_label) = chart_spec(signal, units);
+        let title = format!("{} before alarm {}", title, rule);
+        charts.push((path, points, title, y_label));
+    }
+    let mut text = chart_text(motor_id, from, now);
+    text.push(("alarm", format!("{} {}", alarm_id, rule)));
+
+    let draw = move || {
+        let mut written = 0;
+        for (path, points, title, y_label) in charts {
+            let result = ensure_parent(&path, &space).map_err(Box::<dyn std::error::Error>::from).and_then(|_| {
+                let filename = path.to_string_lossy();
+                draw_chart_with_markers(&filename, &points, &markers, &format, &title, "Time", &y_label, &text)
+            });
+            match result {
+                Ok(()) => written += 1,
+                Err(err) => log::error!("cannot write alarm snapshot {}: {}", path.display(), err),
+            }
+        }
+        written
+    };
+    let written = task::spawn_blocking(draw).await.unwrap_or_else(|err| {
+        log::error!("alarm snapshot {} {} failed: {}", alarm_id, rule, err);
+        0
+    });
+    if written == 0 {
+        return None;
+    }
+    log::info!("alarm snapshot written to {}", folder.display());
+    Some(folder)
+}
@@ -0,0 +1,33 @@
+//! Soft sensors: values estimated from other registers.
+//!
+//! Not every drive exposes the motor speed. `[registers.speed_estimate]`
+//! reads the drive's output frequency instead and estimates the speed of an
+//! induction motor from it: synchronous speed `120 · f / poles` less the
+//! slip. Slip grows with load, so `rated_slip_rpm` (synchronous speed at
+//! rated frequency minus the nameplate speed) is scaled by the current
+//! against `rated_current_a`; without a rated current the full rated slip
+//! is taken off, which is exact at rated load only. A negative frequency
+//! (reverse rotation) gives a negative speed.
+//!
+//! With an estimate configured, `registers.speed` is not read. Every sample
+//! whose speed was estimated lists `speed` in `MotorData::estimated`, which
+//! is stored with it and shown in GraphQL and exports, so readers can tell
+//! an estimate from a measurement.
+
+use super::config::SpeedEstimate;
+
+/// Name in `MotorData::estimated` of an estimated speed.
+pub const SPEED: &str = "speed";
+
+/// Synchronous speed (rpm) at `frequency_hz`.
+pub fn synchronous_speed(frequency_hz: f64, poles: u32) -> f64 {
+    120.0 * frequency_hz / poles as f64
+}
+
+/// Speed (rpm) at output frequency `frequency_hz` and motor current `current_a`.
+pub fn estimate_speed(estimate: &SpeedEstimate, frequency_hz: f64, current_a: f64) -> f64 {
+    let synchronous = synchronous_speed(frequency_hz.abs(), estimate.poles);
+    let load = estimate.rated_current_a.map_or(1.0, |rated| (current_a.abs() / rated).min(2.0));
+    let speed = (synchronous - estimate.rated_slip_rpm * load).max(0.0);
+    speed.copysign(frequency_hz)
+}
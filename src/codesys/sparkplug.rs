@@ -0,0 +1,172 @@
+//! Eclipse Sparkplug B payloads (protobuf), encoded by hand: only the
+//! subset rustsys publishes (scalar metrics) and the node rebirth command
+//! it listens for are supported.
+
+/// Sparkplug B data type codes.
+pub const UINT64: u32 = 8;
+pub const DOUBLE: u32 = 10;
+pub const BOOLEAN: u32 = 11;
+
+pub const REBIRTH: &str = "Node Control/Rebirth";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    UInt64(u64),
+    Double(f64),
+    Boolean(bool),
+    /// A metric without a value (e.g. a sensor that did not report).
+    Null,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    /// Sent in birth certificates; data messages use `alias` only.
+    pub name: Option<String>,
+    pub alias: Option<u64>,
+    pub timestamp: Option<u64>,
+    pub datatype: u32,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Payload {
+    pub timestamp: u64,
+    pub metrics: Vec<Metric>,
+    /// Absent only in NDEATH.
+    pub seq: Option<u64>,
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn key(out: &mut Vec<u8>, field: u32, wire_type: u8) {
+    varint(out, (field as u64) << 3 | wire_type as u64);
+}
+
+fn bytes_field(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    key(out, field, 2);
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn encode_metric(metric: &Metric) -> Vec<u8> {
+    let mut out = Vec::new();
+    if let Some(name) = &metric.name {
+        bytes_field(&mut out, 1, name.as_bytes());
+    }
+    if let Some(alias) = metric.alias {
+        key(&mut out, 2, 0);
+        varint(&mut out, alias);
+    }
+    if let Some(timestamp) = metric.timestamp {
+        key(&mut out, 3, 0);
+        varint(&mut out, timestamp);
+    }
+    key(&mut out, 4, 0);
+    varint(&mut out, metric.datatype as u64);
+    match metric.value {
+        Value::UInt64(v) => {
+            key(&mut out, 11, 0);
+            varint(&mut out, v);
+        }
+        Value::Double(v) => {
+            key(&mut out, 13, 1);
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        Value::Boolean(v) => {
+            key(&mut out, 14, 0);
+            varint(&mut out, v as u64);
+        }
+        Value::Null => {
+            key(&mut out, 7, 0);
+            varint(&mut out, 1);
+        }
+    }
+    out
+}
+
+pub fn encode(payload: &Payload) -> Vec<u8> {
+    let mut out = Vec::new();
+    key(&mut out, 1, 0);
+    varint(&mut out, payload.timestamp);
+    for metric in &payload.metrics {
+        bytes_field(&mut out, 2, &encode_metric(metric));
+    }
+    if let Some(seq) = payload.seq {
+        key(&mut out, 3, 0);
+        varint(&mut out, seq);
+    }
+    out
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.bytes.split_first()?;
+            self.bytes = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(head)
+    }
+
+    /// Next `(field, wire type, varint value or length-delimited bytes)`.
+    fn field(&mut self) -> Option<(u64, u64, u64, &'a [u8])> {
+        let key = self.varint()?;
+        let (field, wire_type) = (key >> 3, key & 7);
+        match wire_type {
+            0 => Some((field, wire_type, self.varint()?, &[])),
+            1 => Some((field, wire_type, 0, self.take(8)?)),
+            2 => {
+                let len = self.varint()? as usize;
+                Some((field, wire_type, 0, self.take(len)?))
+            }
+            5 => Some((field, wire_type, 0, self.take(4)?)),
+            _ => None,
+        }
+    }
+}
+
+/// True if an NCMD payload asks for a rebirth. Malformed payloads are ignored.
+pub fn is_rebirth_request(payload: &[u8]) -> bool {
+    let mut reader = Reader { bytes: payload };
+    while let Some((field, _, _, bytes)) = reader.field() {
+        if field != 2 {
+            continue;
+        }
+        let mut metric = Reader { bytes };
+        let (mut name, mut value) = (None, false);
+        while let Some((field, wire_type, number, bytes)) = metric.field() {
+            match (field, wire_type) {
+                (1, 2) => name = std::str::from_utf8(bytes).ok(),
+                (14, 0) => value = number != 0,
+                _ => {}
+            }
+        }
+        if name == Some(REBIRTH) && value {
+            return true;
+        }
+    }
+    false
+}
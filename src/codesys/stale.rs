@@ -0,0 +1,80 @@
+//! Stale-data (deadman) detection.
+//!
+//! A frozen drive can keep answering on the bus while a register never
+//! changes. Each watched value that stays within `tolerance` of itself for
+//! `window_secs` raises a `stale_<name>` alarm, separate from communication
+//! failures. Values sitting at exactly zero are ignored by default, since a
+//! stopped motor legitimately reads 0.
+
+use super::alarms::SustainedAlarm;
+use super::config::StaleConfig;
+use super::{MotorData, Signal};
+use sqlx::sqlite::SqlitePool;
+
+#[derive(Debug)]
+struct Watch {
+    name: String,
+    /// Last value that differed from its predecessor by more than the tolerance.
+    reference: Option<f64>,
+    changed_at: i64,
+    alarm: SustainedAlarm,
+}
+
+#[derive(Debug)]
+pub struct StaleDetector {
+    config: StaleConfig,
+    watches: Vec<Watch>,
+}
+
+/// Value of a signal or temperature channel by name.
+fn lookup(data: &MotorData, name: &str) -> Option<f64> {
+    match Signal::from_name(name) {
+        Some(signal) => Some(data.value(signal)),
+        None => data.temperatures.get(name).copied(),
+    }
+}
+
+impl StaleDetector {
+    pub fn new(config: StaleConfig) -> Self {
+        let watches = config
+            .signals
+            .iter()
+            .map(|name| Watch {
+                name: name.clone(),
+                reference: None,
+                changed_at: 0,
+                alarm: SustainedAlarm::new(&format!("stale_{}", name), Some(name), config.severity, 0),
+            })
+            .collect();
+        StaleDetector { config, watches }
+    }
+
+    /// Returns `(alarm id, rule, name)` for each stale alarm raised by `data`.
+    pub async fn update(&mut self, pool: &SqlitePool, motor_id: &str, data: &MotorData) -> Vec<(i64, String, String)> {
+        let now = data.seconds();
+        let mut raised = Vec::new();
+        for watch in &mut self.watches {
+            let Some(value) = lookup(data, &watch.name) else {
+                continue;
+            };
+            let changed = watch.reference.is_none_or(|r| (value - r).abs() > self.config.tolerance);
+            if changed {
+                watch.reference = Some(value);
+                watch.changed_at = now;
+            }
+            let frozen_for = now - watch.changed_at;
+            let exempt = self.config.ignore_zero && value == 0.0;
+            let stale = !exempt && frozen_for >= self.config.window_secs;
+            let id = watch
+                .alarm
+                .update(pool, motor_id, stale, now, || {
+                    format!("{} stuck at {} for {} s (drive frozen?)", watch.name, value, frozen_for)
+                })
+                .await;
+            if let Some(id) = id {
+                raised.push((id, watch.alarm.rule.clone(), watch.name.clone()));
+            }
+        }
+        raised
+    }
+}
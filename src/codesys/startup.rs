@@ -0,0 +1,71 @@
+//! Startup order and the components the monitor can run without.
+//!
+//! The monitor needs its configuration and its database: without them it
+//! exits, since it would have nowhere to keep samples. The site claim, the
+//! redundancy pairing and the storage and statistics queues come next and
+//! are required too. Everything else serves or forwards data and starts in
+//! the background once these are in place, so polling begins at once:
+//!
+//! - the HTTP API, Arrow Flight, the IPC socket and the Modbus TCP proxy
+//!   listen when they can. A port still in use, e.g. by a previous instance
+//!   that has not quite exited, or a certificate not yet in place, is
+//!   retried with `retry`. Any other failure, such as a certificate that
+//!   does not parse or a port the monitor may not bind, will not go away by
+//!   waiting: the component is left out and the error logged. TLS files
+//!   that exist are already checked with the configuration (see `tls`);
+//! - MQTT and NATS connect on their first sample and reconnect after every
+//!   failure (see `sink`); a broker that is down at startup only delays
+//!   their messages;
+//! - an optional sink whose queue cannot be created, e.g. when the spool
+//!   directory is not writable, is left out and logged.
+//!
+//! Failures are logged with the time until the next attempt, starting at
+//! `INITIAL_RETRY` and doubling up to `MAX_RETRY`.
+
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::time;
+
+/// Wait before the second attempt to start a component.
+pub const INITIAL_RETRY: Duration = Duration::from_secs(1);
+/// Longest wait between attempts.
+pub const MAX_RETRY: Duration = Duration::from_secs(60);
+
+/// Whether a failure to start may go away by itself: a file not yet in
+/// place or an address still in use.
+pub fn is_transient(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::NotFound | io::ErrorKind::AddrInUse)
+}
+
+/// Runs `attempt` until it succeeds, waiting longer after each transient
+/// failure; `component` names what is started in the log. Any other
+/// failure is logged and returned.
+pub async fn retry<T, F, Fut>(component: &str, mut attempt: F) -> io::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut delay = INITIAL_RETRY;
+    let mut failures = 0;
+    loop {
+        match attempt().await {
+            Ok(started) => {
+                if failures > 0 {
+                    log::info!("{} started after {} failed attempt(s)", component, failures);
+                }
+                return Ok(started);
+            }
+            Err(err) if !is_transient(&err) => {
+                log::error!("running without {}: {}", component, err);
+                return Err(err);
+            }
+            Err(err) => {
+                failures += 1;
+                log::warn!("cannot start {}, retrying in {} s: {}", component, delay.as_secs(), err);
+                time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY);
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Motor run state (stopped, ramping, running) and adaptive polling.
+//!
+//! The state is derived from each sample: standstill below
+//! `stop_speed_rpm`, ramping while speed changes faster than
+//! `ramp_rate_rpm_per_sec` or trails its reference by more than
+//! `ramp_error_rpm`, running otherwise. Leaving `Stopped` takes effect on
+//! the first sample that moves; entering it needs `stop_delay_secs` of
+//! standstill, so a brief dip does not slow polling down. Transitions are
+//! recorded as `state_change` events.
+
+use super::config::AdaptivePollingConfig;
+use super::events;
+use super::MotorData;
+use sqlx::sqlite::SqlitePool;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotorState {
+    Stopped,
+    Ramping,
+    Running,
+}
+
+impl MotorState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MotorState::Stopped => "stopped",
+            MotorState::Ramping => "ramping",
+            MotorState::Running => "running",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StateMachine {
+    config: AdaptivePollingConfig,
+    state: MotorState,
+    /// Timestamp (ms) and speed of the previous sample.
+    previous: Option<(i64, f64)>,
+    /// Start (ms) of the current standstill while not yet `Stopped`.
+    still_since: Option<i64>,
+}
+
+impl StateMachine {
+    /// Starts in `Running`, i.e. polling fast until the state is known.
+    pub fn new(config: AdaptivePollingConfig) -> Self {
+        StateMachine { config, state: MotorState::Running, previous: None, still_since: None }
+    }
+
+    pub fn state(&self) -> MotorState {
+        self.state
+    }
+
+    /// Poll interval for the current state.
+    pub fn poll_interval(&self) -> Duration {
+        match self.state {
+            MotorState::Stopped => Duration::from_millis(self.config.stopped_ms),
+            MotorState::Ramping | MotorState::Running => Duration::from_millis(self.config.running_ms),
+        }
+    }
+
+    fn classify(&self, data: &MotorData) -> MotorState {
+        let limit = self.config.stop_speed_rpm;
+        let still = data.current_speed.abs() <= limit && data.speed_reference.is_none_or(|r| r.abs() <= limit);
+        if still {
+            return MotorState::Stopped;
+        }
+        let rate = match self.previous {
+            Some((timestamp, speed)) if data.timestamp > timestamp => {
+                (data.current_speed - speed) / ((data.timestamp - timestamp) as f64 / 1000.0)
+            }
+            _ => 0.0,
+        };
+        let lagging = data.tracking_error().is_some_and(|e| e.abs() > self.config.ramp_error_rpm);
+        if rate.abs() > self.config.ramp_rate_rpm_per_sec || lagging {
+            MotorState::Ramping
+        } else {
+            MotorState::Running
+        }
+    }
+
+    /// Feeds one sample; returns the new state after a transition.
+    pub async fn update(&mut self, pool: &SqlitePool, motor_id: &str, data: &MotorData) -> Option<MotorState> {
+        let target = self.classify(data);
+        self.previous = Some((data.timestamp, data.current_speed));
+        if target == MotorState::Stopped && self.state != MotorState::Stopped {
+            let since = *self.still_since.get_or_insert(data.timestamp);
+            if data.timestamp - since < self.config.stop_delay_secs as i64 * 1000 {
+                return None;
+            }
+        } else {
+            self.still_since = None;
+        }
+        if target == self.state {
+            return None;
+        }
+
+        let from = self.state;
+        self.state = target;
+        self.still_since = None;
+        let message = format!(
+            "{} -> {}, polling every {} ms",
+            from.name(),
+            target.name(),
+            self.poll_interval().as_millis()
+        );
+        log::info!("motor {}", message);
+        events::record_event(pool, motor_id, data.seconds(), "state_change", &message).await;
+        Some(target)
+    }
+}
@@ -0,0 +1,414 @@
+//! Online per-signal statistics over fixed time windows.
+//!
+//! Each signal keeps a running min/max/mean/variance (Welford) for the 1-minute,
+//! 15-minute and 1-hour window it is currently in. Windows are aligned to the
+//! epoch, so a 15-minute window always starts at :00, :15, :30 or :45. When a
+//! sample lands in a new window the previous one is closed and returned as a
+//! `WindowSummary` for persistence.
+//!
+//! A closed window is never reopened, since its stored summary would be
+//! replaced by one of the few samples that came late. When the host clock
+//! steps back past the start of the open windows, e.g. on an NTP
+//! correction, samples are left out of the statistics until the clock is
+//! back in the open windows; they are still stored.
+//!
+//! Signals listed under `[rollups]` get extra aggregations in every window:
+//! percentiles (`p95`), seconds above a threshold (`time_above_80`) and, for
+//! power, the energy integral (`energy_kwh`). Time-based aggregations hold
+//! each value until the next sample, split at window boundaries; intervals
+//! longer than `max_gap_ms` (the monitor was down or the bus silent) are
+//! not counted. They are stored in `signal_rollups` next to `signal_stats`.
+
+use super::config::RollupConfig;
+use super::site;
+use super::{MotorData, Signal};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum StatsWindow {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+}
+
+impl StatsWindow {
+    pub const ALL: [StatsWindow; 3] = [StatsWindow::OneMinute, StatsWindow::FifteenMinutes, StatsWindow::OneHour];
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            StatsWindow::OneMinute => 60,
+            StatsWindow::FifteenMinutes => 15 * 60,
+            StatsWindow::OneHour => 60 * 60,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            StatsWindow::OneMinute => "1m",
+            StatsWindow::FifteenMinutes => "15m",
+            StatsWindow::OneHour => "1h",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<StatsWindow> {
+        StatsWindow::ALL.iter().copied().find(|w| w.label() == label)
+    }
+
+    /// Start of the window containing `timestamp`.
+    pub fn start_of(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.seconds())
+    }
+}
+
+/// Welford accumulator for one signal in one window, plus the state of its
+/// `[rollups]` aggregations.
+#[derive(Debug, Clone)]
+struct Accumulator {
+    count: u64,
+    min: f64,
+    max: f64,
+    mean: f64,
+    m2: f64,
+    /// Values seen, kept only when percentiles are configured.
+    values: Vec<f64>,
+    /// Seconds above each `time_above` threshold.
+    above: Vec<f64>,
+    energy_kwh: f64,
+}
+
+impl Accumulator {
+    fn new(rollup: Option<&RollupConfig>) -> Self {
+        Accumulator {
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+            values: Vec::new(),
+            above: vec![0.0; rollup.map_or(0, |r| r.time_above.len())],
+            energy_kwh: 0.0,
+        }
+    }
+
+    fn push(&mut self, value: f64, rollup: Option<&RollupConfig>) {
+        if !value.is_finite() {
+            return;
+        }
+        if rollup.is_some_and(|r| !r.percentiles.is_empty()) {
+            self.values.push(value);
+        }
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Credits `ms` milliseconds at `value` to the time-based aggregations.
+    fn dwell(&mut self, rollup: &RollupConfig, value: f64, ms: i64) {
+        if !value.is_finite() || ms <= 0 {
+            return;
+        }
+        let secs = ms as f64 / 1000.0;
+        for (above, threshold) in self.above.iter_mut().zip(&rollup.time_above) {
+            if value > *threshold {
+                *above += secs;
+            }
+        }
+        if rollup.energy {
+            self.energy_kwh += value * secs / 3600.0;
+        }
+    }
+
+    fn aggregates(&self, rollup: Option<&RollupConfig>) -> BTreeMap<String, f64> {
+        let mut aggregates = BTreeMap::new();
+        let Some(rollup) = rollup else {
+            return aggregates;
+        };
+        let mut sorted = self.values.clone();
+        sorted.sort_by(f64::total_cmp);
+        for &p in &rollup.percentiles {
+            if let Some(value) = percentile(&sorted, p) {
+                aggregates.insert(format!("p{}", p), value);
+            }
+        }
+        for (threshold, above) in rollup.time_above.iter().zip(&self.above) {
+            aggregates.insert(format!("time_above_{}", threshold), *above);
+        }
+        if rollup.energy {
+            aggregates.insert("energy_kwh".to_string(), self.energy_kwh);
+        }
+        aggregates
+    }
+
+    /// Population standard deviation of the values seen so far.
+    fn stddev(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            (self.m2 / self.count as f64).sqrt()
+        }
+    }
+}
+
+/// Value at percentile `p` (0–100) of `sorted`, interpolating linearly
+/// between the two nearest ranks; `None` when empty.
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = (p / 100.0).clamp(0.0, 1.0) * last as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    Some(sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WindowSummary {
+    pub signal: Signal,
+    pub window: StatsWindow,
+    pub window_start: i64,
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub stddev: f64,
+    /// `[rollups]` aggregations by name, e.g. `p95` or `energy_kwh`.
+    #[serde(default)]
+    pub aggregates: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct StatsEngine {
+    rollups: HashMap<Signal, RollupConfig>,
+    max_gap_ms: i64,
+    open: HashMap<(Signal, StatsWindow), (i64, Accumulator)>,
+    /// The sample last pushed.
+    previous: Option<MotorData>,
+    /// Whether samples are being left out after the clock stepped back.
+    behind: bool,
+}
+
+impl StatsEngine {
+    /// `rollups` is keyed by signal name; unknown names are ignored (config
+    /// validation rejects them).
+    pub fn new(rollups: &BTreeMap<String, RollupConfig>, max_gap_ms: i64) -> Self {
+        let rollups =
+            rollups.iter().filter_map(|(name, rollup)| Some((Signal::from_name(name)?, rollup.clone()))).collect();
+        StatsEngine { rollups, max_gap_ms, ..StatsEngine::default() }
+    }
+
+    /// Folds a sample into every open window and returns the windows it closed.
+    pub fn push(&mut self, data: &MotorData) -> Vec<WindowSummary> {
+        let window = StatsWindow::OneMinute;
+        let open = self.open.get(&(Signal::Power, window)).map(|(start, _)| *start);
+        if open.is_some_and(|open| window.start_of(data.seconds()) < open) {
+            if !self.behind {
+                log::warn!("the clock stepped back before the open statistics windows; leaving samples out");
+                self.behind = true;
+            }
+            return Vec::new();
+        }
+        if std::mem::take(&mut self.behind) {
+            log::info!("the clock is back in the open statistics windows");
+        }
+        let mut closed = Vec::new();
+        let previous = self
+            .previous
+            .replace(data.clone())
+            .filter(|p| data.timestamp > p.timestamp && data.timestamp - p.timestamp <= self.max_gap_ms);
+        for signal in Signal::ALL {
+            let rollup = self.rollups.get(&signal);
+            // The previous value held since the previous sample.
+            let held = previous.as_ref().map(|p| (p.timestamp, p.value(signal)));
+            for window in StatsWindow::ALL {
+                let start = window.start_of(data.seconds());
+                let entry = self.open.entry((signal, window)).or_insert_with(|| (start, Accumulator::new(rollup)));
+                if entry.0 != start {
+                    if let (Some(rollup), Some((since, value))) = (rollup, held) {
+                        let end = (entry.0 + window.seconds()) * 1000;
+                        entry.1.dwell(rollup, value, end.min(data.timestamp) - since);
+                    }
+                    if entry.1.count > 0 {
+                        closed.push(summarize(signal, window, entry.0, &entry.1, rollup));
+                    }
+                    *entry = (start, Accumulator::new(rollup));
+                    if let (Some(rollup), Some((since, value))) = (rollup, held) {
+                        entry.1.dwell(rollup, value, data.timestamp - since.max(start * 1000));
+                    }
+                } else if let (Some(rollup), Some((since, value))) = (rollup, held) {
+                    entry.1.dwell(rollup, value, data.timestamp - since);
+                }
+                entry.1.push(data.value(signal), rollup);
+            }
+        }
+        closed
+    }
+
+    /// Statistics of the still-open window for `signal`, if it has any samples.
+    pub fn current(&self, signal: Signal, window: StatsWindow) -> Option<WindowSummary> {
+        self.open
+            .get(&(signal, window))
+            .filter(|(_, acc)| acc.count > 0)
+            .map(|(start, acc)| summarize(signal, window, *start, acc, self.rollups.get(&signal)))
+    }
+}
+
+fn summarize(
+    signal: Signal,
+    window: StatsWindow,
+    window_start: i64,
+    acc: &Accumulator,
+    rollup: Option<&RollupConfig>,
+) -> WindowSummary {
+    WindowSummary {
+        signal,
+        window,
+        window_start,
+        count: acc.count,
+        min: acc.min,
+        max: acc.max,
+        avg: acc.mean,
+        stddev: acc.stddev(),
+        aggregates: acc.aggregates(rollup),
+    }
+}
+
+pub async fn setup_stats_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS signal_stats (
+            signal TEXT NOT NULL,
+            window_size TEXT NOT NULL,
+            window_start INTEGER NOT NULL,
+            count INTEGER NOT NULL,
+            min REAL NOT NULL,
+            max REAL NOT NULL,
+            avg REAL NOT NULL,
+            stddev REAL NOT NULL,
+            site TEXT NOT NULL DEFAULT '',
+            PRIMARY KEY (signal, window_size, window_start)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS signal_rollups (
+            signal TEXT NOT NULL,
+            window_size TEXT NOT NULL,
+            window_start INTEGER NOT NULL,
+            aggregation TEXT NOT NULL,
+            value REAL NOT NULL,
+            site TEXT NOT NULL DEFAULT '',
+            PRIMARY KEY (signal, window_size, window_start, aggregation)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+pub async fn insert_window_summaries(pool: &SqlitePool, summaries: &[WindowSummary]) {
+    for summary in summaries {
+        sqlx::query(&format!(
+            r#"
+            INSERT OR REPLACE INTO signal_stats (signal, window_size, window_start, count, min, max, avg, stddev, site)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, {})
+            "#,
+            site::CLAIMED
+        ))
+        .bind(summary.signal.name())
+        .bind(summary.window.label())
+        .bind(summary.window_start)
+        .bind(summary.count as i64)
+        .bind(summary.min)
+        .bind(summary.max)
+        .bind(summary.avg)
+        .bind(summary.stddev)
+        .execute(pool)
+        .await
+        .unwrap();
+        for (aggregation, value) in &summary.aggregates {
+            sqlx::query(&format!(
+                r#"
+                INSERT OR REPLACE INTO signal_rollups (signal, window_size, window_start, aggregation, value, site)
+                VALUES (?, ?, ?, ?, ?, {})
+                "#,
+                site::CLAIMED
+            ))
+            .bind(summary.signal.name())
+            .bind(summary.window.label())
+            .bind(summary.window_start)
+            .bind(aggregation)
+            .bind(value)
+            .execute(pool)
+            .await
+            .unwrap();
+        }
+    }
+}
+
+/// Persisted summaries for `signal` in `window` whose start lies in `[from, to)`.
+pub async fn window_summaries(
+    pool: &SqlitePool,
+    signal: Signal,
+    window: StatsWindow,
+    from: i64,
+    to: i64,
+) -> Vec<WindowSummary> {
+    let rows: Vec<(i64, i64, f64, f64, f64, f64)> = sqlx::query_as(&format!(
+        r#"
+        SELECT window_start, count, min, max, avg, stddev FROM signal_stats
+        WHERE {} AND signal = ? AND window_size = ? AND window_start >= ? AND window_start < ?
+        ORDER BY window_start
+        "#,
+        site::SAMPLES
+    ))
+    .bind(signal.name())
+    .bind(window.label())
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    let aggregates: Vec<(i64, String, f64)> = sqlx::query_as(&format!(
+        r#"
+        SELECT window_start, aggregation, value FROM signal_rollups
+        WHERE {} AND signal = ? AND window_size = ? AND window_start >= ? AND window_start < ?
+        "#,
+        site::SAMPLES
+    ))
+    .bind(signal.name())
+    .bind(window.label())
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+    let mut by_window: HashMap<i64, BTreeMap<String, f64>> = HashMap::new();
+    for (window_start, aggregation, value) in aggregates {
+        by_window.entry(window_start).or_default().insert(aggregation, value);
+    }
+
+    rows.into_iter()
+        .map(|(window_start, count, min, max, avg, stddev)| WindowSummary {
+            signal,
+            window,
+            window_start,
+            count: count as u64,
+            min,
+            max,
+            avg,
+            stddev,
+            aggregates: by_window.remove(&window_start).unwrap_or_default(),
+        })
+        .collect()
+}
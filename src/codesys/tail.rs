@@ -0,0 +1,122 @@
+//! Live sample table (`rustsys tail`).
+//!
+//! Follows new samples as the running monitor stores them, the way
+//! `tail -f` follows a log: the database is opened read-only and polled at
+//! the monitor's poll interval, so it works next to a live monitor, over
+//! SSH, and on a viewer's synced copy alike. The last samples are printed
+//! first; the header is repeated every `HEADER_EVERY` rows. Values are in
+//! display units (`units`), and samples lost on the way to the database
+//! show up as a gap line from their sequence numbers.
+//!
+//! With an `[ipc]` section and the monitor running, samples come from the
+//! monitor's socket (`ipc`) as they are polled instead, without the
+//! backlog; the database is the fallback when the socket cannot be reached.
+
+use super::config::MonitorConfig;
+use super::ipc;
+use super::units::Units;
+use super::{motor_data_range, open_database_read_only, MotorData, Signal};
+use chrono::prelude::*;
+use std::error::Error;
+use std::time::Duration;
+use tokio::time;
+
+/// Samples printed before following.
+const BACKLOG: usize = 10;
+const HEADER_EVERY: usize = 20;
+const COLUMN_WIDTH: usize = 14;
+
+/// Checks `columns` against the configuration; all signals when empty.
+pub fn columns(config: &MonitorConfig, columns: &[String]) -> Result<Vec<String>, String> {
+    if columns.is_empty() {
+        return Ok(Signal::ALL.iter().map(|s| s.name().to_string()).collect());
+    }
+    for name in columns {
+        let known = Signal::from_name(name).is_some()
+            || (name == "speed_reference" && config.registers.speed_reference.is_some())
+            || config.registers.temperatures.contains_key(name);
+        if !known {
+            return Err(format!("unknown signal or temperature channel {:?} for motor {}", name, config.motor_id));
+        }
+    }
+    Ok(columns.to_vec())
+}
+
+fn header(units: &Units, columns: &[String]) -> String {
+    let mut line = format!("{:<25} {:>10}", "TIME", "SEQUENCE");
+    for name in columns {
+        line.push_str(&format!(" {:>width$}", units.column(name), width = COLUMN_WIDTH));
+    }
+    line
+}
+
+fn row(config: &MonitorConfig, units: &Units, columns: &[String], data: &MotorData) -> String {
+    let sequence = data.sequence.map_or("-".to_string(), |s| s.to_string());
+    let mut line = format!("{:<25} {:>10}", config.format.datetime(data.timestamp), sequence);
+    for name in columns {
+        let value = data.named_value(name).map_or("-".to_string(), |v| config.format.number(units.display(name, v), 2));
+        line.push_str(&format!(" {:>width$}", value, width = COLUMN_WIDTH));
+    }
+    line
+}
+
+/// Prints rows with a header every `HEADER_EVERY` and gap lines.
+struct Printer<'a> {
+    config: &'a MonitorConfig,
+    units: Units,
+    columns: &'a [String],
+    rows: usize,
+    last_sequence: Option<i64>,
+}
+
+impl Printer<'_> {
+    fn print(&mut self, data: &MotorData) {
+        if let (Some(previous), Some(sequence)) = (self.last_sequence, data.sequence) {
+            if sequence > previous + 1 {
+                println!("-- {} sample(s) lost --", sequence - previous - 1);
+            }
+        }
+        if self.rows.is_multiple_of(HEADER_EVERY) {
+            println!("{}", header(&self.units, self.columns));
+        }
+        println!("{}", row(self.config, &self.units, self.columns, data));
+        self.rows += 1;
+        self.last_sequence = data.sequence.or(self.last_sequence);
+    }
+}
+
+/// Prints the latest samples of `columns`, then follows new ones until
+/// interrupted.
+pub async fn run(config: MonitorConfig, columns: Vec<String>) -> Result<(), Box<dyn Error>> {
+    let units = Units::new(&config.registers);
+    let mut printer = Printer { config: &config, units, columns: &columns, rows: 0, last_sequence: None };
+    if let Some(settings) = &config.ipc {
+        match ipc::Client::connect(&settings.socket).await {
+            Ok(mut client) => {
+                client.call("tail", serde_json::Value::Null).await?;
+                loop {
+                    printer.print(&client.next_sample().await?);
+                }
+            }
+            Err(err) => {
+                let socket = settings.socket.display();
+                eprintln!("cannot reach the monitor on {} ({}); following the database", socket, err);
+            }
+        }
+    }
+
+    let pool = open_database_read_only(&config.database_url).await?;
+    let poll = Duration::from_millis(config.poll_interval_ms.max(200));
+    let now = Local::now().timestamp_millis();
+    let recent = motor_data_range(&pool, now - config.charts.window_secs * 1000, now + 1, None).await;
+    let mut since = recent.last().map_or(now, |d| d.timestamp);
+    let mut samples = recent[recent.len().saturating_sub(BACKLOG)..].to_vec();
+    loop {
+        for data in &samples {
+            printer.print(data);
+            since = since.max(data.timestamp);
+        }
+        time::sleep(poll).await;
+        samples = motor_data_range(&pool, since + 1, i64::MAX, None).await;
+    }
+}
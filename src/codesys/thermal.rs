@@ -0,0 +1,195 @@
+//! Inverse-time overload model (I²t thermal image).
+//!
+//! Computes what the thermal memory of a motor protection relay would
+//! (IEC 60255-149). The thermal level θ follows the square of the motor
+//! current relative to the permissible continuous current `k · I_B`
+//! (`service_factor` × `rated_current`) with the motor's heating time
+//! constant τ:
+//!
+//! ```text
+//! dθ/dt = ((I / (k · I_B))² − θ) / τ
+//! ```
+//!
+//! θ = 1 is the trip level: a sustained `k · I_B` settles just there, and
+//! a current `r · k · I_B` with r > 1 trips after `τ · ln((r² − θ₀) / (r² − 1))`
+//! from level θ₀. τ is `time_constant_secs`, or derived from the relay trip
+//! class (class 10 trips within 10 s at 7.2 × the permissible current from
+//! cold). A stopped motor cools with `standstill_cooling_factor` × τ.
+//!
+//! Crossing `warn_level` raises `thermal_overload_warning`; reaching the
+//! trip level raises `thermal_overload_critical` and, with `[interlock]`,
+//! stops the motor. Like a relay's thermal memory the level survives
+//! restarts: it is saved every minute and cooled down by the time the
+//! monitor was away.
+
+use super::alarms::{Severity, SustainedAlarm};
+use super::config::ThermalConfig;
+use super::MotorData;
+use sqlx::sqlite::SqlitePool;
+
+/// Current, as a multiple of the permissible current, at which the trip
+/// class is defined.
+const CLASS_CURRENT: f64 = 7.2;
+/// Below this fraction of the rated current the motor counts as stopped.
+const STOPPED_CURRENT: f64 = 0.1;
+/// Alarms clear this far below the level that raised them.
+const HYSTERESIS: f64 = 0.05;
+const SAVE_EVERY_SECS: i64 = 60;
+
+pub async fn setup_thermal_table(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS thermal_state (
+            motor_id TEXT PRIMARY KEY,
+            level REAL NOT NULL,
+            updated_at INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// The last saved thermal level of `motor_id` and when it was saved (epoch
+/// seconds).
+pub async fn stored_level(pool: &SqlitePool, motor_id: &str) -> Option<(f64, i64)> {
+    sqlx::query_as("SELECT level, updated_at FROM thermal_state WHERE motor_id = ?")
+        .bind(motor_id)
+        .fetch_optional(pool)
+        .await
+        .ok()?
+}
+
+async fn save_level(pool: &SqlitePool, motor_id: &str, level: f64, now: i64) {
+    sqlx::query("INSERT OR REPLACE INTO thermal_state (motor_id, level, updated_at) VALUES (?, ?, ?)")
+        .bind(motor_id)
+        .bind(level)
+        .bind(now)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// Heating time constant τ in seconds.
+pub fn time_constant(config: &ThermalConfig) -> f64 {
+    config.time_constant_secs.unwrap_or_else(|| {
+        let r2 = CLASS_CURRENT * CLASS_CURRENT;
+        config.trip_class as f64 / (r2 / (r2 - 1.0)).ln()
+    })
+}
+
+/// Seconds until trip at `ratio` × the permissible current, starting from
+/// `level`; `None` if that current never trips.
+pub fn trip_time(ratio: f64, level: f64, tau: f64) -> Option<f64> {
+    let r2 = ratio * ratio;
+    if r2 <= 1.0 {
+        return None;
+    }
+    Some(if level >= 1.0 { 0.0 } else { tau * ((r2 - level) / (r2 - 1.0)).ln() })
+}
+
+/// `level` after `secs` seconds at `ratio` × the permissible current with
+/// time constant `tau`.
+pub fn step(level: f64, ratio: f64, secs: f64, tau: f64) -> f64 {
+    let settled = ratio * ratio;
+    settled + (level - settled) * (-secs / tau).exp()
+}
+
+#[derive(Debug)]
+pub struct ThermalModel {
+    config: ThermalConfig,
+    tau: f64,
+    level: f64,
+    /// Timestamp (milliseconds) of the last sample with a current.
+    last: Option<i64>,
+    last_saved: i64,
+    warning: SustainedAlarm,
+    trip: SustainedAlarm,
+}
+
+impl ThermalModel {
+    /// Resumes from the saved level, cooled down over the time since it was
+    /// saved; starts cold without one.
+    pub async fn load(pool: &SqlitePool, motor_id: &str, config: ThermalConfig, now: i64) -> Self {
+        let tau = time_constant(&config);
+        let level = match stored_level(pool, motor_id).await {
+            Some((level, saved)) => step(level, 0.0, (now - saved).max(0) as f64, tau * config.standstill_cooling_factor),
+            None => 0.0,
+        };
+        log::info!(
+            "thermal model: tau {:.0} s, permissible current {:.1} A, level {:.0} %",
+            tau,
+            config.service_factor * config.rated_current,
+            level * 100.0
+        );
+        ThermalModel {
+            tau,
+            level,
+            last: None,
+            last_saved: now,
+            warning: SustainedAlarm::new("thermal_overload_warning", Some("current"), Severity::Warning, 0),
+            trip: SustainedAlarm::new("thermal_overload_critical", Some("current"), Severity::Critical, 0),
+            config,
+        }
+    }
+
+    /// Thermal level; 1.0 is the trip level.
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    /// Integrates the sample's current into the level, updates the alarms
+    /// and saves the level once a minute. Returns newly raised alarms as
+    /// `(id, rule)`.
+    pub async fn update(&mut self, pool: &SqlitePool, motor_id: &str, data: &MotorData) -> Vec<(i64, String)> {
+        let Some(current) = data.current else {
+            return Vec::new();
+        };
+        let permissible = self.config.service_factor * self.config.rated_current;
+        let ratio = current / permissible;
+        if let Some(last) = self.last {
+            let secs = (data.timestamp - last).max(0) as f64 / 1000.0;
+            let tau = if current < STOPPED_CURRENT * self.config.rated_current {
+                self.tau * self.config.standstill_cooling_factor
+            } else {
+                self.tau
+            };
+            self.level = step(self.level, ratio, secs, tau);
+        }
+        self.last = Some(data.timestamp);
+
+        let now = data.seconds();
+        let (level, tau) = (self.level, self.tau);
+        let mut raised = Vec::new();
+        let levels = [(&mut self.warning, self.config.warn_level), (&mut self.trip, 1.0)];
+        for (alarm, threshold) in levels {
+            let exceeded = if alarm.is_active() { level > threshold - HYSTERESIS } else { level >= threshold };
+            let id = alarm
+                .update(pool, motor_id, exceeded, now, || {
+                    let trip = match trip_time(ratio, level, tau) {
+                        Some(secs) if level < 1.0 => format!(", trip in {:.0} s", secs),
+                        _ => String::new(),
+                    };
+                    format!(
+                        "thermal image at {:.0} % of trip level: {:.1} A is {:.2} × the permissible {:.1} A{}",
+                        level * 100.0,
+                        current,
+                        ratio,
+                        permissible,
+                        trip
+                    )
+                })
+                .await;
+            if let Some(id) = id {
+                raised.push((id, alarm.rule.clone()));
+            }
+        }
+
+        if now - self.last_saved >= SAVE_EVERY_SECS {
+            save_level(pool, motor_id, self.level, now).await;
+            self.last_saved = now;
+        }
+        raised
+    }
+}
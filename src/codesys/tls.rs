@@ -0,0 +1,139 @@
+//! TLS for the API, Arrow Flight, MQTT and NATS connections.
+//!
+//! `[api.tls]` names a PEM certificate chain and private key; with it the
+//! API serves HTTPS on `api.listen` and Arrow Flight serves gRPC over TLS on
+//! `api.flight_listen`. `[mqtt.tls]` and `[nats.tls]` connect to the broker
+//! over TLS, verifying it against `ca` (the system roots when absent) and,
+//! with `cert` and `key`, authenticating with a client certificate. The
+//! files are read at start-up: a certificate renewed by an ACME client such
+//! as certbot takes effect when the monitor restarts.
+//!
+//! The configuration check reads every file that is already in place with
+//! `check_pem`, so a truncated certificate or a key pasted into the
+//! certificate's file is refused when the configuration is loaded. A file
+//! not yet in place is waited for at start-up (see `startup`).
+//!
+//! With `require_tls = true` the configuration check refuses every plain
+//! connection beyond the machine: the API or Flight listening on a
+//! non-loopback address without `[api.tls]`, MQTT or NATS without TLS, and
+//! the Modbus TCP proxy and redundancy link, which have no TLS of their own.
+
+use super::config::{ClientTlsConfig, TlsConfig};
+use axum_server::tls_rustls::RustlsConfig;
+use rumqttc::{TlsConfiguration, Transport};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use tonic::transport::{Identity, ServerTlsConfig};
+
+/// Whether `listen` is reachable from other machines.
+pub fn is_exposed(listen: SocketAddr) -> bool {
+    !listen.ip().is_loopback()
+}
+
+fn read(path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+}
+
+/// What a PEM file must hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pem {
+    /// One or more `CERTIFICATE` blocks.
+    Certificates,
+    /// A `PRIVATE KEY`, `RSA PRIVATE KEY` or `EC PRIVATE KEY` block.
+    PrivateKey,
+}
+
+impl Pem {
+    fn accepts(self, label: &str) -> bool {
+        match self {
+            Pem::Certificates => label == "CERTIFICATE",
+            Pem::PrivateKey => label.ends_with("PRIVATE KEY"),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Pem::Certificates => "a certificate",
+            Pem::PrivateKey => "a private key",
+        }
+    }
+}
+
+/// Reads `path` and checks that it holds `what` in well-formed PEM blocks.
+/// A missing file fails with `NotFound`, malformed content with
+/// `InvalidData`.
+pub fn check_pem(path: &Path, what: Pem) -> io::Result<()> {
+    let invalid =
+        |message: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+    let text = String::from_utf8(read(path)?).map_err(|_| invalid("is not PEM text".to_string()))?;
+    let mut found = false;
+    let mut lines = text.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        let Some(label) = line.strip_prefix("-----BEGIN ").and_then(|l| l.strip_suffix("-----")) else {
+            continue;
+        };
+        let end = format!("-----END {}-----", label);
+        let mut body = String::new();
+        loop {
+            match lines.next() {
+                Some(line) if line == end => break,
+                Some(line) if line.contains(':') => {} // RFC 1421 headers of encrypted keys
+                Some(line) => body.push_str(line),
+                None => return Err(invalid(format!("the {} block is not closed", label))),
+            }
+        }
+        if !is_base64(&body) {
+            return Err(invalid(format!("the {} block is not valid base64", label)));
+        }
+        found |= what.accepts(label);
+    }
+    if found {
+        Ok(())
+    } else {
+        Err(invalid(format!("holds no {} in PEM format", what.name())))
+    }
+}
+
+fn is_base64(body: &str) -> bool {
+    let data = body.trim_end_matches('=');
+    !body.is_empty()
+        && body.len().is_multiple_of(4)
+        && body.len() - data.len() <= 2
+        && data.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+}
+
+/// Server certificate of the API.
+pub async fn api_config(tls: &TlsConfig) -> io::Result<RustlsConfig> {
+    RustlsConfig::from_pem(read(&tls.cert)?, read(&tls.key)?).await
+}
+
+/// Server certificate of Arrow Flight.
+pub fn flight_config(tls: &TlsConfig) -> io::Result<ServerTlsConfig> {
+    Ok(ServerTlsConfig::new().identity(Identity::from_pem(read(&tls.cert)?, read(&tls.key)?)))
+}
+
+/// Transport of the MQTT connection; a client certificate needs `ca`.
+pub fn mqtt_transport(tls: &ClientTlsConfig) -> io::Result<Transport> {
+    let client_auth = match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => Some((read(cert)?, read(key)?)),
+        _ => None,
+    };
+    match &tls.ca {
+        Some(ca) => Ok(Transport::Tls(TlsConfiguration::Simple { ca: read(ca)?, alpn: None, client_auth })),
+        None => Ok(Transport::tls_with_default_config()),
+    }
+}
+
+/// `options` connecting to NATS over TLS only.
+pub fn nats_options(options: async_nats::ConnectOptions, tls: &ClientTlsConfig) -> async_nats::ConnectOptions {
+    let mut options = options.require_tls(true);
+    if let Some(ca) = &tls.ca {
+        options = options.add_root_certificates(ca.clone());
+    }
+    if let (Some(cert), Some(key)) = (&tls.cert, &tls.key) {
+        options = options.add_client_certificate(cert.clone(), key.clone());
+    }
+    options
+}
@@ -0,0 +1,358 @@
+//! Field-bus transports.
+//!
+//! The polling loop only sees the `Transport` trait, so storage, alarms and
+//! charts work the same whether values come over Modbus RTU or EtherNet/IP.
+
+use super::config::{MonitorConfig, Parity, RegisterDef, TransportKind, WriteVerifyConfig};
+use super::devices::DeviceInfo;
+use super::ethernet_ip::EthernetIpClient;
+use super::serial_ports;
+use async_trait::async_trait;
+use rtu_client::{Client, Context};
+use std::fmt;
+use tokio_serial::SerialPortBuilderExt;
+
+#[derive(Debug)]
+pub enum TransportError {
+    Io(std::io::Error),
+    Modbus(String),
+    Protocol(String),
+    /// A verified write did not take: the register read back differently.
+    Rejected { written: u16, read: f64 },
+    /// A verified write was acknowledged, but reading it back failed, so
+    /// whether it took is unknown.
+    Unverified(Box<TransportError>),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::Io(err) => write!(f, "I/O error: {}", err),
+            TransportError::Modbus(msg) => write!(f, "Modbus error: {}", msg),
+            TransportError::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            TransportError::Rejected { written, read } => {
+                write!(f, "write not taken by the device: wrote {}, read back {}", written, read)
+            }
+            TransportError::Unverified(err) => write!(f, "written, but reading it back failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+impl From<std::io::Error> for TransportError {
+    fn from(err: std::io::Error) -> Self {
+        TransportError::Io(err)
+    }
+}
+
+/// Modbus function codes for reading registers.
+pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+pub const READ_INPUT_REGISTERS: u8 = 0x04;
+
+/// Modbus function code for writing consecutive holding registers.
+pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+/// Registers per Modbus read request.
+const MAX_READ_REGISTERS: u16 = 125;
+
+/// Registers per Modbus write multiple registers request.
+pub const MAX_WRITE_REGISTERS: u16 = 123;
+
+/// Function codes the Modbus specification leaves to vendors.
+pub fn is_user_defined_function(function: u8) -> bool {
+    matches!(function, 65..=72 | 100..=110)
+}
+
+/// Function codes a register map entry may read with.
+pub fn is_read_function(function: u8) -> bool {
+    matches!(function, READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS) || is_user_defined_function(function)
+}
+
+#[async_trait]
+pub trait Transport: Send {
+    /// Reads the raw value behind one register definition. Two-word
+    /// definitions are combined into a single 32-bit value.
+    async fn read(&mut self, def: &RegisterDef) -> Result<f64, TransportError>;
+
+    /// Writes a command value to a holding register (Modbus) or drive
+    /// parameter (EtherNet/IP): operator commands, setpoint ramp steps,
+    /// parameter sets and the interlock stop all go through here. `Ok`
+    /// means the device acknowledged the write, and with `[write_verify]`
+    /// that reading it back gave the value written (`VerifiedWrites`).
+    async fn write(&mut self, def: &RegisterDef, value: u16) -> Result<(), TransportError>;
+
+    /// Writes `values` to consecutive holding registers from `address` in a
+    /// single request (Modbus 0x10), which the device applies as a whole.
+    /// Only when `writes_multiple`.
+    async fn write_multiple(&mut self, address: u16, values: &[u16]) -> Result<(), TransportError> {
+        let _ = (address, values);
+        Err(TransportError::Protocol("writing multiple registers is not supported by this transport".to_string()))
+    }
+
+    /// Whether `write_multiple` is supported.
+    fn writes_multiple(&self) -> bool {
+        false
+    }
+
+    /// Vendor, product and firmware of the connected device, or `None` if
+    /// the transport cannot ask for them.
+    async fn identify(&mut self) -> Result<Option<DeviceInfo>, TransportError> {
+        Ok(None)
+    }
+
+    /// Reads `len` consecutive registers starting at `def.address`, e.g. a
+    /// waveform buffer captured by the drive. Not every transport supports
+    /// block reads.
+    async fn read_block(&mut self, def: &RegisterDef, len: u16) -> Result<Vec<u16>, TransportError> {
+        let _ = (def, len);
+        Err(TransportError::Protocol("block reads are not supported by this transport".to_string()))
+    }
+
+    /// Reads a register of another device on the bus, addressed by its
+    /// Modbus unit id, e.g. an ambient sensor next to the motor. Modbus only.
+    async fn read_unit(&mut self, unit: u8, def: &RegisterDef) -> Result<f64, TransportError> {
+        let _ = def;
+        Err(TransportError::Protocol(format!("cannot address bus device {}: not supported by this transport", unit)))
+    }
+
+    /// Sends a raw request PDU (function code plus `data`) and returns the
+    /// response data after the function code. Escape hatch for vendor
+    /// functions the register map cannot describe; Modbus only.
+    async fn raw(&mut self, function: u8, data: &[u8]) -> Result<Vec<u8>, TransportError> {
+        let _ = data;
+        Err(TransportError::Protocol(format!(
+            "function code {:#04x} is not supported by this transport",
+            function
+        )))
+    }
+}
+
+/// Modbus RTU over a serial line.
+///
+/// Register map entries read with 0x04 (input registers, the default) or
+/// 0x03 (holding registers). An entry with a user-defined function code is
+/// sent like a standard read, `[address hi, lo, count hi, lo]`, and its
+/// response is expected in the standard layout too, a byte count followed by
+/// big-endian words. Vendor functions with other layouts go through
+/// `Transport::raw`.
+pub struct ModbusRtu {
+    ctx: Client,
+    slave_id: u8,
+}
+
+impl ModbusRtu {
+    pub fn open(config: &MonitorConfig) -> Result<Self, TransportError> {
+        let parity = match config.serial.parity {
+            Parity::None => tokio_serial::Parity::None,
+            Parity::Even => tokio_serial::Parity::Even,
+            Parity::Odd => tokio_serial::Parity::Odd,
+        };
+        let path = match &config.serial.usb {
+            Some(usb) => {
+                let path = serial_ports::resolve(usb).map_err(TransportError::Protocol)?;
+                log::info!("USB serial adapter {:04x}:{:04x} is {}", usb.vid, usb.pid, path);
+                path
+            }
+            None => config.serial.path.clone(),
+        };
+        let serial_port = tokio_serial::new(&path, config.serial.baud_rate)
+            .data_bits(tokio_serial::DataBits::Eight)
+            .parity(parity)
+            .stop_bits(tokio_serial::StopBits::One)
+            .flow_control(tokio_serial::FlowControl::None)
+            .open_native_async()
+            .map_err(|e| TransportError::Io(e.into()))?;
+
+        Ok(ModbusRtu { ctx: Client::new(serial_port, config.serial.slave_id), slave_id: config.serial.slave_id })
+    }
+
+    async fn read_words(&mut self, function: u8, address: u16, count: u16) -> Result<Vec<u16>, TransportError> {
+        match function {
+            READ_HOLDING_REGISTERS => self.ctx.read_holding_registers(address, count).await,
+            READ_INPUT_REGISTERS => self.ctx.read_input_registers(address, count).await,
+            function => {
+                let mut request = address.to_be_bytes().to_vec();
+                request.extend_from_slice(&count.to_be_bytes());
+                let response = self.raw(function, &request).await?;
+                return decode_words(&response, count);
+            }
+        }
+        .map_err(|e| TransportError::Modbus(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl Transport for ModbusRtu {
+    async fn read(&mut self, def: &RegisterDef) -> Result<f64, TransportError> {
+        let words = self.read_words(def.function, def.address, def.count).await?;
+        Ok(words.iter().fold(0u32, |acc, w| (acc << 16) | *w as u32) as f64)
+    }
+
+    async fn read_block(&mut self, def: &RegisterDef, len: u16) -> Result<Vec<u16>, TransportError> {
+        let mut words = Vec::with_capacity(len as usize);
+        while words.len() < len as usize {
+            let offset = words.len() as u16;
+            let count = (len - offset).min(MAX_READ_REGISTERS);
+            let address = def.address.checked_add(offset).ok_or_else(|| {
+                TransportError::Protocol(format!("block at {} with {} registers exceeds the address space", def.address, len))
+            })?;
+            words.extend(self.read_words(def.function, address, count).await?);
+        }
+        Ok(words)
+    }
+
+    async fn read_unit(&mut self, unit: u8, def: &RegisterDef) -> Result<f64, TransportError> {
+        self.ctx.set_slave(unit);
+        let result = self.read(def).await;
+        self.ctx.set_slave(self.slave_id);
+        result
+    }
+
+    async fn write(&mut self, def: &RegisterDef, value: u16) -> Result<(), TransportError> {
+        self.ctx
+            .write_single_register(def.address, value)
+            .await
+            .map_err(|e| TransportError::Modbus(e.to_string()))
+    }
+
+    async fn write_multiple(&mut self, address: u16, values: &[u16]) -> Result<(), TransportError> {
+        let count = values.len() as u16;
+        if values.is_empty() || count > MAX_WRITE_REGISTERS {
+            return Err(TransportError::Protocol(format!(
+                "a single request writes 1 to {} registers, got {}",
+                MAX_WRITE_REGISTERS,
+                values.len()
+            )));
+        }
+        // [address, count, byte count, values], answered by [address, count].
+        let mut request = address.to_be_bytes().to_vec();
+        request.extend_from_slice(&count.to_be_bytes());
+        request.push((count * 2) as u8);
+        for value in values {
+            request.extend_from_slice(&value.to_be_bytes());
+        }
+        let response = self.raw(WRITE_MULTIPLE_REGISTERS, &request).await?;
+        if response.get(..4) != Some(&request[..4]) {
+            return Err(TransportError::Protocol(format!(
+                "write of {} register(s) at {} answered with {:02x?}",
+                count, address, response
+            )));
+        }
+        Ok(())
+    }
+
+    fn writes_multiple(&self) -> bool {
+        true
+    }
+
+    async fn raw(&mut self, function: u8, data: &[u8]) -> Result<Vec<u8>, TransportError> {
+        self.ctx
+            .call_custom(function, data)
+            .await
+            .map_err(|e| TransportError::Modbus(e.to_string()))
+    }
+}
+
+/// Words of a read response in the standard layout: a byte count followed
+/// by `count` big-endian registers.
+fn decode_words(response: &[u8], count: u16) -> Result<Vec<u16>, TransportError> {
+    let expected = count as usize * 2;
+    match response.split_first() {
+        Some((&len, words)) if len as usize == expected && words.len() == expected => {
+            Ok(words.chunks_exact(2).map(|w| u16::from_be_bytes([w[0], w[1]])).collect())
+        }
+        _ => Err(TransportError::Protocol(format!(
+            "expected {} register(s) in the response, got {} byte(s)",
+            count,
+            response.len()
+        ))),
+    }
+}
+
+/// Reads every written register back (`[write_verify]`), since some
+/// drives acknowledge a write they then ignore, e.g. an out-of-range
+/// setpoint. Modbus registers are read back as holding registers. A value
+/// further than `tolerance` counts from the one written fails the write
+/// with `TransportError::Rejected`; a failed read-back with
+/// `TransportError::Unverified`.
+pub struct VerifiedWrites {
+    inner: Box<dyn Transport>,
+    config: WriteVerifyConfig,
+}
+
+#[async_trait]
+impl Transport for VerifiedWrites {
+    async fn read(&mut self, def: &RegisterDef) -> Result<f64, TransportError> {
+        self.inner.read(def).await
+    }
+
+    async fn write(&mut self, def: &RegisterDef, value: u16) -> Result<(), TransportError> {
+        self.inner.write(def, value).await?;
+        if self.config.delay_ms > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(self.config.delay_ms)).await;
+        }
+        let def = RegisterDef { function: READ_HOLDING_REGISTERS, ..*def };
+        let read = self.inner.read(&def).await.map_err(unverified)?;
+        if (read - value as f64).abs() > self.config.tolerance as f64 {
+            return Err(TransportError::Rejected { written: value, read });
+        }
+        Ok(())
+    }
+
+    async fn write_multiple(&mut self, address: u16, values: &[u16]) -> Result<(), TransportError> {
+        self.inner.write_multiple(address, values).await?;
+        if self.config.delay_ms > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(self.config.delay_ms)).await;
+        }
+        let def = RegisterDef { address, count: 1, function: READ_HOLDING_REGISTERS };
+        let read = self.inner.read_block(&def, values.len() as u16).await.map_err(unverified)?;
+        for (&written, &read) in values.iter().zip(&read) {
+            if (read as f64 - written as f64).abs() > self.config.tolerance as f64 {
+                return Err(TransportError::Rejected { written, read: read as f64 });
+            }
+        }
+        Ok(())
+    }
+
+    fn writes_multiple(&self) -> bool {
+        self.inner.writes_multiple()
+    }
+
+    async fn identify(&mut self) -> Result<Option<DeviceInfo>, TransportError> {
+        self.inner.identify().await
+    }
+
+    async fn read_block(&mut self, def: &RegisterDef, len: u16) -> Result<Vec<u16>, TransportError> {
+        self.inner.read_block(def, len).await
+    }
+
+    async fn read_unit(&mut self, unit: u8, def: &RegisterDef) -> Result<f64, TransportError> {
+        self.inner.read_unit(unit, def).await
+    }
+
+    async fn raw(&mut self, function: u8, data: &[u8]) -> Result<Vec<u8>, TransportError> {
+        self.inner.raw(function, data).await
+    }
+}
+
+fn unverified(err: TransportError) -> TransportError {
+    TransportError::Unverified(Box::new(err))
+}
+
+/// Opens the transport selected in the configuration.
+pub async fn connect(config: &MonitorConfig) -> Result<Box<dyn Transport>, TransportError> {
+    let transport: Box<dyn Transport> = match config.transport {
+        TransportKind::ModbusRtu => Box::new(ModbusRtu::open(config)?),
+        TransportKind::EthernetIp => {
+            let settings = config.ethernet_ip.as_ref().ok_or_else(|| {
+                TransportError::Protocol("transport is ethernet_ip but [ethernet_ip] is missing".to_string())
+            })?;
+            Box::new(EthernetIpClient::connect(settings).await?)
+        }
+    };
+    Ok(match &config.write_verify {
+        Some(verify) => Box::new(VerifiedWrites { inner: transport, config: verify.clone() }),
+        None => transport,
+    })
+}
@@ -0,0 +1,232 @@
+//! Long-term trends and remaining useful life (RUL).
+//!
+//! Ageing shows up as slow drift over weeks: worn bearings or clogged
+//! cooling make a motor run hotter at the same load, growing losses make it
+//! draw more electrical power for the same mechanical output. From the hourly
+//! statistics (`signal_stats`) of the last `trends.window_days`, one point
+//! per day is derived for each quantity:
+//!
+//! - temperature at load: mean heat over the hours whose mean power lies
+//!   within `load_band_percent` of `reference_load_percent` of rated power,
+//!   so a change of duty does not read as drift;
+//! - efficiency: mechanical power (mean torque at mean speed) over mean
+//!   electrical power, for hours above 10 % load. Torque is a fixed
+//!   placeholder today, so this trend only means something once torque is
+//!   measured.
+//!
+//! - rotor bar and bearing sidebands: daily mean of the MCSA indicators
+//!   (see `mcsa`), when `[mcsa]` is configured.
+//!
+//! A least-squares line through the daily points gives the drift per day
+//! and, where a threshold applies, the days until the line crosses it. The
+//! motor's RUL estimate is the shortest of these. Vibration is not read by
+//! any transport yet, so there is no vibration trend.
+
+use super::calc;
+use super::config::{McsaConfig, MonitorConfig, TrendsConfig};
+use super::fleet;
+use super::mcsa;
+use super::stats::{window_summaries, StatsWindow, WindowSummary};
+use super::{MotorSpecs, Signal};
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+
+const DAY_SECS: i64 = 24 * 3600;
+
+/// Hours below this share of rated power are left out of the efficiency trend.
+const MIN_EFFICIENCY_LOAD: f64 = 0.1;
+
+/// What the trend analysis needs from the monitor configuration.
+#[derive(Debug, Clone)]
+pub struct TrendSettings {
+    pub config: TrendsConfig,
+    pub specs: MotorSpecs,
+    /// Threshold of the temperature trend; see `fleet::heat_limit`.
+    pub heat_limit: Option<f64>,
+    /// Adds the MCSA indicator trends when set.
+    pub mcsa: Option<McsaConfig>,
+}
+
+impl TrendSettings {
+    /// `None` without a `[trends]` section.
+    pub fn from_config(config: &MonitorConfig) -> Option<TrendSettings> {
+        config.trends.as_ref().map(|trends| TrendSettings {
+            config: trends.clone(),
+            specs: config.motor.clone(),
+            heat_limit: fleet::heat_limit(config),
+            mcsa: config.mcsa.clone(),
+        })
+    }
+}
+
+/// `y = intercept + slope · x`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinearFit {
+    pub slope: f64,
+    pub intercept: f64,
+}
+
+/// Least-squares line through `points`; `None` unless there are at least
+/// two distinct x values.
+pub fn linear_fit(points: &[(f64, f64)]) -> Option<LinearFit> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    if sxx <= 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some(LinearFit { slope, intercept: mean_y - slope * mean_x })
+}
+
+/// Time (in units of x) from `x = 0` until `fit` reaches `threshold`,
+/// approached from below when `rising`, from above otherwise. `Some(0.0)`
+/// if already past it, `None` if the line moves away from it.
+pub fn time_to_threshold(fit: &LinearFit, threshold: f64, rising: bool) -> Option<f64> {
+    let (gap, rate) = match rising {
+        true => (threshold - fit.intercept, fit.slope),
+        false => (fit.intercept - threshold, -fit.slope),
+    };
+    if gap <= 0.0 {
+        Some(0.0)
+    } else if rate > 0.0 {
+        Some(gap / rate)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Trend {
+    pub name: &'static str,
+    pub unit: &'static str,
+    /// Days with a point in the window.
+    pub points: usize,
+    /// Fit over days relative to the end of the window (x = 0); `None` with
+    /// fewer than `min_points` points.
+    pub fit: Option<LinearFit>,
+    pub threshold: Option<f64>,
+    /// Whether the threshold is crossed from below (temperature) or from
+    /// above (efficiency).
+    pub rising: bool,
+}
+
+impl Trend {
+    /// Fitted value at the end of the window.
+    pub fn current(&self) -> Option<f64> {
+        self.fit.map(|fit| fit.intercept)
+    }
+
+    pub fn slope_per_day(&self) -> Option<f64> {
+        self.fit.map(|fit| fit.slope)
+    }
+
+    pub fn days_to_threshold(&self) -> Option<f64> {
+        time_to_threshold(&self.fit?, self.threshold?, self.rising)
+    }
+}
+
+/// The shortest time to threshold over `trends`, in days, with the trend
+/// that reaches its threshold first.
+pub fn remaining_useful_life(trends: &[Trend]) -> Option<(f64, &Trend)> {
+    trends
+        .iter()
+        .filter_map(|trend| trend.days_to_threshold().map(|days| (days, trend)))
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+async fn hourly(pool: &SqlitePool, signal: Signal, from: i64, to: i64) -> BTreeMap<i64, WindowSummary> {
+    window_summaries(pool, signal, StatsWindow::OneHour, from, to)
+        .await
+        .into_iter()
+        .map(|summary| (summary.window_start, summary))
+        .collect()
+}
+
+/// Mean of the values per day, as `(days relative to to, mean)`.
+fn daily_means(values: &[(i64, f64)], to: i64) -> Vec<(f64, f64)> {
+    let mut days: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for &(timestamp, value) in values {
+        let day = days.entry(timestamp.div_euclid(DAY_SECS)).or_insert((0.0, 0));
+        day.0 += value;
+        day.1 += 1;
+    }
+    days.into_iter()
+        .map(|(day, (sum, count))| {
+            let middle = day * DAY_SECS + DAY_SECS / 2;
+            ((middle - to) as f64 / DAY_SECS as f64, sum / count as f64)
+        })
+        .collect()
+}
+
+fn trend(name: &'static str, unit: &'static str, points: Vec<(f64, f64)>, min_points: usize, threshold: Option<f64>, rising: bool) -> Trend {
+    let fit = if points.len() >= min_points { linear_fit(&points) } else { None };
+    Trend { name, unit, points: points.len(), fit, threshold, rising }
+}
+
+/// Trends over the `window_days` before `to` (epoch seconds).
+pub async fn analyze(pool: &SqlitePool, settings: &TrendSettings, to: i64) -> Vec<Trend> {
+    let config = &settings.config;
+    let from = to - config.window_days as i64 * DAY_SECS;
+    let power = hourly(pool, Signal::Power, from, to).await;
+    let heat = hourly(pool, Signal::Heat, from, to).await;
+    let torque = hourly(pool, Signal::Torque, from, to).await;
+    let speed = hourly(pool, Signal::Speed, from, to).await;
+
+    let rated = settings.specs.rated_power;
+    let reference = rated * config.reference_load_percent / 100.0;
+    let band = rated * config.load_band_percent / 100.0;
+    let mut at_load = Vec::new();
+    let mut efficiency = Vec::new();
+    for (&start, p) in &power {
+        if let Some(h) = heat.get(&start).filter(|_| (p.avg - reference).abs() <= band) {
+            at_load.push((start, h.avg));
+        }
+        if p.avg > rated * MIN_EFFICIENCY_LOAD {
+            if let (Some(t), Some(s)) = (torque.get(&start), speed.get(&start)) {
+                efficiency.push((start, calc::power_from_torque(t.avg, s.avg) / p.avg));
+            }
+        }
+    }
+
+    let mut trends = vec![
+        trend(
+            "Temperature at load",
+            "°C",
+            daily_means(&at_load, to),
+            config.min_points,
+            settings.heat_limit,
+            true,
+        ),
+        trend("Efficiency", "", daily_means(&efficiency, to), config.min_points, config.min_efficiency, false),
+    ];
+    if let Some(mcsa_config) = &settings.mcsa {
+        let results = mcsa::history(pool, from, to).await;
+        let rotor_bar: Vec<(i64, f64)> = results.iter().filter_map(|r| Some((r.timestamp, r.rotor_bar_db?))).collect();
+        let bearing: Vec<(i64, f64)> = results.iter().filter_map(|r| Some((r.timestamp, r.bearing_db?))).collect();
+        trends.push(trend(
+            "Rotor bar sidebands",
+            "dB",
+            daily_means(&rotor_bar, to),
+            config.min_points,
+            mcsa_config.rotor_bar_alarm_db,
+            true,
+        ));
+        if !mcsa_config.bearing_orders.is_empty() {
+            trends.push(trend(
+                "Bearing sidebands",
+                "dB",
+                daily_means(&bearing, to),
+                config.min_points,
+                mcsa_config.bearing_alarm_db,
+                true,
+            ));
+        }
+    }
+    trends
+}
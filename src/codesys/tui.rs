@@ -0,0 +1,220 @@
+//! Terminal dashboard (`rustsys tui`).
+//!
+//! For headless edge boxes reached over SSH, where neither the PNG charts
+//! nor a browser are at hand. The dashboard opens the monitor's database
+//! read-only, so it runs next to a live monitor without disturbing it, and
+//! redraws once a second: the latest values, a sparkline per signal over
+//! `charts.window_secs`, the active alarms, and link statistics derived
+//! from the stored samples. `q` or Esc quits.
+
+use super::alarms::{self, Alarm, AlarmFilter};
+use super::charts::chart_spec;
+use super::config::MonitorConfig;
+use super::units::Units;
+use super::{motor_data_range, open_database_read_only, MotorData, Signal};
+use chrono::prelude::*;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Sparkline};
+use ratatui::{Frame, Terminal};
+use sqlx::sqlite::SqlitePool;
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// Communication health over the sparkline window, from the stored samples.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinkStats {
+    pub samples: usize,
+    /// Samples the window should hold at the configured poll interval.
+    pub expected: usize,
+    /// Age of the newest sample in seconds; `None` without samples.
+    pub last_age_secs: Option<i64>,
+    /// Sequence numbers skipped (samples lost on the way to the database).
+    pub lost: i64,
+    /// Sequence numbers seen more than once.
+    pub duplicated: i64,
+}
+
+/// Link statistics of `samples` (oldest first) covering `window_ms` up to
+/// `now` (milliseconds).
+pub fn link_stats(samples: &[MotorData], window_ms: i64, poll_interval_ms: u64, now: i64) -> LinkStats {
+    let mut stats = LinkStats {
+        samples: samples.len(),
+        expected: (window_ms / poll_interval_ms.max(1) as i64) as usize,
+        last_age_secs: samples.last().map(|d| (now - d.timestamp).max(0) / 1000),
+        ..LinkStats::default()
+    };
+    let sequences: Vec<i64> = samples.iter().filter_map(|d| d.sequence).collect();
+    for pair in sequences.windows(2) {
+        match pair[1] - pair[0] {
+            1 => {}
+            step if step > 1 => stats.lost += step - 1,
+            _ => stats.duplicated += 1,
+        }
+    }
+    stats
+}
+
+struct View {
+    samples: Vec<MotorData>,
+    alarms: Vec<Alarm>,
+    link: LinkStats,
+}
+
+async fn load(pool: &SqlitePool, config: &MonitorConfig, now: i64) -> View {
+    let window_ms = config.charts.window_secs * 1000;
+    let samples = motor_data_range(pool, now - window_ms, now + 1, None).await;
+    let filter = AlarmFilter { motor_id: Some(config.motor_id.clone()), active_only: true, ..AlarmFilter::default() };
+    let alarms = alarms::list_alarms(pool, &filter).await;
+    let link = link_stats(&samples, window_ms, config.poll_interval_ms, now);
+    View { samples, alarms, link }
+}
+
+/// Sparkline heights: `values` scaled onto 0–100 between their minimum and
+/// maximum, so small movements of a large value stay visible.
+fn sparkline_data(values: &[f64]) -> Vec<u64> {
+    let (min, max) = values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let span = if max > min { max - min } else { 1.0 };
+    values.iter().map(|v| ((v - min) / span * 100.0).round() as u64).collect()
+}
+
+fn draw(frame: &mut Frame, config: &MonitorConfig, units: &Units, view: &View) {
+    let format = &config.format;
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(10), Constraint::Min(10), Constraint::Length(8)])
+        .split(frame.size());
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[0]);
+
+    let mut values = Vec::new();
+    match view.samples.last() {
+        Some(latest) => {
+            values.push(Line::from(format!("at {}", format.datetime(latest.timestamp))));
+            let latest = units.sample(latest);
+            for signal in Signal::ALL {
+                let (_, label) = chart_spec(signal, units);
+                values.push(Line::from(format!("{:<16} {:>12}", label, format.number(latest.value(signal), 1))));
+            }
+            if let Some(reference) = latest.speed_reference {
+                let label = format!("Reference ({})", units.unit("speed_reference"));
+                values.push(Line::from(format!("{:<16} {:>12}", label, format.number(reference, 0))));
+            }
+            for (channel, value) in &latest.temperatures {
+                let label = format!("{} ({})", channel, units.unit(channel));
+                values.push(Line::from(format!("{:<16} {:>12}", label, format.number(*value, 1))));
+            }
+        }
+        None => values.push(Line::from("no samples in the window")),
+    }
+    let title = format!(" {} ", config.motor_id);
+    frame.render_widget(Paragraph::new(values).block(Block::default().title(title).borders(Borders::ALL)), top[0]);
+
+    let link = &view.link;
+    let age = link.last_age_secs.map_or("-".to_string(), |secs| format!("{} s", secs));
+    let stale = link.last_age_secs.is_none_or(|secs| secs * 1000 > 3 * config.poll_interval_ms as i64);
+    let link_lines = vec![
+        Line::from(format!("samples       {} of {} expected", link.samples, link.expected)),
+        Line::from(format!("latest        {} ago", age)),
+        Line::from(format!("lost          {}", link.lost)),
+        Line::from(format!("duplicated    {}", link.duplicated)),
+        Line::from(format!("poll interval {} ms", config.poll_interval_ms)),
+    ];
+    let link_style = if stale { Style::default().fg(Color::Yellow) } else { Style::default() };
+    let link_block = Block::default().title(" Link ").borders(Borders::ALL);
+    frame.render_widget(Paragraph::new(link_lines).style(link_style).block(link_block), top[1]);
+
+    draw_sparklines(frame, config, units, view, rows[1]);
+
+    let items: Vec<ListItem> = view
+        .alarms
+        .iter()
+        .map(|alarm| {
+            let color = match alarm.severity {
+                alarms::Severity::Critical => Color::Red,
+                _ => Color::Yellow,
+            };
+            let raised = format.datetime(alarm.raised_at * 1000);
+            let ack = if alarm.acknowledged_at.is_some() { " (ack)" } else { "" };
+            let text = format!("{} {:<8} {}{}: {}", raised, alarm.severity.name(), alarm.rule, ack, alarm.message);
+            ListItem::new(text).style(Style::default().fg(color))
+        })
+        .collect();
+    let title = format!(" Active alarms ({}) ", view.alarms.len());
+    frame.render_widget(List::new(items).block(Block::default().title(title).borders(Borders::ALL)), rows[2]);
+}
+
+fn draw_sparklines(frame: &mut Frame, config: &MonitorConfig, units: &Units, view: &View, area: Rect) {
+    let constraints = vec![Constraint::Ratio(1, Signal::ALL.len() as u32); Signal::ALL.len()];
+    let cells = Layout::default().direction(Direction::Vertical).constraints(constraints).split(area);
+    for (signal, cell) in Signal::ALL.into_iter().zip(cells.iter()) {
+        let values: Vec<f64> = view.samples.iter().map(|d| units.display(signal.name(), d.value(signal))).collect();
+        // One bar per column; keep the newest samples when there are more.
+        let width = cell.width.saturating_sub(2) as usize;
+        let shown = &values[values.len().saturating_sub(width)..];
+        let (lo, hi) = shown.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let (_, label) = chart_spec(signal, units);
+        let title = if shown.is_empty() {
+            format!(" {} ", label)
+        } else {
+            format!(
+                " {}  {} … {}, last {} min ",
+                label,
+                config.format.number(lo, 1),
+                config.format.number(hi, 1),
+                config.charts.window_secs / 60
+            )
+        };
+        let data = sparkline_data(shown);
+        let sparkline = Sparkline::default()
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .data(&data)
+            .style(Style::default().fg(Color::Red));
+        frame.render_widget(sparkline, *cell);
+    }
+}
+
+/// Whether a key asking to quit was pressed within `timeout`.
+fn quit_requested(timeout: Duration) -> io::Result<bool> {
+    if !event::poll(timeout)? {
+        return Ok(false);
+    }
+    Ok(matches!(
+        event::read()?,
+        Event::Key(key) if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+    ))
+}
+
+/// Runs the dashboard until the user quits; the terminal is restored on
+/// return, also after an error.
+pub async fn run(config: MonitorConfig) -> Result<(), Box<dyn Error>> {
+    let pool = open_database_read_only(&config.database_url).await?;
+    terminal::enable_raw_mode()?;
+    io::stdout().execute(EnterAlternateScreen)?;
+    let result = run_loop(&pool, &config).await;
+    terminal::disable_raw_mode()?;
+    io::stdout().execute(LeaveAlternateScreen)?;
+    result
+}
+
+async fn run_loop(pool: &SqlitePool, config: &MonitorConfig) -> Result<(), Box<dyn Error>> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    let units = Units::new(&config.registers);
+    loop {
+        let view = load(pool, config, Local::now().timestamp_millis()).await;
+        terminal.draw(|frame| draw(frame, config, &units, &view))?;
+        if tokio::task::block_in_place(|| quit_requested(REFRESH))? {
+            return Ok(());
+        }
+    }
+}
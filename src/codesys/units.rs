@@ -0,0 +1,141 @@
+//! Engineering units of signals, temperature channels and pulse inputs.
+//!
+//! Samples are stored, alarmed on and aggregated in the base units of
+//! `calc`: kW, Nm, rpm, °C and Nm.s; pulse inputs in their configured
+//! `unit`. `[registers.units.<name>]` chooses the
+//! unit a value is shown in and its factor from the base unit, e.g.
+//! `{ unit = "W", scale = 1000.0 }` for power in watts. Exports (CSV
+//! headers, Arrow field metadata, `unit.<name>` metadata entries), chart
+//! axes and the terminal dashboard use the display units. A CSV column
+//! without a configured unit keeps its plain name, as before units existed.
+//!
+//! Both units of every value are listed by `Units::all`: in the
+//! configuration snapshots kept with the data (see `config_history`) and by
+//! GraphQL `units`, whose other queries serve the stored values.
+
+use super::config::{RegisterMap, UnitConfig};
+use super::{MotorData, Signal};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Unit of a value as stored; any name other than a signal or the speed
+/// reference is a temperature channel.
+pub fn base_unit(name: &str) -> &'static str {
+    match name {
+        "power" => "kW",
+        "torque" => "Nm",
+        "speed" | "speed_reference" => "rpm",
+        "cycles" => "Nm.s",
+        _ => "°C",
+    }
+}
+
+/// How a value is stored and shown.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnitInfo {
+    pub name: String,
+    /// Unit of the stored value.
+    pub stored: String,
+    /// Unit the value is shown and exported in.
+    pub display: String,
+    /// Factor from the stored to the display unit.
+    pub scale: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Units {
+    configured: BTreeMap<String, UnitConfig>,
+    /// Base unit of each pulse input.
+    pulses: BTreeMap<String, String>,
+    /// Configured temperature channels.
+    channels: Vec<String>,
+}
+
+impl Units {
+    pub fn new(registers: &RegisterMap) -> Self {
+        let pulses = registers.pulses.iter().map(|(name, input)| (name.clone(), input.unit.clone())).collect();
+        let channels = registers.temperatures.keys().cloned().collect();
+        Units { configured: registers.units.clone(), pulses, channels }
+    }
+
+    /// Every signal, `speed_reference`, temperature channel and pulse input
+    /// with its stored and display unit.
+    pub fn all(&self) -> Vec<UnitInfo> {
+        Signal::ALL
+            .iter()
+            .map(|s| s.name())
+            .chain(std::iter::once("speed_reference"))
+            .chain(self.channels.iter().map(String::as_str))
+            .chain(self.pulses.keys().map(String::as_str))
+            .map(|name| UnitInfo {
+                name: name.to_string(),
+                stored: self.pulses.get(name).map_or(base_unit(name), String::as_str).to_string(),
+                display: self.unit(name).to_string(),
+                scale: self.configured.get(name).map_or(1.0, |u| u.scale),
+            })
+            .collect()
+    }
+
+    /// Display unit of a signal, `speed_reference`, temperature channel or
+    /// pulse input.
+    pub fn unit(&self, name: &str) -> &str {
+        match (self.configured.get(name), self.pulses.get(name)) {
+            (Some(configured), _) => &configured.unit,
+            (None, Some(unit)) => unit,
+            (None, None) => base_unit(name),
+        }
+    }
+
+    /// `value` (base unit) in the display unit of `name`.
+    pub fn display(&self, name: &str, value: f64) -> f64 {
+        self.configured.get(name).map_or(value, |u| value * u.scale)
+    }
+
+    /// Column name with its unit, e.g. `power (kW)`.
+    pub fn column(&self, name: &str) -> String {
+        format!("{} ({})", name, self.unit(name))
+    }
+
+    /// CSV header of `name`: `column` when a unit is configured for it, the
+    /// plain name otherwise.
+    pub fn header(&self, name: &str) -> String {
+        if self.configured.contains_key(name) {
+            self.column(name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// Copy of `data` with every value in display units.
+    pub fn sample(&self, data: &MotorData) -> MotorData {
+        if self.configured.is_empty() {
+            return data.clone();
+        }
+        MotorData {
+            timestamp: data.timestamp,
+            current_power: self.display("power", data.current_power),
+            current_torque: self.display("torque", data.current_torque),
+            current_speed: self.display("speed", data.current_speed),
+            current_heat: self.display("heat", data.current_heat),
+            current_cycles: self.display("cycles", data.current_cycles),
+            speed_reference: data.speed_reference.map(|v| self.display("speed_reference", v)),
+            temperatures: data.temperatures.iter().map(|(name, &v)| (name.clone(), self.display(name, v))).collect(),
+            pulses: data.pulses.iter().map(|(name, &v)| (name.clone(), self.display(name, v))).collect(),
+            sequence: data.sequence,
+            current: data.current,
+            estimated: data.estimated.clone(),
+        }
+    }
+
+    /// `unit.<name>` entries for the signals, the speed reference and
+    /// `channels`.
+    pub fn metadata(&self, channels: &[String]) -> BTreeMap<String, String> {
+        Signal::ALL
+            .iter()
+            .map(|s| s.name())
+            .chain(std::iter::once("speed_reference"))
+            .chain(channels.iter().map(String::as_str))
+            .map(|name| (format!("unit.{}", name), self.unit(name).to_string()))
+            .collect()
+    }
+}
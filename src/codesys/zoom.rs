@@ -0,0 +1,133 @@
+//! Pre-rendered zoom levels for HMI image widgets.
+//!
+//! With `[zoom]`, every `every_minutes` one chart per signal and horizon
+//! (`15m`, `2h`, `24h`, `7d` by default) is drawn from the window
+//! statistics, named by `path_template` with `{zoom}` set to the horizon.
+//! An image widget that can only show a file offers zoom by swapping
+//! between `power_15m.png` and `power_7d.png`.
+//!
+//! Each horizon uses the finest statistics window that keeps it within
+//! `MAX_POINTS` points (1-minute means for 2 h, 15-minute means for 24 h,
+//! hourly means for 7 d), and every statistics window is read once per pass
+//! for all signals and horizons. Only closed windows are stored, so a chart
+//! ends up to one window before now. Like the live charts, each gets a
+//! JSON sidecar and is only redrawn when its data changed.
+
+use super::axis::ValueAxis;
+use super::charts::{chart_spec, RenderCache};
+use super::config::{ChartsConfig, FormatConfig, ZoomConfig};
+use super::disk;
+use super::output::{render_path, OutputContext};
+use super::site;
+use super::stats::StatsWindow;
+use super::units::Units;
+use super::{Signal, CHART_SIZE};
+use chrono::prelude::*;
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+
+/// Most points drawn per chart before a coarser window is used.
+const MAX_POINTS: i64 = 500;
+
+/// Seconds in a horizon label: a number followed by `m`, `h` or `d`.
+pub fn horizon_secs(label: &str) -> Option<i64> {
+    let unit = match label.chars().last()? {
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86_400,
+        _ => return None,
+    };
+    let count: i64 = label[..label.len() - 1].parse().ok().filter(|&n| n > 0)?;
+    Some(count * unit)
+}
+
+/// Statistics window a horizon of `secs` is drawn from.
+pub fn resolution(secs: i64) -> StatsWindow {
+    StatsWindow::ALL.iter().copied().find(|w| secs / w.seconds() <= MAX_POINTS).unwrap_or(StatsWindow::OneHour)
+}
+
+/// Window means per signal since `from` (seconds), as `(milliseconds,
+/// value)` at the window start.
+async fn window_means(pool: &SqlitePool, window: StatsWindow, from: i64) -> HashMap<String, Vec<(i64, f64)>> {
+    let rows: Vec<(String, i64, f64)> = sqlx::query_as(&format!(
+        r#"
+        SELECT signal, window_start, avg FROM signal_stats
+        WHERE {} AND window_size = ? AND window_start >= ?
+        ORDER BY window_start
+        "#,
+        site::SAMPLES
+    ))
+    .bind(window.label())
+    .bind(from)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+    let mut means: HashMap<String, Vec<(i64, f64)>> = HashMap::new();
+    for (signal, start, avg) in rows {
+        means.entry(signal).or_default().push((start * 1000, avg));
+    }
+    means
+}
+
+/// Renders every signal at every horizon each `every_minutes`.
+pub async fn run_zoom_renderer(
+    pool: Arc<SqlitePool>,
+    motor_id: String,
+    config: ZoomConfig,
+    charts: ChartsConfig,
+    format: FormatConfig,
+    units: Units,
+    space: disk::Space,
+) {
+    let mut interval = time::interval(Duration::from_secs(config.every_minutes * 60));
+    let mut cache = RenderCache::new(&motor_id, format, space);
+    let horizons: Vec<(&str, i64)> =
+        config.horizons.iter().filter_map(|label| Some((label.as_str(), horizon_secs(label)?))).collect();
+    loop {
+        interval.tick().await;
+        let now = Local::now().timestamp();
+
+        // One query per statistics window, covering its longest horizon.
+        let mut since: HashMap<StatsWindow, i64> = HashMap::new();
+        for &(_, secs) in &horizons {
+            let from = since.entry(resolution(secs)).or_insert(now - secs);
+            *from = (*from).min(now - secs);
+        }
+        let mut means = HashMap::new();
+        for (window, from) in since {
+            means.insert(window, window_means(&pool, window, from).await);
+        }
+
+        for &(label, secs) in &horizons {
+            let series = &means[&resolution(secs)];
+            let template = config.path_template.replace("{zoom}", label);
+            let cutoff = (now - secs) * 1000;
+            for signal in config.signals.iter().filter_map(|name| Signal::from_name(name)) {
+                let data: Vec<(i64, f64)> = series
+                    .get(signal.name())
+                    .map(|points| {
+                        points
+                            .iter()
+                            .filter(|(t, _)| *t >= cutoff)
+                            .map(|&(t, v)| (t, units.display(signal.name(), v)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let ctx = OutputContext { motor: &motor_id, signal: signal.name(), timestamp: now };
+                let path = render_path(&template, &ctx);
+                let (title, y_label) = chart_spec(signal, &units);
+                let title = format!("{}, last {}", title, label);
+                let filename = path.to_string_lossy();
+                let axis = ValueAxis::for_chart(&charts, signal.name());
+                let drawn =
+                    cache.draw_if_changed(&filename, CHART_SIZE, &data, &[], secs, &axis, &title, "Time", &y_label);
+                if let Err(err) = drawn {
+                    log::error!("cannot write zoom chart {}: {}", path.display(), err);
+                }
+            }
+        }
+    }
+}
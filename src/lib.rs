@@ -1,16 +1,109 @@
 pub mod codesys {
-    use rtu_client::{Client, Context};
+    pub mod alarms;
+    pub mod aliases;
+    pub mod analytics;
+    pub mod api;
+    pub mod archive;
+    pub mod audit;
+    pub mod axis;
+    pub mod backup;
+    pub mod blocking;
+    pub mod bundle;
+    pub mod calc;
+    pub mod chart_defs;
+    pub mod charts;
+    #[cfg(feature = "client")]
+    pub mod client;
+    pub mod comparison;
+    pub mod config;
+    pub mod config_history;
+    pub mod control;
+    pub mod correlation;
+    pub mod counters;
+    pub mod demand;
+    pub mod devices;
+    pub mod environment;
+    pub mod equipment;
+    pub mod disk;
+    pub mod ethernet_ip;
+    pub mod events;
+    pub mod export;
+    pub mod export_jobs;
+    pub mod faults;
+    pub mod fleet;
+    pub mod flight;
+    pub mod format;
+    pub mod governor;
+    pub mod graphql;
+    pub mod heatmap;
+    pub mod interlock;
+    pub mod ipc;
+    pub mod jitter;
+    pub mod limits;
+    pub mod lineage;
+    pub mod location;
+    pub mod maintenance;
+    pub mod mcsa;
+    pub mod modbus_proxy;
+    pub mod mqtt;
+    pub mod nats;
+    pub mod notes;
+    pub mod oee;
+    pub mod output;
+    pub mod parameter_sets;
+    pub mod pipeline;
+    pub mod plugins;
+    pub mod pulses;
+    pub mod query_gate;
+    pub mod redundancy;
+    pub mod report;
+    pub mod rules;
+    pub mod schedule;
+    pub mod schema;
+    pub mod secrets;
+    pub mod selftest;
+    pub mod sentinels;
+    pub mod sequence;
+    pub mod serial_ports;
+    pub mod shifts;
+    pub mod setpoint;
+    pub mod sink;
+    pub mod site;
+    pub mod snapshot;
+    pub mod soft_sensors;
+    pub mod sparkplug;
+    pub mod stale;
+    pub mod startup;
+    pub mod state;
+    pub mod stats;
+    pub mod tail;
+    pub mod thermal;
+    pub mod tls;
+    pub mod transport;
+    pub mod trends;
+    pub mod tui;
+    pub mod units;
+    pub mod zoom;
+
+    use schemars::JsonSchema;
     use serde::{Deserialize, Serialize};
-    use sqlx::sqlite::SqlitePool;
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqliteRow};
+    use sqlx::Row;
     use std::time::Duration;
     use tokio::time;
-    use tokio_serial::SerialPortBuilderExt;
+    use plotters::coord::types::{RangedCoordf64, RangedCoordi64};
+    use plotters::coord::Shift;
     use plotters::prelude::*;
     use chrono::prelude::*;
+    use std::collections::BTreeMap;
     use std::sync::Arc;
-    use tokio::sync::Mutex;
 
-    #[derive(Debug, Deserialize, Serialize)]
+    pub use config::MonitorConfig;
+    use config::RegisterMap;
+    use transport::{Transport, TransportError};
+
+    #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+    #[serde(deny_unknown_fields)]
     pub struct MotorSpecs {
         pub rated_power: f64, // kW
         pub rated_torque: f64, // Nm
@@ -19,14 +112,118 @@ pub mod codesys {
         pub max_speed: f64, // rpm
     }
 
-    #[derive(Debug, Deserialize, Serialize)]
+    #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
     pub struct MotorData {
+        /// Milliseconds since the Unix epoch.
         pub timestamp: i64,
         pub current_power: f64,
         pub current_torque: f64,
         pub current_speed: f64,
         pub current_heat: f64,
         pub current_cycles: f64,
+        /// Commanded speed (rpm), when the drive exposes a speed reference.
+        #[serde(default)]
+        pub speed_reference: Option<f64>,
+        /// Named temperature channels (°C), e.g. `winding_u` or `de_bearing`.
+        #[serde(default)]
+        pub temperatures: BTreeMap<String, f64>,
+        /// Rates of pulse inputs in engineering units (see `pulses`), e.g.
+        /// `line_speed`.
+        #[serde(default)]
+        pub pulses: BTreeMap<String, f64>,
+        /// Per-motor sequence number assigned when the sample was read (see
+        /// `sequence`); `None` for samples stored before numbering began.
+        #[serde(default)]
+        pub sequence: Option<i64>,
+        /// Motor current (A) as read. Not stored; feeds the thermal model
+        /// (see `thermal`) of the node that polled it and its standby.
+        #[serde(default)]
+        pub current: Option<f64>,
+        /// Values estimated rather than read, e.g. `speed` (see
+        /// `soft_sensors`) or a register whose fault sentinel was replaced
+        /// (see `sentinels`); empty for measured samples.
+        #[serde(default)]
+        pub estimated: Vec<String>,
+    }
+
+    /// A derived signal carried by every `MotorData` sample.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Signal {
+        Power,
+        Torque,
+        Speed,
+        Heat,
+        Cycles,
+    }
+
+    impl Signal {
+        pub const ALL: [Signal; 5] = [Signal::Power, Signal::Torque, Signal::Speed, Signal::Heat, Signal::Cycles];
+
+        pub fn name(&self) -> &'static str {
+            match self {
+                Signal::Power => "power",
+                Signal::Torque => "torque",
+                Signal::Speed => "speed",
+                Signal::Heat => "heat",
+                Signal::Cycles => "cycles",
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<Signal> {
+            Signal::ALL.iter().copied().find(|s| s.name() == name)
+        }
+    }
+
+    impl MotorData {
+        pub fn value(&self, signal: Signal) -> f64 {
+            match signal {
+                Signal::Power => self.current_power,
+                Signal::Torque => self.current_torque,
+                Signal::Speed => self.current_speed,
+                Signal::Heat => self.current_heat,
+                Signal::Cycles => self.current_cycles,
+            }
+        }
+
+        /// The value of a signal, `speed_reference` or temperature channel.
+        pub fn named_value(&self, name: &str) -> Option<f64> {
+            match Signal::from_name(name) {
+                Some(signal) => Some(self.value(signal)),
+                None if name == "speed_reference" => self.speed_reference,
+                None => self.temperatures.get(name).or_else(|| self.pulses.get(name)).copied(),
+            }
+        }
+
+        /// The timestamp truncated to whole seconds, as used by alarms and
+        /// window statistics.
+        pub fn seconds(&self) -> i64 {
+            self.timestamp.div_euclid(1000)
+        }
+
+        /// Copy with values rounded to the configured storage `precision`.
+        pub fn quantized(&self, precision: &BTreeMap<String, f64>) -> MotorData {
+            let round = |name: &str, value: f64| precision.get(name).map_or(value, |&step| calc::quantize(value, step));
+            MotorData {
+                timestamp: self.timestamp,
+                current_power: round("power", self.current_power),
+                current_torque: round("torque", self.current_torque),
+                current_speed: round("speed", self.current_speed),
+                current_heat: round("heat", self.current_heat),
+                current_cycles: round("cycles", self.current_cycles),
+                speed_reference: self.speed_reference.map(|v| round("speed_reference", v)),
+                temperatures: self.temperatures.iter().map(|(name, &v)| (name.clone(), round(name, v))).collect(),
+                pulses: self.pulses.iter().map(|(name, &v)| (name.clone(), round(name, v))).collect(),
+                sequence: self.sequence,
+                current: self.current,
+                estimated: self.estimated.clone(),
+            }
+        }
+
+        /// Speed reference minus actual speed (rpm), if a reference was read.
+        pub fn tracking_error(&self) -> Option<f64> {
+            self.speed_reference.map(|reference| reference - self.current_speed)
+        }
     }
 
     impl MotorSpecs {
@@ -42,25 +239,184 @@ pub mod codesys {
     }
 
     pub async fn insert_motor_data(pool: &SqlitePool, data: &MotorData) {
-        sqlx::query!(
+        insert_motor_data_batch(pool, std::slice::from_ref(data)).await;
+    }
+
+    /// Inserts several samples in one transaction.
+    pub async fn insert_motor_data_batch(pool: &SqlitePool, batch: &[MotorData]) {
+        let mut tx = pool.begin().await.unwrap();
+        for data in batch {
+            let estimated = (!data.estimated.is_empty()).then(|| data.estimated.join(","));
+            sqlx::query!(
+                r#"
+                INSERT INTO motor_data (timestamp, current_power, current_torque, current_speed, current_heat, current_cycles, speed_reference, sequence, estimated, site)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, (SELECT COALESCE(MAX(name), '') FROM site))
+                "#,
+                data.timestamp,
+                data.current_power,
+                data.current_torque,
+                data.current_speed,
+                data.current_heat,
+                data.current_cycles,
+                data.speed_reference,
+                data.sequence,
+                estimated
+            )
+            .execute(&mut tx)
+            .await
+            .unwrap();
+            for (channel, value) in &data.temperatures {
+                sqlx::query(&format!(
+                    "INSERT INTO temperature_data (timestamp, channel, value, site) VALUES (?, ?, ?, {})",
+                    site::CLAIMED
+                ))
+                .bind(data.timestamp)
+                .bind(channel)
+                .bind(value)
+                .execute(&mut tx)
+                .await
+                .unwrap();
+            }
+            for (channel, value) in &data.pulses {
+                sqlx::query(&format!(
+                    "INSERT INTO pulse_data (timestamp, channel, value, site) VALUES (?, ?, ?, {})",
+                    site::CLAIMED
+                ))
+                    .bind(data.timestamp)
+                    .bind(channel)
+                    .bind(value)
+                    .execute(&mut tx)
+                    .await
+                    .unwrap();
+            }
+        }
+        tx.commit().await.unwrap();
+    }
+
+    fn motor_data_from_row(row: &SqliteRow) -> MotorData {
+        MotorData {
+            timestamp: row.get("timestamp"),
+            current_power: row.get("current_power"),
+            current_torque: row.get("current_torque"),
+            current_speed: row.get("current_speed"),
+            current_heat: row.get("current_heat"),
+            current_cycles: row.get("current_cycles"),
+            speed_reference: row.get("speed_reference"),
+            temperatures: BTreeMap::new(),
+            pulses: BTreeMap::new(),
+            sequence: row.get("sequence"),
+            current: None,
+            estimated: row
+                .get::<Option<String>, _>("estimated")
+                .map_or_else(Vec::new, |names| names.split(',').map(str::to_string).collect()),
+        }
+    }
+
+    /// Samples with `from <= timestamp < to`, oldest first, including those
+    /// already compacted into the archive.
+    pub async fn motor_data_range(pool: &SqlitePool, from: i64, to: i64, limit: Option<i64>) -> Vec<MotorData> {
+        // Raw rows are newer than the archived hours (late rows are merged
+        // in by the next compaction), so a page the archive fills needs none.
+        let mut samples = archive::archived_range(pool, from, to, limit).await;
+        let remaining = limit.map(|limit| limit.max(0) - samples.len() as i64);
+        if remaining != Some(0) {
+            samples.extend(raw_motor_data_range(pool, from, to, remaining).await);
+        }
+        samples.sort_by_key(|d| d.timestamp);
+        if let Some(limit) = limit {
+            samples.truncate(limit.max(0) as usize);
+        }
+        samples
+    }
+
+    /// Samples still held as raw rows in `motor_data`, oldest first.
+    pub async fn raw_motor_data_range(pool: &SqlitePool, from: i64, to: i64, limit: Option<i64>) -> Vec<MotorData> {
+        let rows = sqlx::query(&format!(
             r#"
-            INSERT INTO motor_data (timestamp, current_power, current_torque, current_speed, current_heat, current_cycles)
-            VALUES (?, ?, ?, ?, ?, ?)
+            SELECT timestamp, current_power, current_torque, current_speed, current_heat, current_cycles, speed_reference, sequence, estimated
+            FROM motor_data WHERE {} AND timestamp >= ? AND timestamp < ?
+            ORDER BY timestamp
+            LIMIT ?
             "#,
-            data.timestamp,
-            data.current_power,
-            data.current_torque,
-            data.current_speed,
-            data.current_heat,
-            data.current_cycles
-        )
-        .execute(pool)
+            site::SAMPLES
+        ))
+        .bind(from)
+        .bind(to)
+        .bind(limit.unwrap_or(-1))
+        .fetch_all(pool)
+        .await
+        .unwrap();
+
+        let mut samples: Vec<MotorData> = rows.iter().map(motor_data_from_row).collect();
+        let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+            return samples;
+        };
+        let (first, last) = (first.timestamp, last.timestamp);
+        let temperatures: Vec<(i64, String, f64)> = sqlx::query_as(&format!(
+            "SELECT timestamp, channel, value FROM temperature_data WHERE {} AND timestamp >= ? AND timestamp <= ?",
+            site::SAMPLES
+        ))
+        .bind(first)
+        .bind(last)
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        for (timestamp, channel, value) in temperatures {
+            let start = samples.partition_point(|d| d.timestamp < timestamp);
+            for data in samples[start..].iter_mut().take_while(|d| d.timestamp == timestamp) {
+                data.temperatures.insert(channel.clone(), value);
+            }
+        }
+        let pulses: Vec<(i64, String, f64)> = sqlx::query_as(&format!(
+            "SELECT timestamp, channel, value FROM pulse_data WHERE {} AND timestamp >= ? AND timestamp <= ?",
+            site::SAMPLES
+        ))
+        .bind(first)
+        .bind(last)
+        .fetch_all(pool)
         .await
         .unwrap();
+        for (timestamp, channel, value) in pulses {
+            let start = samples.partition_point(|d| d.timestamp < timestamp);
+            for data in samples[start..].iter_mut().take_while(|d| d.timestamp == timestamp) {
+                data.pulses.insert(channel.clone(), value);
+            }
+        }
+        aliases::SignalNames::load(pool).await.apply(&mut samples);
+        samples
+    }
+
+    /// Adds `column` to `table` when the database predates it.
+    async fn ensure_column(pool: &SqlitePool, table: &str, column: &str, definition: &str) {
+        let columns: Vec<(String,)> = sqlx::query_as(&format!("SELECT name FROM pragma_table_info('{}')", table))
+            .fetch_all(pool)
+            .await
+            .unwrap();
+        if !columns.iter().any(|(name,)| name == column) {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(pool)
+                .await
+                .unwrap();
+        }
     }
 
-    pub async fn setup_database() -> SqlitePool {
-        let pool = SqlitePool::connect("sqlite://motor_data.db").await.unwrap();
+    /// `PRAGMA user_version` of the current schema. Version 1 stores sample
+    /// timestamps in milliseconds instead of seconds; version 2 records the
+    /// site of each sample; version 3 also of each temperature, pulse and
+    /// statistics row.
+    const SCHEMA_VERSION: i64 = 3;
+
+    /// Tables besides `motor_data` whose rows record their site.
+    const SITE_TABLES: [&str; 4] = ["temperature_data", "pulse_data", "signal_stats", "signal_rollups"];
+
+    pub async fn setup_database(database_url: &str) -> SqlitePool {
+        let pool = SqlitePool::connect(database_url).await.unwrap();
+        let (version,): (i64,) = sqlx::query_as("PRAGMA user_version").fetch_one(&pool).await.unwrap();
+        let (existing,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'motor_data'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
         sqlx::query!(
             r#"
             CREATE TABLE IF NOT EXISTS motor_data (
@@ -70,109 +426,894 @@ pub mod codesys {
                 current_torque REAL NOT NULL,
                 current_speed REAL NOT NULL,
                 current_heat REAL NOT NULL,
-                current_cycles REAL NOT NULL
+                current_cycles REAL NOT NULL,
+                speed_reference REAL,
+                sequence INTEGER,
+                estimated TEXT,
+                site TEXT NOT NULL DEFAULT ''
             )
             "#
         )
         .execute(&pool)
         .await
         .unwrap();
+        ensure_column(&pool, "motor_data", "speed_reference", "REAL").await;
+        ensure_column(&pool, "motor_data", "sequence", "INTEGER").await;
+        ensure_column(&pool, "motor_data", "estimated", "TEXT").await;
+        ensure_column(&pool, "motor_data", "site", "TEXT NOT NULL DEFAULT ''").await;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS temperature_data (
+                timestamp INTEGER NOT NULL,
+                channel TEXT NOT NULL,
+                value REAL NOT NULL,
+                site TEXT NOT NULL DEFAULT ''
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("CREATE INDEX IF NOT EXISTS temperature_data_timestamp ON temperature_data (timestamp)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        stats::setup_stats_table(&pool).await;
+        alarms::setup_alarms_table(&pool).await;
+        events::setup_events_table(&pool).await;
+        archive::setup_archive_table(&pool).await;
+        maintenance::setup_maintenance_table(&pool).await;
+        notes::setup_notes_table(&pool).await;
+        audit::setup_audit_table(&pool).await;
+        plugins::setup_plugin_table(&pool).await;
+        devices::setup_devices_table(&pool).await;
+        site::setup_site_table(&pool).await;
+        mcsa::setup_mcsa_table(&pool).await;
+        thermal::setup_thermal_table(&pool).await;
+        environment::setup_environment_table(&pool).await;
+        counters::setup_counters_table(&pool).await;
+        faults::setup_fault_history_table(&pool).await;
+        nats::setup_nats_table(&pool).await;
+        lineage::setup_lineage_table(&pool).await;
+        config_history::setup_config_history_table(&pool).await;
+        pulses::setup_pulse_table(&pool).await;
+        aliases::setup_signal_tables(&pool).await;
+        demand::setup_demand_tables(&pool).await;
+        for table in SITE_TABLES {
+            ensure_column(&pool, table, "site", "TEXT NOT NULL DEFAULT ''").await;
+        }
+
+        // One transaction: a monitor stopped halfway through leaves the
+        // database at its old version, to be migrated again from the start.
+        let mut tx = pool.begin().await.unwrap();
+        if existing > 0 && version < 1 {
+            log::info!("migrating sample timestamps from seconds to milliseconds");
+            for table in ["motor_data", "temperature_data"] {
+                sqlx::query(&format!("UPDATE {} SET timestamp = timestamp * 1000", table))
+                    .execute(&mut tx)
+                    .await
+                    .unwrap();
+            }
+            archive::migrate_to_millis(&mut tx).await;
+        }
+        if existing > 0 && version < 2 {
+            sqlx::query(&format!("UPDATE motor_data SET site = {}", site::CLAIMED)).execute(&mut tx).await.unwrap();
+        }
+        if existing > 0 && version < 3 {
+            for table in SITE_TABLES {
+                sqlx::query(&format!("UPDATE {} SET site = {}", table, site::CLAIMED)).execute(&mut tx).await.unwrap();
+            }
+        }
+        sqlx::query(&format!("PRAGMA user_version = {}", SCHEMA_VERSION)).execute(&mut tx).await.unwrap();
+        tx.commit().await.unwrap();
         pool
     }
 
+    /// Opens an existing database read-only, without creating tables or
+    /// migrating. Databases written by an older schema are refused, since
+    /// their timestamps would be misread.
+    pub async fn open_database_read_only(database_url: &str) -> Result<SqlitePool, Box<dyn std::error::Error>> {
+        let options = database_url.parse::<SqliteConnectOptions>()?.read_only(true);
+        let pool = SqlitePool::connect_with(options).await?;
+        let (version,): (i64,) = sqlx::query_as("PRAGMA user_version").fetch_one(&pool).await?;
+        if version != SCHEMA_VERSION {
+            return Err(format!(
+                "{}: schema version {} (expected {}); open it once with a monitor to migrate",
+                database_url, version, SCHEMA_VERSION
+            )
+            .into());
+        }
+        Ok(pool)
+    }
+
     pub fn draw_chart(filename: &str, data: &[(i64, f64)], title: &str, x_label: &str, y_label: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let root = BitMapBackend::new(filename, (640, 480)).into_drawing_area();
+        draw_chart_with_markers(filename, data, &[], &config::FormatConfig::default(), title, x_label, y_label, &[])
+    }
+
+    /// Like `draw_chart`, plus a labelled vertical line at each marker
+    /// (millisecond timestamp, label), e.g. operator notes. Axis labels and
+    /// date breaks follow `format`; `text` is embedded as PNG `tEXt` chunks
+    /// (see `output::chart_text`). The file is replaced atomically.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_chart_with_markers(
+        filename: &str,
+        data: &[(i64, f64)],
+        markers: &[(i64, String)],
+        format: &config::FormatConfig,
+        title: &str,
+        x_label: &str,
+        y_label: &str,
+        text: &[(&str, String)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let axis = axis::ValueAxis::default();
+        draw_chart_on_axis(filename, CHART_SIZE, data, markers, format, &axis, title, x_label, y_label, text)
+    }
+
+    /// Like `draw_chart_with_markers`, `size` pixels large and with the value
+    /// axis ranged by `axis`. Fails without any sample to draw.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_chart_on_axis(
+        filename: &str,
+        size: (u32, u32),
+        data: &[(i64, f64)],
+        markers: &[(i64, String)],
+        format: &config::FormatConfig,
+        axis: &axis::ValueAxis,
+        title: &str,
+        x_label: &str,
+        y_label: &str,
+        text: &[(&str, String)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        output::write_atomically(std::path::Path::new(filename), |path| {
+            render_chart(path, size, data, markers, format, axis, title, x_label, y_label)?;
+            output::add_png_text(path, text)
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_chart(
+        path: &std::path::Path,
+        size: (u32, u32),
+        data: &[(i64, f64)],
+        markers: &[(i64, String)],
+        format: &config::FormatConfig,
+        axis: &axis::ValueAxis,
+        title: &str,
+        x_label: &str,
+        y_label: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let time_range = axis::time_range(data).ok_or("no samples to chart")?;
+        let root = BitMapBackend::new(path, size).into_drawing_area();
+        render_chart_on(&root, time_range, data, markers, format, axis, title, x_label, y_label)?;
+        root.present()?;
+        Ok(())
+    }
+
+    /// Default pixel size of rendered charts.
+    pub const CHART_SIZE: (u32, u32) = (640, 480);
+
+    /// Draws the chart into `pixels`, an RGB buffer of `size`, with
+    /// `time_range` on the time axis, and returns where it was drawn so that
+    /// later samples can be added with `draw_chart_segment`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_chart_pixels(
+        pixels: &mut [u8],
+        size: (u32, u32),
+        time_range: std::ops::Range<i64>,
+        data: &[(i64, f64)],
+        markers: &[(i64, String)],
+        format: &config::FormatConfig,
+        axis: &axis::ValueAxis,
+        title: &str,
+        x_label: &str,
+        y_label: &str,
+    ) -> Result<charts::ChartFrame, Box<dyn std::error::Error>> {
+        let root = BitMapBackend::with_buffer(pixels, size).into_drawing_area();
+        let frame = render_chart_on(&root, time_range, data, markers, format, axis, title, x_label, y_label)?;
+        root.present()?;
+        Ok(frame)
+    }
+
+    /// Continues the line of a chart drawn by `render_chart_pixels` through
+    /// `points`, which start at the last point already drawn.
+    pub fn draw_chart_segment(
+        pixels: &mut [u8],
+        frame: &charts::ChartFrame,
+        points: &[(i64, f64)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let root = BitMapBackend::with_buffer(pixels, frame.size).into_drawing_area();
+        let coord = Cartesian2d::<RangedCoordi64, RangedCoordf64>::new(
+            frame.x_range.clone(),
+            frame.y_range.clone(),
+            frame.pixel_range.clone(),
+        );
+        root.apply_coord_spec(coord).draw(&PathElement::new(points.to_vec(), RED))?;
+        root.present()?;
+        Ok(())
+    }
+
+    /// Writes `pixels`, an RGB buffer of `size`, to `filename` as a PNG
+    /// with `text` embedded. The file is replaced atomically.
+    pub fn write_chart_pixels(
+        filename: &str,
+        size: (u32, u32),
+        pixels: &[u8],
+        text: &[(&str, String)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        output::write_atomically(std::path::Path::new(filename), |path| {
+            let mut backend = BitMapBackend::new(path, size);
+            backend.blit_bitmap((0, 0), size, pixels).map_err(|err| err.to_string())?;
+            backend.present().map_err(|err| err.to_string())?;
+            output::add_png_text(path, text)
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_chart_on<DB: DrawingBackend>(
+        root: &DrawingArea<DB, Shift>,
+        time_range: std::ops::Range<i64>,
+        data: &[(i64, f64)],
+        markers: &[(i64, String)],
+        format: &config::FormatConfig,
+        axis: &axis::ValueAxis,
+        title: &str,
+        x_label: &str,
+        y_label: &str,
+    ) -> Result<charts::ChartFrame, Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        let value_range = axis.range(data.iter().map(|d| d.1));
         root.fill(&WHITE)?;
-        let mut chart = ChartBuilder::on(&root)
+        let mut chart = ChartBuilder::on(root)
             .caption(title, ("sans-serif", 50).into_font())
             .margin(5)
             .x_label_area_size(30)
-            .y_label_area_size(30)
-            .build_cartesian_2d(data.first().unwrap().0..data.last().unwrap().0, 0.0..data.iter().map(|d| d.1).fold(0.0 / 0.0, f64::max))?;
-
-        chart.configure_mesh().x_desc(x_label).y_desc(y_label).draw()?;
+            .y_label_area_size(60)
+            .build_cartesian_2d(time_range, value_range)?;
+        let (x_range, y_range) = (chart.x_range(), chart.y_range());
+        // X values are epoch milliseconds; label them as wall-clock time.
+        let time_label = |ms: &i64| format.time(*ms);
+        let decimals = format::axis_decimals(y_range.end - y_range.start);
+        let value_label = |v: &f64| format.number(*v, decimals);
+        chart
+            .configure_mesh()
+            .x_desc(x_label)
+            .y_desc(y_label)
+            .x_label_formatter(&time_label)
+            .y_label_formatter(&value_label)
+            .draw()?;
         chart.draw_series(LineSeries::new(
-            data.iter().map(|(x, y)| (*x, *y)),
+            data.iter().filter(|(_, y)| y.is_finite()).map(|(x, y)| (*x, *y)),
             &RED,
         ))?;
 
-        Ok(())
+        // Date breaks where the window crosses midnight.
+        for x in format::day_boundaries(format.zone, x_range.start, x_range.end) {
+            let style = ShapeStyle::from(&BLACK.mix(0.4)).stroke_width(1);
+            chart.draw_series(std::iter::once(PathElement::new(vec![(x, y_range.start), (x, y_range.end)], style)))?;
+            chart.draw_series(std::iter::once(Text::new(format.date(x), (x, y_range.start), ("sans-serif", 12).into_font())))?;
+        }
+
+        for (x, label) in markers.iter().filter(|(x, _)| x_range.contains(x)) {
+            chart.draw_series(std::iter::once(PathElement::new(vec![(*x, y_range.start), (*x, y_range.end)], BLUE)))?;
+            chart.draw_series(std::iter::once(Text::new(label.clone(), (*x, y_range.end), ("sans-serif", 12).into_font())))?;
+        }
+
+        let pixel_range = chart.plotting_area().get_pixel_range();
+        Ok(charts::ChartFrame { x_range, y_range, pixel_range, size: root.dim_in_pixel() })
     }
 
-    pub async fn read_modbus_data(ctx: &mut Client) -> MotorData {
-        let voltage_reading = ctx.read_input_registers(0, 1).await.unwrap()[0] as f64;
-        let current_reading = ctx.read_input_registers(1, 1).await.unwrap()[0] as f64;
-        let heat_reading = ctx.read_input_registers(2, 1).await.unwrap()[0] as f64;
-        let speed_reading = ctx.read_input_registers(3, 1).await.unwrap()[0] as f64;
-        let period = 1.0; // Example period
+    /// Reads one sample. Load cycles are integrated over the time `clock`
+    /// says the sample stands for; fault sentinels are handled by `sentinels`.
+    pub async fn read_motor_data(
+        transport: &mut dyn Transport,
+        registers: &RegisterMap,
+        clock: &mut jitter::SampleClock,
+        filter: &mut sentinels::SentinelFilter,
+    ) -> Result<MotorData, TransportError> {
+        let mut estimated = Vec::new();
+        let voltage_reading = filter.check("voltage", transport.read(&registers.voltage).await?, &mut estimated)?;
+        let current_reading = filter.check("current", transport.read(&registers.current).await?, &mut estimated)?;
+        let heat_reading = filter.check("heat", transport.read(&registers.heat).await?, &mut estimated)?;
+        let speed_reading = match &registers.speed_estimate {
+            Some(estimate) => {
+                let raw = transport.read(&estimate.frequency).await?;
+                let frequency = filter.check(sentinels::FREQUENCY, raw, &mut estimated)?;
+                estimated.push(soft_sensors::SPEED.to_string());
+                soft_sensors::estimate_speed(estimate, frequency, current_reading)
+            }
+            None => filter.check("speed", transport.read(&registers.speed).await?, &mut estimated)?,
+        };
+        let speed_reference = match &registers.speed_reference {
+            Some(def) => filter.check_optional("speed_reference", transport.read(def).await?, &mut estimated),
+            None => None,
+        };
+        let mut temperatures = BTreeMap::new();
+        for (name, channel) in &registers.temperatures {
+            let raw = transport.read(&channel.register()).await?;
+            if let Some(value) = filter.check_optional(name, raw, &mut estimated) {
+                temperatures.insert(name.clone(), value);
+            }
+        }
+        let now = Local::now().timestamp_millis();
+        let period = clock.tick(now);
 
-        let current_power = calculate_power(voltage_reading, current_reading);
+        let current_power = calc::calculate_power(voltage_reading, current_reading);
         let current_torque = 10.1; // Example value
-        let current_cycles = calculate_cycles(current_torque, period);
+        let current_cycles = calc::calculate_cycles(current_torque, period);
 
-        let now = Local::now().timestamp();
-
-        MotorData {
+        Ok(MotorData {
             timestamp: now,
             current_power,
             current_torque,
             current_speed: speed_reading,
             current_heat: heat_reading,
             current_cycles,
-        }
+            speed_reference,
+            temperatures,
+            pulses: BTreeMap::new(),
+            sequence: None,
+            current: Some(current_reading),
+            estimated,
+        })
+    }
+
+    /// Captures an incident snapshot for a newly raised alarm and attaches it,
+    /// when `[snapshots]` is configured. The snapshot is taken in a task of
+    /// its own, so polling carries on meanwhile.
+    #[allow(clippy::too_many_arguments)]
+    fn snapshot_alarm(
+        pool: &Arc<SqlitePool>,
+        config: &MonitorConfig,
+        id: i64,
+        rule: &str,
+        signals: &[Signal],
+        now: i64,
+        series: &charts::SeriesBuffer,
+        space: &disk::Space,
+    ) {
+        let Some(snapshots) = config.snapshots.clone() else {
+            return;
+        };
+        let recent = signals.iter().map(|&signal| (signal, series.series(signal))).collect();
+        let (pool, units, format) = (Arc::clone(pool), units::Units::new(&config.registers), config.format.clone());
+        let (motor_id, rule, space) = (config.motor_id.clone(), rule.to_string(), space.clone());
+        tokio::spawn(async move {
+            let folder =
+                snapshot::capture(&pool, &snapshots, format, &units, &motor_id, id, &rule, recent, now, space).await;
+            if let Some(folder) = folder {
+                alarms::attach_snapshot(&pool, id, &folder.to_string_lossy()).await;
+            }
+        });
     }
 
-    fn calculate_power(volts: f64, amps: f64) -> f64 {
-        volts * amps / 1000.0 // Convert to kW
+    /// Starts the Arrow Flight server if `api.flight_listen` is set.
+    fn spawn_flight(
+        config: &MonitorConfig,
+        pool: &Arc<SqlitePool>,
+        exports: &Arc<export_jobs::ExportQueue>,
+        lineage: &lineage::Source,
+    ) {
+        if let Some(listen) = config.api.flight_listen {
+            let server = flight::FlightServer {
+                pool: Arc::clone(pool),
+                motor_id: config.motor_id.clone(),
+                site: config.site.clone(),
+                exports: Arc::clone(exports),
+                units: units::Units::new(&config.registers),
+                lineage: lineage.clone(),
+                location: location::Location::from_config(config),
+            };
+            tokio::spawn(flight::serve(listen, server, config.api.tls.clone()));
+        }
     }
 
-    fn calculate_cycles(torque: f64, period: f64) -> f64 {
-        torque * period
+    /// Serves the API from a database without polling, e.g. a synced copy of
+    /// a monitor's database on another machine. Nothing is written: GraphQL
+    /// mutations are rejected and no background tasks run.
+    pub async fn run_viewer(config: MonitorConfig) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(listen) = config.api.listen else {
+            return Err("--read-only needs api.listen to be set".into());
+        };
+        let pool = open_database_read_only(&config.database_url).await?;
+        site::check(&pool, config.site.as_deref().unwrap_or_default()).await?;
+        log::info!("read-only viewer on {}", config.database_url);
+        let pool = Arc::new(pool);
+        let lineage_source = lineage::Source::load(&pool, &config).await;
+        let exports = Arc::new(export_jobs::ExportQueue::new(config.api.exports.clone(), disk::Space::default()));
+        let queries = Arc::new(query_gate::QueryGate::open(&config.api.queries, &config.database_url, &pool).await);
+        spawn_flight(&config, queries.pool(), &exports, &lineage_source);
+        let state = api::ApiState {
+            pool,
+            site: config.site.clone(),
+            motor_id: config.motor_id.clone(),
+            specs: config.motor.clone(),
+            format: config.format.clone(),
+            units: units::Units::new(&config.registers),
+            read_only: true,
+            setpoints: None,
+            control: None,
+            exports,
+            sinks: Default::default(),
+            sampling: Default::default(),
+            kpis: oee::KpiSettings::from_config(&config),
+            demand: demand::DemandSettings::from_config(&config),
+            lineage: lineage_source,
+            location: location::Location::from_config(&config),
+            queries,
+        };
+        api::serve(listen, state, config.api.tls.clone()).await;
+        Ok(())
     }
 
-    pub async fn run_motor_monitoring() {
-        // Example motor specification (EY630EAK)
-        let motor = MotorSpecs::new(2.4, 10.1, 1450.0, 25.9, 4800.0);
-        let pool = setup_database().await;
+    /// Polls, stores and serves until the process ends. Fails only at
+    /// startup, when the database cannot be claimed for the configured site.
+    pub async fn run_motor_monitoring(config: MonitorConfig) -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_database(&config.database_url).await;
+        site::claim(&pool, config.site.as_deref().unwrap_or_default())
+            .await
+            .map_err(|err| format!("{}: {}", config.database_url, err))?;
+        aliases::register(&pool, &config.registers).await;
+        let chart_set = chart_defs::ChartSet::new(&config);
+        let history = config_history::Snapshot::new(&config);
+        config_history::record(&pool, &history, chart_set.charts(), Local::now().timestamp_millis()).await;
         let pool = Arc::new(pool);
+        let (setpoint_handle, mut setpoints) = match &config.setpoints {
+            Some(settings) => {
+                let (handle, controller) = setpoint::controller(settings.clone(), &config.motor, config.dry_run);
+                (Some(handle), Some(controller))
+            }
+            None => (None, None),
+        };
+        let (control_handle, mut control) = match &config.control {
+            Some(settings) => {
+                let (handle, executor) = control::controller(settings.clone(), setpoint_handle.clone(), config.dry_run);
+                (Some(handle), Some(executor))
+            }
+            None => (None, None),
+        };
+
+        let lineage_source = lineage::Source::load(&pool, &config).await;
+        let space = disk::Space::default();
+        let exports = Arc::new(export_jobs::ExportQueue::new(config.api.exports.clone(), space.clone()));
+        let queries = Arc::new(query_gate::QueryGate::open(&config.api.queries, &config.database_url, &pool).await);
+        spawn_flight(&config, queries.pool(), &exports, &lineage_source);
+        let supervisor = sink::Supervisor::new();
+        let mut sample_clock = jitter::SampleClock::new(Duration::from_millis(config.poll_interval_ms));
+        let mut sentinel_filter = sentinels::SentinelFilter::new(config.registers.sentinels.clone());
+        let ipc_server = config.ipc.clone().map(|ipc| {
+            let setpoints = setpoint_handle.clone();
+            let controlled = config.control.is_some();
+            ipc::IpcServer::start(ipc, config.motor_id.clone(), setpoints, controlled, supervisor.health())
+        });
+        if let Some(listen) = config.api.listen {
+            let state = api::ApiState {
+                pool: Arc::clone(&pool),
+                site: config.site.clone(),
+                motor_id: config.motor_id.clone(),
+                specs: config.motor.clone(),
+                format: config.format.clone(),
+                units: units::Units::new(&config.registers),
+                read_only: false,
+                setpoints: setpoint_handle,
+                control: control_handle,
+                exports,
+                sinks: supervisor.health(),
+                sampling: sample_clock.jitter(),
+                kpis: oee::KpiSettings::from_config(&config),
+                demand: demand::DemandSettings::from_config(&config),
+                lineage: lineage_source.clone(),
+                location: location::Location::from_config(&config),
+                queries,
+            };
+            tokio::spawn(api::serve(listen, state, config.api.tls.clone()));
+        }
+
+        if let Some(report) = &config.report {
+            tokio::spawn(report::run_report_scheduler(
+                Arc::clone(&pool),
+                config.motor_id.clone(),
+                report.clone(),
+                trends::TrendSettings::from_config(&config),
+                correlation::CorrelationSettings::from_config(&config),
+                config.environment.clone(),
+                oee::KpiSettings::from_config(&config),
+                lineage_source.clone(),
+                location::Location::from_config(&config),
+                space.clone(),
+            ));
+        }
 
-        // Set up Modbus RTU connection
-        let serial_port = tokio_serial::new("/dev/ttyUSB0", 9600)
-            .data_bits(tokio_serial::DataBits::Eight)
-            .parity(tokio_serial::Parity::None)
-            .stop_bits(tokio_serial::StopBits::One)
-            .flow_control(tokio_serial::FlowControl::None)
-            .open_native_async()
+        if let Some(settings) = shifts::ShiftSettings::from_config(&config) {
+            tokio::spawn(shifts::run_shift_scheduler(Arc::clone(&pool), settings, space.clone()));
+        }
+
+        if let Some(settings) = demand::DemandSettings::from_config(&config) {
+            tokio::spawn(demand::run_demand_tracker(Arc::clone(&pool), settings, space.clone()));
+        }
+
+        tokio::spawn(alarms::run_shelf_expiry(Arc::clone(&pool)));
+
+        if let Some(heatmap) = &config.heatmap {
+            tokio::spawn(heatmap::run_heatmap_renderer(
+                Arc::clone(&pool),
+                config.motor_id.clone(),
+                config.motor.clone(),
+                heatmap.clone(),
+                config.format.clone(),
+                config.adaptive_polling.as_ref().map_or(config.poll_interval_ms, |a| a.stopped_ms),
+                space.clone(),
+            ));
+        }
+
+        if let Some(zoom) = &config.zoom {
+            tokio::spawn(zoom::run_zoom_renderer(
+                Arc::clone(&pool),
+                config.motor_id.clone(),
+                zoom.clone(),
+                config.charts.clone(),
+                config.format.clone(),
+                units::Units::new(&config.registers),
+                space.clone(),
+            ));
+        }
+
+        for comparison in &config.comparisons {
+            tokio::spawn(comparison::run_comparison_renderer(
+                Arc::clone(&pool),
+                config.motor_id.clone(),
+                comparison.clone(),
+                config.format.clone(),
+                units::Units::new(&config.registers),
+                space.clone(),
+            ));
+        }
+
+        if let Some(disk) = &config.disk {
+            tokio::spawn(disk::run_disk_guard(Arc::clone(&pool), config.motor_id.clone(), disk.clone(), space.clone()));
+        }
+
+        if let Some(archive) = &config.archive {
+            tokio::spawn(archive::run_compaction(Arc::clone(&pool), archive.clone(), space.clone()));
+        }
+
+        if let Some(maintenance) = &config.maintenance {
+            tokio::spawn(maintenance::run_maintenance_scheduler(
+                Arc::clone(&pool),
+                config.motor_id.clone(),
+                maintenance.clone(),
+            ));
+        }
+
+        if let Some(backup) = &config.backup {
+            tokio::spawn(backup::run_backup_scheduler(
+                Arc::clone(&pool),
+                config.motor_id.clone(),
+                backup.clone(),
+                space.clone(),
+            ));
+        }
+
+        // Every sink runs in its own supervised task behind a bounded queue,
+        // so a slow or failing sink never delays polling or the others.
+        let storage_tx = supervisor
+            .spawn("storage", sink::StorageSink::new(Arc::clone(&pool), config.precision.clone()), &config.sinks.storage)
             .unwrap();
+        let stats_tx = supervisor.spawn("stats", sink::StatsSink::new(Arc::clone(&pool)), &config.sinks.stats).unwrap();
+        // Optional sinks are left out rather than stopping the monitor; see `startup`.
+        let mut samples_tx = sink::FanOut::default();
+        if let Some(mqtt) = &config.mqtt {
+            let channels = config.registers.temperatures.keys().cloned().collect();
+            let (site, motor_id) = (config.site.clone(), config.motor_id.clone());
+            let mqtt = mqtt::MqttSink::new(mqtt.clone(), site, motor_id, channels, config.format.timestamps);
+            match supervisor.spawn("mqtt", mqtt, &config.sinks.mqtt) {
+                Ok(tx) => samples_tx.push(tx),
+                Err(err) => log::error!("running without the mqtt sink: cannot create its queue: {}", err),
+            }
+        }
+        if let Some(stdout) = &config.sinks.stdout {
+            match supervisor.spawn("stdout", sink::StdoutSink, stdout) {
+                Ok(tx) => samples_tx.push(tx),
+                Err(err) => log::error!("running without the stdout sink: cannot create its queue: {}", err),
+            }
+        }
+        if let Some(nats) = &config.nats {
+            let (site, motor_id) = (config.site.clone(), config.motor_id.clone());
+            let nats = nats::NatsSink::new(Arc::clone(&pool), nats.clone(), site, motor_id);
+            match supervisor.spawn("nats", nats, &config.sinks.nats) {
+                Ok(tx) => samples_tx.push(tx),
+                Err(err) => log::error!("running without the nats sink: cannot create its queue: {}", err),
+            }
+        }
 
-        let mut ctx = Client::new(serial_port, 1);
+        let redundancy = match &config.redundancy {
+            Some(settings) => Some(redundancy::Redundancy::start(settings.clone()).await.unwrap()),
+            None => None,
+        };
 
-        let mut interval = time::interval(Duration::from_secs(1));
-        let motor_data = Arc::new(Mutex::new(Vec::new()));
+        // The field-bus connection (Modbus RTU or EtherNet/IP) is opened while
+        // this node is active; a standby never touches the bus.
+        let mut transport: Option<Box<dyn Transport>> = None;
 
+        // With adaptive polling the interval follows the motor state, starting fast.
+        let mut state_machine = config.adaptive_polling.clone().map(state::StateMachine::new);
+        let poll_interval = state_machine.as_ref().map_or(Duration::from_millis(config.poll_interval_ms), |m| m.poll_interval());
+        let mut interval = time::interval(poll_interval);
+        sample_clock.set_nominal(poll_interval);
+        // The series kept here serves alarm snapshots; the chart sink keeps a copy of its own.
+        let mut series = charts::SeriesBuffer::new(config.charts.window_secs);
+        let preload_secs = config.charts.preload_minutes.map_or(config.charts.window_secs, |m| m * 60);
+        if preload_secs > 0 {
+            let loaded = series.preload(&pool, Local::now().timestamp_millis(), preload_secs).await;
+            log::info!("preloaded {} stored sample(s) into the charts", loaded);
+        }
+        let chart_sink = charts::ChartSink::new(
+            Arc::clone(&pool),
+            &config.motor_id,
+            config.charts.clone(),
+            config.format.clone(),
+            units::Units::new(&config.registers),
+            chart_set,
+            history,
+            series.clone(),
+            space.clone(),
+        );
+        let charts_tx = match supervisor.spawn("charts", chart_sink, &config.sinks.charts) {
+            Ok(tx) => Some(tx),
+            Err(err) => {
+                log::error!("running without charts: cannot create their queue: {}", err);
+                None
+            }
+        };
+        // Gaps longer than a few poll intervals don't count towards time-based rollups.
+        let slowest_poll_ms = config.adaptive_polling.as_ref().map_or(config.poll_interval_ms, |a| a.stopped_ms.max(a.running_ms));
+        let mut stats = stats::StatsEngine::new(&config.rollups, (slowest_poll_ms as i64 * 3).max(1000));
+        let mut sequencer = sequence::Sequencer::load(&pool).await;
+        let mut governor = governor::ResourceGovernor::new(config.governor.clone());
+        let mut pending = Vec::new();
+        let mut last_governor_check = time::Instant::now();
+        let mut tick: u64 = 0;
+        let mut tracking_alarm = config.tracking.as_ref().map(|t| {
+            alarms::SustainedAlarm::new("speed_tracking_error", Some("speed"), t.severity, t.duration_secs)
+        });
+        let mut interlock = config.interlock.clone().map(interlock::Interlock::new);
+        let mut thermal_model = match &config.thermal {
+            Some(settings) => {
+                let now = Local::now().timestamp();
+                Some(thermal::ThermalModel::load(&pool, &config.motor_id, settings.clone(), now).await)
+            }
+            None => None,
+        };
+        let mut rule_engine = rules::RuleEngine::new(&config);
+        let mut start_detector = alarms::StartDetector::new(config.limits.start_speed_rpm);
+        let mut stale_detector = config.stale.clone().map(stale::StaleDetector::new);
+        let zone = location::Zone::from_config(&config);
+        let mut schedule_monitor = config.schedule.clone().map(|s| schedule::ScheduleMonitor::new(s, zone));
+        let mut equipment_monitor =
+            config.equipment.clone().map(|e| equipment::EquipmentMonitor::new(e, &config.motor));
+        let mut mcsa_monitor = config.mcsa.clone().map(|m| mcsa::McsaMonitor::new(m, config.motor.clone()));
+        let modbus_proxy = config.modbus_proxy.clone().map(modbus_proxy::ModbusProxy::start);
+        let mut environment_poller = config.environment.clone().map(environment::EnvironmentPoller::new);
+        let mut pulse_reader = pulses::PulseReader::new(config.registers.pulses.clone());
+        let mut counter_reader = match &config.counters {
+            Some(counters) => Some(counters::CounterReader::load(&pool, counters.clone()).await),
+            None => None,
+        };
+        let mut fault_reader = match &config.fault_history {
+            Some(faults) => Some(faults::FaultHistoryReader::load(&pool, &config.motor_id, faults.clone()).await),
+            None => None,
+        };
+        let mut plugin_host = plugins::PluginHost::load(&config.plugins);
+        // Logged when polling starts or stops failing, not on every tick.
+        let mut poll_failing = false;
         loop {
             interval.tick().await;
+            tick += 1;
+
+            if last_governor_check.elapsed() >= Duration::from_secs(governor.check_interval_secs()) {
+                governor.check();
+                last_governor_check = time::Instant::now();
+            }
 
-            let data = read_modbus_data(&mut ctx).await;
-
-            let mut motor_data_lock = motor_data.lock().await;
-            motor_data_lock.push((data.timestamp, data.current_power));
-            motor_data_lock.push((data.timestamp, data.current_torque));
-            motor_data_lock.push((data.timestamp, data.current_speed));
-            motor_data_lock.push((data.timestamp, data.current_heat));
-            motor_data_lock.push((data.timestamp, data.current_cycles));
-
-            let pool = Arc::clone(&pool);
-            tokio::spawn(async move {
-                insert_motor_data(&pool, &data).await;
-            });
-
-            // Update graphs
-            draw_chart("current_power.png", &motor_data_lock, "Current Power", "Time", "Power (kW)").unwrap();
-            draw_chart("current_torque.png", &motor_data_lock, "Current Torque", "Time", "Torque (Nm)").unwrap();
-            draw_chart("current_speed.png", &motor_data_lock, "Current Speed", "Time", "Speed (rpm)").unwrap();
-            draw_chart("current_heat.png", &motor_data_lock, "Current Heat", "Time", "Heat (°C)").unwrap();
-            draw_chart("current_cycles.png", &motor_data_lock, "Current Cycles", "Time", "Cycles (Nm.s)").unwrap();
+            let samples = match &redundancy {
+                Some(peer) if !peer.is_active() => {
+                    if transport.take().is_some() {
+                        log::info!("standby: released the field bus");
+                    }
+                    let mirrored = peer.take_mirrored();
+                    sequencer.observe(&mirrored);
+                    mirrored
+                }
+                _ => {
+                    if transport.is_none() {
+                        match transport::connect(&config).await {
+                            Ok(mut connected) => {
+                                let now = Local::now().timestamp();
+                                devices::identify(&pool, &config.motor_id, connected.as_mut(), now).await;
+                                transport = Some(connected);
+                            }
+                            Err(err) => {
+                                log::warn!("cannot open field bus: {}", err);
+                                continue;
+                            }
+                        }
+                    }
+                    let read = read_motor_data(
+                        transport.as_deref_mut().unwrap(),
+                        &config.registers,
+                        &mut sample_clock,
+                        &mut sentinel_filter,
+                    );
+                    let mut data = match read.await {
+                        Ok(data) => {
+                            if std::mem::take(&mut poll_failing) {
+                                log::info!("polling recovered");
+                            }
+                            data
+                        }
+                        Err(err) => {
+                            if !std::mem::replace(&mut poll_failing, true) {
+                                log::warn!("poll failed: {}", err);
+                            }
+                            continue;
+                        }
+                    };
+                    if !pulse_reader.is_empty() {
+                        pulse_reader.read(transport.as_deref_mut().unwrap(), &mut data).await;
+                    }
+                    sequencer.assign(&mut data);
+                    if let Some(monitor) = mcsa_monitor.as_mut() {
+                        monitor.maybe_capture(&pool, &config.motor_id, transport.as_deref_mut().unwrap(), &data).await;
+                    }
+                    if let Some(proxy) = &modbus_proxy {
+                        proxy.refresh(transport.as_deref_mut().unwrap()).await;
+                    }
+                    if let Some(poller) = environment_poller.as_mut() {
+                        poller.maybe_read(&pool, transport.as_deref_mut().unwrap(), data.seconds()).await;
+                    }
+                    if let Some(reader) = counter_reader.as_mut() {
+                        let transport = transport.as_deref_mut().unwrap();
+                        reader.maybe_read(&pool, &config.motor_id, transport, data.seconds()).await;
+                    }
+                    if let Some(reader) = fault_reader.as_mut() {
+                        let transport = transport.as_deref_mut().unwrap();
+                        reader.maybe_read(&pool, &config.motor_id, transport, data.seconds()).await;
+                    }
+                    match &redundancy {
+                        Some(peer) if !peer.publish(&data).await => Vec::new(),
+                        _ => vec![data],
+                    }
+                }
+            };
+
+            let render = tick.is_multiple_of(governor.render_every());
+            if let Some(tx) = &charts_tx {
+                tx.send(charts::ChartUpdate { samples: samples.clone(), render }).await;
+            }
+            let mut closed_windows = Vec::new();
+            for data in samples {
+                series.push(&data);
+                if let (Some(alarm), Some(tracking), Some(error)) =
+                    (tracking_alarm.as_mut(), config.tracking.as_ref(), data.tracking_error())
+                {
+                    let exceeded = error.abs() > tracking.max_error_rpm;
+                    let raised = alarm
+                        .update(&pool, &config.motor_id, exceeded, data.seconds(), || {
+                            format!(
+                                "speed tracking error {:.0} rpm (reference {:.0}, actual {:.0}) above {:.0} rpm for {} s",
+                                error,
+                                data.speed_reference.unwrap_or_default(),
+                                data.current_speed,
+                                tracking.max_error_rpm,
+                                tracking.duration_secs
+                            )
+                        })
+                        .await;
+                    if let Some(id) = raised {
+                        let signals = [Signal::Speed, Signal::Torque, Signal::Power];
+                        snapshot_alarm(&pool, &config, id, &alarm.rule, &signals, data.timestamp, &series, &space);
+                    }
+                }
+                if start_detector.observe(data.current_speed) {
+                    rule_engine.motor_started(data.seconds());
+                }
+                for raised in rule_engine.update(&pool, &config.motor_id, &data).await {
+                    if raised.actions.contains(&config::RuleAction::Snapshot) {
+                        let (id, rule) = (raised.id, &raised.rule);
+                        snapshot_alarm(&pool, &config, id, rule, &raised.signals, data.timestamp, &series, &space);
+                    }
+                }
+                if let Some(model) = thermal_model.as_mut() {
+                    for (id, rule) in model.update(&pool, &config.motor_id, &data).await {
+                        let signals = [Signal::Power, Signal::Heat];
+                        snapshot_alarm(&pool, &config, id, &rule, &signals, data.timestamp, &series, &space);
+                    }
+                }
+                if let Some(detector) = stale_detector.as_mut() {
+                    for (id, rule, name) in detector.update(&pool, &config.motor_id, &data).await {
+                        let signal = Signal::from_name(&name).unwrap_or(Signal::Heat);
+                        snapshot_alarm(&pool, &config, id, &rule, &[signal], data.timestamp, &series, &space);
+                    }
+                }
+                if let Some(monitor) = schedule_monitor.as_mut() {
+                    for (id, rule) in monitor.update(&pool, &config.motor_id, &data).await {
+                        let signals = [Signal::Speed, Signal::Power];
+                        snapshot_alarm(&pool, &config, id, &rule, &signals, data.timestamp, &series, &space);
+                    }
+                }
+                if let Some(monitor) = equipment_monitor.as_mut() {
+                    for (id, rule) in monitor.update(&pool, &config.motor_id, &data).await {
+                        let signals = [Signal::Speed, Signal::Power];
+                        snapshot_alarm(&pool, &config, id, &rule, &signals, data.timestamp, &series, &space);
+                    }
+                }
+                if !plugin_host.is_empty() {
+                    for (id, rule) in plugin_host.process(&pool, &config.motor_id, &data).await {
+                        snapshot_alarm(&pool, &config, id, &rule, &Signal::ALL, data.timestamp, &series, &space);
+                    }
+                }
+                if let (Some(guard), Some(settings)) = (interlock.as_mut(), &config.interlock) {
+                    if let Some(trip) = guard.check(&data, &config.motor, thermal_model.as_ref().map(|m| m.level())) {
+                        if let Some(controller) = setpoints.as_mut() {
+                            controller.halt();
+                        }
+                        interlock::execute(
+                            &pool,
+                            settings,
+                            transport.as_deref_mut(),
+                            &config.motor_id,
+                            &trip,
+                            data.seconds(),
+                            config.dry_run,
+                        )
+                        .await;
+                    }
+                    if let Some(executor) = control.as_ref() {
+                        executor.set_interlock_tripped(guard.is_tripped());
+                    }
+                    if let Some(controller) = setpoints.as_mut() {
+                        controller.set_interlock_tripped(guard.is_tripped());
+                    }
+                }
+                if let Some(controller) = setpoints.as_mut() {
+                    controller.observe(&data);
+                }
+                if let Some(machine) = state_machine.as_mut() {
+                    if machine.update(&pool, &config.motor_id, &data).await.is_some() {
+                        let period = machine.poll_interval();
+                        interval = time::interval_at(time::Instant::now() + period, period);
+                        sample_clock.set_nominal(period);
+                    }
+                }
+                closed_windows.extend(stats.push(&data));
+                if !samples_tx.is_empty() {
+                    samples_tx.send(data.clone()).await;
+                }
+                if let Some(server) = &ipc_server {
+                    server.publish(&data);
+                }
+                pending.push(data);
+            }
+
+            if let Some(executor) = control.as_mut() {
+                let now = Local::now().timestamp();
+                if executor.step(&pool, transport.as_deref_mut(), &config.motor_id, now).await {
+                    if let Some(controller) = setpoints.as_mut() {
+                        controller.halt();
+                    }
+                }
+            }
+            if let Some(controller) = setpoints.as_mut() {
+                controller.step(&pool, transport.as_deref_mut(), &config.motor_id, Local::now().timestamp()).await;
+            }
+
+            if pending.len() >= governor.batch_size() {
+                storage_tx.send(std::mem::take(&mut pending)).await;
+            }
+            if !closed_windows.is_empty() {
+                stats_tx.send(closed_windows).await;
+            }
         }
     }
 }
@@ -1,6 +1,245 @@
 use codesys::codesys::*;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage: rustsys [--read-only] [--dry-run] [CONFIG.toml]\n       rustsys selftest [CONFIG.toml]\n       rustsys ports\n       rustsys tui [CONFIG.toml]\n       rustsys tail [--motor ID] [--signal NAME]... [CONFIG.toml...]\n       rustsys status [CONFIG.toml]\n       rustsys setpoint speed|torque VALUE [--by NAME] [--dry-run] [CONFIG.toml]\n       rustsys db restore FILE [CONFIG.toml]\n       rustsys [--read-only] --bundle BUNDLE.tar --trusted-key KEY.pub\n       rustsys --schema NAME\n       rustsys --fleet-report FROM TO CONFIG.toml...";
+
+fn load_config(args: &[String]) -> Result<MonitorConfig, Box<dyn Error>> {
+    match args {
+        [] => Ok(config::load("rustsys.toml")?),
+        [path] if !path.starts_with("--") => Ok(config::load(path)?),
+        [flag, bundle, key_flag, key] if flag == "--bundle" && key_flag == "--trusted-key" => {
+            Ok(bundle::load_bundle(Path::new(bundle), Path::new(key))?)
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// `--fleet-report FROM TO CONFIG...`: compares the motors of several
+/// configurations over a period given in epoch milliseconds or RFC 3339.
+async fn fleet_report(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [from, to, paths @ ..] = args else {
+        return Err(USAGE.into());
+    };
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+    let parse = |text: &str| format::parse_timestamp(text).ok_or_else(|| format!("invalid timestamp {:?}", text));
+    let (from, to) = (parse(from)?, parse(to)?);
+    let configs = paths.iter().map(config::load).collect::<Result<Vec<_>, _>>()?;
+    let metrics = fleet::collect(&configs, from, to).await?;
+    print!("{}", fleet::render_markdown(&metrics, from, to));
+    Ok(())
+}
+
+/// `selftest [CONFIG]`: reads every configured register once and prints a
+/// pass/fail table. Returns whether all checks passed.
+async fn selftest(args: &[String]) -> Result<bool, Box<dyn Error>> {
+    let config = load_config(args)?;
+    let (device, checks) = selftest::run(&config).await;
+    if let Some(info) = &device {
+        println!("device: {}\n", devices::describe(info));
+    }
+    print!("{}", selftest::render_table(&checks));
+    Ok(checks.iter().all(selftest::Check::passed))
+}
+
+/// `tail [--motor ID] [--signal NAME]... [CONFIG...]`: follows new samples
+/// of the motor `--motor` picks among the configurations (the only one
+/// without it).
+async fn tail(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (mut motor, mut signals, mut paths) = (None, Vec::new(), Vec::new());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--motor" => motor = Some(args.next().ok_or(USAGE)?.clone()),
+            "--signal" => signals.push(args.next().ok_or(USAGE)?.clone()),
+            flag if flag.starts_with("--") => return Err(USAGE.into()),
+            path => paths.push(path.to_string()),
+        }
+    }
+    let mut configs = match paths.as_slice() {
+        [] => vec![config::load("rustsys.toml")?],
+        paths => paths.iter().map(config::load).collect::<Result<Vec<_>, _>>()?,
+    };
+    let config = match motor {
+        Some(motor) => {
+            let found = configs.iter().position(|c| c.motor_id == motor);
+            configs.swap_remove(found.ok_or_else(|| format!("no configuration for motor {:?}", motor))?)
+        }
+        None if configs.len() == 1 => configs.remove(0),
+        None => return Err("several configurations given; pick one with --motor".into()),
+    };
+    let columns = tail::columns(&config, &signals)?;
+    tail::run(config, columns).await
+}
+
+/// Connects to the socket of the running monitor of `config`.
+async fn connect(config: &MonitorConfig) -> Result<ipc::Client, Box<dyn Error>> {
+    let settings = config.ipc.as_ref().ok_or("the configuration has no [ipc] section")?;
+    let client = ipc::Client::connect(&settings.socket)
+        .await
+        .map_err(|err| format!("cannot reach the monitor on {}: {}", settings.socket.display(), err))?;
+    Ok(client)
+}
+
+/// `status [CONFIG]`: prints the running monitor's status.
+async fn status(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = load_config(args)?;
+    let status = connect(&config).await?.call("status", serde_json::Value::Null).await?;
+    println!("{}", serde_json::to_string_pretty(&status).unwrap());
+    Ok(())
+}
+
+/// `setpoint speed|torque VALUE [--by NAME] [--dry-run] [CONFIG]`: requests
+/// a setpoint from the running monitor, audited under `--by` (`$USER` by
+/// default). Refused when the monitor has `[control]` configured.
+async fn setpoint(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [kind, value, rest @ ..] = args else {
+        return Err(USAGE.into());
+    };
+    let value: f64 = value.parse().map_err(|_| format!("invalid setpoint value {:?}", value))?;
+    let (mut by, mut dry_run, mut paths) = (None, false, Vec::new());
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--by" => by = Some(rest.next().ok_or(USAGE)?.clone()),
+            "--dry-run" => dry_run = true,
+            flag if flag.starts_with("--") => return Err(USAGE.into()),
+            path => paths.push(path.to_string()),
+        }
+    }
+    let by = by.or_else(|| std::env::var("USER").ok()).unwrap_or_else(|| "cli".to_string());
+    let config = load_config(&paths)?;
+    let params = serde_json::json!({ "kind": kind, "value": value, "by": by, "dry_run": dry_run });
+    connect(&config).await?.call("setpoint", params).await?;
+    println!("{} setpoint {} requested{}", kind, value, if dry_run { " (dry run)" } else { "" });
+    Ok(())
+}
+
+/// `db restore FILE [CONFIG]`: replaces the configured database with a
+/// verified backup.
+async fn restore(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [file, rest @ ..] = args else {
+        return Err(USAGE.into());
+    };
+    let config = load_config(rest)?;
+    println!("{}", backup::restore(&config.database_url, Path::new(file)).await?);
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() {
-    run_motor_monitoring().await;
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "selftest") {
+        match selftest(&args[1..]).await {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(err) => {
+                eprintln!("{}", err.to_string().trim_end());
+                process::exit(1);
+            }
+        }
+    }
+    if args.first().is_some_and(|a| a == "tui") {
+        let result = match load_config(&args[1..]) {
+            Ok(config) => tui::run(config).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err.to_string().trim_end());
+            process::exit(1);
+        }
+        return;
+    }
+    if args.first().is_some_and(|a| a == "tail") {
+        if let Err(err) = tail(&args[1..]).await {
+            eprintln!("{}", err.to_string().trim_end());
+            process::exit(1);
+        }
+        return;
+    }
+    if args.first().is_some_and(|a| a == "ports") {
+        match serial_ports::list() {
+            Ok(ports) if ports.is_empty() => println!("no serial ports found"),
+            Ok(ports) => ports.iter().for_each(|port| println!("{}", port)),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if args.first().is_some_and(|a| a == "status") {
+        if let Err(err) = status(&args[1..]).await {
+            eprintln!("{}", err.to_string().trim_end());
+            process::exit(1);
+        }
+        return;
+    }
+    if args.first().is_some_and(|a| a == "setpoint") {
+        if let Err(err) = setpoint(&args[1..]).await {
+            eprintln!("{}", err.to_string().trim_end());
+            process::exit(1);
+        }
+        return;
+    }
+    if args.len() >= 2 && args[0] == "db" && args[1] == "restore" {
+        if let Err(err) = restore(&args[2..]).await {
+            eprintln!("{}", err.to_string().trim_end());
+            process::exit(1);
+        }
+        return;
+    }
+    if args.first().is_some_and(|a| a == "--fleet-report") {
+        if let Err(err) = fleet_report(&args[1..]).await {
+            eprintln!("{}", err.to_string().trim_end());
+            process::exit(1);
+        }
+        return;
+    }
+    if let [flag, name] = args.as_slice() {
+        if flag == "--schema" {
+            match schema::schema(name) {
+                Some(schema) => println!("{}", serde_json::to_string_pretty(&schema).unwrap()),
+                None => {
+                    eprintln!("unknown schema {:?}; available: {}", name, schema::NAMES.join(", "));
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+    }
+    // Leading flags, in any order.
+    let (mut read_only, mut dry_run) = (false, false);
+    loop {
+        match args.first().map(String::as_str) {
+            Some("--read-only") => read_only = true,
+            Some("--dry-run") => dry_run = true,
+            _ => break,
+        }
+        args.remove(0);
+    }
+    let mut config = match load_config(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err.to_string().trim_end());
+            process::exit(1);
+        }
+    };
+    config.dry_run |= dry_run;
+    let timestamps = config.format.timestamps;
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format(move |buf, record| {
+            let now = chrono::Local::now().timestamp_millis();
+            writeln!(buf, "[{} {} {}] {}", timestamps.render(now), record.level(), record.target(), record.args())
+        })
+        .init();
+    let result = if read_only { run_viewer(config).await } else { run_motor_monitoring(config).await };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,124 @@
+//! Image-regression tests for chart rendering.
+//!
+//! Each test renders a deterministic dataset into the target directory and
+//! compares it with the golden PNG in `tests/golden/`. Font rasterisation
+//! differs slightly between machines, so images match when at most
+//! `MAX_DIFFERING_PIXELS` of the pixels differ by more than
+//! `CHANNEL_TOLERANCE` in any channel.
+//!
+//! After an intended change to chart output, regenerate the goldens with
+//! `UPDATE_GOLDEN=1 cargo test --test chart_golden` and review the new
+//! images before committing them. A missing golden fails the test unless
+//! `UPDATE_GOLDEN` is set.
+
+mod support;
+
+use codesys::codesys::config::{FormatConfig, NumberLocale};
+use codesys::codesys::location::Zone;
+use codesys::codesys::heatmap::{dwell_grid, draw_heatmap};
+use codesys::codesys::{draw_chart_with_markers, MotorData, MotorSpecs};
+use std::path::{Path, PathBuf};
+
+const CHANNEL_TOLERANCE: u8 = 16;
+const MAX_DIFFERING_PIXELS: f64 = 0.005;
+
+/// 2023-11-14 21:00:00 UTC, three hours before a date change.
+const START_MS: i64 = 1_700_000_000_000 - 4_400_000;
+
+fn output_path(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("charts");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(format!("{}.png", name))
+}
+
+/// Default formatting with axis labels in UTC rather than the host's zone,
+/// so renders are reproducible.
+fn utc_format() -> FormatConfig {
+    FormatConfig { zone: Zone::parse("UTC").unwrap(), ..FormatConfig::default() }
+}
+
+/// One sample per minute for `minutes`, a slow sine with a step halfway.
+fn sine_series(minutes: i64) -> Vec<(i64, f64)> {
+    (0..minutes)
+        .map(|i| {
+            let step = if i >= minutes / 2 { 20.0 } else { 0.0 };
+            (START_MS + i * 60_000, 50.0 + 10.0 * (i as f64 / 15.0).sin() + step)
+        })
+        .collect()
+}
+
+/// One sample per minute for `minutes`.
+fn samples(minutes: i64) -> Vec<MotorData> {
+    support::samples(START_MS, 60_000, minutes)
+}
+
+fn assert_matches_golden(name: &str, rendered: &Path) {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        std::fs::copy(rendered, &golden).unwrap();
+        eprintln!("wrote golden image {}", golden.display());
+        return;
+    }
+    assert!(
+        golden.exists(),
+        "{}: no golden image {}; render it with UPDATE_GOLDEN=1 and review it (rendered: {})",
+        name,
+        golden.display(),
+        rendered.display()
+    );
+
+    let actual = image::open(rendered).unwrap().to_rgb8();
+    let expected = image::open(&golden).unwrap().to_rgb8();
+    assert_eq!(actual.dimensions(), expected.dimensions(), "{}: image size changed", name);
+    let differing = actual
+        .pixels()
+        .zip(expected.pixels())
+        .filter(|(a, e)| a.0.iter().zip(e.0.iter()).any(|(a, e)| a.abs_diff(*e) > CHANNEL_TOLERANCE))
+        .count();
+    let fraction = differing as f64 / (actual.width() * actual.height()) as f64;
+    assert!(
+        fraction <= MAX_DIFFERING_PIXELS,
+        "{}: {:.2}% of pixels differ from {} (rendered: {})",
+        name,
+        fraction * 100.0,
+        golden.display(),
+        rendered.display()
+    );
+}
+
+#[test]
+fn line_chart() {
+    let path = output_path("line_chart");
+    draw_chart_with_markers(path.to_str().unwrap(), &sine_series(90), &[], &utc_format(), "Power", "Time", "Power (W)", &[])
+        .unwrap();
+    assert_matches_golden("line_chart", &path);
+}
+
+#[test]
+fn line_chart_with_markers_and_date_break() {
+    let path = output_path("markers_date_break");
+    let markers = vec![(START_MS + 30 * 60_000, "replaced belt".to_string())];
+    let data = sine_series(240);
+    draw_chart_with_markers(path.to_str().unwrap(), &data, &markers, &utc_format(), "Heat", "Time", "Heat (°C)", &[])
+        .unwrap();
+    assert_matches_golden("markers_date_break", &path);
+}
+
+#[test]
+fn line_chart_decimal_comma() {
+    let path = output_path("decimal_comma");
+    let format = FormatConfig { locale: NumberLocale::De, group_thousands: true, ..utc_format() };
+    let data: Vec<(i64, f64)> = sine_series(60).into_iter().map(|(t, v)| (t, v * 100.0)).collect();
+    draw_chart_with_markers(path.to_str().unwrap(), &data, &[], &format, "Speed", "Zeit", "Drehzahl (rpm)", &[]).unwrap();
+    assert_matches_golden("decimal_comma", &path);
+}
+
+#[test]
+fn heatmap() {
+    let path = output_path("heatmap");
+    let specs = MotorSpecs::new(2.4, 10.1, 1450.0, 25.9, 4800.0);
+    let grid = dwell_grid(&samples(600), &specs, 15, 20, 180_000).unwrap();
+    draw_heatmap(path.to_str().unwrap(), &grid, &utc_format(), "Heat vs. load", &[]).unwrap();
+    assert_matches_golden("heatmap", &path);
+}
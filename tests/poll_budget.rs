@@ -0,0 +1,112 @@
+//! Per-cycle latency budget of the poll loop.
+//!
+//! The monitor polls once a second by default, and the work of a cycle has
+//! to fit in that second next to the bus round trips, which are not timed
+//! here. Each stage gets a share of `CYCLE_BUDGET`:
+//!
+//! - decode: one sample read through the register map of
+//!   `rustsys.example.toml` from an in-memory transport, with the derived
+//!   signals computed and pushed into the chart series;
+//! - store: a storage batch of 60 samples, the default
+//!   `governor.max_batch_size`, written in one transaction;
+//! - render: the five default charts redrawn in full over a 600-sample
+//!   window, the worst case of a render tick.
+//!
+//! A stage is timed as the median of `RUNS` runs. Timings depend on the
+//! machine and on what else runs on it, so the test is ignored by default;
+//! run `cargo test --release --test poll_budget -- --ignored` to hold the
+//! budget. Unoptimized builds are many times slower, so without
+//! optimizations every budget is multiplied by `DEBUG_SLOWDOWN` and only
+//! gross regressions fail. For precise numbers use
+//! `cargo bench --bench poll_loop`.
+
+mod support;
+
+use codesys::codesys::config;
+use codesys::codesys::{
+    charts, draw_chart, insert_motor_data_batch, jitter, read_motor_data, sentinels, setup_database, MotorData,
+    Signal,
+};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use support::{FixedTransport, START_MS};
+
+const CYCLE_BUDGET: Duration = Duration::from_millis(1000);
+const DECODE_BUDGET: Duration = Duration::from_millis(5);
+const STORE_BUDGET: Duration = Duration::from_millis(100);
+const RENDER_BUDGET: Duration = Duration::from_millis(500);
+const DEBUG_SLOWDOWN: u32 = 20;
+const RUNS: usize = 5;
+
+fn output_dir() -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("budget");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// One sample per second for `secs`.
+fn samples(secs: i64) -> Vec<MotorData> {
+    support::samples(START_MS, 1000, secs)
+}
+
+fn median(mut run: impl FnMut()) -> Duration {
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[RUNS / 2]
+}
+
+fn budget(budget: Duration) -> Duration {
+    if cfg!(debug_assertions) {
+        budget * DEBUG_SLOWDOWN
+    } else {
+        budget
+    }
+}
+
+#[test]
+#[ignore = "timing-sensitive; run with --ignored on a quiet machine"]
+fn poll_cycle_fits_budget() {
+    let config = config::parse(include_str!("../rustsys.example.toml")).expect("rustsys.example.toml is valid");
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let dir = output_dir();
+    let window = samples(600);
+
+    let mut transport = FixedTransport;
+    let mut clock = jitter::SampleClock::new(Duration::from_millis(config.poll_interval_ms));
+    let mut filter = sentinels::SentinelFilter::new(config.registers.sentinels.clone());
+    let mut series = charts::SeriesBuffer::new(config.charts.window_secs);
+    let decode = median(|| {
+        let data = runtime.block_on(read_motor_data(&mut transport, &config.registers, &mut clock, &mut filter));
+        series.push(&data.unwrap());
+    });
+
+    let path = dir.join("store.db");
+    let _ = std::fs::remove_file(&path);
+    let pool = runtime.block_on(setup_database(&format!("sqlite://{}?mode=rwc", path.display())));
+    let batch = samples(60);
+    let store = median(|| runtime.block_on(insert_motor_data_batch(&pool, &batch)));
+
+    let mut series = charts::SeriesBuffer::new(600);
+    for data in &window {
+        series.push(data);
+    }
+    let render = median(|| {
+        for signal in Signal::ALL {
+            let path = dir.join(format!("{}.png", signal.name()));
+            draw_chart(path.to_str().unwrap(), &series.series(signal), signal.name(), "Time", "Value").unwrap();
+        }
+    });
+
+    let report = format!("decode {:?}, store {:?}, render {:?}", decode, store, render);
+    assert!(decode <= budget(DECODE_BUDGET), "decode over its {:?} budget: {}", budget(DECODE_BUDGET), report);
+    assert!(store <= budget(STORE_BUDGET), "store over its {:?} budget: {}", budget(STORE_BUDGET), report);
+    assert!(render <= budget(RENDER_BUDGET), "render over its {:?} budget: {}", budget(RENDER_BUDGET), report);
+    let cycle = decode + store + render;
+    assert!(cycle <= budget(CYCLE_BUDGET), "cycle over its {:?} budget: {}", budget(CYCLE_BUDGET), report);
+}
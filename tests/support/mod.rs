@@ -0,0 +1,55 @@
+//! Test data shared by the integration tests and the benchmarks.
+//!
+//! Tests include it with `mod support;`; benchmarks with
+//! `#[path = "../tests/support/mod.rs"] mod support;`. Not every target
+//! uses every item.
+
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use codesys::codesys::config::RegisterDef;
+use codesys::codesys::transport::{Transport, TransportError};
+use codesys::codesys::MotorData;
+use std::collections::BTreeMap;
+
+/// 2023-11-14 22:13:20 UTC.
+pub const START_MS: i64 = 1_700_000_000_000;
+
+/// Answers every read with a value derived from the register address.
+pub struct FixedTransport;
+
+#[async_trait]
+impl Transport for FixedTransport {
+    async fn read(&mut self, def: &RegisterDef) -> Result<f64, TransportError> {
+        Ok(100.0 + def.address as f64)
+    }
+
+    async fn write(&mut self, _def: &RegisterDef, _value: u16) -> Result<(), TransportError> {
+        Ok(())
+    }
+}
+
+/// `count` samples `step_ms` apart from `start_ms` of a motor rated like
+/// the one in `rustsys.example.toml` (2.4 kW, 10.1 N·m), its load a slow
+/// sine of the sample index.
+pub fn samples(start_ms: i64, step_ms: i64, count: i64) -> Vec<MotorData> {
+    (0..count)
+        .map(|i| {
+            let load = (i as f64 / 60.0).sin().abs();
+            MotorData {
+                timestamp: start_ms + i * step_ms,
+                current_power: 2.4 * load,
+                current_torque: 10.1 * load,
+                current_speed: 1450.0,
+                current_heat: 40.0 + 50.0 * load,
+                current_cycles: 0.1 * load,
+                speed_reference: None,
+                temperatures: BTreeMap::new(),
+                pulses: BTreeMap::new(),
+                sequence: None,
+                current: Some(10.0 * load),
+                estimated: Vec::new(),
+            }
+        })
+        .collect()
+}